@@ -0,0 +1,249 @@
+//! Throughput of the conversion hot path and the sinks on synthetic messages, i.e.
+//! `cargo run --release -p katniss-bench -- --save baseline.json` on main and
+//! `cargo run --release -p katniss-bench -- --baseline baseline.json` on a branch
+
+mod generate;
+mod stages;
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use clap::Parser;
+use object_store::{memory::InMemory, path::Path as StorePath};
+use prost_reflect::DescriptorPool;
+
+use katniss_ingestor::{
+    sinks::{
+        BufferEncoder, BufferSink, CsvEncoder, IpcFileEncoder, IpcStreamSink, JsonLinesEncoder,
+        JsonLinesSink, ParquetEncoder, ParquetStoreSink, StoreSink,
+    },
+    LanceIngestor,
+};
+use katniss_pb2arrow::ArrowBatchConfig;
+
+use crate::generate::MessageGenerator;
+use crate::stages::{regressions, Measurement, Workload};
+
+#[derive(Parser)]
+#[command(version, about)]
+struct Args {
+    /// Serialized FileDescriptorSet with the message, the spacecorp test protos if not given
+    #[arg(long)]
+    descriptor_set: Option<PathBuf>,
+
+    /// Message to generate, a full name or a short one if no other package has it
+    #[arg(long, default_value = "eto.pb2arrow.tests.spacecorp.Packet")]
+    message: String,
+
+    #[arg(long, default_value_t = 100_000)]
+    messages: usize,
+
+    /// Each stage runs this many times, the fastest counts
+    #[arg(long, default_value_t = 3)]
+    iterations: usize,
+
+    #[arg(long, default_value_t = 1024)]
+    records_per_batch: usize,
+
+    /// Messages per buffer for the rotator stage
+    #[arg(long, default_value_t = 10_000)]
+    rotate_every: u64,
+
+    /// Values per repeated field, at most
+    #[arg(long, default_value_t = 4)]
+    max_repeated: usize,
+
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+
+    /// Write the measurements as json, to compare a later run against with --baseline
+    #[arg(long)]
+    save: Option<PathBuf>,
+
+    /// Fail if any stage is slower than in these saved measurements
+    #[arg(long)]
+    baseline: Option<PathBuf>,
+
+    /// Drop in messages/sec allowed against --baseline, in percent
+    #[arg(long, default_value_t = 10.0)]
+    tolerance: f64,
+}
+
+fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+    let pool = match &args.descriptor_set {
+        Some(path) => DescriptorPool::decode(std::fs::read(path)?.as_slice())?,
+        None => katniss_test::descriptor_pool()?,
+    };
+    let mut config = ArrowBatchConfig::new(args.message.clone());
+    config.records_per_arrow_batch = args.records_per_batch;
+    let props = config.props(pool)?;
+
+    let mut generator = MessageGenerator::new(args.seed).with_max_repeated(args.max_repeated);
+    let messages = (0..args.messages)
+        .map(|_| generator.message(&props.descriptor))
+        .collect();
+    let workload = Workload::try_new(props, messages)?;
+
+    let mut measurements = vec![
+        workload.measure("convert", args.iterations, || workload.convert(), |_| None)?,
+        workload.measure(
+            "rotate",
+            args.iterations,
+            || workload.rotate(args.rotate_every),
+            |_| None,
+        )?,
+    ];
+    for (stage, encoder) in encoders(&workload) {
+        let encoder = match encoder {
+            Ok(encoder) => encoder,
+            Err(err) => {
+                eprintln!("skipping {stage}: {err}");
+                continue;
+            }
+        };
+        measurements.push(workload.measure(
+            stage,
+            args.iterations,
+            || workload.encode(encoder.as_ref()),
+            |bytes| Some(bytes.len() as u64),
+        )?);
+    }
+
+    let runtime = tokio::runtime::Runtime::new()?;
+    let scratch = tempfile::tempdir()?;
+    for (stage, new_sink) in sinks(&workload, scratch.path()) {
+        measurements.push(workload.measure(
+            stage,
+            args.iterations,
+            || runtime.block_on(workload.write(new_sink()?)),
+            |_| None,
+        )?);
+    }
+    print_table(&measurements);
+
+    if let Some(path) = &args.save {
+        std::fs::write(path, serde_json::to_string_pretty(&measurements)?)?;
+    }
+    if let Some(path) = &args.baseline {
+        let baseline: Vec<Measurement> = serde_json::from_slice(&std::fs::read(path)?)?;
+        let regressed = regressions(&baseline, &measurements, args.tolerance / 100.0);
+        if !regressed.is_empty() {
+            anyhow::bail!("slower than {}: {}", path.display(), regressed.join(", "));
+        }
+    }
+    Ok(())
+}
+
+type Encoder = katniss_ingestor::Result<Box<dyn BufferEncoder>>;
+
+/// Every sink's encoder, the ones that can't take the schema (csv for nested messages) are
+/// skipped with their error
+#[cfg_attr(not(feature = "avro"), allow(unused_mut))]
+fn encoders(workload: &Workload) -> Vec<(&'static str, Encoder)> {
+    let schema = &workload.props.schema;
+    let mut encoders: Vec<(_, Encoder)> = vec![
+        ("parquet", Ok(Box::new(ParquetEncoder::new()))),
+        ("arrow", Ok(Box::new(IpcFileEncoder))),
+        ("jsonl", Ok(Box::new(JsonLinesEncoder))),
+        (
+            "csv",
+            CsvEncoder::try_new(schema).map(|e| Box::new(e) as Box<dyn BufferEncoder>),
+        ),
+    ];
+    #[cfg(feature = "avro")]
+    encoders.push((
+        "avro",
+        katniss_ingestor::sinks::AvroEncoder::try_new(&workload.props)
+            .map(|e| Box::new(e) as Box<dyn BufferEncoder>),
+    ));
+    encoders
+}
+
+type MakeSink<'a> = Box<dyn Fn() -> anyhow::Result<Box<dyn BufferSink>> + 'a>;
+
+/// Every sink that writes somewhere local, made fresh for each run so lance always starts a new
+/// dataset under scratch. Object store sinks write to memory, so this is encoding plus the sink's
+/// own overhead. Flight, Kafka and Postgres need a server and aren't measured
+fn sinks<'a>(workload: &'a Workload, scratch: &'a Path) -> Vec<(&'static str, MakeSink<'a>)> {
+    let schema = &workload.props.schema;
+    let runs = AtomicUsize::new(0);
+    vec![
+        (
+            "lance",
+            make_sink(move || {
+                let run = runs.fetch_add(1, Ordering::Relaxed);
+                let uri = format!("file://{}", scratch.join(format!("{run}.lance")).display());
+                let sink = LanceIngestor::new(uri, schema.clone())?;
+                Ok(Box::new(sink) as Box<dyn BufferSink>)
+            }),
+        ),
+        (
+            "parquet sink",
+            make_sink(|| {
+                let store = Box::new(InMemory::new());
+                let sink = ParquetStoreSink::new(
+                    store,
+                    StorePath::from("bench"),
+                    schema.clone(),
+                    ParquetEncoder::new(),
+                );
+                Ok(Box::new(sink) as Box<dyn BufferSink>)
+            }),
+        ),
+        (
+            "arrow sink",
+            make_sink(|| {
+                let store = Box::new(InMemory::new());
+                let sink = StoreSink::new(
+                    store,
+                    StorePath::from("bench"),
+                    schema.clone(),
+                    IpcFileEncoder,
+                );
+                Ok(Box::new(sink) as Box<dyn BufferSink>)
+            }),
+        ),
+        (
+            "ipc stream",
+            make_sink(|| {
+                let sink = IpcStreamSink::try_new(std::io::sink(), schema)?;
+                Ok(Box::new(sink) as Box<dyn BufferSink>)
+            }),
+        ),
+        (
+            "jsonl sink",
+            make_sink(|| {
+                let sink = JsonLinesSink::new(std::io::sink());
+                Ok(Box::new(sink) as Box<dyn BufferSink>)
+            }),
+        ),
+    ]
+}
+
+fn make_sink<'a, F>(f: F) -> MakeSink<'a>
+where
+    F: Fn() -> anyhow::Result<Box<dyn BufferSink>> + 'a,
+{
+    Box::new(f)
+}
+
+fn print_table(measurements: &[Measurement]) {
+    println!(
+        "{:<12} {:>14} {:>10} {:>12}",
+        "stage", "messages/sec", "MB/sec", "output MB"
+    );
+    for m in measurements {
+        let output = m
+            .output_bytes
+            .map(|bytes| format!("{:.1}", bytes as f64 / 1_000_000.0))
+            .unwrap_or_default();
+        println!(
+            "{:<12} {:>14.0} {:>10.1} {:>12}",
+            m.stage,
+            m.messages_per_sec(),
+            m.mb_per_sec(),
+            output
+        );
+    }
+}
@@ -0,0 +1,197 @@
+use std::time::{Duration, Instant};
+
+use chrono::{TimeZone, Utc};
+use prost::Message;
+use serde::{Deserialize, Serialize};
+
+use katniss_ingestor::{
+    sinks::{BufferEncoder, BufferSink},
+    TemporalBuffer, TemporalRotator,
+};
+use katniss_pb2arrow::{
+    exports::{DynamicMessage, RecordBatch},
+    ArrowBatchProps, RecordConverter,
+};
+
+/// One stage's best run
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Measurement {
+    pub stage: String,
+    pub messages: u64,
+    /// Encoded size of the messages going in, so stages are comparable
+    pub input_bytes: u64,
+    /// What a sink encoded them to
+    pub output_bytes: Option<u64>,
+    pub seconds: f64,
+}
+
+impl Measurement {
+    pub fn messages_per_sec(&self) -> f64 {
+        self.messages as f64 / self.seconds
+    }
+
+    pub fn mb_per_sec(&self) -> f64 {
+        self.input_bytes as f64 / 1_000_000.0 / self.seconds
+    }
+}
+
+/// What the stages are run over, the batches are what the sinks encode
+pub struct Workload {
+    pub props: ArrowBatchProps,
+    pub messages: Vec<DynamicMessage>,
+    pub input_bytes: u64,
+    pub batches: Vec<RecordBatch>,
+}
+
+impl Workload {
+    pub fn try_new(props: ArrowBatchProps, messages: Vec<DynamicMessage>) -> anyhow::Result<Self> {
+        let input_bytes = messages.iter().map(|m| m.encoded_len() as u64).sum();
+        let mut workload = Self {
+            props,
+            messages,
+            input_bytes,
+            batches: Vec::new(),
+        };
+        workload.batches = workload.convert()?;
+        Ok(workload)
+    }
+
+    /// RecordConverter on its own, a batch every records_per_arrow_batch messages
+    pub fn convert(&self) -> anyhow::Result<Vec<RecordBatch>> {
+        let mut records = RecordConverter::try_new(&self.props)?;
+        let mut batches = Vec::new();
+        for msg in &self.messages {
+            records.append_message(msg)?;
+            if records.len() == self.props.records_per_arrow_batch {
+                batches.push(records.records()?);
+            }
+        }
+        if !records.is_empty() {
+            batches.push(records.records()?);
+        }
+        Ok(batches)
+    }
+
+    /// A TemporalRotator on a clock that moves a millisecond per message, so buffers rotate
+    /// every rotate_every messages
+    pub fn rotate(&self, rotate_every: u64) -> anyhow::Result<usize> {
+        let start = Utc::now();
+        let mut rotator =
+            TemporalRotator::new(&self.props, start, Duration::from_millis(rotate_every))?;
+        let mut buffers = 0;
+        for (i, msg) in self.messages.iter().enumerate() {
+            let now = start + chrono::Duration::milliseconds(i as i64);
+            if rotator
+                .ingest_potentially_blocking(msg.clone(), now)?
+                .is_some()
+            {
+                buffers += 1;
+            }
+        }
+        let end = start + chrono::Duration::milliseconds(self.messages.len() as i64);
+        Ok(buffers + usize::from(rotator.rotate(end)?.is_some()))
+    }
+
+    /// The converted batches as a single buffer's worth of output
+    pub fn encode(&self, encoder: &dyn BufferEncoder) -> anyhow::Result<Vec<u8>> {
+        Ok(encoder.encode(&self.props.schema, &self.batches)?)
+    }
+
+    /// Writes the converted batches to the sink as a single buffer and closes it
+    pub async fn write(&self, mut sink: Box<dyn BufferSink>) -> anyhow::Result<()> {
+        let buffer = TemporalBuffer {
+            begin_at: Utc.timestamp_millis_opt(0).unwrap(),
+            end_at: Utc
+                .timestamp_millis_opt(self.messages.len() as i64)
+                .unwrap(),
+            batches: self.batches.clone(),
+        };
+        sink.write_buffer(buffer).await?;
+        sink.close().await?;
+        Ok(())
+    }
+
+    /// Runs f iterations times and keeps the fastest
+    pub fn measure<T>(
+        &self,
+        stage: &str,
+        iterations: usize,
+        mut f: impl FnMut() -> anyhow::Result<T>,
+        output_bytes: impl Fn(&T) -> Option<u64>,
+    ) -> anyhow::Result<Measurement> {
+        let mut best: Option<(Duration, Option<u64>)> = None;
+        for _ in 0..iterations.max(1) {
+            let started = Instant::now();
+            let output = f()?;
+            let elapsed = started.elapsed();
+            if best.map_or(true, |(fastest, _)| elapsed < fastest) {
+                best = Some((elapsed, output_bytes(&output)));
+            }
+        }
+        let (elapsed, output_bytes) = best.expect("ran at least once");
+        Ok(Measurement {
+            stage: stage.to_owned(),
+            messages: self.messages.len() as u64,
+            input_bytes: self.input_bytes,
+            output_bytes,
+            seconds: elapsed.as_secs_f64(),
+        })
+    }
+}
+
+/// Stages whose messages/sec dropped by more than tolerance (a fraction, i.e. 0.1) against the
+/// baseline, as "stage: before -> after". Stages only in one of them are ignored
+pub fn regressions(
+    baseline: &[Measurement],
+    current: &[Measurement],
+    tolerance: f64,
+) -> Vec<String> {
+    current
+        .iter()
+        .filter_map(|now| {
+            let before = baseline.iter().find(|b| b.stage == now.stage)?;
+            let floor = before.messages_per_sec() * (1.0 - tolerance);
+            (now.messages_per_sec() < floor).then(|| {
+                format!(
+                    "{}: {:.0} -> {:.0} messages/sec",
+                    now.stage,
+                    before.messages_per_sec(),
+                    now.messages_per_sec()
+                )
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn measurement(stage: &str, seconds: f64) -> Measurement {
+        Measurement {
+            stage: stage.to_owned(),
+            messages: 1000,
+            input_bytes: 2_000_000,
+            output_bytes: None,
+            seconds,
+        }
+    }
+
+    #[test]
+    fn slower_stages_past_the_tolerance_are_regressions() {
+        let baseline = [measurement("convert", 1.0), measurement("parquet", 1.0)];
+        assert_eq!(baseline[0].messages_per_sec(), 1000.0);
+        assert_eq!(baseline[0].mb_per_sec(), 2.0);
+
+        let current = [
+            measurement("convert", 1.05),
+            measurement("parquet", 2.0),
+            measurement("avro", 9.0),
+        ];
+        assert_eq!(
+            regressions(&baseline, &current, 0.1),
+            ["parquet: 1000 -> 500 messages/sec"]
+        );
+        assert!(regressions(&baseline, &current, 0.6).is_empty());
+    }
+}
@@ -0,0 +1,81 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use chrono::{DateTime, Utc};
+
+use crate::temporal_rotator::TemporalBuffer;
+
+/// Callback sent along with a message (see PipelineBuilder::build_acked), i.e. to commit a
+/// Kafka/MQTT offset with at-least-once semantics instead of committing on receive.
+/// Fired once the buffer holding the message has been written by the sink, or straight away
+/// if the pipeline deliberately drops the message (filtered, shed, duplicate, late) so it never will be.
+/// Never fired if the message is lost to an error.
+pub struct Ack(Box<dyn FnOnce() + Send>);
+
+impl Ack {
+    pub fn new<F: FnOnce() + Send + 'static>(f: F) -> Self {
+        Self(Box::new(f))
+    }
+
+    pub(crate) fn fire(self) {
+        (self.0)()
+    }
+}
+
+impl fmt::Debug for Ack {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Ack")
+    }
+}
+
+/// Acks for messages sitting in buffers that are still open, keyed by the buffer's begin_at
+#[derive(Debug, Default)]
+pub(crate) struct PendingAcks {
+    by_buffer: BTreeMap<DateTime<Utc>, Vec<Ack>>,
+}
+
+impl PendingAcks {
+    pub(crate) fn hold(&mut self, begin_at: DateTime<Utc>, ack: Ack) {
+        self.by_buffer.entry(begin_at).or_default().push(ack);
+    }
+
+    /// Acks for the messages in a buffer that's been rotated out
+    pub(crate) fn release(&mut self, buffer: &TemporalBuffer) -> Vec<Ack> {
+        self.by_buffer.remove(&buffer.begin_at).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use super::*;
+
+    #[test]
+    fn acks_are_released_with_their_buffer() -> anyhow::Result<()> {
+        let fired = Arc::new(AtomicUsize::new(0));
+        let ack = || {
+            let fired = fired.clone();
+            Ack::new(move || {
+                fired.fetch_add(1, Ordering::SeqCst);
+            })
+        };
+
+        let period = std::time::Duration::from_secs(1);
+        let first = TemporalBuffer::new(Utc::now(), period)?;
+        let second = TemporalBuffer::new(first.end_at, period)?;
+
+        let mut pending = PendingAcks::default();
+        pending.hold(first.begin_at, ack());
+        pending.hold(first.begin_at, ack());
+        pending.hold(second.begin_at, ack());
+
+        pending.release(&first).into_iter().for_each(Ack::fire);
+        assert_eq!(fired.load(Ordering::SeqCst), 2);
+        assert!(pending.release(&first).is_empty());
+        Ok(())
+    }
+}
@@ -1,14 +1,25 @@
+use std::sync::Arc;
+
+use arrow_array::builder::StringBuilder;
+use arrow_schema::SchemaRef;
 use katniss_pb2arrow::{
     exports::{DynamicMessage, RecordBatch},
-    ArrowBatchProps, RecordConverter,
+    ArrowBatchProps, KatnissArrowError, RecordConverter,
 };
 
-use crate::Result;
+use crate::{fan_in::source_tagged_schema, Result};
 
 /// Ingests individual Protobuf Messages, and returns a batch if batch_size threshhold is crossed.
 pub struct ProtobufBatchIngestor {
     batch_size: usize,
     converter: RecordConverter,
+    sources: Option<SourceColumn>,
+}
+
+/// Values for the extra column tagging each row with its source, see FanIn
+struct SourceColumn {
+    schema: SchemaRef,
+    values: StringBuilder,
 }
 
 impl ProtobufBatchIngestor {
@@ -16,31 +27,75 @@ impl ProtobufBatchIngestor {
         Ok(Self {
             batch_size: props.records_per_arrow_batch,
             converter: RecordConverter::try_from(props)?,
+            sources: None,
+        })
+    }
+
+    /// Append a column holding the source each message was ingested from
+    pub fn with_source_column(mut self, props: &ArrowBatchProps, name: &str) -> Self {
+        self.sources = Some(SourceColumn {
+            schema: source_tagged_schema(&props.schema, name),
+            values: StringBuilder::with_capacity(self.batch_size, self.batch_size * 8),
+        });
+        self
+    }
+
+    /// An empty ingestor with the same props and source column, see
+    /// RecordConverter::try_clone_empty
+    pub fn try_clone_empty(&self) -> Result<Self> {
+        Ok(Self {
+            batch_size: self.batch_size,
+            converter: self.converter.try_clone_empty()?,
+            sources: self.sources.as_ref().map(|sources| SourceColumn {
+                schema: sources.schema.clone(),
+                values: StringBuilder::with_capacity(self.batch_size, self.batch_size * 8),
+            }),
         })
     }
 
     /// Ingests a single message, returns a Record Batch if batch size has been reached
     pub fn ingest_message(&mut self, msg: DynamicMessage) -> Result<Option<RecordBatch>> {
+        self.ingest_sourced(msg, None)
+    }
+
+    /// Like ingest_message, source is ignored unless there's a source column
+    pub fn ingest_sourced(
+        &mut self,
+        msg: DynamicMessage,
+        source: Option<&str>,
+    ) -> Result<Option<RecordBatch>> {
         self.converter.append_message(&msg)?;
+        if let Some(sources) = &mut self.sources {
+            sources.values.append_option(source);
+        }
 
         if self.converter.len() >= self.batch_size {
-            Ok(Some(self.converter.records()?))
+            Ok(Some(self.finish()?))
         } else {
             Ok(None)
         }
     }
 
     pub fn finish(&mut self) -> Result<RecordBatch> {
-        let records = self.converter.records();
-        Ok(records?)
+        let records = self.converter.records()?;
+        let Some(sources) = &mut self.sources else {
+            return Ok(records);
+        };
+
+        let mut columns = records.columns().to_vec();
+        columns.push(Arc::new(sources.values.finish()));
+        Ok(RecordBatch::try_new(sources.schema.clone(), columns)?)
+    }
+
+    /// See RecordConverter::take_warnings, taken after every finish there's at most a batch's worth
+    pub fn take_warnings(&mut self) -> Vec<KatnissArrowError> {
+        self.converter.take_warnings()
     }
 
-    #[allow(unused)]
     pub fn len(&self) -> usize {
         self.converter.len()
     }
 
-    #[allow(unused)]
     pub fn is_empty(&self) -> bool {
         self.len() == 0
     }
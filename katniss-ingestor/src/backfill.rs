@@ -0,0 +1,871 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use async_compression::tokio::bufread::{GzipDecoder, ZstdDecoder};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use chrono::{DateTime, Utc};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, BufReader, ReadBuf};
+
+use arrow_array::{Array, StructArray};
+use arrow_schema::SchemaRef;
+
+use katniss_pb2arrow::{
+    exports::{prost_reflect::MessageDescriptor, DynamicMessage},
+    ArrowBatchProps, RecordConverter,
+};
+
+use crate::{
+    errors::KatinssIngestorError,
+    event_time::{EventTime, EventTimeRotator},
+    filter::Predicate,
+    sinks::BufferSink,
+    tcp_source::read_varint,
+    temporal_rotator::TemporalBuffer,
+    Result,
+};
+
+/// How messages are laid out in the files being backfilled
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum Framing {
+    /// Each message prefixed by its varint encoded length (protobuf's writeDelimitedTo)
+    #[default]
+    LengthDelimited,
+    /// Each message prefixed by its length as a 4 byte big endian integer
+    FixedLength,
+    /// One message per line in protobuf's JSON mapping, blank lines are skipped
+    JsonLines,
+    /// One base64 encoded message per line, blank lines are skipped
+    Base64Lines,
+    /// Each file is one wrapper message with the messages in a repeated field
+    /// i.e. `Log { repeated Packet packets = 1; }` is `Wrapped { message: "...Log", field: "packets" }`
+    Wrapped { message: String, field: String },
+}
+
+/// Counts from a finished backfill
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BackfillReport {
+    pub files: usize,
+    /// Bytes of input read so far, a file counts as far into it as the last message read.
+    /// Compressed files count by what the decompressor has read of them on disk
+    pub bytes: u64,
+    pub messages: u64,
+    pub buffers: usize,
+    pub rows: usize,
+    pub dropped_filtered: u64,
+    pub dropped_late: u64,
+    pub dropped_missing_time: u64,
+    /// Messages (counting from the first file, including resumed over ones) that have made it
+    /// into the sink, see Backfill::with_resume_from
+    pub offset: u64,
+    /// Nulls written per column, dot separated for nested fields, i.e. "header.stamp"
+    pub null_counts: BTreeMap<String, u64>,
+}
+
+/// What Backfill::dry_run found
+#[derive(Debug, Clone, PartialEq)]
+pub struct DryRunReport {
+    pub schema: SchemaRef,
+    pub files_read: usize,
+    pub messages_converted: usize,
+    /// Messages in all the files, extrapolated from the ones read
+    pub estimated_messages: u64,
+    /// Files that couldn't be decoded and messages that couldn't be converted, and why
+    pub errors: Vec<String>,
+}
+
+/// Reprocesses recorded messages through event time rotation into a sink,
+/// so the output lines up with what live ingestion would have written
+pub struct Backfill {
+    props: ArrowBatchProps,
+    event_time: EventTime,
+    period: Duration,
+    framing: Framing,
+    filters: Vec<Predicate>,
+    progress: Option<Box<dyn FnMut(&BackfillReport) + Send>>,
+    resume_from: u64,
+    offset: u64,
+}
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// Messages between progress callbacks within a file
+const PROGRESS_EVERY: u64 = 10_000;
+
+impl Backfill {
+    pub fn new(props: ArrowBatchProps, event_time: EventTime, period: Duration) -> Self {
+        Self {
+            props,
+            event_time,
+            period,
+            framing: Framing::default(),
+            filters: Vec::new(),
+            progress: None,
+            resume_from: 0,
+            offset: 0,
+        }
+    }
+
+    pub fn with_framing(mut self, framing: Framing) -> Self {
+        self.framing = framing;
+        self
+    }
+
+    /// Same as PipelineBuilder::with_filter
+    pub fn with_filter(mut self, predicate: Predicate) -> Self {
+        self.filters.push(predicate);
+        self
+    }
+
+    /// Called with the counts so far after every file and every 10000 messages,
+    /// i.e. to draw a progress bar of BackfillReport::bytes against the total size of the files
+    pub fn with_progress(mut self, progress: impl FnMut(&BackfillReport) + Send + 'static) -> Self {
+        self.progress = Some(Box::new(progress));
+        self
+    }
+
+    /// Skips this many messages from the start of the first file, i.e. the offset of a backfill
+    /// that failed partway so a rerun doesn't write them twice. Offsets are exact for inputs in
+    /// event time order. With out of order messages the offset stops at the oldest message still
+    /// held by an open window, so nothing is lost but some already written rows may be written again
+    pub fn with_resume_from(mut self, offset: u64) -> Self {
+        self.resume_from = offset;
+        self
+    }
+
+    /// Ingests files in the order given, writing each window to the sink as it closes
+    /// and flushing whatever is still open at the end
+    pub async fn run<S: BufferSink>(
+        &mut self,
+        files: &[PathBuf],
+        sink: &mut S,
+    ) -> Result<BackfillReport> {
+        let mut report = BackfillReport {
+            offset: self.resume_from,
+            ..Default::default()
+        };
+        self.offset = self.resume_from;
+        let mut rotator = EventTimeRotator::new(&self.props, self.event_time.clone(), self.period);
+        let mut consumed = 0;
+        // index of the first message in each open window, the oldest is where a rerun has to start
+        let mut unflushed: BTreeMap<DateTime<Utc>, u64> = BTreeMap::new();
+
+        for file in files {
+            let mut frames =
+                Frames::open(file, self.framing.clone(), &self.props.descriptor).await?;
+            let bytes_before = report.bytes;
+            while let Some(msg) = frames.next().await? {
+                consumed += 1;
+                report.bytes = bytes_before + frames.position();
+                if consumed <= self.resume_from {
+                    continue;
+                }
+                report.messages += 1;
+                if report.messages % PROGRESS_EVERY == 0 {
+                    self.report_progress(&report);
+                }
+                if !self.filters.iter().all(|f| f.matches(&msg)) {
+                    report.dropped_filtered += 1;
+                    continue;
+                }
+
+                let finished = rotator.ingest_potentially_blocking(msg)?;
+                if let Some(window) = rotator.last_window() {
+                    unflushed.entry(window).or_insert(consumed - 1);
+                }
+                if !finished.is_empty() {
+                    for buffer in &finished {
+                        unflushed.remove(&buffer.begin_at);
+                    }
+                    write_all(sink, finished, &mut report).await?;
+                    self.offset = unflushed.values().min().copied().unwrap_or(consumed);
+                    report.offset = self.offset;
+                }
+            }
+            report.files += 1;
+            report.bytes = bytes_before + frames.size;
+            self.report_progress(&report);
+        }
+
+        write_all(sink, rotator.flush_all()?, &mut report).await?;
+        sink.close().await?;
+        self.offset = self.offset.max(consumed);
+        report.offset = self.offset;
+        report.dropped_late = rotator.dropped_late;
+        report.dropped_missing_time = rotator.dropped_missing_time;
+        Ok(report)
+    }
+
+    /// Messages from the start of the first file known to be in the sink, i.e. what to resume
+    /// from after run failed partway
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Decodes files in order until `limit` messages have been converted to arrow, without
+    /// writing anything, i.e. as a cheap check before a long backfill. Errors are collected
+    /// rather than returned, files are always read to the end so the estimate is per byte
+    pub async fn dry_run(&self, files: &[PathBuf], limit: usize) -> Result<DryRunReport> {
+        let mut converter = RecordConverter::try_new(&self.props)?;
+        let mut report = DryRunReport {
+            schema: self.props.schema.clone(),
+            files_read: 0,
+            messages_converted: 0,
+            estimated_messages: 0,
+            errors: Vec::new(),
+        };
+        let (mut bytes_read, mut messages_read) = (0, 0);
+
+        for file in files {
+            if report.messages_converted >= limit {
+                break;
+            }
+            let mut frames =
+                Frames::open(file, self.framing.clone(), &self.props.descriptor).await?;
+            bytes_read += frames.size;
+            report.files_read += 1;
+
+            for i in 0.. {
+                let msg = match frames.next().await {
+                    Ok(Some(msg)) => msg,
+                    Ok(None) => break,
+                    Err(err) => {
+                        report.errors.push(format!("{}: {err}", file.display()));
+                        break;
+                    }
+                };
+                messages_read += 1;
+                if report.messages_converted >= limit {
+                    continue;
+                }
+                match converter.append_message(&msg) {
+                    Ok(()) => report.messages_converted += 1,
+                    Err(err) => report
+                        .errors
+                        .push(format!("{} message {i}: {err}", file.display())),
+                }
+            }
+        }
+        if let Err(err) = converter.records() {
+            report.errors.push(err.to_string());
+        }
+
+        if bytes_read > 0 {
+            let total_bytes = files
+                .iter()
+                .map(|f| Ok(fs::metadata(f)?.len()))
+                .sum::<Result<u64>>()?;
+            report.estimated_messages =
+                (messages_read as f64 * total_bytes as f64 / bytes_read as f64).round() as u64;
+        }
+        Ok(report)
+    }
+
+    fn report_progress(&mut self, report: &BackfillReport) {
+        if let Some(progress) = &mut self.progress {
+            progress(report);
+        }
+    }
+}
+
+/// A file's messages read one frame at a time, so only the message being decoded is in memory
+/// (a Wrapped file is one message, it's read whole). Gzip and zstd files are decompressed as
+/// they're read, picked by their magic bytes rather than the extension
+struct Frames<'a> {
+    path: &'a Path,
+    framing: Framing,
+    descriptor: MessageDescriptor,
+    reader: Box<dyn AsyncBufRead + Unpin + Send>,
+    /// Bytes of the file on disk read so far
+    position: Arc<AtomicU64>,
+    size: u64,
+    line: usize,
+    frame: Vec<u8>,
+    wrapped: Option<std::vec::IntoIter<DynamicMessage>>,
+}
+
+impl<'a> Frames<'a> {
+    async fn open(
+        path: &'a Path,
+        framing: Framing,
+        descriptor: &MessageDescriptor,
+    ) -> Result<Frames<'a>> {
+        let file = tokio::fs::File::open(path).await?;
+        let size = file.metadata().await?.len();
+        let position = Arc::new(AtomicU64::new(0));
+        let mut file = Counted {
+            inner: BufReader::new(file),
+            read: position.clone(),
+        };
+        let head = file.fill_buf().await?;
+        let reader: Box<dyn AsyncBufRead + Unpin + Send> = if head.starts_with(&GZIP_MAGIC) {
+            let mut decoder = GzipDecoder::new(file);
+            decoder.multiple_members(true);
+            Box::new(BufReader::new(decoder))
+        } else if head.starts_with(&ZSTD_MAGIC) {
+            let mut decoder = ZstdDecoder::new(file);
+            decoder.multiple_members(true);
+            Box::new(BufReader::new(decoder))
+        } else {
+            Box::new(file)
+        };
+
+        Ok(Self {
+            path,
+            framing,
+            descriptor: descriptor.clone(),
+            reader,
+            position,
+            size,
+            line: 0,
+            frame: Vec::new(),
+            wrapped: None,
+        })
+    }
+
+    /// How far into the file on disk the messages so far were read from
+    fn position(&self) -> u64 {
+        self.position.load(Ordering::Relaxed).min(self.size)
+    }
+
+    /// The next message, None at the end of the file
+    async fn next(&mut self) -> Result<Option<DynamicMessage>> {
+        self.read_next().await.map_err(|err| match err {
+            KatinssIngestorError::IoError(err) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                KatinssIngestorError::TruncatedFile(self.path.to_path_buf())
+            }
+            err => err,
+        })
+    }
+
+    async fn read_next(&mut self) -> Result<Option<DynamicMessage>> {
+        let descriptor = self.descriptor.clone();
+        match &self.framing {
+            Framing::LengthDelimited => {
+                let Some(len) = read_varint(&mut self.reader).await? else {
+                    return Ok(None);
+                };
+                self.read_frame(len).await?;
+                Ok(Some(DynamicMessage::decode(
+                    descriptor,
+                    self.frame.as_slice(),
+                )?))
+            }
+            Framing::FixedLength => {
+                let mut len = [0; 4];
+                if self.reader.fill_buf().await?.is_empty() {
+                    return Ok(None);
+                }
+                self.reader.read_exact(&mut len).await?;
+                self.read_frame(u32::from_be_bytes(len).into()).await?;
+                Ok(Some(DynamicMessage::decode(
+                    descriptor,
+                    self.frame.as_slice(),
+                )?))
+            }
+            Framing::JsonLines | Framing::Base64Lines => loop {
+                self.frame.clear();
+                if self.reader.read_until(b'\n', &mut self.frame).await? == 0 {
+                    return Ok(None);
+                }
+                self.line += 1;
+                let invalid = |err: String| {
+                    KatinssIngestorError::InvalidLine(self.path.to_path_buf(), self.line, err)
+                };
+                let line = std::str::from_utf8(&self.frame)
+                    .map_err(|err| invalid(err.to_string()))?
+                    .trim();
+                if line.is_empty() {
+                    continue;
+                }
+
+                if self.framing == Framing::JsonLines {
+                    let mut json = serde_json::Deserializer::from_str(line);
+                    return DynamicMessage::deserialize(descriptor, &mut json)
+                        .and_then(|msg| json.end().map(|_| Some(msg)))
+                        .map_err(|err| invalid(err.to_string()));
+                }
+                let msg = BASE64
+                    .decode(line)
+                    .map_err(|err| invalid(err.to_string()))?;
+                return Ok(Some(DynamicMessage::decode(descriptor, msg.as_slice())?));
+            },
+            Framing::Wrapped { message, field } => {
+                if self.wrapped.is_none() {
+                    let wrapper = descriptor
+                        .parent_pool()
+                        .get_message_by_name(message)
+                        .ok_or_else(|| KatinssIngestorError::UnknownMessage(message.clone()))?;
+                    let mut bytes = Vec::new();
+                    self.reader.read_to_end(&mut bytes).await?;
+                    let wrapper = DynamicMessage::decode(wrapper, bytes.as_slice())?;
+
+                    let unknown = || KatinssIngestorError::UnknownField(field.clone());
+                    let values = wrapper.get_field_by_name(field).ok_or_else(unknown)?;
+                    let messages = values
+                        .as_list()
+                        .ok_or_else(unknown)?
+                        .iter()
+                        .map(|v| {
+                            v.as_message()
+                                .filter(|m| m.descriptor() == descriptor)
+                                .cloned()
+                                .ok_or_else(unknown)
+                        })
+                        .collect::<Result<Vec<_>>>()?;
+                    self.wrapped = Some(messages.into_iter());
+                }
+                Ok(self.wrapped.as_mut().and_then(Iterator::next))
+            }
+        }
+    }
+
+    /// Reads the next len bytes into frame, a bogus length fails when the file runs out
+    /// rather than allocating all of it up front
+    async fn read_frame(&mut self, len: u64) -> Result<()> {
+        self.frame.clear();
+        (&mut self.reader)
+            .take(len)
+            .read_to_end(&mut self.frame)
+            .await?;
+        if (self.frame.len() as u64) < len {
+            return Err(KatinssIngestorError::TruncatedFile(self.path.to_path_buf()));
+        }
+        Ok(())
+    }
+}
+
+/// Counts the bytes read through it, shared so they can be read while a decoder owns it
+struct Counted<R> {
+    inner: R,
+    read: Arc<AtomicU64>,
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for Counted<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let filled = buf.filled().len();
+        let polled = Pin::new(&mut self.inner).poll_read(cx, buf);
+        let read = (buf.filled().len() - filled) as u64;
+        self.read.fetch_add(read, Ordering::Relaxed);
+        polled
+    }
+}
+
+impl<R: AsyncBufRead + Unpin> AsyncBufRead for Counted<R> {
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+        Pin::new(&mut self.get_mut().inner).poll_fill_buf(cx)
+    }
+
+    fn consume(mut self: Pin<&mut Self>, amt: usize) {
+        self.read.fetch_add(amt as u64, Ordering::Relaxed);
+        Pin::new(&mut self.inner).consume(amt)
+    }
+}
+
+/// Backfill every file in dir (sorted by name, not recursive) with length delimited framing
+pub async fn backfill<S: BufferSink>(
+    props: ArrowBatchProps,
+    dir: &Path,
+    event_time: EventTime,
+    period: Duration,
+    sink: &mut S,
+) -> Result<BackfillReport> {
+    let mut files = fs::read_dir(dir)?
+        .map(|entry| Ok(entry?.path()))
+        .collect::<Result<Vec<_>>>()?;
+    files.retain(|f| f.is_file());
+    files.sort();
+
+    Backfill::new(props, event_time, period)
+        .run(&files, sink)
+        .await
+}
+
+async fn write_all<S: BufferSink>(
+    sink: &mut S,
+    buffers: Vec<TemporalBuffer>,
+    report: &mut BackfillReport,
+) -> Result<()> {
+    for buffer in buffers {
+        report.buffers += 1;
+        report.rows += buffer.num_rows();
+        for batch in &buffer.batches {
+            for (field, column) in batch.schema().fields().iter().zip(batch.columns()) {
+                count_nulls(&mut report.null_counts, field.name(), column.as_ref());
+            }
+        }
+        sink.write_buffer(buffer).await?;
+    }
+    Ok(())
+}
+
+fn count_nulls(counts: &mut BTreeMap<String, u64>, path: &str, column: &dyn Array) {
+    *counts.entry(path.to_owned()).or_default() += column.null_count() as u64;
+    if let Some(parent) = column.as_any().downcast_ref::<StructArray>() {
+        for (field, child) in parent.fields().iter().zip(parent.columns()) {
+            let path = format!("{path}.{}", field.name());
+            count_nulls(counts, &path, child.as_ref());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+
+    use async_trait::async_trait;
+    use flate2::write::GzEncoder;
+    use prost::Message;
+
+    use katniss_test::{
+        descriptor_pool,
+        protos::spacecorp::{Log, Packet, Timestamp},
+    };
+
+    use super::*;
+
+    const PACKET: &str = "eto.pb2arrow.tests.spacecorp.Packet";
+
+    #[derive(Default)]
+    struct Collect(Vec<TemporalBuffer>);
+
+    #[async_trait]
+    impl BufferSink for Collect {
+        async fn write_buffer(&mut self, buffer: TemporalBuffer) -> Result<()> {
+            self.0.push(buffer);
+            Ok(())
+        }
+    }
+
+    fn packets(seconds: &[i64]) -> Vec<Packet> {
+        seconds
+            .iter()
+            .map(|&seconds| Packet {
+                timestamp: Some(Timestamp { seconds, nanos: 0 }),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    fn backfill_job() -> anyhow::Result<Backfill> {
+        let props = ArrowBatchProps::try_new(descriptor_pool()?, PACKET.to_owned())?;
+        Ok(Backfill::new(
+            props,
+            EventTime::new("timestamp"),
+            Duration::from_secs(10),
+        ))
+    }
+
+    #[tokio::test]
+    async fn it_backfills_a_directory_of_delimited_files() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        for (name, seconds) in [("b", &[15, 21][..]), ("a", &[1, 2, 12][..])] {
+            let mut bytes = Vec::new();
+            for packet in packets(seconds) {
+                packet.encode_length_delimited(&mut bytes)?;
+            }
+            fs::write(dir.path().join(name), bytes)?;
+        }
+
+        let props = ArrowBatchProps::try_new(descriptor_pool()?, PACKET.to_owned())?;
+        let mut sink = Collect::default();
+        let report = backfill(
+            props,
+            dir.path(),
+            EventTime::new("timestamp"),
+            Duration::from_secs(10),
+            &mut sink,
+        )
+        .await?;
+
+        assert_eq!(report.files, 2);
+        assert_eq!(report.messages, 5);
+        assert_eq!(report.null_counts["timestamp"], 0);
+        assert_eq!(report.null_counts["timestamp.seconds"], 0);
+        assert_eq!(
+            sink.0.iter().map(|b| b.num_rows()).collect::<Vec<_>>(),
+            vec![2, 2, 1]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_reads_wrapped_files_and_rejects_truncated_ones() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let wrapped = dir.path().join("log.pb");
+        fs::write(
+            &wrapped,
+            Log {
+                packets: packets(&[1, 2, 11]),
+            }
+            .encode_to_vec(),
+        )?;
+
+        let job = backfill_job()?.with_framing(Framing::Wrapped {
+            message: "eto.pb2arrow.tests.spacecorp.Log".to_owned(),
+            field: "packets".to_owned(),
+        });
+        let progress = Arc::new(Mutex::new(Vec::new()));
+        let seen = progress.clone();
+        let mut job = job.with_progress(move |report| seen.lock().unwrap().push(report.clone()));
+        let mut sink = Collect::default();
+        let report = job.run(&[wrapped.clone()], &mut sink).await?;
+        assert_eq!(report.rows, 3);
+        assert_eq!(report.buffers, 2);
+        assert_eq!(report.bytes, fs::metadata(&wrapped)?.len());
+        let seen: Vec<_> = progress
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|r| (r.files, r.messages))
+            .collect();
+        assert_eq!(seen, [(1, 3)]);
+
+        let truncated = dir.path().join("truncated");
+        let mut bytes = Vec::new();
+        packets(&[1])[0].encode_length_delimited(&mut bytes)?;
+        fs::write(&truncated, &bytes[..bytes.len() - 1])?;
+        assert!(matches!(
+            backfill_job()?.run(&[truncated], &mut sink).await,
+            Err(KatinssIngestorError::TruncatedFile(_))
+        ));
+
+        // a 4GB length is read until the file runs out, not allocated
+        let bogus = dir.path().join("bogus");
+        fs::write(&bogus, [0xff, 0xff, 0xff, 0xff, 0x0f, 0x01])?;
+        assert!(matches!(
+            backfill_job()?.run(&[bogus], &mut sink).await,
+            Err(KatinssIngestorError::TruncatedFile(_))
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_reports_progress_within_a_file() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let file = dir.path().join("big");
+        let mut bytes = Vec::new();
+        for packet in packets(&vec![1; 2 * PROGRESS_EVERY as usize]) {
+            packet.encode_length_delimited(&mut bytes)?;
+        }
+        fs::write(&file, &bytes)?;
+
+        let progress = Arc::new(Mutex::new(Vec::new()));
+        let seen = progress.clone();
+        let mut job = backfill_job()?.with_progress(move |report| {
+            seen.lock().unwrap().push((report.files, report.bytes));
+        });
+        job.run(&[file], &mut Collect::default()).await?;
+
+        let size = bytes.len() as u64;
+        let seen = progress.lock().unwrap().clone();
+        assert_eq!(seen, [(0, size / 2), (0, size), (1, size)]);
+        Ok(())
+    }
+
+    /// Fails on the nth write
+    struct FailOn(usize, Collect);
+
+    #[async_trait]
+    impl BufferSink for FailOn {
+        async fn write_buffer(&mut self, buffer: TemporalBuffer) -> Result<()> {
+            if self.1 .0.len() + 1 == self.0 {
+                return Err(KatinssIngestorError::PipelineClosed);
+            }
+            self.1.write_buffer(buffer).await
+        }
+    }
+
+    #[tokio::test]
+    async fn it_resumes_from_the_offset_of_a_failed_run() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut files = Vec::new();
+        for (name, seconds) in [("a", &[1, 2, 12][..]), ("b", &[15, 21][..])] {
+            let mut bytes = Vec::new();
+            for packet in packets(seconds) {
+                packet.encode_length_delimited(&mut bytes)?;
+            }
+            files.push(dir.path().join(name));
+            fs::write(files.last().unwrap(), bytes)?;
+        }
+
+        let mut failed = backfill_job()?;
+        let mut sink = FailOn(2, Collect::default());
+        assert!(failed.run(&files, &mut sink).await.is_err());
+        assert_eq!(failed.offset(), 2);
+        assert_eq!(sink.1 .0.len(), 1);
+
+        let mut sink = Collect::default();
+        let report = backfill_job()?
+            .with_resume_from(failed.offset())
+            .run(&files, &mut sink)
+            .await?;
+        assert_eq!(report.messages, 3);
+        assert_eq!(report.offset, 5);
+        assert_eq!(
+            sink.0.iter().map(|b| b.num_rows()).collect::<Vec<_>>(),
+            vec![2, 1]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_does_not_resume_past_rows_held_by_open_windows() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let file = dir.path().join("late");
+        let mut bytes = Vec::new();
+        // 3 is late but within the allowed lateness, it closes 0..10 with 12 still unwritten
+        for packet in packets(&[1, 12, 3, 16, 26]) {
+            packet.encode_length_delimited(&mut bytes)?;
+        }
+        fs::write(&file, bytes)?;
+        let job = || -> anyhow::Result<Backfill> {
+            let props = ArrowBatchProps::try_new(descriptor_pool()?, PACKET.to_owned())?;
+            let event_time =
+                EventTime::new("timestamp").with_allowed_lateness(Duration::from_secs(5));
+            Ok(Backfill::new(props, event_time, Duration::from_secs(10)))
+        };
+
+        let mut failed = job()?;
+        let mut sink = FailOn(2, Collect::default());
+        assert!(failed.run(&[file.clone()], &mut sink).await.is_err());
+        assert_eq!(sink.1 .0[0].num_rows(), 2);
+        assert_eq!(failed.offset(), 1);
+
+        let mut sink = Collect::default();
+        job()?
+            .with_resume_from(failed.offset())
+            .run(&[file], &mut sink)
+            .await?;
+        assert_eq!(
+            sink.0.iter().map(|b| b.num_rows()).collect::<Vec<_>>(),
+            vec![1, 2, 1]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_decompresses_gzip_and_zstd_files() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut bytes = Vec::new();
+        for packet in packets(&[1, 2, 11]) {
+            packet.encode_length_delimited(&mut bytes)?;
+        }
+        // two gzip members, like files appended to with gzip >>
+        let (first, second) = bytes.split_at(bytes.len() / 2);
+        let mut gzip = Vec::new();
+        for member in [first, second] {
+            let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(member)?;
+            gzip.extend(encoder.finish()?);
+        }
+
+        // named without their extensions, it's the magic bytes that count
+        let files = [dir.path().join("a"), dir.path().join("b")];
+        fs::write(&files[0], gzip)?;
+        fs::write(&files[1], zstd::encode_all(bytes.as_slice(), 0)?)?;
+
+        let mut sink = Collect::default();
+        let report = backfill_job()?.run(&files, &mut sink).await?;
+        assert_eq!(report.messages, 6);
+        let on_disk = fs::metadata(&files[0])?.len() + fs::metadata(&files[1])?.len();
+        assert_eq!(report.bytes, on_disk);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn dry_runs_convert_a_sample_and_estimate_the_rest() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut files = vec![dir.path().join("corrupt")];
+        fs::write(&files[0], [0x05, 0x01])?;
+        for name in ["a", "b", "c"] {
+            let mut bytes = Vec::new();
+            for packet in packets(&[1, 2]) {
+                packet.encode_length_delimited(&mut bytes)?;
+            }
+            files.push(dir.path().join(name));
+            fs::write(files.last().unwrap(), bytes)?;
+        }
+
+        let report = backfill_job()?.dry_run(&files, 3).await?;
+        assert_eq!(report.files_read, 3);
+        assert_eq!(report.messages_converted, 3);
+        assert_eq!(report.errors.len(), 1);
+        assert!(report.errors[0].contains("corrupt"));
+        // 4 messages in 2 of the 3 good files, plus a couple of corrupt bytes
+        assert!((5..=6).contains(&report.estimated_messages));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_reads_fixed_length_json_and_base64_framings() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let packets = packets(&[1, 2, 11]);
+
+        let mut fixed = Vec::new();
+        for packet in &packets {
+            let msg = packet.encode_to_vec();
+            fixed.extend((msg.len() as u32).to_be_bytes());
+            fixed.extend(msg);
+        }
+        let json = packets
+            .iter()
+            .map(|p| {
+                format!(
+                    r#"{{"timestamp": {{"seconds": {}}}}}"#,
+                    p.timestamp.as_ref().unwrap().seconds
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        let base64 = packets
+            .iter()
+            .map(|p| BASE64.encode(p.encode_to_vec()))
+            .collect::<Vec<_>>()
+            .join("\n\n");
+
+        for (framing, bytes) in [
+            (Framing::FixedLength, fixed),
+            (Framing::JsonLines, json.into_bytes()),
+            (Framing::Base64Lines, base64.into_bytes()),
+        ] {
+            let file = dir.path().join(format!("{framing:?}"));
+            fs::write(&file, bytes)?;
+            let mut sink = Collect::default();
+            let report = backfill_job()?
+                .with_framing(framing)
+                .run(&[file], &mut sink)
+                .await?;
+            assert_eq!(report.messages, 3);
+            assert_eq!(report.buffers, 2);
+        }
+
+        let bad = dir.path().join("bad.jsonl");
+        fs::write(&bad, "{}\n\n{\"nope\": 1}")?;
+        let mut job = backfill_job()?.with_framing(Framing::JsonLines);
+        assert!(matches!(
+            job.run(&[bad], &mut Collect::default()).await,
+            Err(KatinssIngestorError::InvalidLine(_, 3, _))
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,422 @@
+use std::any::Any;
+use std::sync::{Arc, RwLock};
+
+use arrow_array::RecordBatchOptions;
+use arrow_schema::{DataType, Schema, SchemaRef};
+use async_trait::async_trait;
+use datafusion::{
+    catalog::schema::{MemorySchemaProvider, SchemaProvider},
+    datasource::{
+        file_format::parquet::ParquetFormat,
+        listing::{ListingOptions, ListingTable, ListingTableConfig, ListingTableUrl},
+        TableProvider,
+    },
+    error::{DataFusionError, Result as DataFusionResult},
+    execution::{
+        context::{SessionContext, SessionState},
+        TaskContext,
+    },
+    logical_expr::{BinaryExpr, Expr, Operator, TableProviderFilterPushDown, TableType},
+    physical_plan::{
+        stream::RecordBatchStreamAdapter,
+        streaming::{PartitionStream, StreamingTableExec},
+        ExecutionPlan, SendableRecordBatchStream,
+    },
+    scalar::ScalarValue,
+};
+use futures::{stream, StreamExt, TryStreamExt};
+use lance::dataset::Dataset;
+
+use katniss_pb2arrow::exports::RecordBatch;
+
+use crate::Result;
+
+/// A schema of tables over what pipelines have written, so one SessionContext can query
+/// historical data, i.e. `select * from ingested.packets` next to a LiveBuffersTable.
+/// Object stores other than the local filesystem have to be registered with the session first
+pub struct IngestedCatalog {
+    schema: Arc<MemorySchemaProvider>,
+}
+
+impl IngestedCatalog {
+    /// Adds the schema `name` to the session's default catalog
+    pub fn register(ctx: &SessionContext, name: &str) -> Result<Self> {
+        let catalog_name = ctx.state().config_options().catalog.default_catalog.clone();
+        let catalog = ctx
+            .catalog(&catalog_name)
+            .ok_or_else(|| DataFusionError::Plan(format!("No catalog {catalog_name}")))?;
+
+        let schema = Arc::new(MemorySchemaProvider::new());
+        catalog.register_schema(name, schema.clone())?;
+        Ok(Self { schema })
+    }
+
+    /// A directory of parquet files, i.e. from ParquetStoreSink or PartitionedSink, with the
+    /// schema read from the files. Partitions are directories that aren't columns in the files,
+    /// they become Utf8 columns, i.e. "date" for a FileNaming of "date={date}/{start}_{end}.{ext}"
+    pub async fn register_parquet(
+        &self,
+        ctx: &SessionContext,
+        table: &str,
+        uri: &str,
+        partitions: &[&str],
+    ) -> Result<()> {
+        let url = ListingTableUrl::parse(uri)?;
+        let partitions = partitions
+            .iter()
+            .map(|p| (p.to_string(), DataType::Utf8))
+            .collect();
+        let options = ListingOptions::new(Arc::new(ParquetFormat::default()))
+            .with_file_extension(".parquet")
+            .with_table_partition_cols(partitions);
+        let schema = options.infer_schema(&ctx.state(), &url).await?;
+
+        let config = ListingTableConfig::new(url)
+            .with_listing_options(options)
+            .with_schema(schema);
+        self.register_table(table, Arc::new(ListingTable::try_new(config)?))
+    }
+
+    /// A lance dataset, i.e. from LanceIngestor, queries read its latest version. If a column
+    /// has been dropped or retyped since, queries keep reading the version before that until
+    /// the table is registered again
+    pub async fn register_lance(&self, table: &str, uri: &str) -> Result<()> {
+        let dataset = Dataset::open(uri).await?;
+        let schema = Arc::new(Schema::from(dataset.schema()));
+        let lance = LanceTable {
+            uri: uri.to_owned(),
+            current: RwLock::new((Arc::new(dataset), schema)),
+        };
+        self.register_table(table, Arc::new(lance))
+    }
+
+    fn register_table(&self, name: &str, table: Arc<dyn TableProvider>) -> Result<()> {
+        self.schema.register_table(name.to_owned(), table)?;
+        Ok(())
+    }
+}
+
+/// Reopens the dataset on every scan so appends since registering are included.
+/// Projections, filters lance can evaluate and limits are handed to lance's scanner
+struct LanceTable {
+    uri: String,
+    /// The newest version a scan has opened and its schema, which is the table's
+    current: RwLock<(Arc<Dataset>, SchemaRef)>,
+}
+
+impl LanceTable {
+    /// The latest version, unless it's missing one of the planned columns or changed its
+    /// type, then the version the query was planned against
+    async fn dataset(&self, planned: &Schema) -> Result<Arc<Dataset>> {
+        let latest = Dataset::open(&self.uri).await?;
+        let schema = Schema::from(latest.schema());
+        // lance adds columns after the existing ones, so the planned indices still line up
+        let readable = planned.fields().iter().enumerate().all(|(i, field)| {
+            schema
+                .fields()
+                .get(i)
+                .is_some_and(|f| f.name() == field.name() && f.data_type() == field.data_type())
+        });
+        let mut current = self.current.write().expect("lance table lock poisoned");
+        if readable {
+            *current = (Arc::new(latest), Arc::new(schema));
+        }
+        Ok(current.0.clone())
+    }
+}
+
+#[async_trait]
+impl TableProvider for LanceTable {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        let current = self.current.read().expect("lance table lock poisoned");
+        current.1.clone()
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::Base
+    }
+
+    /// Inexact, DataFusion still applies the filter to what lance returns
+    fn supports_filter_pushdown(
+        &self,
+        filter: &Expr,
+    ) -> DataFusionResult<TableProviderFilterPushDown> {
+        Ok(match lance_filter(filter) {
+            Some(_) => TableProviderFilterPushDown::Inexact,
+            None => TableProviderFilterPushDown::Unsupported,
+        })
+    }
+
+    async fn scan(
+        &self,
+        _state: &SessionState,
+        projection: Option<&Vec<usize>>,
+        filters: &[Expr],
+        limit: Option<usize>,
+    ) -> DataFusionResult<Arc<dyn ExecutionPlan>> {
+        let planned = self.schema();
+        let dataset = self
+            .dataset(&planned)
+            .await
+            .map_err(|err| DataFusionError::External(Box::new(err)))?;
+        let schema = match projection {
+            Some(projection) => Arc::new(planned.project(projection)?),
+            None => planned,
+        };
+        let filter = filters
+            .iter()
+            .filter_map(lance_filter)
+            .map(|f| format!("({f})"))
+            .collect::<Vec<_>>();
+        let scan = LanceScan {
+            dataset,
+            schema: schema.clone(),
+            filter: (!filter.is_empty()).then(|| filter.join(" AND ")),
+            limit,
+        };
+        Ok(Arc::new(StreamingTableExec::try_new(
+            schema,
+            vec![Arc::new(scan)],
+            None,
+            false,
+        )?))
+    }
+}
+
+/// One lance scan, read as DataFusion pulls batches
+struct LanceScan {
+    dataset: Arc<Dataset>,
+    /// The projected columns
+    schema: SchemaRef,
+    filter: Option<String>,
+    limit: Option<usize>,
+}
+
+impl LanceScan {
+    async fn batches(
+        self,
+    ) -> Result<impl futures::Stream<Item = DataFusionResult<RecordBatch>> + Send + 'static> {
+        let mut columns = self
+            .schema
+            .fields()
+            .iter()
+            .map(|f| f.name().as_str())
+            .collect::<Vec<_>>();
+        // i.e. count(*), lance needs a column to read to know how many rows there are
+        let counting = columns.is_empty();
+        let first = self
+            .dataset
+            .schema()
+            .fields
+            .first()
+            .map(|f| f.name.as_str());
+        if counting {
+            columns.extend(first);
+        }
+
+        let mut scanner = self.dataset.scan();
+        scanner.project(&columns)?;
+        if let Some(filter) = &self.filter {
+            scanner.filter(filter)?;
+        }
+        if let Some(limit) = self.limit {
+            scanner.limit(Some(limit as i64), None)?;
+        }
+
+        let schema = self.schema;
+        let batches = scanner.try_into_stream().await?.map(move |batch| {
+            let batch = batch.map_err(|err| DataFusionError::External(Box::new(err)))?;
+            let columns = if counting {
+                Vec::new()
+            } else {
+                batch.columns().to_vec()
+            };
+            // lance's field metadata can differ from the registered schema's
+            let options = RecordBatchOptions::new().with_row_count(Some(batch.num_rows()));
+            Ok(RecordBatch::try_new_with_options(
+                schema.clone(),
+                columns,
+                &options,
+            )?)
+        });
+        Ok(batches)
+    }
+}
+
+impl PartitionStream for LanceScan {
+    fn schema(&self) -> &SchemaRef {
+        &self.schema
+    }
+
+    fn execute(&self, _ctx: Arc<TaskContext>) -> SendableRecordBatchStream {
+        let scan = LanceScan {
+            dataset: self.dataset.clone(),
+            schema: self.schema.clone(),
+            filter: self.filter.clone(),
+            limit: self.limit,
+        };
+        let batches = stream::once(async move {
+            scan.batches()
+                .await
+                .map_err(|err| DataFusionError::External(Box::new(err)))
+        })
+        .try_flatten();
+        Box::pin(RecordBatchStreamAdapter::new(self.schema.clone(), batches))
+    }
+}
+
+/// The filter as the sql lance's scanner takes, None if lance can't evaluate it, i.e. it
+/// calls a function. Only plain column names are passed along
+fn lance_filter(expr: &Expr) -> Option<String> {
+    Some(match expr {
+        Expr::Column(column) => {
+            let plain = column
+                .name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_')
+                && !column.name.starts_with(|c: char| c.is_ascii_digit());
+            plain.then(|| column.name.clone())?
+        }
+        Expr::Literal(value) => lance_literal(value)?,
+        Expr::BinaryExpr(BinaryExpr { left, op, right }) => {
+            let op = match op {
+                Operator::Eq => "=",
+                Operator::NotEq => "!=",
+                Operator::Lt => "<",
+                Operator::LtEq => "<=",
+                Operator::Gt => ">",
+                Operator::GtEq => ">=",
+                Operator::And => "AND",
+                Operator::Or => "OR",
+                _ => return None,
+            };
+            format!("({}) {op} ({})", lance_filter(left)?, lance_filter(right)?)
+        }
+        Expr::Not(inner) => format!("NOT ({})", lance_filter(inner)?),
+        Expr::IsNull(inner) => format!("({}) IS NULL", lance_filter(inner)?),
+        Expr::IsNotNull(inner) => format!("({}) IS NOT NULL", lance_filter(inner)?),
+        _ => return None,
+    })
+}
+
+fn lance_literal(value: &ScalarValue) -> Option<String> {
+    Some(match value {
+        ScalarValue::Boolean(Some(v)) => v.to_string(),
+        ScalarValue::Int8(Some(v)) => v.to_string(),
+        ScalarValue::Int16(Some(v)) => v.to_string(),
+        ScalarValue::Int32(Some(v)) => v.to_string(),
+        ScalarValue::Int64(Some(v)) => v.to_string(),
+        ScalarValue::UInt8(Some(v)) => v.to_string(),
+        ScalarValue::UInt16(Some(v)) => v.to_string(),
+        ScalarValue::UInt32(Some(v)) => v.to_string(),
+        ScalarValue::UInt64(Some(v)) => v.to_string(),
+        ScalarValue::Float32(Some(v)) if v.is_finite() => v.to_string(),
+        ScalarValue::Float64(Some(v)) if v.is_finite() => v.to_string(),
+        ScalarValue::Utf8(Some(v)) | ScalarValue::LargeUtf8(Some(v)) => {
+            format!("'{}'", v.replace('\'', "''"))
+        }
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::{cast::AsArray, types::Int64Type};
+    use chrono::Utc;
+    use datafusion::prelude::{col, lit};
+    use parquet::arrow::ArrowWriter;
+
+    use katniss_test::{protos::spacecorp::Packet, test_util::ProtoBatch};
+
+    use super::*;
+    use crate::{temporal_rotator::TemporalBuffer, LanceIngestor};
+
+    async fn count(ctx: &SessionContext, sql: &str) -> anyhow::Result<i64> {
+        let batches = ctx.sql(sql).await?.collect().await?;
+        Ok(batches[0].column(0).as_primitive::<Int64Type>().value(0))
+    }
+
+    fn packets(n: u64) -> anyhow::Result<RecordBatch> {
+        let packets = (0..n)
+            .map(|i| Packet {
+                sender_uid: i,
+                ..Default::default()
+            })
+            .collect::<Vec<_>>();
+        Ok(ProtoBatch::SpaceCorp(&packets).arrow_batch()?)
+    }
+
+    #[tokio::test]
+    async fn it_queries_ingested_parquet_and_lance() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        for (date, rows) in [("2026-10-15", 3), ("2026-10-16", 4)] {
+            let partition = dir.path().join(format!("date={date}"));
+            std::fs::create_dir_all(&partition)?;
+            let batch = packets(rows)?;
+            let file = std::fs::File::create(partition.join("0_1.parquet"))?;
+            let mut writer = ArrowWriter::try_new(file, batch.schema(), None)?;
+            writer.write(&batch)?;
+            writer.close()?;
+        }
+
+        let lance_dir = tempfile::tempdir()?;
+        let lance_uri = format!(
+            "file://{}",
+            lance_dir.path().join("packets.lance").display()
+        );
+        let batch = packets(5)?;
+        let lance = LanceIngestor::new(&lance_uri, batch.schema())?;
+        let buffer = TemporalBuffer {
+            begin_at: Utc::now(),
+            end_at: Utc::now(),
+            batches: vec![batch],
+        };
+        lance.write(buffer.clone()).await?;
+
+        let ctx = SessionContext::new();
+        let ingested = IngestedCatalog::register(&ctx, "ingested")?;
+        let parquet_uri = format!("{}/", dir.path().display());
+        ingested
+            .register_parquet(&ctx, "archive", &parquet_uri, &["date"])
+            .await?;
+        ingested.register_lance("recent", &lance_uri).await?;
+
+        assert_eq!(
+            count(&ctx, "select count(*) from ingested.archive").await?,
+            7
+        );
+        let one_day = "select count(*) from ingested.archive where date = '2026-10-16'";
+        assert_eq!(count(&ctx, one_day).await?, 4);
+        let recent = "select count(*) from ingested.recent where sender_uid >= 2";
+        assert_eq!(count(&ctx, recent).await?, 3);
+        let limited = "select sender_uid from ingested.recent where sender_uid >= 2 limit 2";
+        let batches = ctx.sql(limited).await?.collect().await?;
+        assert_eq!(batches.iter().map(|b| b.num_rows()).sum::<usize>(), 2);
+        assert_eq!(batches[0].num_columns(), 1);
+
+        // appends since registering are read
+        lance.write(buffer).await?;
+        assert_eq!(
+            count(&ctx, "select count(*) from ingested.recent").await?,
+            10
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn filters_lance_can_evaluate_are_pushed_down() {
+        let filter = col("sender_uid")
+            .gt_eq(lit(2u64))
+            .and(!col("name").eq(lit("o'neil")));
+        assert_eq!(
+            lance_filter(&filter).as_deref(),
+            Some("((sender_uid) >= (2)) AND (NOT ((name) = ('o''neil')))")
+        );
+        assert_eq!(lance_filter(&col("name").like(lit("o%"))), None);
+        assert_eq!(lance_filter(&col("has space").is_null()), None);
+    }
+}
@@ -0,0 +1,266 @@
+use object_store::{path::Path, ObjectMeta, ObjectStore};
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+use katniss_pb2arrow::exports::RecordBatch;
+
+use crate::{
+    sinks::{manifest, store_from_uri, BufferEncoder, ParquetEncoder},
+    Result,
+};
+
+/// What a compaction did
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CompactionReport {
+    pub files_read: usize,
+    pub files_written: usize,
+    pub rows: usize,
+}
+
+/// Rewrites runs of small parquet files in a partition into fewer, bigger ones, since frequent
+/// rotation leaves lots of small files that query engines are slow to scan.
+/// Files are merged in name order (time order with the default FileNaming), rows keep their
+/// order and the footer metadata (i.e. the embedded descriptor) of each run's first file is kept.
+/// The merged file is written before the small ones are deleted, so readers listing the partition
+/// partway through can see rows twice but never lose any.
+pub struct Compactor {
+    store: Box<dyn ObjectStore>,
+    prefix: Path,
+    encoder: ParquetEncoder,
+    target_file_size: usize,
+}
+
+impl Compactor {
+    pub fn new(store: Box<dyn ObjectStore>, prefix: Path) -> Self {
+        Self {
+            store,
+            prefix,
+            encoder: ParquetEncoder::new(),
+            target_file_size: 128 * 1024 * 1024,
+        }
+    }
+
+    /// See StoreSink::from_uri
+    pub fn from_uri(uri: &str) -> Result<Self> {
+        let (store, prefix) = store_from_uri(uri)?;
+        Ok(Self::new(store, prefix))
+    }
+
+    /// Files this big or bigger are left alone, smaller ones are merged up to about this size
+    /// (measured before recompression). 128MB by default
+    pub fn with_target_file_size(mut self, bytes: usize) -> Self {
+        self.target_file_size = bytes.max(1);
+        self
+    }
+
+    /// Writer properties for the merged files
+    pub fn with_encoder(mut self, encoder: ParquetEncoder) -> Self {
+        self.encoder = encoder;
+        self
+    }
+
+    pub fn store(&self) -> &dyn ObjectStore {
+        self.store.as_ref()
+    }
+
+    /// Compacts the parquet files directly under the prefix, subdirectories are left alone
+    pub async fn compact(&self) -> Result<CompactionReport> {
+        let mut files: Vec<ObjectMeta> = self
+            .store
+            .list_with_delimiter(Some(&self.prefix))
+            .await?
+            .objects
+            .into_iter()
+            .filter(|o| o.location.extension() == Some("parquet"))
+            .collect();
+        files.sort_by(|a, b| a.location.cmp(&b.location));
+
+        let mut report = CompactionReport::default();
+        for run in self.runs(&files) {
+            let rows = self.merge(run).await?;
+            report.files_read += run.len();
+            report.files_written += 1;
+            report.rows += rows;
+        }
+        Ok(report)
+    }
+
+    /// Consecutive small files that add up to at most the target size, at least two to a run
+    fn runs<'a>(&self, files: &'a [ObjectMeta]) -> Vec<&'a [ObjectMeta]> {
+        let mut runs = Vec::new();
+        let (mut start, mut size) = (0, 0);
+
+        for (i, file) in files.iter().enumerate() {
+            let small = file.size < self.target_file_size;
+            if !small || size + file.size > self.target_file_size {
+                if i - start > 1 {
+                    runs.push(&files[start..i]);
+                }
+                (start, size) = if small { (i, 0) } else { (i + 1, 0) };
+            }
+            if small {
+                size += file.size;
+            }
+        }
+        if files.len() - start > 1 {
+            runs.push(&files[start..]);
+        }
+        runs
+    }
+
+    /// Writes the run as one file named after its first file, then deletes the rest and updates
+    /// the partition's manifest if it has one
+    async fn merge(&self, run: &[ObjectMeta]) -> Result<usize> {
+        let mut batches: Vec<RecordBatch> = Vec::new();
+        let mut encoder = None;
+
+        for file in run {
+            let bytes = self.store.get(&file.location).await?.bytes().await?;
+            let reader = ParquetRecordBatchReaderBuilder::try_new(bytes)?;
+            if encoder.is_none() {
+                let key_value_metadata = reader.metadata().file_metadata().key_value_metadata();
+                encoder = Some(
+                    self.encoder
+                        .clone()
+                        .with_key_value_metadata(key_value_metadata.into_iter().flatten()),
+                );
+            }
+            for batch in reader.build()? {
+                batches.push(batch?);
+            }
+        }
+
+        let (Some(encoder), Some(first)) = (encoder, batches.first()) else {
+            return Ok(0);
+        };
+        let bytes = encoder.encode(&first.schema(), &batches)?;
+
+        let (merged, rest) = run.split_first().expect("runs have at least two files");
+        let location = &merged.location;
+        self.store.put(location, bytes.into()).await?;
+        for file in rest {
+            self.store.delete(&file.location).await?;
+        }
+
+        let filenames: Vec<_> = run
+            .iter()
+            .filter_map(|f| f.location.filename().map(str::to_owned))
+            .collect();
+        manifest::merge_entries(self.store.as_ref(), &self.prefix, &filenames).await?;
+        Ok(batches.iter().map(RecordBatch::num_rows).sum())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone, Utc};
+    use object_store::memory::InMemory;
+
+    use katniss_pb2arrow::ArrowBatchProps;
+    use katniss_test::{descriptor_pool, protos::spacecorp::Packet, test_util::ProtoBatch};
+
+    use super::*;
+    use crate::{
+        sinks::{manifest::Manifests, MANIFEST_FILENAME},
+        ParquetMessageReader,
+    };
+
+    #[tokio::test]
+    async fn small_files_are_merged_in_order() -> anyhow::Result<()> {
+        let props = ArrowBatchProps::try_new(
+            descriptor_pool()?,
+            "eto.pb2arrow.tests.spacecorp.Packet".to_owned(),
+        )?;
+        let encoder = ParquetEncoder::new().with_descriptor(&props.descriptor);
+        let store = InMemory::new();
+        let mut manifests = Manifests::default();
+
+        for sender_uid in 0..4 {
+            let packets = vec![
+                Packet {
+                    sender_uid,
+                    ..Default::default()
+                };
+                3
+            ];
+            let batch = ProtoBatch::SpaceCorp(&packets).arrow_batch()?;
+            let bytes = encoder.encode(&batch.schema(), &[batch])?;
+            let location = Path::from(format!("packets/{sender_uid}000_{sender_uid}999.parquet"));
+            store.put(&location, bytes.into()).await?;
+
+            let begin_at = Utc.timestamp_millis_opt(sender_uid as i64 * 1000).unwrap();
+            let end_at = begin_at + Duration::milliseconds(999);
+            manifests
+                .record(&store, &location, packets.len(), begin_at, end_at)
+                .await?;
+        }
+        manifests.finish(&store).await?;
+
+        let compactor = Compactor::new(Box::new(store), Path::from("packets"));
+        let report = compactor.compact().await?;
+        assert_eq!(
+            report,
+            CompactionReport {
+                files_read: 4,
+                files_written: 1,
+                rows: 12
+            }
+        );
+
+        let listed = compactor
+            .store()
+            .list_with_delimiter(Some(&Path::from("packets")))
+            .await?;
+        let location = Path::from("packets/0000_0999.parquet");
+        let parquet_files: Vec<_> = listed
+            .objects
+            .iter()
+            .filter(|o| o.location.extension() == Some("parquet"))
+            .collect();
+        assert_eq!(parquet_files.len(), 1);
+        assert_eq!(parquet_files[0].location, location);
+
+        let manifest = Path::from(format!("packets/{MANIFEST_FILENAME}"));
+        let manifest = compactor.store().get(&manifest).await?.bytes().await?;
+        let lines: Vec<_> = std::str::from_utf8(&manifest)?.lines().collect();
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].contains(r#""path":"0000_0999.parquet""#));
+        assert!(lines[0].contains(r#""rows":12"#));
+        assert!(lines[0].contains(r#""end_millis":3999"#));
+
+        let bytes = compactor.store().get(&location).await?.bytes().await?;
+        let reader = ParquetMessageReader::try_new(bytes)?;
+        assert_eq!(reader.descriptor(), &props.descriptor);
+
+        let senders = reader
+            .map(|msg| Ok(msg?.transcode_to::<Packet>()?.sender_uid))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        assert_eq!(senders, [0, 0, 0, 1, 1, 1, 2, 2, 2, 3, 3, 3]);
+        Ok(())
+    }
+
+    #[test]
+    fn runs_skip_files_that_are_big_enough() {
+        let file = |name: &str, size| ObjectMeta {
+            location: Path::from(name),
+            last_modified: Utc::now(),
+            size,
+        };
+        let files = [
+            file("a", 10),
+            file("b", 10),
+            file("c", 100),
+            file("d", 10),
+            file("e", 60),
+            file("f", 50),
+            file("g", 40),
+        ];
+        let compactor =
+            Compactor::new(Box::new(InMemory::new()), Path::default()).with_target_file_size(100);
+        let runs: Vec<Vec<_>> = compactor
+            .runs(&files)
+            .iter()
+            .map(|run| run.iter().map(|f| f.location.to_string()).collect())
+            .collect();
+        assert_eq!(runs, vec![vec!["a", "b"], vec!["d", "e"], vec!["f", "g"]]);
+    }
+}
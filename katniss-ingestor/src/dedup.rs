@@ -0,0 +1,321 @@
+use std::collections::VecDeque;
+use std::f64::consts::LN_2;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use katniss_pb2arrow::exports::{prost_reflect::Value, DynamicMessage};
+
+use crate::{errors::KatinssIngestorError, field_path::FieldPath, Result};
+
+const INDEX_MAGIC: &[u8; 4] = b"KDDP";
+const INDEX_VERSION: u8 = 1;
+
+/// Drops messages whose unique id has already been seen in the last few windows so that
+/// redelivered messages (i.e. after a reconnect) don't turn into duplicate rows.
+/// Ids are tracked in a bloom filter per window so a (tunably rare) false positive
+/// will drop a message that was never seen before.
+pub struct Deduplicator {
+    id_path: FieldPath,
+    expected_per_window: usize,
+    false_positive_rate: f64,
+    max_windows: usize,
+    /// back is the current window
+    windows: VecDeque<BloomFilter>,
+    persist_to: Option<PathBuf>,
+    pub duplicates_dropped: u64,
+}
+
+impl Deduplicator {
+    /// id_path is a dot separated path to a string, bytes, integer, or enum field
+    /// remembers ids for max_windows rotations (including the current one)
+    pub fn new(id_path: &str, max_windows: usize) -> Self {
+        let mut dedup = Self {
+            id_path: FieldPath::parse(id_path),
+            expected_per_window: 100_000,
+            false_positive_rate: 0.0001,
+            max_windows: max_windows.max(1),
+            windows: VecDeque::new(),
+            persist_to: None,
+            duplicates_dropped: 0,
+        };
+        dedup.windows.push_back(dedup.new_filter());
+        dedup
+    }
+
+    /// Sizes each window's filter, defaults are 100k messages at a 0.01% false positive rate.
+    /// Windows loaded by persisted_at are kept, the new size starts with the next rotation
+    pub fn with_capacity(mut self, expected_per_window: usize, false_positive_rate: f64) -> Self {
+        self.expected_per_window = expected_per_window;
+        self.false_positive_rate = false_positive_rate;
+        if self.persist_to.is_none() {
+            self.windows = VecDeque::from([self.new_filter()]);
+        }
+        self
+    }
+
+    /// Save the index to path once each rotation's buffers are written (see rotate),
+    /// and start from it if it already exists
+    pub fn persisted_at<P: AsRef<Path>>(mut self, path: P) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        if path.is_file() {
+            self.windows = read_index(&path)?;
+            while self.windows.len() > self.max_windows {
+                self.windows.pop_front();
+            }
+            if self.windows.is_empty() {
+                self.windows.push_back(self.new_filter());
+            }
+        }
+        self.persist_to = Some(path);
+        Ok(self)
+    }
+
+    /// Returns true if the message's id has been seen before, otherwise remembers it
+    /// Messages without an id are never considered duplicates
+    pub fn check_and_insert(&mut self, msg: &DynamicMessage) -> bool {
+        let Some(hash) = self.id_path.with_value(msg, hash_value) else {
+            return false;
+        };
+
+        if self.windows.iter().any(|w| w.contains(hash)) {
+            self.duplicates_dropped += 1;
+            return true;
+        }
+
+        self.windows
+            .back_mut()
+            .expect("always at least one window")
+            .insert(hash);
+        false
+    }
+
+    /// Start a new window, forgetting the oldest one if there are more than max_windows.
+    /// With persisted_at the index as of now is returned to be persisted once the sink has
+    /// written the rotated buffers, persisting it before would drop their redeliveries if the
+    /// write failed
+    #[must_use]
+    pub fn rotate(&mut self) -> Option<DedupSnapshot> {
+        self.windows.push_back(self.new_filter());
+        while self.windows.len() > self.max_windows {
+            self.windows.pop_front();
+        }
+
+        self.persist_to.as_ref().map(|path| DedupSnapshot {
+            path: path.clone(),
+            windows: self.windows.clone(),
+        })
+    }
+
+    fn new_filter(&self) -> BloomFilter {
+        BloomFilter::new(self.expected_per_window, self.false_positive_rate)
+    }
+}
+
+/// A Deduplicator's index as of a rotation, see Deduplicator::rotate
+#[derive(Debug, Clone)]
+pub struct DedupSnapshot {
+    path: PathBuf,
+    windows: VecDeque<BloomFilter>,
+}
+
+impl DedupSnapshot {
+    pub fn persist(&self) -> Result<()> {
+        write_index(&self.path, &self.windows)
+    }
+}
+
+fn hash_value(value: &Value) -> Option<u64> {
+    let hash = match value {
+        Value::String(s) => fnv1a(s.as_bytes()),
+        Value::Bytes(b) => fnv1a(b),
+        Value::I32(v) | Value::EnumNumber(v) => fnv1a(&v.to_le_bytes()),
+        Value::I64(v) => fnv1a(&v.to_le_bytes()),
+        Value::U32(v) => fnv1a(&v.to_le_bytes()),
+        Value::U64(v) => fnv1a(&v.to_le_bytes()),
+        _ => return None,
+    };
+    Some(hash)
+}
+
+/// Stable across processes and rust versions (unlike std's DefaultHasher) since it's persisted
+fn fnv1a(bytes: &[u8]) -> u64 {
+    let hash = bytes.iter().fold(0xcbf29ce484222325_u64, |hash, b| {
+        (hash ^ *b as u64).wrapping_mul(0x100000001b3)
+    });
+
+    // splitmix64 finalizer, fnv's high bits are a bit weak for double hashing
+    let hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    let hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d049bb133111eb);
+    hash ^ (hash >> 31)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct BloomFilter {
+    words: Vec<u64>,
+    num_hashes: u32,
+}
+
+impl BloomFilter {
+    fn new(expected_items: usize, false_positive_rate: f64) -> Self {
+        let n = expected_items.max(1) as f64;
+        let num_bits = (-n * false_positive_rate.ln() / (LN_2 * LN_2))
+            .ceil()
+            .max(64.0) as usize;
+        let num_hashes = ((num_bits as f64 / n) * LN_2).round().max(1.0) as u32;
+
+        Self {
+            words: vec![0; (num_bits + 63) / 64],
+            num_hashes,
+        }
+    }
+
+    fn num_bits(&self) -> u64 {
+        self.words.len() as u64 * 64
+    }
+
+    fn bit_indices(&self, hash: u64) -> impl Iterator<Item = u64> {
+        let (h1, h2) = (hash & 0xffff_ffff, hash >> 32);
+        let num_bits = self.num_bits();
+        (0..self.num_hashes as u64).map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) % num_bits)
+    }
+
+    fn insert(&mut self, hash: u64) {
+        for bit in self.bit_indices(hash).collect::<Vec<_>>() {
+            self.words[(bit / 64) as usize] |= 1 << (bit % 64);
+        }
+    }
+
+    fn contains(&self, hash: u64) -> bool {
+        self.bit_indices(hash)
+            .all(|bit| self.words[(bit / 64) as usize] & (1 << (bit % 64)) != 0)
+    }
+}
+
+/// Written to a temp file then renamed so a crash never leaves a half written index
+fn write_index(path: &Path, windows: &VecDeque<BloomFilter>) -> Result<()> {
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(INDEX_MAGIC);
+    bytes.push(INDEX_VERSION);
+    bytes.extend_from_slice(&(windows.len() as u32).to_le_bytes());
+    for window in windows {
+        bytes.extend_from_slice(&window.num_hashes.to_le_bytes());
+        bytes.extend_from_slice(&(window.words.len() as u64).to_le_bytes());
+        for word in &window.words {
+            bytes.extend_from_slice(&word.to_le_bytes());
+        }
+    }
+
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, bytes)?;
+    fs::rename(tmp, path)?;
+    Ok(())
+}
+
+fn read_index(path: &Path) -> Result<VecDeque<BloomFilter>> {
+    let bytes = fs::read(path)?;
+    let corrupt = || KatinssIngestorError::CorruptDedupIndex(path.to_path_buf());
+
+    let mut cursor = bytes
+        .strip_prefix(INDEX_MAGIC.as_slice())
+        .and_then(|rest| rest.strip_prefix(&[INDEX_VERSION]))
+        .ok_or_else(corrupt)?;
+
+    let mut take = |n: usize| -> Result<&[u8]> {
+        if cursor.len() < n {
+            return Err(corrupt());
+        }
+        let (head, tail) = cursor.split_at(n);
+        cursor = tail;
+        Ok(head)
+    };
+
+    let num_windows = u32::from_le_bytes(take(4)?.try_into().unwrap());
+    let mut windows = VecDeque::with_capacity(num_windows as usize);
+    for _ in 0..num_windows {
+        let num_hashes = u32::from_le_bytes(take(4)?.try_into().unwrap());
+        let num_words = u64::from_le_bytes(take(8)?.try_into().unwrap()) as usize;
+        let words = take(num_words.checked_mul(8).ok_or_else(corrupt)?)?
+            .chunks_exact(8)
+            .map(|w| u64::from_le_bytes(w.try_into().unwrap()))
+            .collect::<Vec<_>>();
+
+        if words.is_empty() || num_hashes == 0 {
+            return Err(corrupt());
+        }
+        windows.push_back(BloomFilter { words, num_hashes });
+    }
+
+    Ok(windows)
+}
+
+#[cfg(test)]
+mod tests {
+    use katniss_test::{protos::spacecorp::Packet, test_util::to_dynamic};
+
+    use super::*;
+
+    const PACKET: &str = "eto.pb2arrow.tests.spacecorp.Packet";
+
+    fn packet(uid: u64) -> anyhow::Result<DynamicMessage> {
+        to_dynamic(
+            &Packet {
+                sender_uid: uid,
+                ..Default::default()
+            },
+            PACKET,
+        )
+    }
+
+    #[test]
+    fn it_drops_ids_seen_in_recent_windows() -> anyhow::Result<()> {
+        let mut dedup = Deduplicator::new("sender_uid", 2).with_capacity(1_000, 0.0001);
+
+        assert!(!dedup.check_and_insert(&packet(1)?));
+        assert!(dedup.check_and_insert(&packet(1)?));
+
+        assert!(dedup.rotate().is_none());
+        assert!(dedup.check_and_insert(&packet(1)?));
+        assert!(!dedup.check_and_insert(&packet(2)?));
+
+        // window with 1 in it falls off
+        assert!(dedup.rotate().is_none());
+        assert!(!dedup.check_and_insert(&packet(1)?));
+        assert!(dedup.check_and_insert(&packet(2)?));
+
+        // unset ids are never duplicates
+        assert!(!dedup.check_and_insert(&to_dynamic(&Packet::default(), PACKET)?));
+        assert!(!dedup.check_and_insert(&to_dynamic(&Packet::default(), PACKET)?));
+
+        assert_eq!(dedup.duplicates_dropped, 3);
+        Ok(())
+    }
+
+    #[test]
+    fn it_persists_across_restarts() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("dedup.idx");
+
+        let mut dedup = Deduplicator::new("sender_uid", 3)
+            .with_capacity(1_000, 0.0001)
+            .persisted_at(&path)?;
+        dedup.check_and_insert(&packet(42)?);
+        let snapshot = dedup.rotate().expect("persisted");
+        // nothing is persisted until the rotated buffers are written
+        assert!(!path.exists());
+        snapshot.persist()?;
+
+        // the capacity doesn't replace the loaded windows, whichever comes first
+        let mut restarted = Deduplicator::new("sender_uid", 3)
+            .persisted_at(&path)?
+            .with_capacity(1_000, 0.0001);
+        assert!(restarted.check_and_insert(&packet(42)?));
+        assert!(!restarted.check_and_insert(&packet(43)?));
+
+        fs::write(&path, b"garbage")?;
+        assert!(matches!(
+            Deduplicator::new("sender_uid", 3).persisted_at(&path),
+            Err(KatinssIngestorError::CorruptDedupIndex(_))
+        ));
+        Ok(())
+    }
+}
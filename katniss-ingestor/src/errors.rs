@@ -4,6 +4,7 @@ use std::{
     time::SystemTimeError,
 };
 
+use arrow_schema::ArrowError;
 use chrono::OutOfRangeError;
 use katniss_pb2arrow::KatnissArrowError;
 use thiserror::Error;
@@ -12,30 +13,153 @@ use crate::temporal_rotator::TemporalBuffer;
 
 #[derive(Error, Debug)]
 pub enum KatinssIngestorError {
+    #[error("Arrow Error: {0}")]
+    ArrowError(#[from] ArrowError),
+
+    #[cfg(feature = "avro")]
+    #[error("Avro Error: {0}")]
+    AvroError(#[from] apache_avro::Error),
+
     #[error("Pipeline Clog: {0}")]
     BufferRecv(#[from] RecvError),
 
+    #[error("Corrupt embedded descriptor: {0}")]
+    CorruptDescriptorMetadata(String),
+
+    #[error("Corrupt dedup index: {0:?}")]
+    CorruptDedupIndex(std::path::PathBuf),
+
+    #[cfg(feature = "datafusion")]
+    #[error("DataFusion Error: {0}")]
+    DataFusionError(#[from] datafusion::error::DataFusionError),
+
+    #[error("{0} went from version {1} to {2} while rows were expired, nothing was deleted")]
+    DatasetChanged(String, u64, u64),
+
+    #[error("Descriptor Error: {0}")]
+    DescriptorError(#[from] katniss_pb2arrow::exports::prost_reflect::DescriptorError),
+
+    #[cfg(feature = "flight")]
+    #[error("Flight Error: {0}")]
+    FlightError(#[from] arrow_flight::error::FlightError),
+
+    #[error("Incompatible schema change: {0}")]
+    IncompatibleSchema(String),
+
+    #[error("Invalid config: {0}")]
+    InvalidConfig(String),
+
+    #[error("Can't decode line {1} of {0:?}: {2}")]
+    InvalidLine(std::path::PathBuf, usize, String),
+
+    #[error("{0:?} can't be a partition directory")]
+    InvalidPartitionValue(String),
+
+    #[error("Invalid predicate: {0}")]
+    InvalidPredicate(String),
+
+    #[error("Invalid query, expected select <columns> from buffer [where ...]: {0}")]
+    InvalidQuery(String),
+
+    #[error("Invalid storage uri: {0}")]
+    InvalidStorageUri(String),
+
+    #[error("Vector column {0} must be a repeated float field")]
+    InvalidVectorColumn(String),
+
     #[error("Io Errror")]
     IoError(#[from] std::io::Error),
 
+    #[error("Pipeline task died: {0}")]
+    JoinError(#[from] tokio::task::JoinError),
+
+    #[cfg(feature = "kafka")]
+    #[error("Kafka Error: {0}")]
+    KafkaError(#[from] rdkafka::error::KafkaError),
+
     #[error("Lance Error: {0}")]
     LanceError(#[from] lance::Error),
 
+    #[cfg(feature = "mcap")]
+    #[error("MCAP Error: {0}")]
+    McapError(#[from] mcap::McapError),
+
+    #[cfg(feature = "mcap")]
+    #[error("{0} can't be an MCAP log time, they're nanoseconds from 1970 until 2262")]
+    McapLogTime(chrono::DateTime<chrono::Utc>),
+
+    #[error("File has no embedded message descriptor")]
+    NoEmbeddedDescriptor,
+
     #[error("Something: {0}")]
     NegativeDurationError(#[from] OutOfRangeError),
 
+    #[error("Schema must be flat but has nested columns: {0:?}")]
+    NonFlatSchema(Vec<String>),
+
     #[error("Object Store Error: {0}")]
     ObjectStoreError(#[from] object_store::Error),
 
+    #[error("Parquet Error: {0}")]
+    ParquetError(#[from] parquet::errors::ParquetError),
+
     #[error("Pipeline Channel Closed")]
     PipelineClosed,
 
+    #[cfg(feature = "postgres")]
+    #[error("Postgres Error: {0}")]
+    PostgresError(#[from] tokio_postgres::Error),
+
     #[error("Protobuf Conversion Error: {0}")]
     Pb2ArrowArror(#[from] KatnissArrowError),
 
+    #[error("Protobuf Decode Error: {0}")]
+    ProtoDecodeError(#[from] prost::DecodeError),
+
+    #[error("{0} can't change its columns while running")]
+    SchemaChangeUnsupported(String),
+
+    #[error("Partition {0} already has a _SUCCESS marker, late files aren't written into it")]
+    SealedPartition(String),
+
     #[error("Temporal Pipeline Clog: {0}")]
     TemporalBufferSend(#[from] SendError<TemporalBuffer>),
 
+    #[cfg(feature = "flight")]
+    #[error("Grpc Transport Error: {0}")]
+    TonicTransportError(#[from] tonic::transport::Error),
+
+    #[error("Timestamp {0} microseconds from the epoch is out of range")]
+    TimestampOutOfRange(i64),
+
     #[error("Timelord Error: {0}")]
     TimeyWimeyStuff(#[from] SystemTimeError),
+
+    #[error("File ends partway through a message: {0:?}")]
+    TruncatedFile(std::path::PathBuf),
+
+    #[error("Unknown parquet encoding {0}")]
+    UnknownEncoding(String),
+
+    #[error("No field named {0}")]
+    UnknownField(String),
+
+    #[error("No message named {0} in descriptor pool")]
+    UnknownMessage(String),
+
+    #[error("No oneof named {0}")]
+    UnknownOneof(String),
+
+    #[error("{0} columns can't be written to {1}")]
+    UnsupportedColumnType(String, &'static str),
+
+    #[error("Can't sort by column {0}")]
+    UnsortableColumn(String),
+
+    #[error("Vectors in {0} have {1} values but a row has {2}")]
+    VectorDimension(String, usize, usize),
+
+    #[cfg(feature = "websocket")]
+    #[error("WebSocket Error: {0}")]
+    WebSocketError(#[from] tokio_tungstenite::tungstenite::Error),
 }
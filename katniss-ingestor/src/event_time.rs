@@ -0,0 +1,469 @@
+use std::collections::{btree_map::Entry, BTreeMap};
+use std::time::Duration;
+
+use arrow_schema::TimeUnit;
+use chrono::{DateTime, TimeZone, Utc};
+
+use katniss_pb2arrow::{
+    exports::{prost_reflect::Value, DynamicMessage, RecordBatch},
+    ArrowBatchProps, KatnissArrowError,
+};
+
+use crate::{
+    arrow::ProtobufBatchIngestor,
+    field_path::FieldPath,
+    temporal_rotator::{aligned_window_start, TemporalBuffer},
+    Result,
+};
+
+/// What to do with messages whose window has already been closed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum LatenessPolicy {
+    /// Count and drop them
+    #[default]
+    Drop,
+    /// Put them in the oldest window that's still open
+    OldestOpenWindow,
+}
+
+/// Describes where a message's event time lives and how long to wait for stragglers
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EventTime {
+    field_path: FieldPath,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default = "EventTime::default_unit", with = "snake_case_unit")
+    )]
+    unit: TimeUnit,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, with = "crate::pipeline_config::secs")
+    )]
+    allowed_lateness: Duration,
+    #[cfg_attr(feature = "serde", serde(default))]
+    lateness_policy: LatenessPolicy,
+}
+
+impl EventTime {
+    /// Dot separated path to the timestamp field i.e. "header.stamp"
+    /// The field can be a Timestamp-like message (seconds + nanos) or an integer (millis by default)
+    pub fn new(field_path: &str) -> Self {
+        Self {
+            field_path: FieldPath::parse(field_path),
+            unit: Self::default_unit(),
+            allowed_lateness: Duration::ZERO,
+            lateness_policy: LatenessPolicy::default(),
+        }
+    }
+
+    fn default_unit() -> TimeUnit {
+        TimeUnit::Millisecond
+    }
+
+    /// Unit of integer timestamp fields
+    pub fn with_unit(mut self, unit: TimeUnit) -> Self {
+        self.unit = unit;
+        self
+    }
+
+    /// How far behind the newest event a window's end has to fall before it's closed
+    pub fn with_allowed_lateness(mut self, allowed_lateness: Duration) -> Self {
+        self.allowed_lateness = allowed_lateness;
+        self
+    }
+
+    pub fn with_lateness_policy(mut self, lateness_policy: LatenessPolicy) -> Self {
+        self.lateness_policy = lateness_policy;
+        self
+    }
+
+    /// The message's event time, None if the field is missing, unset, or not a timestamp
+    pub fn extract(&self, msg: &DynamicMessage) -> Option<DateTime<Utc>> {
+        self.field_path.with_value(msg, |v| self.to_datetime(v))
+    }
+
+    fn to_datetime(&self, value: &Value) -> Option<DateTime<Utc>> {
+        let int = match value {
+            Value::Message(msg) => {
+                let seconds = msg.get_field_by_name("seconds")?.as_i64()?;
+                let nanos = msg
+                    .get_field_by_name("nanos")
+                    .and_then(|n| n.as_i32())
+                    .unwrap_or(0);
+                return Utc.timestamp_opt(seconds, nanos.try_into().ok()?).single();
+            }
+            Value::I64(v) => *v,
+            Value::I32(v) => *v as i64,
+            Value::U64(v) => (*v).try_into().ok()?,
+            Value::U32(v) => *v as i64,
+            _ => return None,
+        };
+
+        match self.unit {
+            TimeUnit::Second => Utc.timestamp_opt(int, 0).single(),
+            TimeUnit::Millisecond => Utc.timestamp_millis_opt(int).single(),
+            TimeUnit::Microsecond => Some(Utc.timestamp_nanos(int.checked_mul(1_000)?)),
+            TimeUnit::Nanosecond => Some(Utc.timestamp_nanos(int)),
+        }
+    }
+}
+
+struct OpenWindow {
+    converter: ProtobufBatchIngestor,
+    buffer: TemporalBuffer,
+}
+
+/// Like TemporalRotator but assigns messages to epoch aligned windows by their event time
+/// instead of arrival time, so replayed or delayed data lands where it belongs.
+/// Several windows can be open at once, a window is closed once the watermark
+/// (newest event time - allowed lateness) passes its end.
+pub struct EventTimeRotator {
+    props: ArrowBatchProps,
+    /// Empty converter each window's is cloned from, made with the first window
+    template: Option<ProtobufBatchIngestor>,
+    event_time: EventTime,
+    period: Duration,
+    open: BTreeMap<DateTime<Utc>, OpenWindow>,
+    source_column: Option<String>,
+    newest_event: Option<DateTime<Utc>>,
+    /// Start of the earliest window that can still accept messages
+    closed_until: Option<DateTime<Utc>>,
+    /// Start of the window the last message went into, None if it was dropped
+    last_window: Option<DateTime<Utc>>,
+    warnings: Vec<KatnissArrowError>,
+    pub dropped_late: u64,
+    pub dropped_missing_time: u64,
+}
+
+impl EventTimeRotator {
+    pub fn new(props: &ArrowBatchProps, event_time: EventTime, period: Duration) -> Self {
+        Self {
+            props: props.clone(),
+            template: None,
+            event_time,
+            period,
+            open: BTreeMap::new(),
+            source_column: None,
+            newest_event: None,
+            closed_until: None,
+            last_window: None,
+            warnings: Vec::new(),
+            dropped_late: 0,
+            dropped_missing_time: 0,
+        }
+    }
+
+    /// Adds the message to its window and returns any windows the watermark has moved past
+    /// Blocking: see TemporalRotator::ingest_potentially_blocking
+    pub fn ingest_potentially_blocking(
+        &mut self,
+        msg: DynamicMessage,
+    ) -> Result<Vec<TemporalBuffer>> {
+        self.ingest_sourced_potentially_blocking(msg, None)
+    }
+
+    /// Tag each row with the source it came from, see FanIn
+    pub fn with_source_column(mut self, name: &str) -> Self {
+        self.source_column = Some(name.to_owned());
+        self.template = None;
+        self
+    }
+
+    /// Like ingest_potentially_blocking, source is ignored unless there's a source column
+    pub fn ingest_sourced_potentially_blocking(
+        &mut self,
+        msg: DynamicMessage,
+        source: Option<&str>,
+    ) -> Result<Vec<TemporalBuffer>> {
+        self.last_window = None;
+        let Some(event_at) = self.event_time.extract(&msg) else {
+            self.dropped_missing_time += 1;
+            return Ok(Vec::new());
+        };
+
+        let on_time = aligned_window_start(event_at, self.period)?;
+        // events too far from the epoch to window are handled like late ones
+        let late = match (on_time, self.closed_until) {
+            (Some(start), Some(closed_until)) => start < closed_until,
+            (start, _) => start.is_none(),
+        };
+        let window_start = if late {
+            match self.event_time.lateness_policy {
+                LatenessPolicy::Drop => None,
+                LatenessPolicy::OldestOpenWindow => {
+                    self.open.keys().next().copied().or(self.closed_until)
+                }
+            }
+        } else {
+            on_time
+        };
+        let Some(window_start) = window_start else {
+            self.dropped_late += 1;
+            return Ok(Vec::new());
+        };
+
+        let window = match self.open.entry(window_start) {
+            Entry::Occupied(o) => o.into_mut(),
+            Entry::Vacant(v) => {
+                if self.template.is_none() {
+                    let mut template = ProtobufBatchIngestor::try_new(&self.props)?;
+                    if let Some(column) = &self.source_column {
+                        template = template.with_source_column(&self.props, column);
+                    }
+                    self.template = Some(template);
+                }
+                let template = self.template.as_ref().expect("made above");
+                v.insert(OpenWindow {
+                    converter: template.try_clone_empty()?,
+                    buffer: TemporalBuffer::new(window_start, self.period)?,
+                })
+            }
+        };
+
+        if let Some(batch) = window.converter.ingest_sourced(msg, source)? {
+            window.buffer.batches.push(batch);
+            self.warnings.extend(window.converter.take_warnings());
+        }
+        self.last_window = Some(window_start);
+
+        if on_time.is_some() {
+            self.newest_event = self.newest_event.max(Some(event_at));
+        }
+        let Some(newest_event) = self.newest_event else {
+            return Ok(Vec::new());
+        };
+        let lateness = chrono::Duration::from_std(self.event_time.allowed_lateness)?;
+        let watermark = newest_event
+            .checked_sub_signed(lateness)
+            .unwrap_or(DateTime::<Utc>::MIN_UTC);
+        self.close_windows_through(watermark)
+    }
+
+    /// Close every open window regardless of the watermark, i.e. at the end of a backfill
+    pub fn flush_all(&mut self) -> Result<Vec<TemporalBuffer>> {
+        self.close_windows_through(DateTime::<Utc>::MAX_UTC)
+    }
+
+    /// What lenient conversion nulled out in the batches finished since the last call
+    pub fn take_warnings(&mut self) -> Vec<KatnissArrowError> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    /// Batches held by the windows that are still open, oldest first
+    pub fn open_batches(&self) -> Vec<RecordBatch> {
+        self.open
+            .values()
+            .flat_map(|w| w.buffer.batches.iter().cloned())
+            .collect()
+    }
+
+    /// begin_at of the buffer the last ingested message went into, None if it was dropped
+    pub fn last_window(&self) -> Option<DateTime<Utc>> {
+        self.last_window
+    }
+
+    /// Rows in the open windows, including ones not yet encoded to arrow
+    pub fn buffered_rows(&self) -> usize {
+        self.open
+            .values()
+            .map(|w| w.buffer.num_rows() + w.converter.len())
+            .sum()
+    }
+
+    fn close_windows_through(&mut self, watermark: DateTime<Utc>) -> Result<Vec<TemporalBuffer>> {
+        let mut closed = Vec::new();
+        while let Some(entry) = self.open.first_entry() {
+            if entry.get().buffer.end_at > watermark {
+                break;
+            }
+
+            let OpenWindow {
+                mut converter,
+                mut buffer,
+            } = entry.remove();
+            buffer.batches.push(converter.finish()?);
+            self.warnings.extend(converter.take_warnings());
+            self.closed_until = Some(buffer.end_at);
+            closed.push(buffer);
+        }
+        Ok(closed)
+    }
+}
+
+/// TimeUnit as "second", "millisecond" etc like the rest of the config's enums
+#[cfg(feature = "serde")]
+mod snake_case_unit {
+    use arrow_schema::TimeUnit;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        unit: &TimeUnit,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_str(match unit {
+            TimeUnit::Second => "second",
+            TimeUnit::Millisecond => "millisecond",
+            TimeUnit::Microsecond => "microsecond",
+            TimeUnit::Nanosecond => "nanosecond",
+        })
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<TimeUnit, D::Error> {
+        match String::deserialize(deserializer)?.as_str() {
+            "second" => Ok(TimeUnit::Second),
+            "millisecond" => Ok(TimeUnit::Millisecond),
+            "microsecond" => Ok(TimeUnit::Microsecond),
+            "nanosecond" => Ok(TimeUnit::Nanosecond),
+            unit => Err(D::Error::unknown_variant(
+                unit,
+                &["second", "millisecond", "microsecond", "nanosecond"],
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use katniss_test::{
+        descriptor_pool,
+        protos::spacecorp::{Packet, Timestamp},
+        test_util::to_dynamic,
+    };
+
+    use super::*;
+
+    const PACKET: &str = "eto.pb2arrow.tests.spacecorp.Packet";
+
+    fn packet_at(seconds: i64) -> anyhow::Result<DynamicMessage> {
+        to_dynamic(
+            &Packet {
+                timestamp: Some(Timestamp { seconds, nanos: 0 }),
+                ..Default::default()
+            },
+            PACKET,
+        )
+    }
+
+    fn rotator(policy: LatenessPolicy) -> anyhow::Result<EventTimeRotator> {
+        let props = ArrowBatchProps::try_new(descriptor_pool()?, PACKET.to_owned())?;
+        let event_time = EventTime::new("timestamp")
+            .with_allowed_lateness(Duration::from_secs(5))
+            .with_lateness_policy(policy);
+        Ok(EventTimeRotator::new(
+            &props,
+            event_time,
+            Duration::from_secs(10),
+        ))
+    }
+
+    #[test]
+    fn it_extracts_event_times() -> anyhow::Result<()> {
+        let event_time = EventTime::new("timestamp");
+        assert_eq!(
+            event_time.extract(&packet_at(1_000)?),
+            Utc.timestamp_opt(1_000, 0).single()
+        );
+        assert_eq!(
+            event_time.extract(&to_dynamic(&Packet::default(), PACKET)?),
+            None
+        );
+
+        let uid_as_seconds = EventTime::new("sender_uid").with_unit(TimeUnit::Second);
+        let msg = to_dynamic(
+            &Packet {
+                sender_uid: 60,
+                ..Default::default()
+            },
+            PACKET,
+        )?;
+        assert_eq!(
+            uid_as_seconds.extract(&msg),
+            Utc.timestamp_opt(60, 0).single()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn it_windows_by_event_time() -> anyhow::Result<()> {
+        let mut rotator = rotator(LatenessPolicy::Drop)?;
+
+        assert!(rotator
+            .ingest_potentially_blocking(packet_at(101)?)?
+            .is_empty());
+        // out of order but inside the allowed lateness
+        assert!(rotator
+            .ingest_potentially_blocking(packet_at(112)?)?
+            .is_empty());
+        assert!(rotator
+            .ingest_potentially_blocking(packet_at(108)?)?
+            .is_empty());
+
+        // watermark moves to 110, closing [100, 110)
+        let closed = rotator.ingest_potentially_blocking(packet_at(115)?)?;
+        assert_eq!(closed.len(), 1);
+        assert_eq!(closed[0].begin_at, Utc.timestamp_opt(100, 0).unwrap());
+        assert_eq!(closed[0].end_at, Utc.timestamp_opt(110, 0).unwrap());
+        assert_eq!(closed[0].num_rows(), 2);
+
+        // too late, its window is gone
+        assert!(rotator
+            .ingest_potentially_blocking(packet_at(109)?)?
+            .is_empty());
+        assert_eq!(rotator.dropped_late, 1);
+
+        let rest = rotator.flush_all()?;
+        assert_eq!(rest.len(), 1);
+        assert_eq!(rest[0].num_rows(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn events_too_far_out_to_window_are_late() -> anyhow::Result<()> {
+        // past 2262 the event's nanoseconds don't fit in an i64
+        let year_3000 = 32_503_680_000;
+
+        let mut dropping = rotator(LatenessPolicy::Drop)?;
+        assert!(dropping
+            .ingest_potentially_blocking(packet_at(year_3000)?)?
+            .is_empty());
+        assert_eq!(dropping.dropped_late, 1);
+        assert!(dropping.flush_all()?.is_empty());
+
+        let mut oldest = rotator(LatenessPolicy::OldestOpenWindow)?;
+        oldest.ingest_potentially_blocking(packet_at(101)?)?;
+        // doesn't move the watermark, so [100, 110) stays open
+        assert!(oldest
+            .ingest_potentially_blocking(packet_at(year_3000)?)?
+            .is_empty());
+        let rest = oldest.flush_all()?;
+        assert_eq!(rest.len(), 1);
+        assert_eq!(rest[0].num_rows(), 2);
+        assert_eq!(oldest.dropped_late, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn late_messages_can_go_to_the_oldest_open_window() -> anyhow::Result<()> {
+        let mut rotator = rotator(LatenessPolicy::OldestOpenWindow)?;
+
+        rotator.ingest_potentially_blocking(packet_at(101)?)?;
+        rotator.ingest_potentially_blocking(packet_at(125)?)?;
+        rotator.ingest_potentially_blocking(packet_at(105)?)?;
+
+        let rest = rotator.flush_all()?;
+        assert_eq!(
+            rest.iter().map(|b| b.num_rows()).collect::<Vec<_>>(),
+            vec![2]
+        );
+        assert_eq!(rotator.dropped_late, 0);
+        Ok(())
+    }
+}
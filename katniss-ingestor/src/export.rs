@@ -0,0 +1,255 @@
+use std::io::Write;
+#[cfg(feature = "mcap")]
+use std::{borrow::Cow, collections::BTreeMap, io::Seek, sync::Arc};
+
+#[cfg(feature = "mcap")]
+use chrono::Utc;
+use futures::{Stream, StreamExt};
+#[cfg(feature = "mcap")]
+use mcap::{records::MessageHeader, Channel, Schema, Writer};
+use prost::Message;
+
+#[cfg(feature = "mcap")]
+use katniss_pb2arrow::exports::prost_reflect::MessageDescriptor;
+use katniss_pb2arrow::exports::DynamicMessage;
+
+use crate::Result;
+#[cfg(feature = "mcap")]
+use crate::{errors::KatinssIngestorError, EventTime};
+
+/// Counts from export
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExportReport {
+    pub messages: u64,
+    /// Encoded size of the messages, without framing
+    pub bytes: u64,
+}
+
+/// Where export writes messages to
+pub trait MessageWriter {
+    fn write(&mut self, msg: &DynamicMessage) -> Result<()>;
+
+    /// Called once after the last message
+    fn finish(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Writes messages read back from katniss outputs, i.e. a LanceMessageReader's or a
+/// ParquetMessageReader's, to feed a simulator or a consumer that only speaks protobuf.
+/// Can be called more than once with the same writer, finish it after the last call
+pub async fn export<S, W>(messages: S, writer: &mut W) -> Result<ExportReport>
+where
+    S: Stream<Item = Result<DynamicMessage>>,
+    W: MessageWriter + ?Sized,
+{
+    let mut report = ExportReport::default();
+    let mut messages = Box::pin(messages);
+    while let Some(msg) = messages.next().await {
+        let msg = msg?;
+        writer.write(&msg)?;
+        report.messages += 1;
+        report.bytes += msg.encoded_len() as u64;
+    }
+    Ok(report)
+}
+
+/// Varint length delimited messages (protobuf's writeDelimitedTo), what serve_tcp and
+/// Framing::LengthDelimited read
+pub struct DelimitedWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> DelimitedWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write> MessageWriter for DelimitedWriter<W> {
+    fn write(&mut self, msg: &DynamicMessage) -> Result<()> {
+        Ok(self
+            .writer
+            .write_all(&msg.encode_length_delimited_to_vec())?)
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        Ok(self.writer.flush()?)
+    }
+}
+
+/// An MCAP recording with a single protobuf channel, with the descriptor's pool embedded as
+/// the channel's schema so ingest_mcap (or Foxglove) can read it back
+#[cfg(feature = "mcap")]
+pub struct McapWriter<W: Write + Seek> {
+    writer: Writer<'static, W>,
+    channel: u16,
+    sequence: u32,
+    event_time: Option<EventTime>,
+}
+
+#[cfg(feature = "mcap")]
+impl<W: Write + Seek> McapWriter<W> {
+    pub fn try_new(writer: W, topic: &str, descriptor: &MessageDescriptor) -> Result<Self> {
+        let mut writer = Writer::new(writer)?;
+        let channel = writer.add_channel(&Channel {
+            topic: topic.to_owned(),
+            schema: Some(Arc::new(Schema {
+                name: descriptor.full_name().to_owned(),
+                encoding: "protobuf".to_owned(),
+                data: Cow::Owned(descriptor.parent_pool().encode_to_vec()),
+            })),
+            message_encoding: "protobuf".to_owned(),
+            metadata: BTreeMap::new(),
+        })?;
+        Ok(Self {
+            writer,
+            channel,
+            sequence: 0,
+            event_time: None,
+        })
+    }
+
+    /// Log messages at their event time instead of the time they're exported,
+    /// messages without one still get the export time
+    pub fn with_event_time(mut self, event_time: EventTime) -> Self {
+        self.event_time = Some(event_time);
+        self
+    }
+}
+
+#[cfg(feature = "mcap")]
+impl<W: Write + Seek> MessageWriter for McapWriter<W> {
+    fn write(&mut self, msg: &DynamicMessage) -> Result<()> {
+        let time = self
+            .event_time
+            .as_ref()
+            .and_then(|event_time| event_time.extract(msg))
+            .unwrap_or_else(Utc::now);
+        let nanos = time
+            .timestamp_nanos_opt()
+            .and_then(|nanos| u64::try_from(nanos).ok())
+            .ok_or(KatinssIngestorError::McapLogTime(time))?;
+        let header = MessageHeader {
+            channel_id: self.channel,
+            sequence: self.sequence,
+            log_time: nanos,
+            publish_time: nanos,
+        };
+        self.writer
+            .write_to_known_channel(&header, &msg.encode_to_vec())?;
+        self.sequence += 1;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        Ok(self.writer.finish()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::stream;
+
+    use katniss_test::{protos::spacecorp::Packet, test_util::to_dynamic};
+
+    use super::*;
+
+    fn packets() -> anyhow::Result<Vec<DynamicMessage>> {
+        (0..3)
+            .map(|sender_uid| {
+                let packet = Packet {
+                    sender_uid,
+                    ..Default::default()
+                };
+                Ok(to_dynamic(&packet, "eto.pb2arrow.tests.spacecorp.Packet")?)
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn messages_are_exported_length_delimited() -> anyhow::Result<()> {
+        let messages = packets()?;
+        let mut writer = DelimitedWriter::new(Vec::new());
+        let report = export(stream::iter(messages.into_iter().map(Ok)), &mut writer).await?;
+        writer.finish()?;
+        assert_eq!(report.messages, 3);
+
+        let mut bytes = &writer.into_inner()[..];
+        let mut read = Vec::new();
+        while !bytes.is_empty() {
+            read.push(Packet::decode_length_delimited(&mut bytes)?.sender_uid);
+        }
+        assert_eq!(read, [0, 1, 2]);
+        Ok(())
+    }
+
+    #[cfg(feature = "mcap")]
+    #[tokio::test]
+    async fn messages_are_exported_as_mcap() -> anyhow::Result<()> {
+        use std::{fs::File, io::BufWriter};
+
+        use katniss_test::protos::spacecorp::Timestamp;
+        use mcap::MessageStream;
+
+        let messages = (0..3)
+            .map(|seconds| {
+                let packet = Packet {
+                    timestamp: Some(Timestamp { seconds, nanos: 0 }),
+                    ..Default::default()
+                };
+                Ok(to_dynamic(&packet, "eto.pb2arrow.tests.spacecorp.Packet")?)
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let descriptor = messages[0].descriptor();
+
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("packets.mcap");
+        let file = BufWriter::new(File::create(&path)?);
+        let mut writer = McapWriter::try_new(file, "/packets", &descriptor)?
+            .with_event_time(EventTime::new("timestamp"));
+        export(stream::iter(messages.into_iter().map(Ok)), &mut writer).await?;
+        writer.finish()?;
+        drop(writer);
+
+        let bytes = std::fs::read(&path)?;
+        let mut log_times = Vec::new();
+        for message in MessageStream::new(&bytes)? {
+            let message = message?;
+            let schema = message.channel.schema.as_ref().unwrap();
+            assert_eq!(schema.name, "eto.pb2arrow.tests.spacecorp.Packet");
+            Packet::decode(&message.data[..])?;
+            log_times.push(message.log_time);
+        }
+        assert_eq!(log_times, [0, 1_000_000_000, 2_000_000_000]);
+        Ok(())
+    }
+
+    #[cfg(feature = "mcap")]
+    #[test]
+    fn times_mcap_cant_log_are_errors() -> anyhow::Result<()> {
+        use katniss_test::protos::spacecorp::Timestamp;
+
+        let descriptor = packets()?[0].descriptor();
+        let mut writer =
+            McapWriter::try_new(std::io::Cursor::new(Vec::new()), "/packets", &descriptor)?
+                .with_event_time(EventTime::new("timestamp"));
+        // before 1970 and after 2262
+        for seconds in [-1, 10_000_000_000] {
+            let packet = Packet {
+                timestamp: Some(Timestamp { seconds, nanos: 0 }),
+                ..Default::default()
+            };
+            let msg = to_dynamic(&packet, "eto.pb2arrow.tests.spacecorp.Packet")?;
+            assert!(matches!(
+                writer.write(&msg),
+                Err(KatinssIngestorError::McapLogTime(_))
+            ));
+        }
+        Ok(())
+    }
+}
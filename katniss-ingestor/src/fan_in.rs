@@ -0,0 +1,150 @@
+use std::sync::Arc;
+
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use tokio::sync::mpsc::UnboundedSender;
+
+use katniss_pb2arrow::exports::DynamicMessage;
+
+use crate::{ack::Ack, errors::KatinssIngestorError, Result};
+
+/// A message along with the name of the source it came from and who to tell once it's written
+#[derive(Debug)]
+pub(crate) struct Sourced {
+    pub source: Option<Arc<str>>,
+    pub msg: DynamicMessage,
+    pub ack: Option<Ack>,
+}
+
+impl Sourced {
+    pub(crate) fn new(source: Option<Arc<str>>, msg: DynamicMessage) -> Self {
+        Self {
+            source,
+            msg,
+            ack: None,
+        }
+    }
+
+    /// The pipeline is done with the message without writing it
+    pub(crate) fn acknowledge(self) {
+        if let Some(ack) = self.ack {
+            ack.fire();
+        }
+    }
+}
+
+/// Whatever a pipeline's head channel carries
+pub(crate) trait IntoSourced: Send + 'static {
+    fn into_sourced(self) -> Sourced;
+}
+
+impl IntoSourced for DynamicMessage {
+    fn into_sourced(self) -> Sourced {
+        Sourced::new(None, self)
+    }
+}
+
+impl IntoSourced for (Arc<str>, DynamicMessage) {
+    fn into_sourced(self) -> Sourced {
+        Sourced::new(Some(self.0), self.1)
+    }
+}
+
+impl IntoSourced for (DynamicMessage, Ack) {
+    fn into_sourced(self) -> Sourced {
+        Sourced {
+            ack: Some(self.1),
+            ..Sourced::new(None, self.0)
+        }
+    }
+}
+
+/// The pipeline's schema with the source column appended,
+/// sinks for a fan in pipeline need to be created with this rather than props.schema
+pub fn source_tagged_schema(schema: &Schema, source_column: &str) -> SchemaRef {
+    let fields = schema
+        .fields()
+        .iter()
+        .cloned()
+        .chain([Arc::new(Field::new(source_column, DataType::Utf8, true))])
+        .collect::<Vec<_>>();
+    Arc::new(Schema::new_with_metadata(fields, schema.metadata().clone()))
+}
+
+/// Head of a pipeline that merges several sources (i.e. sockets or topics for a fleet of
+/// identical devices) into one rotator, tagging each row with the source it came from.
+/// See PipelineBuilder::build_fan_in
+#[derive(Debug, Clone)]
+pub struct FanIn {
+    tx: UnboundedSender<(Arc<str>, DynamicMessage)>,
+}
+
+impl FanIn {
+    pub(crate) fn new(tx: UnboundedSender<(Arc<str>, DynamicMessage)>) -> Self {
+        Self { tx }
+    }
+
+    /// Sender for one source, every message sent through it gets tagged with name
+    pub fn source(&self, name: &str) -> SourceSender {
+        SourceSender {
+            name: name.into(),
+            tx: self.tx.clone(),
+        }
+    }
+}
+
+/// Cheaply cloneable sender for a single source of a FanIn
+#[derive(Debug, Clone)]
+pub struct SourceSender {
+    name: Arc<str>,
+    tx: UnboundedSender<(Arc<str>, DynamicMessage)>,
+}
+
+impl SourceSender {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn send(&self, msg: DynamicMessage) -> Result<()> {
+        self.tx
+            .send((self.name.clone(), msg))
+            .map_err(|_| KatinssIngestorError::PipelineClosed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::cast::AsArray;
+
+    use katniss_pb2arrow::ArrowBatchProps;
+    use katniss_test::{descriptor_pool, protos::spacecorp::Packet, test_util::to_dynamic};
+
+    use super::*;
+    use crate::arrow::ProtobufBatchIngestor;
+
+    const PACKET: &str = "eto.pb2arrow.tests.spacecorp.Packet";
+
+    #[test]
+    fn rows_are_tagged_with_their_source() -> anyhow::Result<()> {
+        let props = ArrowBatchProps::try_new(descriptor_pool()?, PACKET.to_owned())?;
+        let mut ingestor =
+            ProtobufBatchIngestor::try_new(&props)?.with_source_column(&props, "robot");
+
+        let packet = || to_dynamic(&Packet::default(), PACKET);
+        ingestor.ingest_sourced(packet()?, Some("robot-1"))?;
+        ingestor.ingest_sourced(packet()?, Some("robot-2"))?;
+        ingestor.ingest_message(packet()?)?;
+
+        let batch = ingestor.finish()?;
+        assert_eq!(batch.schema(), source_tagged_schema(&props.schema, "robot"));
+        assert_eq!(
+            batch
+                .column_by_name("robot")
+                .unwrap()
+                .as_string::<i32>()
+                .iter()
+                .collect::<Vec<_>>(),
+            vec![Some("robot-1"), Some("robot-2"), None]
+        );
+        Ok(())
+    }
+}
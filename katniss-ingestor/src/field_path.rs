@@ -0,0 +1,93 @@
+use arrow_array::ArrayRef;
+use katniss_pb2arrow::exports::{
+    prost_reflect::{FieldDescriptor, Kind, MessageDescriptor, Value},
+    DynamicMessage, RecordBatch,
+};
+
+use crate::sinks::column_at;
+
+/// Dot separated path into (possibly nested) message fields i.e. "header.robot_id"
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(from = "String", into = "String")
+)]
+pub struct FieldPath(Vec<String>);
+
+impl FieldPath {
+    pub fn parse(path: &str) -> Self {
+        Self(path.split('.').map(str::to_owned).collect())
+    }
+
+    /// Calls f with the value at the end of the path
+    /// None if any field along the way isn't set (or doesn't exist)
+    pub fn with_value<R, F>(&self, msg: &DynamicMessage, f: F) -> Option<R>
+    where
+        F: FnOnce(&Value) -> Option<R>,
+    {
+        with_value_at(msg, &self.0, true, f)
+    }
+
+    /// Like with_value but unset fields (and unset parents) give their default value
+    /// None only if the path doesn't exist on the message
+    pub fn with_value_or_default<R, F>(&self, msg: &DynamicMessage, f: F) -> Option<R>
+    where
+        F: FnOnce(&Value) -> Option<R>,
+    {
+        with_value_at(msg, &self.0, false, f)
+    }
+
+    /// Column at the end of the path through struct columns, None if the path doesn't exist
+    pub(crate) fn column(&self, batch: &RecordBatch) -> Option<ArrayRef> {
+        column_at(batch, &self.0)
+    }
+
+    /// Descriptor of the field at the end of the path, None if the path doesn't exist
+    pub fn resolve(&self, descriptor: &MessageDescriptor) -> Option<FieldDescriptor> {
+        let (last, parents) = self.0.split_last()?;
+        let mut descriptor = descriptor.clone();
+        for name in parents {
+            descriptor = match descriptor.get_field_by_name(name)?.kind() {
+                Kind::Message(child) => child,
+                _ => return None,
+            };
+        }
+        descriptor.get_field_by_name(last)
+    }
+}
+
+impl std::fmt::Display for FieldPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0.join("."))
+    }
+}
+
+impl From<String> for FieldPath {
+    fn from(path: String) -> Self {
+        Self::parse(&path)
+    }
+}
+
+impl From<FieldPath> for String {
+    fn from(path: FieldPath) -> Self {
+        path.to_string()
+    }
+}
+
+fn with_value_at<R, F>(msg: &DynamicMessage, path: &[String], require_set: bool, f: F) -> Option<R>
+where
+    F: FnOnce(&Value) -> Option<R>,
+{
+    let (name, rest) = path.split_first()?;
+    if require_set && !msg.has_field_by_name(name) {
+        return None;
+    }
+
+    let value = msg.get_field_by_name(name)?;
+    if rest.is_empty() {
+        f(&value)
+    } else {
+        with_value_at(value.as_message()?, rest, require_set, f)
+    }
+}
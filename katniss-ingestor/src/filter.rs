@@ -0,0 +1,558 @@
+use std::cmp::Ordering;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use arrow_array::{
+    cast::AsArray,
+    types::{Float64Type, Int64Type},
+    Array, ArrayRef, BooleanArray, GenericListArray, OffsetSizeTrait,
+};
+use arrow_cast::cast;
+use arrow_schema::DataType;
+use katniss_pb2arrow::exports::{
+    prost_reflect::{Kind, Value},
+    DynamicMessage, RecordBatch,
+};
+
+use crate::{errors::KatinssIngestorError, field_path::FieldPath};
+
+/// Right hand side of a comparison
+/// Strings are compared against enum fields by value name i.e. `severity >= WARN`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Literal {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Str(String),
+}
+
+impl From<bool> for Literal {
+    fn from(value: bool) -> Self {
+        Self::Bool(value)
+    }
+}
+
+impl From<i64> for Literal {
+    fn from(value: i64) -> Self {
+        Self::Int(value)
+    }
+}
+
+impl From<f64> for Literal {
+    fn from(value: f64) -> Self {
+        Self::Float(value)
+    }
+}
+
+impl From<&str> for Literal {
+    fn from(value: &str) -> Self {
+        Self::Str(value.to_owned())
+    }
+}
+
+impl From<String> for Literal {
+    fn from(value: String) -> Self {
+        Self::Str(value)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl CompareOp {
+    fn matches(self, ordering: Ordering) -> bool {
+        match self {
+            Self::Eq => ordering.is_eq(),
+            Self::Ne => ordering.is_ne(),
+            Self::Lt => ordering.is_lt(),
+            Self::Le => ordering.is_le(),
+            Self::Gt => ordering.is_gt(),
+            Self::Ge => ordering.is_ge(),
+        }
+    }
+}
+
+/// A check run against every message at the head of the pipeline, before arrow encoding,
+/// messages that don't match are dropped.
+/// Unset fields compare as their default value, comparisons on repeated fields match if any element does,
+/// and a comparison between mismatched types (or a path that doesn't exist) never matches.
+#[derive(Clone)]
+pub enum Predicate {
+    Compare {
+        field: FieldPath,
+        op: CompareOp,
+        literal: Literal,
+    },
+    In {
+        field: FieldPath,
+        literals: Vec<Literal>,
+    },
+    IsSet(FieldPath),
+    Not(Box<Predicate>),
+    All(Vec<Predicate>),
+    Any(Vec<Predicate>),
+    Custom(Arc<dyn Fn(&DynamicMessage) -> bool + Send + Sync>),
+}
+
+impl Predicate {
+    pub fn compare<L: Into<Literal>>(field: &str, op: CompareOp, literal: L) -> Self {
+        Self::Compare {
+            field: FieldPath::parse(field),
+            op,
+            literal: literal.into(),
+        }
+    }
+
+    pub fn is_in<L: Into<Literal>>(field: &str, literals: impl IntoIterator<Item = L>) -> Self {
+        Self::In {
+            field: FieldPath::parse(field),
+            literals: literals.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    pub fn is_set(field: &str) -> Self {
+        Self::IsSet(FieldPath::parse(field))
+    }
+
+    pub fn custom<F: Fn(&DynamicMessage) -> bool + Send + Sync + 'static>(f: F) -> Self {
+        Self::Custom(Arc::new(f))
+    }
+
+    pub fn and(self, other: Predicate) -> Self {
+        match self {
+            Self::All(mut predicates) => {
+                predicates.push(other);
+                Self::All(predicates)
+            }
+            _ => Self::All(vec![self, other]),
+        }
+    }
+
+    pub fn or(self, other: Predicate) -> Self {
+        match self {
+            Self::Any(mut predicates) => {
+                predicates.push(other);
+                Self::Any(predicates)
+            }
+            _ => Self::Any(vec![self, other]),
+        }
+    }
+
+    pub fn matches(&self, msg: &DynamicMessage) -> bool {
+        match self {
+            Self::Compare { field, op, literal } => field
+                .with_value_or_default(msg, |value| {
+                    Some(any_element(value, |v| {
+                        compare(msg, field, v, literal).is_some_and(|o| op.matches(o))
+                    }))
+                })
+                .unwrap_or(false),
+            Self::In { field, literals } => field
+                .with_value_or_default(msg, |value| {
+                    Some(any_element(value, |v| {
+                        literals
+                            .iter()
+                            .any(|l| compare(msg, field, v, l) == Some(Ordering::Equal))
+                    }))
+                })
+                .unwrap_or(false),
+            Self::IsSet(field) => field.with_value(msg, |_| Some(())).is_some(),
+            Self::Not(predicate) => !predicate.matches(msg),
+            Self::All(predicates) => predicates.iter().all(|p| p.matches(msg)),
+            Self::Any(predicates) => predicates.iter().any(|p| p.matches(msg)),
+            Self::Custom(f) => f(msg),
+        }
+    }
+
+    /// Which rows of the batch match, i.e. for TemporalBuffer::filter.
+    /// Same rules as matches, except null values never satisfy a comparison
+    /// and enum names only compare against enums stored as strings.
+    /// Custom predicates only run on messages, so they're an error here
+    pub fn evaluate(&self, batch: &RecordBatch) -> Result<BooleanArray, KatinssIngestorError> {
+        Ok(BooleanArray::from(self.evaluate_rows(batch)?))
+    }
+
+    fn evaluate_rows(&self, batch: &RecordBatch) -> Result<Vec<bool>, KatinssIngestorError> {
+        let rows = batch.num_rows();
+        match self {
+            Self::Compare { field, op, literal } => any_in_column(batch, field, |values| {
+                compare_column(values, literal, |o| op.matches(o))
+            }),
+            Self::In { field, literals } => any_in_column(batch, field, |values| {
+                let mut matched = vec![false; values.len()];
+                for literal in literals {
+                    let equal = compare_column(values, literal, Ordering::is_eq)?;
+                    matched.iter_mut().zip(equal).for_each(|(m, e)| *m |= e);
+                }
+                Ok(matched)
+            }),
+            Self::IsSet(field) => Ok(match field.column(batch) {
+                Some(column) => (0..rows).map(|i| column.is_valid(i)).collect(),
+                None => vec![false; rows],
+            }),
+            Self::Not(predicate) => Ok(predicate
+                .evaluate_rows(batch)?
+                .into_iter()
+                .map(|m| !m)
+                .collect()),
+            Self::All(predicates) => predicates.iter().try_fold(vec![true; rows], |all, p| {
+                let matched = p.evaluate_rows(batch)?;
+                Ok(all.into_iter().zip(matched).map(|(a, m)| a && m).collect())
+            }),
+            Self::Any(predicates) => predicates.iter().try_fold(vec![false; rows], |any, p| {
+                let matched = p.evaluate_rows(batch)?;
+                Ok(any.into_iter().zip(matched).map(|(a, m)| a || m).collect())
+            }),
+            Self::Custom(_) => Err(KatinssIngestorError::InvalidPredicate(
+                "custom predicates can't run on arrow batches".to_owned(),
+            )),
+        }
+    }
+}
+
+impl std::ops::Not for Predicate {
+    type Output = Predicate;
+
+    fn not(self) -> Self::Output {
+        Self::Not(Box::new(self))
+    }
+}
+
+/// Parses a single comparison, i.e. `severity >= WARN`, `header.robot_id in (3, 5, 8)`
+/// or `name != "bob"`, unquoted words that aren't numbers or booleans are strings
+impl FromStr for Predicate {
+    type Err = KatinssIngestorError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || KatinssIngestorError::InvalidPredicate(s.to_owned());
+        let s = s.trim();
+
+        if let Some((field, list)) = s.split_once(" in ") {
+            let list = list
+                .trim()
+                .strip_prefix('(')
+                .and_then(|l| l.strip_suffix(')'))
+                .ok_or_else(invalid)?;
+            let literals = list.split(',').map(parse_literal).collect::<Vec<_>>();
+            return Ok(Self::is_in(field.trim(), literals));
+        }
+
+        let start = s.find(['=', '!', '<', '>']).ok_or_else(invalid)?;
+        let (field, rest) = s.split_at(start);
+        let (op, literal) = [
+            ("==", CompareOp::Eq),
+            ("!=", CompareOp::Ne),
+            ("<=", CompareOp::Le),
+            (">=", CompareOp::Ge),
+            ("=", CompareOp::Eq),
+            ("<", CompareOp::Lt),
+            (">", CompareOp::Gt),
+        ]
+        .into_iter()
+        .find_map(|(token, op)| rest.strip_prefix(token).map(|literal| (op, literal)))
+        .ok_or_else(invalid)?;
+
+        if field.trim().is_empty() || literal.trim().is_empty() {
+            return Err(invalid());
+        }
+        Ok(Self::compare(field.trim(), op, parse_literal(literal)))
+    }
+}
+
+fn parse_literal(s: &str) -> Literal {
+    let s = s.trim();
+    if let Some(quoted) = ['"', '\'']
+        .into_iter()
+        .find_map(|q| s.strip_prefix(q).and_then(|s| s.strip_suffix(q)))
+    {
+        return Literal::Str(quoted.to_owned());
+    }
+
+    if let Ok(b) = s.parse() {
+        Literal::Bool(b)
+    } else if let Ok(i) = s.parse() {
+        Literal::Int(i)
+    } else if let Ok(f) = s.parse() {
+        Literal::Float(f)
+    } else {
+        Literal::Str(s.to_owned())
+    }
+}
+
+/// Runs f over the column at the path, rows of list columns match if any element does.
+/// A path that doesn't exist never matches
+fn any_in_column<F>(
+    batch: &RecordBatch,
+    field: &FieldPath,
+    f: F,
+) -> Result<Vec<bool>, KatinssIngestorError>
+where
+    F: Fn(&ArrayRef) -> Result<Vec<bool>, KatinssIngestorError>,
+{
+    let Some(column) = field.column(batch) else {
+        return Ok(vec![false; batch.num_rows()]);
+    };
+    match column.data_type() {
+        DataType::List(_) => any_in_lists(column.as_list::<i32>(), f),
+        DataType::LargeList(_) => any_in_lists(column.as_list::<i64>(), f),
+        _ => f(&column),
+    }
+}
+
+fn any_in_lists<O, F>(list: &GenericListArray<O>, f: F) -> Result<Vec<bool>, KatinssIngestorError>
+where
+    O: OffsetSizeTrait,
+    F: Fn(&ArrayRef) -> Result<Vec<bool>, KatinssIngestorError>,
+{
+    let matched = f(list.values())?;
+    Ok(list
+        .value_offsets()
+        .windows(2)
+        .enumerate()
+        .map(|(i, w)| {
+            let elements = &matched[w[0].as_usize()..w[1].as_usize()];
+            list.is_valid(i) && elements.contains(&true)
+        })
+        .collect())
+}
+
+/// compare for a whole column, values of a different type than the literal never match.
+/// Integers outside of i64 are cast to null, so they don't match either
+fn compare_column(
+    values: &ArrayRef,
+    literal: &Literal,
+    op: impl Fn(Ordering) -> bool,
+) -> Result<Vec<bool>, KatinssIngestorError> {
+    let data_type = values.data_type();
+    let float = matches!(
+        data_type,
+        DataType::Float16 | DataType::Float32 | DataType::Float64
+    );
+    let string = match data_type {
+        DataType::Dictionary(_, value_type) => value_type.as_ref(),
+        data_type => data_type,
+    };
+
+    Ok(match literal {
+        Literal::Bool(l) if data_type == &DataType::Boolean => values
+            .as_boolean()
+            .iter()
+            .map(|v| v.is_some_and(|v| op(v.cmp(l))))
+            .collect(),
+        Literal::Int(l) if data_type.is_numeric() && !float => cast(values, &DataType::Int64)?
+            .as_primitive::<Int64Type>()
+            .iter()
+            .map(|v| v.is_some_and(|v| op(v.cmp(l))))
+            .collect(),
+        Literal::Int(_) | Literal::Float(_) if data_type.is_numeric() => {
+            let l = literal_float(literal).expect("numeric literal");
+            cast(values, &DataType::Float64)?
+                .as_primitive::<Float64Type>()
+                .iter()
+                .map(|v| v.and_then(|v| v.partial_cmp(&l)).is_some_and(&op))
+                .collect()
+        }
+        Literal::Str(l) if matches!(string, DataType::Utf8 | DataType::LargeUtf8) => {
+            cast(values, &DataType::Utf8)?
+                .as_string::<i32>()
+                .iter()
+                .map(|v| v.is_some_and(|v| op(v.cmp(l.as_str()))))
+                .collect()
+        }
+        _ => vec![false; values.len()],
+    })
+}
+
+fn any_element(value: &Value, f: impl Fn(&Value) -> bool) -> bool {
+    match value {
+        Value::List(values) => values.iter().any(f),
+        _ => f(value),
+    }
+}
+
+fn compare(
+    msg: &DynamicMessage,
+    field: &FieldPath,
+    value: &Value,
+    literal: &Literal,
+) -> Option<Ordering> {
+    let int = match value {
+        Value::Bool(v) => return Some(v.cmp(&literal_bool(literal)?)),
+        Value::String(v) => return Some(v.as_str().cmp(literal_str(literal)?)),
+        Value::F32(v) => return (*v as f64).partial_cmp(&literal_float(literal)?),
+        Value::F64(v) => return v.partial_cmp(&literal_float(literal)?),
+        Value::EnumNumber(v) => {
+            let number = match literal {
+                Literal::Str(name) => match field.resolve(&msg.descriptor())?.kind() {
+                    Kind::Enum(e) => e.get_value_by_name(name)?.number() as i64,
+                    _ => return None,
+                },
+                _ => literal_int(literal)?,
+            };
+            return Some((*v as i64).cmp(&number));
+        }
+        Value::I32(v) => *v as i128,
+        Value::I64(v) => *v as i128,
+        Value::U32(v) => *v as i128,
+        Value::U64(v) => *v as i128,
+        _ => return None,
+    };
+
+    match literal {
+        Literal::Int(l) => Some(int.cmp(&(*l as i128))),
+        Literal::Float(l) => (int as f64).partial_cmp(l),
+        _ => None,
+    }
+}
+
+fn literal_bool(literal: &Literal) -> Option<bool> {
+    match literal {
+        Literal::Bool(b) => Some(*b),
+        _ => None,
+    }
+}
+
+fn literal_str(literal: &Literal) -> Option<&str> {
+    match literal {
+        Literal::Str(s) => Some(s),
+        _ => None,
+    }
+}
+
+fn literal_int(literal: &Literal) -> Option<i64> {
+    match literal {
+        Literal::Int(i) => Some(*i),
+        _ => None,
+    }
+}
+
+fn literal_float(literal: &Literal) -> Option<f64> {
+    match literal {
+        Literal::Float(f) => Some(*f),
+        Literal::Int(i) => Some(*i as f64),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use katniss_test::{
+        protos::spacecorp::{packet::Msg, JumpDriveMode, JumpDriveStatus, Packet},
+        test_util::{to_dynamic, ProtoBatch},
+    };
+
+    use super::*;
+
+    fn packet(sender_uid: u64, mode: Option<JumpDriveMode>) -> anyhow::Result<DynamicMessage> {
+        to_dynamic(
+            &Packet {
+                sender_uid,
+                msg: mode.map(|mode| {
+                    Msg::JumpDriveStatus(JumpDriveStatus {
+                        mode: mode as i32,
+                        ..Default::default()
+                    })
+                }),
+                ..Default::default()
+            },
+            "eto.pb2arrow.tests.spacecorp.Packet",
+        )
+    }
+
+    #[test]
+    fn it_compares_scalars_and_enums() -> anyhow::Result<()> {
+        let broken = packet(3, Some(JumpDriveMode::OutOfDilithium))?;
+        let warming = packet(5, Some(JumpDriveMode::HyperdriveWarming))?;
+        let no_status = packet(7, None)?;
+
+        let errors: Predicate = "jump_drive_status.mode >= OUT_OF_DILITHIUM".parse()?;
+        assert!(errors.matches(&broken));
+        assert!(!errors.matches(&warming));
+        assert!(!errors.matches(&no_status));
+
+        let fleet: Predicate = "sender_uid in (3, 7)".parse()?;
+        assert!(fleet.matches(&broken));
+        assert!(!fleet.matches(&warming));
+        assert!(fleet.matches(&no_status));
+
+        let combined = fleet.and(!Predicate::is_set("jump_drive_status"));
+        assert!(!combined.matches(&broken));
+        assert!(combined.matches(&no_status));
+
+        let custom = Predicate::custom(|m| m.has_field_by_name("jump_drive_status"))
+            .or(Predicate::compare("sender_uid", CompareOp::Gt, 100));
+        assert!(custom.matches(&warming));
+        assert!(!custom.matches(&no_status));
+
+        // types that don't line up never match
+        assert!(!"sender_uid == bob".parse::<Predicate>()?.matches(&broken));
+        Ok(())
+    }
+
+    #[test]
+    fn it_evaluates_over_arrow_batches() -> anyhow::Result<()> {
+        let packets = [
+            Packet {
+                sender_uid: 3,
+                ..Default::default()
+            },
+            Packet {
+                sender_uid: 5,
+                msg: Some(Msg::JumpDriveStatus(JumpDriveStatus::default())),
+                ..Default::default()
+            },
+            Packet {
+                sender_uid: 7,
+                ..Default::default()
+            },
+        ];
+        let batch = ProtoBatch::SpaceCorp(&packets).arrow_batch()?;
+        let matching = |predicate: Predicate| -> anyhow::Result<Vec<bool>> {
+            Ok(predicate.evaluate(&batch)?.iter().flatten().collect())
+        };
+
+        assert_eq!(
+            matching("sender_uid > 4".parse()?)?,
+            vec![false, true, true]
+        );
+        assert_eq!(
+            matching("sender_uid in (3, 7.0)".parse()?)?,
+            vec![true, false, true]
+        );
+        assert_eq!(
+            matching(!Predicate::is_set("jump_drive_status"))?,
+            vec![true, false, true]
+        );
+        assert_eq!(
+            matching(
+                "sender_uid >= 5"
+                    .parse::<Predicate>()?
+                    .and("sender_uid < 7".parse()?)
+            )?,
+            vec![false, true, false]
+        );
+        // types that don't line up and paths that don't exist never match
+        assert_eq!(matching("sender_uid == bob".parse()?)?, vec![false; 3]);
+        assert_eq!(matching("nope == 3".parse()?)?, vec![false; 3]);
+
+        assert!(Predicate::custom(|_| true).evaluate(&batch).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn it_rejects_malformed_predicates() {
+        for bad in ["sender_uid", "sender_uid in 3, 7", ">= 3", "sender_uid >="] {
+            assert!(
+                bad.parse::<Predicate>().is_err(),
+                "{bad} should not have parsed"
+            );
+        }
+    }
+}
@@ -0,0 +1,277 @@
+use std::time::Duration;
+
+use arrow_flight::{FlightClient, Ticket};
+use chrono::{DateTime, Utc};
+use futures::{Stream, StreamExt, TryStreamExt};
+use tokio::{
+    select,
+    time::{sleep_until, Instant},
+};
+use tonic::transport::Endpoint;
+
+use katniss_pb2arrow::exports::RecordBatch;
+
+use crate::{sinks::BufferSink, temporal_rotator::TemporalBuffer, Result};
+
+/// Counts from a finished FlightSource::mirror
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MirrorReport {
+    pub batches: usize,
+    pub rows: usize,
+    /// Buffers handed to the sink
+    pub buffers: usize,
+}
+
+/// Pulls batches from a remote Arrow Flight endpoint via DoGet, i.e. to mirror the live
+/// buffers another collector serves with a FlightSqlEndpoint into Lance
+pub struct FlightSource {
+    client: FlightClient,
+    ticket: Ticket,
+    period: Option<Duration>,
+}
+
+impl FlightSource {
+    /// Connect to a flight endpoint i.e. http://collector:50051, every DoGet is for `ticket`
+    pub async fn connect<E: Into<String>>(endpoint: E, ticket: Ticket) -> Result<Self> {
+        let channel = Endpoint::try_from(endpoint.into())?.connect().await?;
+        Ok(Self::new(FlightClient::new(channel), ticket))
+    }
+
+    pub fn new(client: FlightClient, ticket: Ticket) -> Self {
+        Self {
+            client,
+            ticket,
+            period: None,
+        }
+    }
+
+    /// Collect batches into a buffer per period, like a pipeline's rotation, instead of
+    /// handing each batch to the sink as it arrives
+    pub fn with_rotation(mut self, period: Duration) -> Self {
+        self.period = Some(period);
+        self
+    }
+
+    /// The batches of one DoGet as they arrive
+    pub async fn batches(&mut self) -> Result<impl Stream<Item = Result<RecordBatch>>> {
+        let stream = self.client.do_get(self.ticket.clone()).await?;
+        Ok(stream.map_err(Into::into))
+    }
+
+    /// Writes everything a DoGet returns to the sink, closing it once the stream ends.
+    /// If the stream fails what already arrived is still written before the sink is closed.
+    /// Wrap the sink in a PartitionedSink to re-partition the rows
+    pub async fn mirror<S: BufferSink>(&mut self, sink: &mut S) -> Result<MirrorReport> {
+        let batches = self.batches().await?;
+        mirror(batches, self.period, sink).await
+    }
+}
+
+async fn mirror<S: BufferSink>(
+    batches: impl Stream<Item = Result<RecordBatch>>,
+    period: Option<Duration>,
+    sink: &mut S,
+) -> Result<MirrorReport> {
+    let mut batches = Box::pin(batches);
+    let mut report = MirrorReport::default();
+    let mut pending: Option<(TemporalBuffer, Instant)> = None;
+    let mut last_end: Option<DateTime<Utc>> = None;
+
+    loop {
+        let due = pending.as_ref().map(|(_, due)| *due);
+        let next = select! {
+            next = batches.next() => next,
+            _ = sleep_until(due.unwrap_or_else(Instant::now)), if due.is_some() => {
+                let (buffer, _) = pending.take().expect("only due with a pending buffer");
+                write(sink, buffer, &mut report).await?;
+                continue;
+            }
+        };
+        let Some(batch) = next else {
+            break;
+        };
+        let batch = match batch {
+            Ok(batch) => batch,
+            Err(e) => {
+                if let Some((buffer, _)) = pending.take() {
+                    write(sink, buffer, &mut report).await?;
+                }
+                sink.close().await?;
+                return Err(e);
+            }
+        };
+        report.batches += 1;
+        report.rows += batch.num_rows();
+
+        let Some(period) = period else {
+            // store sinks name files by their window, so every batch gets its own millisecond
+            let begin_at = last_end.map_or_else(Utc::now, |end| Utc::now().max(end));
+            let end_at = begin_at + chrono::Duration::milliseconds(1);
+            last_end = Some(end_at);
+            let buffer = TemporalBuffer {
+                begin_at,
+                end_at,
+                batches: vec![batch],
+            };
+            write(sink, buffer, &mut report).await?;
+            continue;
+        };
+        if pending.is_none() {
+            let buffer = TemporalBuffer::new(Utc::now(), period)?;
+            pending = Some((buffer, Instant::now() + period));
+        }
+        if let Some((buffer, _)) = &mut pending {
+            buffer.batches.push(batch);
+        }
+    }
+
+    if let Some((buffer, _)) = pending {
+        write(sink, buffer, &mut report).await?;
+    }
+    sink.close().await?;
+    Ok(report)
+}
+
+async fn write<S: BufferSink>(
+    sink: &mut S,
+    buffer: TemporalBuffer,
+    report: &mut MirrorReport,
+) -> Result<()> {
+    report.buffers += 1;
+    sink.write_buffer(buffer).await
+}
+
+#[cfg(all(test, feature = "flight-sql"))]
+mod tests {
+    use std::io::ErrorKind;
+
+    use arrow_flight::{
+        sql::{CommandStatementQuery, ProstMessageExt},
+        FlightDescriptor,
+    };
+    use async_trait::async_trait;
+    use datafusion::execution::context::SessionContext;
+    use futures::stream;
+    use prost::Message;
+    use tokio::net::TcpListener;
+    use tonic::transport::Server;
+
+    use katniss_test::{protos::spacecorp::Packet, test_util::ProtoBatch};
+
+    use super::*;
+    use crate::FlightSqlEndpoint;
+
+    #[derive(Default)]
+    struct Collect {
+        buffers: Vec<TemporalBuffer>,
+        closed: bool,
+    }
+
+    #[async_trait]
+    impl BufferSink for Collect {
+        async fn write_buffer(&mut self, buffer: TemporalBuffer) -> Result<()> {
+            self.buffers.push(buffer);
+            Ok(())
+        }
+
+        async fn close(&mut self) -> Result<()> {
+            self.closed = true;
+            Ok(())
+        }
+    }
+
+    /// Plans the query with GetFlightInfo, DoGet only accepts the handle it returns
+    async fn plan_ticket(endpoint: &str, query: &str) -> anyhow::Result<Ticket> {
+        let channel = Endpoint::try_from(endpoint.to_string())?.connect().await?;
+        let command = CommandStatementQuery {
+            query: query.into(),
+            transaction_id: None,
+        };
+        let descriptor = FlightDescriptor::new_cmd(command.as_any().encode_to_vec());
+        let info = FlightClient::new(channel)
+            .get_flight_info(descriptor)
+            .await?;
+        let ticket = info.endpoint[0].ticket.clone();
+        Ok(ticket.expect("statement queries have a ticket"))
+    }
+
+    #[tokio::test]
+    async fn it_gives_every_unrotated_batch_its_own_window() -> anyhow::Result<()> {
+        let packets = vec![Packet::default(); 3];
+        let batch = ProtoBatch::SpaceCorp(&packets).arrow_batch()?;
+        let batches = stream::iter((0..50).map(move |_| Ok(batch.clone())));
+
+        let mut sink = Collect::default();
+        mirror(batches, None, &mut sink).await?;
+        assert_eq!(sink.buffers.len(), 50);
+        for pair in sink.buffers.windows(2) {
+            assert!(pair[0].begin_at < pair[0].end_at);
+            assert!(pair[0].end_at <= pair[1].begin_at);
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_flushes_and_closes_when_the_stream_fails() -> anyhow::Result<()> {
+        let packets = vec![Packet::default(); 3];
+        let batch = ProtoBatch::SpaceCorp(&packets).arrow_batch()?;
+        let failure = std::io::Error::new(ErrorKind::ConnectionReset, "remote went away");
+        let batches = stream::iter(vec![Ok(batch.clone()), Ok(batch), Err(failure.into())]);
+
+        let mut sink = Collect::default();
+        let result = mirror(batches, Some(Duration::from_secs(60)), &mut sink).await;
+        assert!(result.is_err());
+        assert_eq!(sink.buffers.len(), 1);
+        assert_eq!(sink.buffers[0].num_rows(), 6);
+        assert!(sink.closed);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_mirrors_a_remote_stream_into_a_sink() -> anyhow::Result<()> {
+        let packets = (0..10)
+            .map(|i| Packet {
+                sender_uid: i,
+                ..Default::default()
+            })
+            .collect::<Vec<_>>();
+        let ctx = SessionContext::new();
+        ctx.register_batch("packets", ProtoBatch::SpaceCorp(&packets).arrow_batch()?)?;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let incoming = stream::unfold(listener, |listener| async move {
+            let conn = listener.accept().await.map(|(conn, _)| conn);
+            Some((conn, listener))
+        });
+        tokio::spawn(
+            Server::builder()
+                .add_service(FlightSqlEndpoint::new(ctx).into_service())
+                .serve_with_incoming(incoming),
+        );
+
+        let query = "select * from packets where sender_uid < 7";
+        let endpoint = format!("http://{addr}");
+
+        let mut sink = Collect::default();
+        let ticket = plan_ticket(&endpoint, query).await?;
+        let report = FlightSource::connect(endpoint.clone(), ticket)
+            .await?
+            .mirror(&mut sink)
+            .await?;
+        assert_eq!(report.rows, 7);
+        assert_eq!(report.buffers, report.batches);
+        assert!(sink.closed);
+
+        let mut sink = Collect::default();
+        let ticket = plan_ticket(&endpoint, query).await?;
+        let report = FlightSource::connect(endpoint, ticket)
+            .await?
+            .with_rotation(Duration::from_secs(60))
+            .mirror(&mut sink)
+            .await?;
+        assert_eq!(report.buffers, 1);
+        assert_eq!(sink.buffers[0].num_rows(), 7);
+        Ok(())
+    }
+}
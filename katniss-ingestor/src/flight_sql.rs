@@ -0,0 +1,714 @@
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use arrow_array::{
+    builder::BufferBuilder, new_empty_array, ArrayRef, BinaryArray, BooleanArray, StringArray,
+    UInt32Array, UnionArray,
+};
+use arrow_flight::{
+    encode::FlightDataEncoderBuilder,
+    error::FlightError,
+    flight_service_server::{FlightService, FlightServiceServer},
+    sql::{
+        Any, CommandGetSqlInfo, CommandGetTables, CommandStatementQuery, ProstMessageExt, SqlInfo,
+        TicketStatementQuery,
+    },
+    Action, ActionType, Criteria, Empty, FlightData, FlightDescriptor, FlightEndpoint, FlightInfo,
+    HandshakeRequest, HandshakeResponse, IpcMessage, PutResult, SchemaAsIpc, SchemaResult, Ticket,
+};
+use arrow_ipc::writer::IpcWriteOptions;
+use arrow_schema::{
+    ArrowError, DataType, Field, Fields, Schema, SchemaRef, UnionFields, UnionMode,
+};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use datafusion::{
+    dataframe::DataFrame,
+    error::DataFusionError,
+    execution::context::{SQLOptions, SessionContext},
+    logical_expr::TableType,
+};
+use futures::{
+    stream::{self, BoxStream},
+    StreamExt, TryStreamExt,
+};
+use prost::Message;
+use tonic::{transport::Server, Request, Response, Status, Streaming};
+
+use katniss_pb2arrow::exports::RecordBatch;
+
+use crate::{live_buffers::LiveBuffers, Result};
+
+/// Where the CLI serves Flight SQL unless told otherwise, only reachable from the same host
+pub const DEFAULT_FLIGHT_SQL_ADDR: &str = "127.0.0.1:32010";
+
+/// Planned statements waiting for their DoGet, the oldest are forgotten past this many
+const MAX_PENDING_STATEMENTS: usize = 1024;
+
+/// Flight SQL statement queries against a SessionContext, so BI tools and ADBC clients can
+/// connect straight to the collector. Register what should be queryable first, i.e. a
+/// LiveBuffersTable for the last few minutes and an IngestedCatalog for what's on disk.
+/// Queries are read only: DDL (which could read any file the server can), DML and statements
+/// like SET are rejected. GetSqlInfo and GetTables are answered for clients that start with
+/// them, prepared statements and the other metadata commands aren't supported.
+/// Anyone who can connect can query unless a token is required, see with_token
+#[derive(Clone)]
+pub struct FlightSqlEndpoint {
+    ctx: SessionContext,
+    token: Option<String>,
+    pending: Arc<Mutex<PendingStatements>>,
+}
+
+impl FlightSqlEndpoint {
+    pub fn new(ctx: SessionContext) -> Self {
+        Self {
+            ctx,
+            token: None,
+            pending: Arc::default(),
+        }
+    }
+
+    /// An endpoint whose session only has the live buffers, as the table `name`
+    pub fn for_live_buffers(live_buffers: &LiveBuffers, name: &str) -> Result<Self> {
+        let ctx = SessionContext::new();
+        live_buffers.register(&ctx, name)?;
+        Ok(Self::new(ctx))
+    }
+
+    /// Require `authorization: Bearer <token>` on every call. The handshake also takes basic
+    /// auth with the token as the password (what ADBC sends) and answers with the bearer header
+    pub fn with_token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    /// The grpc service, for adding to a server alongside others
+    pub fn into_service(self) -> FlightServiceServer<Self> {
+        FlightServiceServer::new(self)
+    }
+
+    /// Serve on `addr` until the server errors, see DEFAULT_FLIGHT_SQL_ADDR
+    pub async fn serve(self, addr: SocketAddr) -> Result<()> {
+        Server::builder()
+            .add_service(self.into_service())
+            .serve(addr)
+            .await?;
+        Ok(())
+    }
+
+    async fn plan(&self, query: &str) -> std::result::Result<DataFrame, Status> {
+        let read_only = SQLOptions::new()
+            .with_allow_ddl(false)
+            .with_allow_dml(false)
+            .with_allow_statements(false);
+        self.ctx
+            .sql_with_options(query, read_only)
+            .await
+            .map_err(status)
+    }
+
+    fn authorize<T>(&self, request: &Request<T>) -> std::result::Result<(), Status> {
+        let Some(token) = &self.token else {
+            return Ok(());
+        };
+        let header = request
+            .metadata()
+            .get("authorization")
+            .and_then(|value| value.to_str().ok());
+        let authorized = header.map_or(false, |header| {
+            header.strip_prefix("Bearer ") == Some(token.as_str())
+                || basic_password(header).as_deref() == Some(token.as_str())
+        });
+        match authorized {
+            true => Ok(()),
+            false => Err(Status::unauthenticated("missing or wrong token")),
+        }
+    }
+
+    /// Every table in the session matching the command's filters
+    async fn tables(&self, command: &CommandGetTables) -> std::result::Result<RecordBatch, Status> {
+        let matches = |pattern: &Option<String>, value: &str| {
+            pattern
+                .as_deref()
+                .map_or(true, |pattern| like(pattern, value))
+        };
+        let (mut catalogs, mut schemas, mut names, mut types, mut table_schemas) =
+            (vec![], vec![], vec![], vec![], vec![]);
+
+        for catalog_name in self.ctx.catalog_names() {
+            if command
+                .catalog
+                .as_ref()
+                .map_or(false, |c| *c != catalog_name)
+            {
+                continue;
+            }
+            let Some(catalog) = self.ctx.catalog(&catalog_name) else {
+                continue;
+            };
+            for schema_name in catalog.schema_names() {
+                if !matches(&command.db_schema_filter_pattern, &schema_name) {
+                    continue;
+                }
+                let Some(schema) = catalog.schema(&schema_name) else {
+                    continue;
+                };
+                for table_name in schema.table_names() {
+                    if !matches(&command.table_name_filter_pattern, &table_name) {
+                        continue;
+                    }
+                    let Some(table) = schema.table(&table_name).await else {
+                        continue;
+                    };
+                    let table_type = match table.table_type() {
+                        TableType::Base => "TABLE",
+                        TableType::View => "VIEW",
+                        TableType::Temporary => "LOCAL TEMPORARY",
+                    };
+                    if !command.table_types.is_empty()
+                        && !command.table_types.iter().any(|t| t == table_type)
+                    {
+                        continue;
+                    }
+                    if command.include_schema {
+                        let IpcMessage(bytes) =
+                            SchemaAsIpc::new(&table.schema(), &IpcWriteOptions::default())
+                                .try_into()
+                                .map_err(internal)?;
+                        table_schemas.push(bytes.to_vec());
+                    }
+                    catalogs.push(catalog_name.clone());
+                    schemas.push(schema_name.clone());
+                    names.push(table_name);
+                    types.push(table_type);
+                }
+            }
+        }
+
+        let mut columns: Vec<ArrayRef> = vec![
+            Arc::new(StringArray::from(catalogs)),
+            Arc::new(StringArray::from(schemas)),
+            Arc::new(StringArray::from(names)),
+            Arc::new(StringArray::from(types)),
+        ];
+        if command.include_schema {
+            columns.push(Arc::new(BinaryArray::from_iter_values(table_schemas)));
+        }
+        RecordBatch::try_new(tables_schema(command.include_schema), columns).map_err(internal)
+    }
+}
+
+/// Planned statements by handle, DoGet takes them out so each is run once
+#[derive(Default)]
+struct PendingStatements {
+    next_handle: u64,
+    statements: HashMap<Vec<u8>, DataFrame>,
+    order: VecDeque<Vec<u8>>,
+}
+
+impl PendingStatements {
+    fn insert(&mut self, df: DataFrame) -> Vec<u8> {
+        let handle = self.next_handle.to_be_bytes().to_vec();
+        self.next_handle += 1;
+        self.statements.insert(handle.clone(), df);
+        self.order.push_back(handle.clone());
+        while self.order.len() > MAX_PENDING_STATEMENTS {
+            if let Some(oldest) = self.order.pop_front() {
+                self.statements.remove(&oldest);
+            }
+        }
+        handle
+    }
+
+    fn take(&mut self, handle: &[u8]) -> Option<DataFrame> {
+        self.order.retain(|h| h != handle);
+        self.statements.remove(handle)
+    }
+}
+
+/// Planning errors are the client's fault, anything else is ours
+fn status(err: DataFusionError) -> Status {
+    match err {
+        DataFusionError::SQL(_) | DataFusionError::Plan(_) | DataFusionError::SchemaError(_) => {
+            Status::invalid_argument(err.to_string())
+        }
+        err => Status::internal(err.to_string()),
+    }
+}
+
+fn internal(err: ArrowError) -> Status {
+    Status::internal(err.to_string())
+}
+
+fn decode_any(bytes: &[u8]) -> std::result::Result<Any, Status> {
+    Any::decode(bytes).map_err(|err| Status::invalid_argument(err.to_string()))
+}
+
+fn unpack<T: ProstMessageExt>(any: &Any) -> std::result::Result<Option<T>, Status> {
+    any.unpack::<T>()
+        .map_err(|err| Status::invalid_argument(err.to_string()))
+}
+
+fn unsupported(any: &Any) -> Status {
+    Status::unimplemented(format!("Unsupported command {}", any.type_url))
+}
+
+/// The password of a basic auth header
+fn basic_password(header: &str) -> Option<String> {
+    let decoded = BASE64.decode(header.strip_prefix("Basic ")?).ok()?;
+    let credentials = String::from_utf8(decoded).ok()?;
+    let (_, password) = credentials.split_once(':')?;
+    Some(password.to_owned())
+}
+
+/// SQL LIKE, % matches any run of characters and _ any one
+fn like(pattern: &str, value: &str) -> bool {
+    fn matches(pattern: &[char], value: &[char]) -> bool {
+        match pattern.split_first() {
+            None => value.is_empty(),
+            Some(('%', rest)) => (0..=value.len()).any(|i| matches(rest, &value[i..])),
+            Some(('_', rest)) => !value.is_empty() && matches(rest, &value[1..]),
+            Some((c, rest)) => value.first() == Some(c) && matches(rest, &value[1..]),
+        }
+    }
+    let pattern: Vec<char> = pattern.chars().collect();
+    let value: Vec<char> = value.chars().collect();
+    matches(&pattern, &value)
+}
+
+/// GetTables' result schema from the Flight SQL spec
+fn tables_schema(include_schema: bool) -> SchemaRef {
+    let mut fields = vec![
+        Field::new("catalog_name", DataType::Utf8, true),
+        Field::new("db_schema_name", DataType::Utf8, true),
+        Field::new("table_name", DataType::Utf8, false),
+        Field::new("table_type", DataType::Utf8, false),
+    ];
+    if include_schema {
+        fields.push(Field::new("table_schema", DataType::Binary, false));
+    }
+    Arc::new(Schema::new(fields))
+}
+
+/// The dense union GetSqlInfo values are in, by type id
+fn sql_info_value_fields() -> Vec<Field> {
+    let int32_list = DataType::List(Arc::new(Field::new("item", DataType::Int32, true)));
+    let entries = Fields::from(vec![
+        Field::new("keys", DataType::Int32, false),
+        Field::new("values", int32_list, true),
+    ]);
+    vec![
+        Field::new("string_value", DataType::Utf8, false),
+        Field::new("bool_value", DataType::Boolean, false),
+        Field::new("bigint_value", DataType::Int64, false),
+        Field::new("int32_bitmask", DataType::Int32, false),
+        Field::new(
+            "string_list",
+            DataType::List(Arc::new(Field::new("item", DataType::Utf8, true))),
+            false,
+        ),
+        Field::new(
+            "int32_to_int32_list_map",
+            DataType::Map(
+                Arc::new(Field::new("entries", DataType::Struct(entries), false)),
+                false,
+            ),
+            false,
+        ),
+    ]
+}
+
+/// GetSqlInfo's result schema from the Flight SQL spec
+fn sql_info_schema() -> SchemaRef {
+    let values = UnionFields::new(0..6, sql_info_value_fields());
+    Arc::new(Schema::new(vec![
+        Field::new("info_name", DataType::UInt32, false),
+        Field::new("value", DataType::Union(values, UnionMode::Dense), false),
+    ]))
+}
+
+enum InfoValue {
+    Text(String),
+    Flag(bool),
+}
+
+/// What's known about the server, only the ids in requested (or all of them if it's empty)
+fn sql_info(requested: &[u32]) -> std::result::Result<RecordBatch, ArrowError> {
+    let infos = [
+        (
+            SqlInfo::FlightSqlServerName,
+            InfoValue::Text("katniss".into()),
+        ),
+        (
+            SqlInfo::FlightSqlServerVersion,
+            InfoValue::Text(env!("CARGO_PKG_VERSION").into()),
+        ),
+        (
+            SqlInfo::FlightSqlServerArrowVersion,
+            InfoValue::Text("43".into()),
+        ),
+        (SqlInfo::FlightSqlServerReadOnly, InfoValue::Flag(true)),
+    ];
+
+    let (mut names, mut type_ids, mut offsets) = (vec![], vec![], vec![]);
+    let (mut texts, mut flags) = (vec![], vec![]);
+    for (info, value) in infos {
+        let id = info as u32;
+        if !requested.is_empty() && !requested.contains(&id) {
+            continue;
+        }
+        names.push(id);
+        match value {
+            InfoValue::Text(text) => {
+                type_ids.push(0i8);
+                offsets.push(texts.len() as i32);
+                texts.push(text);
+            }
+            InfoValue::Flag(flag) => {
+                type_ids.push(1i8);
+                offsets.push(flags.len() as i32);
+                flags.push(flag);
+            }
+        }
+    }
+
+    let mut texts = Some(StringArray::from(texts));
+    let mut flags = Some(BooleanArray::from(flags));
+    let children = sql_info_value_fields()
+        .into_iter()
+        .enumerate()
+        .map(|(i, field)| {
+            let array: ArrayRef = match i {
+                0 => Arc::new(texts.take().expect("one string child")),
+                1 => Arc::new(flags.take().expect("one bool child")),
+                _ => new_empty_array(field.data_type()),
+            };
+            (field, array)
+        })
+        .collect();
+    let mut type_id_buffer = BufferBuilder::<i8>::new(type_ids.len());
+    type_id_buffer.append_slice(&type_ids);
+    let mut offset_buffer = BufferBuilder::<i32>::new(offsets.len());
+    offset_buffer.append_slice(&offsets);
+    let values = UnionArray::try_new(
+        &[0, 1, 2, 3, 4, 5],
+        type_id_buffer.finish(),
+        Some(offset_buffer.finish()),
+        children,
+    )?;
+
+    RecordBatch::try_new(
+        sql_info_schema(),
+        vec![Arc::new(UInt32Array::from(names)), Arc::new(values)],
+    )
+}
+
+fn one_batch(
+    batch: RecordBatch,
+) -> BoxStream<'static, std::result::Result<RecordBatch, FlightError>> {
+    stream::once(async { Ok(batch) }).boxed()
+}
+
+#[tonic::async_trait]
+impl FlightService for FlightSqlEndpoint {
+    type HandshakeStream = BoxStream<'static, std::result::Result<HandshakeResponse, Status>>;
+    type ListFlightsStream = BoxStream<'static, std::result::Result<FlightInfo, Status>>;
+    type DoGetStream = BoxStream<'static, std::result::Result<FlightData, Status>>;
+    type DoPutStream = BoxStream<'static, std::result::Result<PutResult, Status>>;
+    type DoActionStream = BoxStream<'static, std::result::Result<arrow_flight::Result, Status>>;
+    type ListActionsStream = BoxStream<'static, std::result::Result<ActionType, Status>>;
+    type DoExchangeStream = BoxStream<'static, std::result::Result<FlightData, Status>>;
+
+    /// Checks the credentials and answers with the bearer token to send from then on
+    async fn handshake(
+        &self,
+        request: Request<Streaming<HandshakeRequest>>,
+    ) -> std::result::Result<Response<Self::HandshakeStream>, Status> {
+        self.authorize(&request)?;
+        let responses: Self::HandshakeStream =
+            stream::once(async { Ok(HandshakeResponse::default()) }).boxed();
+        let mut response = Response::new(responses);
+        if let Some(token) = &self.token {
+            let bearer = format!("Bearer {token}")
+                .parse()
+                .map_err(|_| Status::internal("token isn't a valid header value"))?;
+            response.metadata_mut().insert("authorization", bearer);
+        }
+        Ok(response)
+    }
+
+    async fn list_flights(
+        &self,
+        _request: Request<Criteria>,
+    ) -> std::result::Result<Response<Self::ListFlightsStream>, Status> {
+        Err(Status::unimplemented("list_flights"))
+    }
+
+    /// Statement queries are planned here and kept for DoGet, their ticket is the plan's handle.
+    /// Metadata commands are their own ticket
+    async fn get_flight_info(
+        &self,
+        request: Request<FlightDescriptor>,
+    ) -> std::result::Result<Response<FlightInfo>, Status> {
+        self.authorize(&request)?;
+        let descriptor = request.into_inner();
+        let command = decode_any(&descriptor.cmd)?;
+
+        let (schema, ticket) = if let Some(query) = unpack::<CommandStatementQuery>(&command)? {
+            let df = self.plan(&query.query).await?;
+            let schema = Schema::from(df.schema());
+            let handle = self.pending.lock().unwrap().insert(df);
+            let ticket = TicketStatementQuery {
+                statement_handle: handle.into(),
+            };
+            (schema, ticket.as_any().encode_to_vec())
+        } else if let Some(tables) = unpack::<CommandGetTables>(&command)? {
+            let schema = tables_schema(tables.include_schema);
+            (Schema::clone(&schema), descriptor.cmd.to_vec())
+        } else if unpack::<CommandGetSqlInfo>(&command)?.is_some() {
+            (Schema::clone(&sql_info_schema()), descriptor.cmd.to_vec())
+        } else {
+            return Err(unsupported(&command));
+        };
+
+        let endpoint = FlightEndpoint::new().with_ticket(Ticket::new(ticket));
+        let info = FlightInfo::new()
+            .try_with_schema(&schema)
+            .map_err(internal)?
+            .with_endpoint(endpoint)
+            .with_descriptor(descriptor);
+        Ok(Response::new(info))
+    }
+
+    async fn get_schema(
+        &self,
+        request: Request<FlightDescriptor>,
+    ) -> std::result::Result<Response<SchemaResult>, Status> {
+        self.authorize(&request)?;
+        let command = decode_any(&request.into_inner().cmd)?;
+        let schema = if let Some(query) = unpack::<CommandStatementQuery>(&command)? {
+            Arc::new(Schema::from(self.plan(&query.query).await?.schema()))
+        } else if let Some(tables) = unpack::<CommandGetTables>(&command)? {
+            tables_schema(tables.include_schema)
+        } else if unpack::<CommandGetSqlInfo>(&command)?.is_some() {
+            sql_info_schema()
+        } else {
+            return Err(unsupported(&command));
+        };
+        let result = SchemaAsIpc::new(&schema, &IpcWriteOptions::default())
+            .try_into()
+            .map_err(internal)?;
+        Ok(Response::new(result))
+    }
+
+    /// Runs the statement planned by get_flight_info, each ticket can be fetched once
+    async fn do_get(
+        &self,
+        request: Request<Ticket>,
+    ) -> std::result::Result<Response<Self::DoGetStream>, Status> {
+        self.authorize(&request)?;
+        let command = decode_any(&request.into_inner().ticket)?;
+
+        let batches = if let Some(ticket) = unpack::<TicketStatementQuery>(&command)? {
+            let df = self
+                .pending
+                .lock()
+                .unwrap()
+                .take(&ticket.statement_handle)
+                .ok_or_else(|| Status::not_found("unknown or already fetched statement"))?;
+            df.execute_stream()
+                .await
+                .map_err(status)?
+                .map_err(|err| FlightError::ExternalError(Box::new(err)))
+                .boxed()
+        } else if let Some(tables) = unpack::<CommandGetTables>(&command)? {
+            one_batch(self.tables(&tables).await?)
+        } else if let Some(info) = unpack::<CommandGetSqlInfo>(&command)? {
+            one_batch(sql_info(&info.info).map_err(internal)?)
+        } else {
+            return Err(unsupported(&command));
+        };
+
+        let flight_data = FlightDataEncoderBuilder::new()
+            .build(batches)
+            .map_err(Status::from);
+        Ok(Response::new(Box::pin(flight_data)))
+    }
+
+    async fn do_put(
+        &self,
+        _request: Request<Streaming<FlightData>>,
+    ) -> std::result::Result<Response<Self::DoPutStream>, Status> {
+        Err(Status::unimplemented("do_put"))
+    }
+
+    async fn do_action(
+        &self,
+        _request: Request<Action>,
+    ) -> std::result::Result<Response<Self::DoActionStream>, Status> {
+        Err(Status::unimplemented("do_action"))
+    }
+
+    async fn list_actions(
+        &self,
+        _request: Request<Empty>,
+    ) -> std::result::Result<Response<Self::ListActionsStream>, Status> {
+        Err(Status::unimplemented("list_actions"))
+    }
+
+    async fn do_exchange(
+        &self,
+        _request: Request<Streaming<FlightData>>,
+    ) -> std::result::Result<Response<Self::DoExchangeStream>, Status> {
+        Err(Status::unimplemented("do_exchange"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::{cast::AsArray, types::Int64Type};
+    use arrow_flight::FlightClient;
+    use chrono::Utc;
+    use tokio::net::TcpListener;
+    use tonic::transport::Endpoint;
+
+    use katniss_test::{protos::spacecorp::Packet, test_util::ProtoBatch};
+
+    use super::*;
+    use crate::temporal_rotator::TemporalBuffer;
+
+    async fn client_for(endpoint: FlightSqlEndpoint) -> anyhow::Result<FlightClient> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let incoming = stream::unfold(listener, |listener| async move {
+            let conn = listener.accept().await.map(|(conn, _)| conn);
+            Some((conn, listener))
+        });
+        tokio::spawn(
+            Server::builder()
+                .add_service(endpoint.into_service())
+                .serve_with_incoming(incoming),
+        );
+
+        let channel = Endpoint::try_from(format!("http://{addr}"))?
+            .connect()
+            .await?;
+        Ok(FlightClient::new(channel))
+    }
+
+    fn packets_endpoint() -> anyhow::Result<FlightSqlEndpoint> {
+        let packets = (0..10)
+            .map(|i| Packet {
+                sender_uid: i,
+                ..Default::default()
+            })
+            .collect::<Vec<_>>();
+        let buffer = TemporalBuffer {
+            begin_at: Utc::now(),
+            end_at: Utc::now(),
+            batches: vec![ProtoBatch::SpaceCorp(&packets).arrow_batch()?],
+        };
+        let live = LiveBuffers::new(buffer.batches[0].schema(), 1);
+        live.sync(&buffer.batches, &[]);
+        Ok(FlightSqlEndpoint::for_live_buffers(&live, "packets")?)
+    }
+
+    fn query(sql: &str) -> FlightDescriptor {
+        let query = CommandStatementQuery {
+            query: sql.into(),
+            ..Default::default()
+        };
+        FlightDescriptor::new_cmd(query.as_any().encode_to_vec())
+    }
+
+    fn is_code(err: FlightError, code: tonic::Code) -> bool {
+        matches!(err, FlightError::Tonic(status) if status.code() == code)
+    }
+
+    #[tokio::test]
+    async fn it_answers_statement_queries() -> anyhow::Result<()> {
+        let mut client = client_for(packets_endpoint()?).await?;
+
+        let info = client
+            .get_flight_info(query("select count(*) from packets where sender_uid >= 4"))
+            .await?;
+        let ticket = info.endpoint[0].ticket.clone().unwrap();
+        let batches = client
+            .do_get(ticket.clone())
+            .await?
+            .try_collect::<Vec<_>>()
+            .await?;
+        assert_eq!(batches[0].column(0).as_primitive::<Int64Type>().value(0), 6);
+        // the plan was handed over with the first fetch
+        let err = client.do_get(ticket).await.unwrap_err();
+        assert!(is_code(err, tonic::Code::NotFound));
+
+        let err = client
+            .get_flight_info(query("select * from nowhere"))
+            .await
+            .unwrap_err();
+        assert!(is_code(err, tonic::Code::InvalidArgument));
+
+        // nothing but queries, external tables could read any file on the server
+        for sql in [
+            "create external table passwd stored as csv location '/etc/passwd'",
+            "drop table packets",
+            "set datafusion.execution.batch_size = 1",
+        ] {
+            let err = client.get_flight_info(query(sql)).await.unwrap_err();
+            assert!(is_code(err, tonic::Code::InvalidArgument), "{sql}");
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_answers_metadata_commands() -> anyhow::Result<()> {
+        let mut client = client_for(packets_endpoint()?).await?;
+
+        let info = CommandGetSqlInfo {
+            info: vec![SqlInfo::FlightSqlServerName as u32],
+        };
+        let info = client
+            .get_flight_info(FlightDescriptor::new_cmd(info.as_any().encode_to_vec()))
+            .await?;
+        let ticket = info.endpoint[0].ticket.clone().unwrap();
+        let batches = client.do_get(ticket).await?.try_collect::<Vec<_>>().await?;
+        assert_eq!(batches[0].num_rows(), 1);
+        let values = batches[0].column(1).as_any().downcast_ref::<UnionArray>();
+        let name = values.unwrap().value(0);
+        assert_eq!(name.as_string::<i32>().value(0), "katniss");
+
+        let tables = CommandGetTables {
+            table_name_filter_pattern: Some("pack%".into()),
+            include_schema: true,
+            ..Default::default()
+        };
+        let info = client
+            .get_flight_info(FlightDescriptor::new_cmd(tables.as_any().encode_to_vec()))
+            .await?;
+        let ticket = info.endpoint[0].ticket.clone().unwrap();
+        let batches = client.do_get(ticket).await?.try_collect::<Vec<_>>().await?;
+        assert_eq!(batches[0].num_rows(), 1);
+        assert_eq!(batches[0].column(2).as_string::<i32>().value(0), "packets");
+        assert_eq!(batches[0].column(3).as_string::<i32>().value(0), "TABLE");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_requires_the_token() -> anyhow::Result<()> {
+        let mut client = client_for(packets_endpoint()?.with_token("sesame")).await?;
+        let err = client.get_flight_info(query("select 1")).await.unwrap_err();
+        assert!(is_code(err, tonic::Code::Unauthenticated));
+
+        client.add_header("authorization", "Bearer sesame")?;
+        client.get_flight_info(query("select 1")).await?;
+
+        assert_eq!(
+            basic_password(&format!("Basic {}", BASE64.encode("adbc:sesame"))).as_deref(),
+            Some("sesame")
+        );
+        assert!(like("pack%", "packets") && like("p_ckets", "packets"));
+        assert!(!like("pack", "packets"));
+        Ok(())
+    }
+}
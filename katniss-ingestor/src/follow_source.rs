@@ -0,0 +1,126 @@
+use std::convert::Infallible;
+use std::future::Future;
+use std::io;
+use std::path::Path;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+
+use tokio::{
+    fs::File,
+    io::{AsyncRead, BufReader, ReadBuf},
+    sync::mpsc::UnboundedSender,
+    time::{sleep, Sleep},
+};
+
+use katniss_pb2arrow::exports::{prost_reflect::MessageDescriptor, DynamicMessage};
+
+use crate::{tcp_source::read_messages, Result};
+
+/// How long to wait at the end of the file before looking for more
+const POLL_EVERY: Duration = Duration::from_millis(200);
+
+/// Reads varint length delimited messages from the start of the file and keeps reading as
+/// they're appended, i.e. `tail -f` for a recorder's log. A message that's only partly
+/// written is waited on rather than treated as truncated
+pub async fn follow_file(
+    path: &Path,
+    descriptor: MessageDescriptor,
+    head: UnboundedSender<DynamicMessage>,
+) -> Result<Infallible> {
+    let file = File::open(path).await?;
+    read_messages(BufReader::new(Follow::new(file)), descriptor, head).await?;
+    unreachable!("followed files never end")
+}
+
+/// Never reaches the end of the inner reader, waits for it to grow instead
+struct Follow<R> {
+    inner: R,
+    waiting: Option<Pin<Box<Sleep>>>,
+}
+
+impl<R> Follow<R> {
+    fn new(inner: R) -> Self {
+        Self {
+            inner,
+            waiting: None,
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for Follow<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        loop {
+            if let Some(waiting) = &mut self.waiting {
+                ready!(waiting.as_mut().poll(cx));
+                self.waiting = None;
+            }
+            let filled = buf.filled().len();
+            ready!(Pin::new(&mut self.inner).poll_read(cx, buf))?;
+            if buf.filled().len() > filled || buf.remaining() == 0 {
+                return Poll::Ready(Ok(()));
+            }
+            self.waiting = Some(Box::pin(sleep(POLL_EVERY)));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use prost::Message;
+    use tokio::sync::mpsc::unbounded_channel;
+
+    use katniss_pb2arrow::ArrowBatchProps;
+    use katniss_test::{descriptor_pool, protos::spacecorp::Packet};
+
+    use super::*;
+
+    fn delimited(sender_uid: u64) -> anyhow::Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        Packet {
+            sender_uid,
+            ..Default::default()
+        }
+        .encode_length_delimited(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    #[tokio::test]
+    async fn it_picks_up_messages_appended_to_the_file() -> anyhow::Result<()> {
+        let props = ArrowBatchProps::try_new(
+            descriptor_pool()?,
+            "eto.pb2arrow.tests.spacecorp.Packet".to_owned(),
+        )?;
+        let mut file = tempfile::NamedTempFile::new()?;
+        file.write_all(&delimited(1)?)?;
+        file.flush()?;
+
+        let (head, mut rx) = unbounded_channel();
+        let path = file.path().to_owned();
+        let follower =
+            tokio::spawn(async move { follow_file(&path, props.descriptor.clone(), head).await });
+        let first = rx.recv().await.unwrap();
+        assert_eq!(first.transcode_to::<Packet>()?.sender_uid, 1);
+
+        // a message written in two halves is only sent once it's whole
+        let second = delimited(2)?;
+        let (start, end) = second.split_at(second.len() / 2);
+        file.write_all(start)?;
+        file.flush()?;
+        sleep(POLL_EVERY * 2).await;
+        assert!(rx.try_recv().is_err());
+        file.write_all(end)?;
+        file.flush()?;
+
+        let second = rx.recv().await.unwrap();
+        assert_eq!(second.transcode_to::<Packet>()?.sender_uid, 2);
+        follower.abort();
+        Ok(())
+    }
+}
@@ -0,0 +1,168 @@
+use std::io::{Read, Seek, SeekFrom};
+
+use arrow_ipc::reader::{FileReader, StreamReader};
+
+use katniss_pb2arrow::{
+    exports::{prost_reflect::MessageDescriptor, DynamicMessage, RecordBatchReader},
+    ArrowToProtoConverter,
+};
+
+use crate::Result;
+
+/// What every IPC file starts with, streams start with a schema message instead
+const FILE_MAGIC: &[u8; 6] = b"ARROW1";
+
+/// Reads Arrow IPC data from other tools into protobuf messages of the descriptor's type.
+/// The schema is checked up front so every column that doesn't fit its field is reported at
+/// once rather than failing on the first batch
+pub struct IpcMessageReader {
+    converter: ArrowToProtoConverter,
+    batches: Box<dyn RecordBatchReader>,
+    pending: std::vec::IntoIter<DynamicMessage>,
+}
+
+impl IpcMessageReader {
+    /// An IPC file (aka Feather v2) or stream, whichever the input starts with
+    pub fn try_new<R: Read + Seek + 'static>(
+        mut reader: R,
+        descriptor: MessageDescriptor,
+    ) -> Result<Self> {
+        let mut magic = [0; 6];
+        let is_file = match reader.read_exact(&mut magic) {
+            Ok(()) => &magic == FILE_MAGIC,
+            Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => false,
+            Err(err) => return Err(err.into()),
+        };
+        reader.seek(SeekFrom::Start(0))?;
+
+        if is_file {
+            Self::from_batches(FileReader::try_new(reader, None)?, descriptor)
+        } else {
+            Self::from_batches(StreamReader::try_new(reader, None)?, descriptor)
+        }
+    }
+
+    /// An IPC stream that can't seek, i.e. stdin or a socket
+    pub fn from_stream<R: Read + 'static>(
+        reader: R,
+        descriptor: MessageDescriptor,
+    ) -> Result<Self> {
+        Self::from_batches(StreamReader::try_new(reader, None)?, descriptor)
+    }
+
+    fn from_batches<B: RecordBatchReader + 'static>(
+        batches: B,
+        descriptor: MessageDescriptor,
+    ) -> Result<Self> {
+        let converter = ArrowToProtoConverter::new(descriptor);
+        converter.check_schema(&batches.schema())?;
+        Ok(Self {
+            converter,
+            batches: Box::new(batches),
+            pending: Vec::new().into_iter(),
+        })
+    }
+
+    pub fn descriptor(&self) -> &MessageDescriptor {
+        self.converter.descriptor()
+    }
+}
+
+impl Iterator for IpcMessageReader {
+    type Item = Result<DynamicMessage>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(msg) = self.pending.next() {
+                return Some(Ok(msg));
+            }
+
+            let batch = match self.batches.next()? {
+                Ok(batch) => batch,
+                Err(err) => return Some(Err(err.into())),
+            };
+            match self.converter.messages(&batch) {
+                Ok(messages) => self.pending = messages.into_iter(),
+                Err(err) => return Some(Err(err.into())),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use std::sync::Arc;
+
+    use arrow_array::{RecordBatch, StringArray, UInt64Array};
+    use arrow_ipc::writer::StreamWriter;
+    use arrow_schema::{DataType, Field, Schema};
+
+    use katniss_pb2arrow::{FieldMismatch, KatnissArrowError};
+    use katniss_test::{descriptor_pool, protos::spacecorp::Packet, test_util::ProtoBatch};
+
+    use super::*;
+    use crate::{
+        errors::KatinssIngestorError,
+        sinks::{BufferEncoder, IpcFileEncoder},
+    };
+
+    fn packet_descriptor() -> anyhow::Result<MessageDescriptor> {
+        Ok(descriptor_pool()?
+            .get_message_by_name("eto.pb2arrow.tests.spacecorp.Packet")
+            .unwrap())
+    }
+
+    #[test]
+    fn ipc_files_and_streams_convert_to_messages() -> anyhow::Result<()> {
+        let packets: Vec<_> = (0..4)
+            .map(|sender_uid| Packet {
+                sender_uid,
+                ..Default::default()
+            })
+            .collect();
+        let batch = ProtoBatch::SpaceCorp(&packets).arrow_batch()?;
+        let file = IpcFileEncoder.encode(&batch.schema(), &[batch.clone()])?;
+        let mut stream = StreamWriter::try_new(Vec::new(), &batch.schema())?;
+        stream.write(&batch)?;
+        let stream = stream.into_inner()?;
+
+        for bytes in [file, stream] {
+            let reader = IpcMessageReader::try_new(Cursor::new(bytes), packet_descriptor()?)?;
+            let read = reader
+                .map(|msg| Ok(msg?.transcode_to::<Packet>()?))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            assert_eq!(read, packets);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn mismatched_columns_are_reported_before_reading() -> anyhow::Result<()> {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("sender_uid", DataType::UInt64, true),
+            Field::new("callsign", DataType::Utf8, true),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(UInt64Array::from(vec![1])),
+                Arc::new(StringArray::from(vec!["rocinante"])),
+            ],
+        )?;
+        let mut stream = StreamWriter::try_new(Vec::new(), &schema)?;
+        stream.write(&batch)?;
+        let bytes = stream.into_inner()?;
+
+        let Err(err) = IpcMessageReader::from_stream(Cursor::new(bytes), packet_descriptor()?)
+        else {
+            panic!("callsign isn't a Packet field");
+        };
+        assert!(matches!(
+            err,
+            KatinssIngestorError::Pb2ArrowArror(KatnissArrowError::SchemaMismatch(mismatches))
+                if mismatches == [FieldMismatch::UnknownField("callsign".to_owned())]
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,123 @@
+use std::collections::{BTreeSet, HashMap};
+use std::convert::Infallible;
+
+use rdkafka::{
+    consumer::{CommitMode, Consumer, StreamConsumer},
+    ClientConfig, Message, Offset, TopicPartitionList,
+};
+use tokio::{
+    select,
+    sync::mpsc::{unbounded_channel, UnboundedSender},
+};
+
+use katniss_pb2arrow::exports::{prost_reflect::MessageDescriptor, DynamicMessage};
+
+use crate::{ack::Ack, errors::KatinssIngestorError, status::PipelineHandle, Result};
+
+/// A consumer for the group that starts from the earliest offset the first time and leaves
+/// committing to consume_kafka
+pub fn kafka_consumer(brokers: &str, group_id: &str, topic: &str) -> Result<StreamConsumer> {
+    let consumer: StreamConsumer = ClientConfig::new()
+        .set("bootstrap.servers", brokers)
+        .set("group.id", group_id)
+        .set("enable.auto.commit", "false")
+        .set("auto.offset.reset", "earliest")
+        .create()?;
+    consumer.subscribe(&[topic])?;
+    Ok(consumer)
+}
+
+/// Decodes every record's payload as the descriptor's message and sends it down an acked
+/// pipeline (see PipelineBuilder::build_acked). A partition's offset is only committed once
+/// every message before it has been acked, so a restart re-reads anything not yet written.
+/// Records that don't decode are skipped and committed past, counted in the handle's
+/// PipelineStatus::dropped_malformed
+pub async fn consume_kafka(
+    consumer: StreamConsumer,
+    descriptor: MessageDescriptor,
+    head: UnboundedSender<(DynamicMessage, Ack)>,
+    handle: PipelineHandle,
+) -> Result<Infallible> {
+    let (acked, mut rx_acked) = unbounded_channel();
+    let mut offsets: HashMap<(String, i32), PartitionOffsets> = HashMap::new();
+    loop {
+        select! {
+            received = consumer.recv() => {
+                let received = received?;
+                let key = (received.topic().to_owned(), received.partition());
+                let offset = received.offset();
+                let msg = DynamicMessage::decode(
+                    descriptor.clone(),
+                    received.payload().unwrap_or_default(),
+                );
+                offsets.entry(key.clone()).or_default().received(offset);
+                let Ok(msg) = msg else {
+                    // retrying won't fix it, so it's acked straight away like a filtered message
+                    handle.update(|s| s.dropped_malformed += 1);
+                    let _ = acked.send((key, offset));
+                    continue;
+                };
+
+                let acked = acked.clone();
+                let ack = Ack::new(move || {
+                    // the consumer's gone if this fails, nothing left to commit to
+                    let _ = acked.send((key, offset));
+                });
+                head.send((msg, ack))
+                    .map_err(|_| KatinssIngestorError::PipelineClosed)?;
+            }
+            Some((key, offset)) = rx_acked.recv() => {
+                let Some(next) = offsets.get_mut(&key).and_then(|o| o.acked(offset)) else {
+                    continue;
+                };
+                let mut commit = TopicPartitionList::new();
+                commit.add_partition_offset(&key.0, key.1, Offset::Offset(next))?;
+                consumer.commit(&commit, CommitMode::Async)?;
+            }
+        }
+    }
+}
+
+/// Offsets of a partition's messages that are still in the pipeline
+#[derive(Debug, Default)]
+struct PartitionOffsets {
+    in_flight: BTreeSet<i64>,
+    /// One past the newest offset received
+    next: i64,
+    committed: Option<i64>,
+}
+
+impl PartitionOffsets {
+    fn received(&mut self, offset: i64) {
+        self.in_flight.insert(offset);
+        self.next = self.next.max(offset + 1);
+    }
+
+    /// The offset to commit if this ack moves it forward, i.e. the oldest message still in flight
+    fn acked(&mut self, offset: i64) -> Option<i64> {
+        self.in_flight.remove(&offset);
+        let committable = self.in_flight.first().copied().unwrap_or(self.next);
+        if self.committed.map_or(false, |c| c >= committable) {
+            return None;
+        }
+        self.committed = Some(committable);
+        Some(committable)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn offsets_are_committed_up_to_the_oldest_unacked_message() {
+        let mut offsets = PartitionOffsets::default();
+        (10..14).for_each(|offset| offsets.received(offset));
+
+        // filtered messages are acked straight away, that mustn't skip over 10 and 11
+        assert_eq!(offsets.acked(12), Some(10));
+        assert_eq!(offsets.acked(11), None);
+        assert_eq!(offsets.acked(10), Some(13));
+        assert_eq!(offsets.acked(13), Some(14));
+    }
+}
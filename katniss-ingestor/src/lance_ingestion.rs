@@ -1,24 +1,28 @@
-use std::{convert::Infallible, sync::Arc};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
 
 use arrow_array::RecordBatchIterator;
 use arrow_schema::Schema;
-use chrono::Utc;
+use async_trait::async_trait;
 use lance::dataset::{Dataset, WriteMode, WriteParams};
-use tokio::{
-    sync::mpsc::{unbounded_channel, UnboundedSender},
-    task::{block_in_place, JoinSet},
-};
+use tokio::sync::mpsc::UnboundedSender;
 
-use katniss_pb2arrow::exports::prost_reflect::DynamicMessage;
+use katniss_pb2arrow::exports::{
+    prost_reflect::{DynamicMessage, MessageDescriptor},
+    RecordBatch,
+};
 use katniss_pb2arrow::ArrowBatchProps;
 
 use crate::errors::KatinssIngestorError;
-use crate::temporal_rotator::{TemporalBuffer, TemporalRotator};
+use crate::lance_retention::LanceRetention;
+use crate::lance_time_travel::{clear_ingest_windows_before, IngestWindow};
+use crate::lance_vectors::{VectorColumn, VectorIndex};
+use crate::oneof_fanout::oneof_fanout_pipeline;
+use crate::pipeline::{ingestion_pipeline, LoopJoinSet, PipelineBuilder};
+use crate::sinks::{descriptor_from_schema, descriptor_metadata, BufferSink, DESCRIPTOR_SET_KEY};
+use crate::temporal_rotator::TemporalBuffer;
 use crate::Result;
 
-/// Set Of Tokio Tasks that never return unless they error
-pub type LoopJoinSet = JoinSet<Result<Infallible>>; // (Infallible used in place of !)
-
 /// Start a pipeline that ingests dynamic messages to Lance
 /// Returns:
 /// * a channel that functions as the head of the pipeline
@@ -30,43 +34,28 @@ pub async fn lance_ingestion_pipeline(
     batch_period: std::time::Duration,
     storage_uri: String, // object_store: Box<dyn ObjectStore>, // this should probably be some sort of lance or gcp props or something
 ) -> Result<(UnboundedSender<DynamicMessage>, LoopJoinSet)> {
-    let now = Utc::now();
-    let mut rotator = TemporalRotator::new(&props, now, batch_period)?;
-
-    let (head, mut rx_msg) = unbounded_channel();
-    let (tx_buffer, mut rx_buffer) = unbounded_channel();
-    let ingestor = LanceIngestor::new(storage_uri, props.schema)?;
-
-    let mut tasks = JoinSet::new();
-    tasks.spawn(async move {
-        loop {
-            let msg = rx_msg
-                .recv()
-                .await
-                .ok_or_else(|| KatinssIngestorError::PipelineClosed)?;
-
-            if let Some(last_batch) =
-                block_in_place(|| rotator.ingest_potentially_blocking(msg, Utc::now()))?
-            {
-                tx_buffer
-                    .send(last_batch)
-                    .map_err(|_| KatinssIngestorError::PipelineClosed)?;
-            }
-        }
-    });
-
-    tasks.spawn(async move {
-        loop {
-            let buf = rx_buffer
-                .recv()
-                .await
-                .ok_or_else(|| KatinssIngestorError::PipelineClosed)?;
-
-            ingestor.write(buf).await?;
-        }
-    });
+    let ingestor =
+        LanceIngestor::new(storage_uri, props.schema.clone())?.with_descriptor(&props.descriptor);
+    ingestion_pipeline(props, batch_period, ingestor).await
+}
 
-    Ok((head, tasks))
+/// Like lance_ingestion_pipeline but with a dataset per variant of an envelope's oneof
+/// at {storage_uri}/{variant}.lance, see oneof_fanout_pipeline
+pub async fn lance_oneof_fanout_pipeline(
+    props: ArrowBatchProps,
+    oneof: &str,
+    batch_period: std::time::Duration,
+    storage_uri: String,
+) -> Result<(UnboundedSender<DynamicMessage>, LoopJoinSet)> {
+    let base = storage_uri.trim_end_matches('/');
+    oneof_fanout_pipeline(&props, oneof, |variant, variant_props| {
+        let ingestor = LanceIngestor::new(
+            format!("{base}/{variant}.lance"),
+            variant_props.schema.clone(),
+        )?
+        .with_descriptor(&variant_props.descriptor);
+        Ok(PipelineBuilder::new(variant_props, batch_period, ingestor))
+    })
 }
 
 pub struct LanceIngestor {
@@ -74,9 +63,31 @@ pub struct LanceIngestor {
     storage_uri: String,
     write_params: WriteParams,
     schema: Arc<Schema>,
+    retention: Option<LanceRetention>,
+    vector_columns: Vec<VectorColumn>,
+    vector_index: Option<VectorIndex>,
+    buffers_since_index: usize,
+}
+
+/// Held while committing to a dataset, one per storage uri. Lance at this revision doesn't
+/// tell an append that another writer committed the same version first, so writers in this
+/// process take turns and each append goes on top of the latest version.
+/// Writers in other processes aren't covered
+pub(crate) fn commit_lock(storage_uri: &str) -> Arc<tokio::sync::Mutex<()>> {
+    static LOCKS: OnceLock<Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>> = OnceLock::new();
+    let mut locks = LOCKS
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    locks
+        .entry(storage_uri.trim_end_matches('/').to_string())
+        .or_default()
+        .clone()
 }
 
 impl LanceIngestor {
+    /// Appends to the dataset at storage_uri in row groups of 10240,
+    /// see the with_ methods to tune file layout
     pub fn new<P: AsRef<str>>(storage_uri: P, schema: Arc<Schema>) -> Result<Self> {
         let filename = storage_uri.as_ref().to_string();
         let write_params = WriteParams {
@@ -89,21 +100,173 @@ impl LanceIngestor {
             storage_uri: filename,
             write_params,
             schema,
+            retention: None,
+            vector_columns: Vec::new(),
+            vector_index: None,
+            buffers_since_index: 0,
         })
     }
 
+    pub fn with_max_rows_per_group(mut self, max_rows_per_group: usize) -> Self {
+        self.write_params.max_rows_per_group = max_rows_per_group;
+        self
+    }
+
+    pub fn with_max_rows_per_file(mut self, max_rows_per_file: usize) -> Self {
+        self.write_params.max_rows_per_file = max_rows_per_file;
+        self
+    }
+
+    /// Append (the default), Create or Overwrite.
+    /// As a BufferSink only the first buffer is written with Create or Overwrite, later ones
+    /// append to it, so a backfill can start a dataset over without each window replacing the last
+    pub fn with_mode(mut self, mode: WriteMode) -> Self {
+        self.write_params.mode = mode;
+        self
+    }
+
+    /// Replace every write param, i.e. to set object store or commit options
+    pub fn with_write_params(mut self, write_params: WriteParams) -> Self {
+        self.write_params = write_params;
+        self
+    }
+
+    /// Put the message's descriptor set and name in the dataset's schema metadata, so readers
+    /// can get the proto schema back from the dataset alone with lance_descriptor
+    pub fn with_descriptor(mut self, descriptor: &MessageDescriptor) -> Self {
+        let mut metadata = self.schema.metadata().clone();
+        metadata.extend(descriptor_metadata(descriptor));
+        self.schema = Arc::new(Schema::clone(&self.schema).with_metadata(metadata));
+        self
+    }
+
+    /// Store a repeated float field (i.e. an embedding) as a FixedSizeList<Float32> of
+    /// `dimension` values so lance can search and index it, every row needs that many values
+    pub fn with_vector_column(mut self, column: &str, dimension: i32) -> Result<Self> {
+        let vector_column = VectorColumn::new(column, dimension);
+        self.schema = vector_column.apply_to_schema(&self.schema)?;
+        self.vector_columns.push(vector_column);
+        Ok(self)
+    }
+
+    /// Keep a vector index up to date as a BufferSink writes buffers
+    pub fn with_vector_index(mut self, index: VectorIndex) -> Self {
+        self.vector_index = Some(index);
+        self
+    }
+
+    /// Apply the retention after every buffer written as a BufferSink
+    pub fn with_retention(mut self, retention: LanceRetention) -> Self {
+        self.retention = Some(retention);
+        self
+    }
+
+    pub fn write_params(&self) -> &WriteParams {
+        &self.write_params
+    }
+
+    /// Writes the buffer as a new version and records its IngestWindow, see scan_time_range
     pub async fn write(&self, buffer: TemporalBuffer) -> Result<Dataset> {
-        let reader =
-            RecordBatchIterator::new(buffer.batches.into_iter().map(Ok), self.schema.clone());
+        let rows = buffer
+            .batches
+            .iter()
+            .map(RecordBatch::num_rows)
+            .sum::<usize>();
+        let (begin_at, end_at) = (buffer.begin_at, buffer.end_at);
+        // rows_before has to be counted before another writer appends
+        let lock = commit_lock(&self.storage_uri);
+        let _committing = lock.lock().await;
+        let dataset = self.commit(buffer.batches).await?;
+
+        let version = dataset.version().version;
+        IngestWindow {
+            version,
+            begin_at,
+            end_at,
+            rows_before: dataset.count_rows().await?.saturating_sub(rows),
+            rows,
+        }
+        .record(&self.storage_uri)
+        .await?;
+        if matches!(self.write_params.mode, WriteMode::Overwrite) {
+            clear_ingest_windows_before(&self.storage_uri, version).await?;
+        }
+        Ok(dataset)
+    }
 
-        let dataset = Dataset::write(
+    async fn commit(&self, buffer: Vec<RecordBatch>) -> Result<Dataset> {
+        let mut batches = Vec::with_capacity(buffer.len());
+        for batch in buffer {
+            let batch = self
+                .vector_columns
+                .iter()
+                .try_fold(batch, |batch, vectors| vectors.apply_to_batch(&batch))?;
+            // batches don't carry the schema's metadata
+            batches.push(batch.with_schema(self.schema.clone())?);
+        }
+        let reader = RecordBatchIterator::new(batches.into_iter().map(Ok), self.schema.clone());
+        Ok(Dataset::write(
             reader,
             self.storage_uri.as_ref(),
             Some(self.write_params.clone()),
         )
-        .await?;
+        .await?)
+    }
+}
 
-        Ok(dataset)
+/// The message descriptor embedded by LanceIngestor::with_descriptor, None for datasets without one
+pub fn lance_descriptor(dataset: &Dataset) -> Result<Option<MessageDescriptor>> {
+    descriptor_from_schema(&Schema::from(dataset.schema()))
+}
+
+#[async_trait]
+impl BufferSink for LanceIngestor {
+    async fn write_buffer(&mut self, buffer: TemporalBuffer) -> Result<()> {
+        let dataset = self.write(buffer).await?;
+        self.write_params.mode = WriteMode::Append;
+
+        if let Some(index) = &self.vector_index {
+            self.buffers_since_index += 1;
+            if self.buffers_since_index >= index.rebuild_every() && index.build(&dataset).await? {
+                self.buffers_since_index = 0;
+            }
+        }
+        if let Some(retention) = &self.retention {
+            let report = retention
+                .prune_with_params(&self.storage_uri, &self.write_params)
+                .await?;
+            // the rewrite doesn't carry the index over
+            if let (Some(index), true) = (&self.vector_index, report.rows_deleted > 0) {
+                index
+                    .build(&Dataset::open(&self.storage_uri).await?)
+                    .await?;
+                self.buffers_since_index = 0;
+            }
+        }
+        Ok(())
+    }
+
+    /// Appends need the dataset's schema, so only changes that leave the columns as they are
+    /// (i.e. a new enum value) get through, the embedded descriptor is updated
+    async fn reload_schema(&mut self, props: &ArrowBatchProps) -> Result<()> {
+        let schema = self
+            .vector_columns
+            .iter()
+            .try_fold(props.schema.clone(), |schema, vectors| {
+                vectors.apply_to_schema(&schema)
+            })?;
+        if schema.fields() != self.schema.fields() {
+            return Err(KatinssIngestorError::SchemaChangeUnsupported(
+                self.storage_uri.clone(),
+            ));
+        }
+
+        if self.schema.metadata().contains_key(DESCRIPTOR_SET_KEY) {
+            let mut metadata = self.schema.metadata().clone();
+            metadata.extend(descriptor_metadata(&props.descriptor));
+            self.schema = Arc::new(Schema::clone(&self.schema).with_metadata(metadata));
+        }
+        Ok(())
     }
 }
 
@@ -113,7 +276,7 @@ mod tests {
     use std::sync::atomic::AtomicI64;
     use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-    use chrono::DateTime;
+    use chrono::{DateTime, Utc};
     use futures::stream::StreamExt;
     use tokio::{select, spawn, task::yield_now};
 
@@ -164,6 +327,7 @@ mod tests {
 
         let dataset = ingestor.write(buffer).await?;
         assert_eq!(dataset.count_rows().await?, 3);
+        assert!(lance_descriptor(&dataset)?.is_none());
 
         let protos = &[packet_with_nested_inner_enum_field()];
         let buffer = temporal_buffer(ProtoBatch::SpaceCorp(protos), Utc::now(), Utc::now())?;
@@ -179,6 +343,81 @@ mod tests {
         let dataset = ingestor.write(buffer).await?;
         assert_eq!(dataset.count_rows().await?, 5);
 
+        let overwriting = ingestor.with_mode(WriteMode::Overwrite);
+        let buffer = temporal_buffer(ProtoBatch::SpaceCorp(protos), Utc::now(), Utc::now())?;
+        let dataset = overwriting.write(buffer).await?;
+        assert_eq!(dataset.count_rows().await?, 1);
+
+        // as a sink only the first buffer overwrites
+        let mut sink = overwriting;
+        for _ in 0..2 {
+            let buffer = temporal_buffer(ProtoBatch::SpaceCorp(protos), Utc::now(), Utc::now())?;
+            sink.write_buffer(buffer).await?;
+        }
+        let dataset = Dataset::open(&format!("file://{}", filename.to_str().unwrap())).await?;
+        assert_eq!(dataset.count_rows().await?, 2);
+
+        let mut sink = sink.with_retention(LanceRetention::new().with_max_versions(2));
+        let buffer = temporal_buffer(ProtoBatch::SpaceCorp(protos), Utc::now(), Utc::now())?;
+        sink.write_buffer(buffer).await?;
+        let manifests = std::fs::read_dir(filename.join("_versions"))?.count();
+        assert_eq!(manifests, 2);
+        let dataset = Dataset::open(&format!("file://{}", filename.to_str().unwrap())).await?;
+        assert_eq!(dataset.count_rows().await?, 3);
+
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn concurrent_appends_all_land() -> anyhow::Result<()> {
+        const ROUNDS: usize = 10;
+        let mut filename = std::env::current_dir()?;
+        filename.push(format!(
+            "test_concurrent_appends_{}.lance",
+            timestamp_string(Utc::now())
+        ));
+        let uri = format!("file://{}", filename.to_str().unwrap());
+
+        let protos = &[Packet::default()];
+        let schema = ProtoBatch::SpaceCorp(protos).arrow_batch()?.schema();
+        LanceIngestor::new(&uri, schema.clone())?
+            .with_mode(WriteMode::Create)
+            .write(temporal_buffer(
+                ProtoBatch::SpaceCorp(protos),
+                Utc::now(),
+                Utc::now(),
+            )?)
+            .await?;
+
+        // both appenders start every round on the same version
+        let barrier = Arc::new(tokio::sync::Barrier::new(2));
+        let appenders: Vec<_> = (0..2)
+            .map(|_| {
+                let ingestor = LanceIngestor::new(&uri, schema.clone()).unwrap();
+                let barrier = barrier.clone();
+                spawn(async move {
+                    for _ in 0..ROUNDS {
+                        let protos = &[Packet::default()];
+                        let buffer =
+                            temporal_buffer(ProtoBatch::SpaceCorp(protos), Utc::now(), Utc::now())?;
+                        barrier.wait().await;
+                        ingestor.write(buffer).await?;
+                    }
+                    anyhow::Ok(())
+                })
+            })
+            .collect();
+        for task in appenders {
+            // an appender that failed would leave the other waiting at the barrier
+            tokio::time::timeout(Duration::from_secs(60), task).await???;
+        }
+
+        let dataset = Dataset::open(&uri).await?;
+        assert_eq!(dataset.count_rows().await?, 1 + 2 * ROUNDS);
+        let mut windows = crate::lance_time_travel::ingest_windows(&uri).await?;
+        windows.sort_by_key(|w| w.version);
+        assert_eq!(windows.len(), 1 + 2 * ROUNDS);
+        assert!(windows.iter().enumerate().all(|(i, w)| w.rows_before == i));
         Ok(())
     }
 
@@ -203,6 +442,7 @@ mod tests {
 
         let arrow_props = encoding_props("eto.pb2arrow.tests.spacecorp.JumpDriveStatus");
         let descriptor = arrow_props.descriptor.clone();
+        let embedded = arrow_props.descriptor.clone();
         let now = Utc::now();
         let timestamp = timestamp_string(now);
 
@@ -244,6 +484,7 @@ mod tests {
         block_until_file_exists(manifest_path.to_str().unwrap(), Duration::from_millis(1000));
 
         let dataset = Dataset::open(&storage_uri).await.unwrap();
+        assert_eq!(lance_descriptor(&dataset)?, Some(embedded));
         let scanner = dataset.scan();
         let batches = scanner
             .try_into_stream()
@@ -0,0 +1,196 @@
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::{DateTime, TimeZone, Utc};
+use futures::TryStreamExt;
+use object_store::{path::Path, ObjectMeta};
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+use crate::{
+    lance_ingestion::LanceIngestor,
+    sinks::{descriptor_from_metadata, manifest, store_from_uri, BufferSink},
+    temporal_rotator::TemporalBuffer,
+    Result, WriteMode,
+};
+
+/// Counts from a finished migration
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MigrationReport {
+    pub datasets: usize,
+    pub files: usize,
+    pub rows: usize,
+}
+
+/// Converts a directory of parquet files written by katniss into lance datasets, i.e. when
+/// switching storage backends. Every directory holding parquet files becomes a dataset at the
+/// same relative path under lance_uri, so `packets/2023-03-08/20/*.parquet` ends up in
+/// `{lance_uri}/2023-03-08/20`. Files are appended one version each in name order, with the
+/// ingest window from the partition's manifest (or the file's modification time without one)
+/// so scan_time_range works on the result. Embedded descriptors are carried over.
+/// Datasets are created, so a migration fails rather than appending to an existing one
+pub async fn migrate_parquet_to_lance(
+    parquet_uri: &str,
+    lance_uri: &str,
+) -> Result<MigrationReport> {
+    let (store, prefix) = store_from_uri(parquet_uri)?;
+    let files: Vec<ObjectMeta> = store.list(Some(&prefix)).await?.try_collect().await?;
+
+    let mut partitions: BTreeMap<Path, Vec<ObjectMeta>> = BTreeMap::new();
+    for file in files {
+        if file.location.extension() != Some("parquet") {
+            continue;
+        }
+        let mut parts: Vec<_> = file.location.parts().collect();
+        parts.pop();
+        partitions
+            .entry(Path::from_iter(parts))
+            .or_default()
+            .push(file);
+    }
+
+    let mut report = MigrationReport::default();
+    for (partition, mut files) in partitions {
+        files.sort_by(|a, b| a.location.cmp(&b.location));
+        let windows: HashMap<String, (i64, i64)> =
+            manifest::read_entries(store.as_ref(), &partition)
+                .await?
+                .unwrap_or_default()
+                .into_iter()
+                .map(|entry| (entry.filename, (entry.begin_millis, entry.end_millis)))
+                .collect();
+
+        let relative: Vec<_> = partition
+            .prefix_match(&prefix)
+            .into_iter()
+            .flatten()
+            .map(|part| part.as_ref().to_owned())
+            .collect();
+        let dataset_uri = if relative.is_empty() {
+            lance_uri.to_owned()
+        } else {
+            format!("{}/{}", lance_uri.trim_end_matches('/'), relative.join("/"))
+        };
+
+        let mut ingestor: Option<LanceIngestor> = None;
+        for file in &files {
+            let bytes = store.get(&file.location).await?.bytes().await?;
+            let builder = ParquetRecordBatchReaderBuilder::try_new(bytes)?;
+
+            if ingestor.is_none() {
+                let key_value_metadata = builder.metadata().file_metadata().key_value_metadata();
+                let descriptor = descriptor_from_metadata(key_value_metadata)?;
+                let created = LanceIngestor::new(&dataset_uri, builder.schema().clone())?
+                    .with_mode(WriteMode::Create);
+                ingestor = Some(match descriptor {
+                    Some(descriptor) => created.with_descriptor(&descriptor),
+                    None => created,
+                });
+            }
+
+            let batches = builder
+                .build()?
+                .collect::<std::result::Result<Vec<_>, _>>()?;
+            let rows = batches.iter().map(|b| b.num_rows()).sum::<usize>();
+            let (begin_at, end_at) = file
+                .location
+                .filename()
+                .and_then(|name| windows.get(name))
+                .and_then(|&(begin, end)| Some((from_millis(begin)?, from_millis(end)?)))
+                .unwrap_or((file.last_modified, file.last_modified));
+
+            if let Some(ingestor) = ingestor.as_mut() {
+                ingestor
+                    .write_buffer(TemporalBuffer {
+                        begin_at,
+                        end_at,
+                        batches,
+                    })
+                    .await?;
+            }
+            report.files += 1;
+            report.rows += rows;
+        }
+        report.datasets += 1;
+    }
+    Ok(report)
+}
+
+fn from_millis(millis: i64) -> Option<DateTime<Utc>> {
+    Utc.timestamp_millis_opt(millis).single()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use lance::dataset::Dataset;
+
+    use katniss_pb2arrow::ArrowBatchProps;
+    use katniss_test::{descriptor_pool, protos::spacecorp::Packet, test_util::ProtoBatch};
+
+    use super::*;
+    use crate::{
+        ingest_windows, lance_descriptor,
+        sinks::{BufferEncoder, ParquetEncoder},
+    };
+
+    #[tokio::test]
+    async fn partitions_become_datasets() -> anyhow::Result<()> {
+        let props = ArrowBatchProps::try_new(
+            descriptor_pool()?,
+            "eto.pb2arrow.tests.spacecorp.Packet".to_owned(),
+        )?;
+        let encoder = ParquetEncoder::new().with_descriptor(&props.descriptor);
+
+        let mut path = std::env::current_dir()?;
+        let now = Utc::now().timestamp_micros();
+        path.push(format!("test_lance_migration_{now}"));
+        let parquet_uri = format!("file://{}/parquet", path.to_str().unwrap());
+        let lance_uri = format!("file://{}/lance", path.to_str().unwrap());
+
+        let (store, prefix) = store_from_uri(&parquet_uri)?;
+        let mut manifests = manifest::Manifests::default();
+        let start = Utc.timestamp_millis_opt(0).unwrap();
+        for (hour, file, rows) in [("00", "0", 1), ("00", "1", 2), ("01", "0", 3)] {
+            let packets = vec![Packet::default(); rows];
+            let batch = ProtoBatch::SpaceCorp(&packets).arrow_batch()?;
+            let bytes = encoder.encode(&batch.schema(), &[batch])?;
+            let location = prefix.child(hour).child(format!("{file}.parquet"));
+            store.put(&location, bytes.into()).await?;
+
+            let begin_at = start + Duration::minutes(rows as i64);
+            manifests
+                .record(store.as_ref(), &location, rows, begin_at, begin_at)
+                .await?;
+        }
+        manifests.finish(store.as_ref()).await?;
+
+        let report = migrate_parquet_to_lance(&parquet_uri, &lance_uri).await?;
+        assert_eq!(
+            report,
+            MigrationReport {
+                datasets: 2,
+                files: 3,
+                rows: 6
+            }
+        );
+
+        let first_hour = format!("{lance_uri}/00");
+        let dataset = Dataset::open(&first_hour).await?;
+        assert_eq!(dataset.count_rows().await?, 3);
+        assert_eq!(lance_descriptor(&dataset)?, Some(props.descriptor.clone()));
+
+        let windows = ingest_windows(&first_hour).await?;
+        let begins: Vec<_> = windows.iter().map(|w| w.begin_at).collect();
+        assert_eq!(
+            begins,
+            [start + Duration::minutes(1), start + Duration::minutes(2)]
+        );
+
+        let dataset = Dataset::open(&format!("{lance_uri}/01")).await?;
+        assert_eq!(dataset.count_rows().await?, 3);
+
+        assert!(migrate_parquet_to_lance(&parquet_uri, &lance_uri)
+            .await
+            .is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,382 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+
+use arrow_array::RecordBatchIterator;
+use arrow_schema::{Schema, SchemaRef};
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use lance::dataset::{Dataset, WriteMode, WriteParams};
+use object_store::{path::Path, ObjectStore};
+
+use katniss_pb2arrow::exports::RecordBatch;
+
+use crate::{
+    errors::KatinssIngestorError,
+    lance_ingestion::commit_lock,
+    lance_time_travel::{
+        clear_ingest_windows_before, ingest_windows, record_ingest_windows, IngestWindow,
+    },
+    sinks::store_from_uri,
+    Result,
+};
+
+/// What a prune deleted
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RetentionReport {
+    pub versions_deleted: usize,
+    pub rows_deleted: usize,
+    pub files_deleted: usize,
+}
+
+/// Deletes old versions of a lance dataset, and optionally old rows. Every append commits a new
+/// version whose manifest lists every fragment so far, so a continuously appended dataset's
+/// manifests grow without bound. Appends never stop referencing data files so only version
+/// manifests are deleted for that. The latest version is always kept
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct LanceRetention {
+    max_versions: Option<usize>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, with = "crate::pipeline_config::secs::option")
+    )]
+    max_age: Option<Duration>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, with = "crate::pipeline_config::secs::option")
+    )]
+    row_ttl: Option<Duration>,
+}
+
+impl LanceRetention {
+    /// Keeps every version, see the with_ methods
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Delete all but the newest n versions
+    pub fn with_max_versions(mut self, n: usize) -> Self {
+        self.max_versions = Some(n.max(1));
+        self
+    }
+
+    /// Delete versions committed longer ago than this
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Delete rows of buffers that ended longer ago than this, going by the IngestWindows
+    /// LanceIngestor records, so a dataset on a small disk can be appended to indefinitely.
+    /// This lance revision can't drop fragments, so the remaining rows are streamed into a new
+    /// version and the data files only older versions used are deleted, along with the versions.
+    /// That needs room for the kept rows twice over while it runs, so rows are only expired once
+    /// the oldest are a quarter of the ttl past it. LanceIngestors in this process wait for the
+    /// rewrite, if a writer elsewhere commits during it the prune stops with DatasetChanged
+    /// before deleting anything, and its rows are in the latest version
+    pub fn with_row_ttl(mut self, ttl: Duration) -> Self {
+        self.row_ttl = Some(ttl);
+        self
+    }
+
+    /// Deletes the rows and versions past retention from the dataset at storage_uri
+    pub async fn prune(&self, storage_uri: &str) -> Result<RetentionReport> {
+        self.prune_with_params(storage_uri, &WriteParams::default())
+            .await
+    }
+
+    /// Like prune, rows past the ttl are rewritten with the dataset's write params (the mode
+    /// is ignored), i.e. LanceIngestor::write_params
+    pub async fn prune_with_params(
+        &self,
+        storage_uri: &str,
+        params: &WriteParams,
+    ) -> Result<RetentionReport> {
+        let (store, prefix) = store_from_uri(storage_uri)?;
+        let mut report = RetentionReport::default();
+
+        let mut max_versions = self.max_versions;
+        if let Some(ttl) = self.row_ttl {
+            let (rows, files) =
+                expire_rows(storage_uri, store.as_ref(), &prefix, ttl, params).await?;
+            if rows > 0 {
+                // older versions point at the deleted files
+                max_versions = Some(1);
+            }
+            report.rows_deleted = rows;
+            report.files_deleted = files;
+        }
+
+        let cutoff = match self.max_age {
+            Some(max_age) => Some(Utc::now() - chrono::Duration::from_std(max_age)?),
+            None => None,
+        };
+        report.versions_deleted = delete_versions(
+            store.as_ref(),
+            &prefix,
+            max_versions.unwrap_or(usize::MAX),
+            cutoff,
+        )
+        .await?;
+        Ok(report)
+    }
+}
+
+/// Rewrites the dataset without the expired windows' rows,
+/// returns how many rows and data files were deleted
+async fn expire_rows(
+    storage_uri: &str,
+    store: &dyn ObjectStore,
+    prefix: &Path,
+    ttl: Duration,
+    params: &WriteParams,
+) -> Result<(usize, usize)> {
+    let ttl = chrono::Duration::from_std(ttl)?;
+    let expires_at = Utc::now() - ttl;
+    let windows = ingest_windows(storage_uri).await?;
+    match windows.first() {
+        Some(oldest) if oldest.end_at <= expires_at - ttl / 4 => {}
+        _ => return Ok((0, 0)),
+    }
+    let expired = windows
+        .iter()
+        .filter(|w| w.end_at <= expires_at)
+        .map(|w| w.rows_before + w.rows)
+        .max()
+        .unwrap_or(0);
+
+    let lock = commit_lock(storage_uri);
+    let _committing = lock.lock().await;
+    let dataset = Dataset::open(storage_uri).await?;
+    let opened = dataset.version().version;
+    let total = dataset.count_rows().await?;
+    let referenced_before = referenced_files(&dataset);
+    let schema = Arc::new(Schema::from(dataset.schema()));
+    let mut scanner = dataset.scan();
+    scanner.limit(total.saturating_sub(expired) as i64, Some(expired as i64))?;
+    let mut stream = scanner.try_into_stream().await?;
+
+    // the first chunk overwrites, the rest are appended to it
+    let mut rewritten: Option<Dataset> = None;
+    let mut chunk = Vec::new();
+    let mut chunk_rows = 0;
+    while let Some(batch) = stream.try_next().await? {
+        chunk_rows += batch.num_rows();
+        chunk.push(batch);
+        if chunk_rows >= params.max_rows_per_file {
+            let mode = rewritten
+                .as_ref()
+                .map_or(WriteMode::Overwrite, |_| WriteMode::Append);
+            if rewritten.is_none() {
+                ensure_unchanged(storage_uri, opened).await?;
+            }
+            rewritten = Some(write_chunk(&mut chunk, &schema, storage_uri, params, mode).await?);
+            chunk_rows = 0;
+        }
+    }
+    let rewritten = match rewritten {
+        Some(dataset) if chunk.is_empty() => dataset,
+        rewritten => {
+            if rewritten.is_none() {
+                ensure_unchanged(storage_uri, opened).await?;
+            }
+            let mode = rewritten.map_or(WriteMode::Overwrite, |_| WriteMode::Append);
+            write_chunk(&mut chunk, &schema, storage_uri, params, mode).await?
+        }
+    };
+
+    let version = rewritten.version().version;
+    let kept: Vec<_> = windows
+        .iter()
+        .filter(|w| w.end_at > expires_at)
+        .map(|window| IngestWindow {
+            version,
+            rows_before: window.rows_before.saturating_sub(expired),
+            ..window.clone()
+        })
+        .collect();
+    record_ingest_windows(storage_uri, &kept).await?;
+    clear_ingest_windows_before(storage_uri, version).await?;
+
+    // the versions get deleted after this, a commit on top of the old rows would go with them
+    ensure_unchanged(storage_uri, version).await?;
+    let referenced = referenced_files(&rewritten);
+    let mut files_deleted = 0;
+    for file in referenced_before.difference(&referenced) {
+        store
+            .delete(&prefix.child("data").child(file.as_str()))
+            .await?;
+        files_deleted += 1;
+    }
+    Ok((expired.min(total), files_deleted))
+}
+
+/// Errors if another writer committed a version after this one
+async fn ensure_unchanged(storage_uri: &str, version: u64) -> Result<()> {
+    let latest = Dataset::open(storage_uri).await?.version().version;
+    if latest != version {
+        return Err(KatinssIngestorError::DatasetChanged(
+            storage_uri.to_string(),
+            version,
+            latest,
+        ));
+    }
+    Ok(())
+}
+
+/// Writes and empties the chunk, with an empty batch if there's nothing in it so the
+/// dataset still gets its version
+async fn write_chunk(
+    chunk: &mut Vec<RecordBatch>,
+    schema: &SchemaRef,
+    storage_uri: &str,
+    params: &WriteParams,
+    mode: WriteMode,
+) -> Result<Dataset> {
+    let mut batches = std::mem::take(chunk);
+    if batches.is_empty() {
+        batches.push(RecordBatch::new_empty(schema.clone()));
+    }
+    let reader = RecordBatchIterator::new(batches.into_iter().map(Ok), schema.clone());
+    let params = WriteParams {
+        mode,
+        ..params.clone()
+    };
+    Ok(Dataset::write(reader, storage_uri, Some(params)).await?)
+}
+
+/// The data files the dataset's fragments are made of
+fn referenced_files(dataset: &Dataset) -> HashSet<String> {
+    dataset
+        .get_fragments()
+        .iter()
+        .flat_map(|fragment| fragment.metadata().files.iter().map(|f| f.path.clone()))
+        .collect()
+}
+
+/// Deletes version manifests past the newest `keep` or older than the cutoff,
+/// returns how many were deleted
+async fn delete_versions(
+    store: &dyn ObjectStore,
+    prefix: &Path,
+    keep: usize,
+    cutoff: Option<DateTime<Utc>>,
+) -> Result<usize> {
+    let mut versions: Vec<_> = store
+        .list_with_delimiter(Some(&prefix.child("_versions")))
+        .await?
+        .objects
+        .into_iter()
+        .filter_map(|o| {
+            let version = o.location.filename()?.strip_suffix(".manifest")?;
+            Some((version.parse::<u64>().ok()?, o))
+        })
+        .collect();
+    versions.sort_by_key(|(version, _)| std::cmp::Reverse(*version));
+
+    let mut deleted = 0;
+    for (i, (_, manifest)) in versions.iter().enumerate().skip(1) {
+        let too_old = cutoff.is_some_and(|cutoff| manifest.last_modified < cutoff);
+        if i >= keep || too_old {
+            store.delete(&manifest.location).await?;
+            deleted += 1;
+        }
+    }
+    Ok(deleted)
+}
+
+#[cfg(test)]
+mod tests {
+    use katniss_test::{protos::spacecorp::Packet, test_util::ProtoBatch};
+
+    use super::*;
+    use crate::{temporal_rotator::TemporalBuffer, LanceIngestor};
+
+    #[tokio::test]
+    async fn rows_past_their_ttl_are_deleted() -> anyhow::Result<()> {
+        let mut path = std::env::current_dir()?;
+        let now = Utc::now().timestamp_micros();
+        path.push(format!("test_lance_ttl_{now}.lance"));
+        let uri = format!("file://{}", path.to_str().unwrap());
+
+        let schema = ProtoBatch::SpaceCorp(&[Packet::default()])
+            .arrow_batch()?
+            .schema();
+        let ingestor = LanceIngestor::new(&uri, schema)?;
+
+        // a day old, and just now
+        let day = chrono::Duration::days(1);
+        for (begin_at, rows) in [(Utc::now() - day, 2), (Utc::now(), 3)] {
+            let packets = vec![Packet::default(); rows];
+            ingestor
+                .write(TemporalBuffer {
+                    begin_at,
+                    end_at: begin_at + chrono::Duration::minutes(1),
+                    batches: vec![ProtoBatch::SpaceCorp(&packets).arrow_batch()?],
+                })
+                .await?;
+        }
+
+        // a fragment another writer hasn't committed yet
+        let (store, prefix) = store_from_uri(&uri)?;
+        let uncommitted = prefix.child("data").child("uncommitted.lance");
+        store.put(&uncommitted, vec![0u8; 8].into()).await?;
+
+        let retention = LanceRetention::new().with_row_ttl(Duration::from_secs(60 * 60));
+        let report = retention.prune(&uri).await?;
+        assert_eq!(report.rows_deleted, 2);
+        assert!(report.files_deleted > 0);
+        assert!(store.head(&uncommitted).await.is_ok());
+
+        let dataset = Dataset::open(&uri).await?;
+        assert_eq!(dataset.count_rows().await?, 3);
+        let windows = ingest_windows(&uri).await?;
+        assert_eq!(windows.len(), 1);
+        assert_eq!(windows[0].rows_before, 0);
+
+        let scanned: Vec<RecordBatch> = dataset
+            .scan()
+            .try_into_stream()
+            .await?
+            .try_collect()
+            .await?;
+        assert_eq!(scanned.iter().map(RecordBatch::num_rows).sum::<usize>(), 3);
+
+        let untouched = LanceRetention::new().with_row_ttl(Duration::from_secs(60 * 60));
+        assert_eq!(untouched.prune(&uri).await?.rows_deleted, 0);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn newer_versions_stop_a_rewrite() -> anyhow::Result<()> {
+        let mut path = std::env::current_dir()?;
+        let now = Utc::now().timestamp_micros();
+        path.push(format!("test_lance_ttl_changed_{now}.lance"));
+        let uri = format!("file://{}", path.to_str().unwrap());
+
+        let packets = [Packet::default()];
+        let schema = ProtoBatch::SpaceCorp(&packets).arrow_batch()?.schema();
+        let ingestor = LanceIngestor::new(&uri, schema)?;
+        let mut versions = Vec::new();
+        for _ in 0..2 {
+            let dataset = ingestor
+                .write(TemporalBuffer {
+                    begin_at: Utc::now(),
+                    end_at: Utc::now(),
+                    batches: vec![ProtoBatch::SpaceCorp(&packets).arrow_batch()?],
+                })
+                .await?;
+            versions.push(dataset.version().version);
+        }
+
+        ensure_unchanged(&uri, versions[1]).await?;
+        let err = ensure_unchanged(&uri, versions[0]).await.unwrap_err();
+        assert!(matches!(err, KatinssIngestorError::DatasetChanged(..)));
+        Ok(())
+    }
+}
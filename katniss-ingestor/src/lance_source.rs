@@ -0,0 +1,142 @@
+use std::ops::Range;
+
+use chrono::{DateTime, Utc};
+use futures::stream::{self, BoxStream, StreamExt, TryStreamExt};
+use lance::dataset::Dataset;
+
+use katniss_pb2arrow::{
+    exports::{prost_reflect::MessageDescriptor, DynamicMessage},
+    ArrowToProtoConverter,
+};
+
+use crate::{errors::KatinssIngestorError, lance_descriptor, EventTime, Result};
+
+/// Reads a lance dataset back into protobuf messages, i.e. to replay archived telemetry into a
+/// simulator. Datasets written with LanceIngestor::with_descriptor know their own message type
+pub struct LanceMessageReader {
+    dataset: Dataset,
+    converter: ArrowToProtoConverter,
+    time_range: Option<(EventTime, Range<DateTime<Utc>>)>,
+}
+
+impl LanceMessageReader {
+    /// Opens the dataset at the uri and uses its embedded descriptor
+    pub async fn open(uri: &str) -> Result<Self> {
+        Self::try_new(Dataset::open(uri).await?)
+    }
+
+    /// Uses the descriptor embedded in the dataset's schema
+    pub fn try_new(dataset: Dataset) -> Result<Self> {
+        let descriptor =
+            lance_descriptor(&dataset)?.ok_or(KatinssIngestorError::NoEmbeddedDescriptor)?;
+        Ok(Self::new_with_descriptor(dataset, descriptor))
+    }
+
+    /// For datasets without an embedded descriptor
+    pub fn new_with_descriptor(dataset: Dataset, descriptor: MessageDescriptor) -> Self {
+        Self {
+            dataset,
+            converter: ArrowToProtoConverter::new(descriptor),
+            time_range: None,
+        }
+    }
+
+    /// Only messages whose event time is in the range, messages without one are skipped
+    pub fn with_time_range(mut self, event_time: EventTime, range: Range<DateTime<Utc>>) -> Self {
+        self.time_range = Some((event_time, range));
+        self
+    }
+
+    pub fn descriptor(&self) -> &MessageDescriptor {
+        self.converter.descriptor()
+    }
+
+    /// Every message in the dataset in storage order, converted a batch at a time
+    pub async fn messages(&self) -> Result<BoxStream<'_, Result<DynamicMessage>>> {
+        let batches = self.dataset.scan().try_into_stream().await?;
+
+        Ok(batches
+            .map(|batch| -> Result<_> {
+                let messages = self.converter.messages(&batch?)?;
+                let in_range = messages.into_iter().filter(|msg| self.in_range(msg));
+                Ok(stream::iter(
+                    in_range
+                        .map(Ok::<_, KatinssIngestorError>)
+                        .collect::<Vec<_>>(),
+                ))
+            })
+            .try_flatten()
+            .boxed())
+    }
+
+    fn in_range(&self, msg: &DynamicMessage) -> bool {
+        let Some((event_time, range)) = &self.time_range else {
+            return true;
+        };
+        event_time
+            .extract(msg)
+            .is_some_and(|time| range.contains(&time))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use katniss_pb2arrow::ArrowBatchProps;
+    use katniss_test::{
+        descriptor_pool,
+        protos::spacecorp::{Packet, Timestamp},
+        test_util::ProtoBatch,
+    };
+
+    use super::*;
+    use crate::{temporal_rotator::TemporalBuffer, LanceIngestor};
+
+    #[tokio::test]
+    async fn it_replays_a_time_range() -> anyhow::Result<()> {
+        let props = ArrowBatchProps::try_new(
+            descriptor_pool()?,
+            "eto.pb2arrow.tests.spacecorp.Packet".to_owned(),
+        )?;
+        let packets: Vec<_> = (0..5)
+            .map(|seconds| Packet {
+                timestamp: Some(Timestamp { seconds, nanos: 0 }),
+                sender_uid: seconds as u64,
+                msg: None,
+            })
+            .collect();
+        let batch = ProtoBatch::SpaceCorp(&packets).arrow_batch()?;
+
+        let mut path = std::env::current_dir()?;
+        let now = Utc::now().timestamp_micros();
+        path.push(format!("test_lance_replay_{now}.lance"));
+        let uri = format!("file://{}", path.to_str().unwrap());
+
+        LanceIngestor::new(&uri, batch.schema())?
+            .with_descriptor(&props.descriptor)
+            .write(TemporalBuffer {
+                begin_at: Utc::now(),
+                end_at: Utc::now(),
+                batches: vec![batch],
+            })
+            .await?;
+
+        let reader = LanceMessageReader::open(&uri).await?.with_time_range(
+            EventTime::new("timestamp"),
+            Utc.timestamp_opt(1, 0).unwrap()..Utc.timestamp_opt(3, 0).unwrap(),
+        );
+        assert_eq!(reader.descriptor(), &props.descriptor);
+
+        let replayed: Vec<_> = reader
+            .messages()
+            .await?
+            .map(|msg| Ok(msg?.transcode_to::<Packet>()?))
+            .collect::<Vec<anyhow::Result<_>>>()
+            .await
+            .into_iter()
+            .collect::<anyhow::Result<_>>()?;
+        assert_eq!(replayed, packets[1..3]);
+        Ok(())
+    }
+}
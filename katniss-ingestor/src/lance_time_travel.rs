@@ -0,0 +1,219 @@
+use std::ops::Range;
+use std::sync::Arc;
+
+use arrow_array::{ArrayRef, Int64Array, UInt64Array};
+use arrow_json::ReaderBuilder;
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use chrono::{DateTime, TimeZone, Utc};
+use lance::dataset::{scanner::Scanner, Dataset};
+
+use katniss_pb2arrow::exports::RecordBatch;
+
+use crate::{
+    sinks::{store_from_uri, BufferEncoder, JsonLinesEncoder},
+    Result,
+};
+
+/// Directory in the dataset holding one json line per version LanceIngestor wrote
+pub const INGEST_WINDOWS_DIR: &str = "_katniss_windows";
+
+/// The buffer a dataset version was written from, and where its rows landed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IngestWindow {
+    pub version: u64,
+    pub begin_at: DateTime<Utc>,
+    pub end_at: DateTime<Utc>,
+    /// Rows in the dataset before this version
+    pub rows_before: usize,
+    pub rows: usize,
+}
+
+fn window_schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        Field::new("version", DataType::UInt64, false),
+        Field::new("begin_millis", DataType::Int64, false),
+        Field::new("end_millis", DataType::Int64, false),
+        Field::new("rows_before", DataType::UInt64, false),
+        Field::new("rows", DataType::UInt64, false),
+    ]))
+}
+
+impl IngestWindow {
+    pub(crate) async fn record(&self, storage_uri: &str) -> Result<()> {
+        record_ingest_windows(storage_uri, std::slice::from_ref(self)).await
+    }
+}
+
+/// Records windows of the same version in that version's file, i.e. the ones a rewrite keeps
+pub(crate) async fn record_ingest_windows(
+    storage_uri: &str,
+    windows: &[IngestWindow],
+) -> Result<()> {
+    let Some(first) = windows.first() else {
+        return Ok(());
+    };
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(UInt64Array::from_iter_values(
+            windows.iter().map(|w| w.version),
+        )),
+        Arc::new(Int64Array::from_iter_values(
+            windows.iter().map(|w| w.begin_at.timestamp_millis()),
+        )),
+        Arc::new(Int64Array::from_iter_values(
+            windows.iter().map(|w| w.end_at.timestamp_millis()),
+        )),
+        Arc::new(UInt64Array::from_iter_values(
+            windows.iter().map(|w| w.rows_before as u64),
+        )),
+        Arc::new(UInt64Array::from_iter_values(
+            windows.iter().map(|w| w.rows as u64),
+        )),
+    ];
+    let schema = window_schema();
+    let batch = RecordBatch::try_new(schema.clone(), columns)?;
+    let bytes = JsonLinesEncoder.encode(&schema, &[batch])?;
+
+    let (store, prefix) = store_from_uri(storage_uri)?;
+    let filename = format!("{:020}.jsonl", first.version);
+    let location = prefix.child(INGEST_WINDOWS_DIR).child(filename);
+    store.put(&location, bytes.into()).await?;
+    Ok(())
+}
+
+/// Forgets the windows of versions before this one, i.e. when the dataset is overwritten.
+/// The new version's windows are recorded first so a crash in between leaves stale windows
+/// rather than none
+pub(crate) async fn clear_ingest_windows_before(storage_uri: &str, version: u64) -> Result<()> {
+    let (store, prefix) = store_from_uri(storage_uri)?;
+    let listed = store
+        .list_with_delimiter(Some(&prefix.child(INGEST_WINDOWS_DIR)))
+        .await?;
+    for object in listed.objects {
+        let recorded = object
+            .location
+            .filename()
+            .and_then(|name| name.strip_suffix(".jsonl"))
+            .and_then(|v| v.parse::<u64>().ok());
+        if recorded.map_or(true, |recorded| recorded < version) {
+            store.delete(&object.location).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Every window recorded for the dataset, oldest version first
+pub async fn ingest_windows(storage_uri: &str) -> Result<Vec<IngestWindow>> {
+    let (store, prefix) = store_from_uri(storage_uri)?;
+    let mut listed = store
+        .list_with_delimiter(Some(&prefix.child(INGEST_WINDOWS_DIR)))
+        .await?
+        .objects;
+    listed.sort_by(|a, b| a.location.cmp(&b.location));
+
+    let mut windows = Vec::with_capacity(listed.len());
+    for object in listed {
+        let bytes = store.get(&object.location).await?.bytes().await?;
+        for batch in ReaderBuilder::new(window_schema()).build(bytes.as_ref())? {
+            let batch = batch?;
+            let column = |i| batch.column(i).as_any();
+            let (Some(versions), Some(begins), Some(ends), Some(rows_before), Some(rows)) = (
+                column(0).downcast_ref::<UInt64Array>(),
+                column(1).downcast_ref::<Int64Array>(),
+                column(2).downcast_ref::<Int64Array>(),
+                column(3).downcast_ref::<UInt64Array>(),
+                column(4).downcast_ref::<UInt64Array>(),
+            ) else {
+                unreachable!("batches are read with the window schema");
+            };
+            windows.extend((0..batch.num_rows()).filter_map(|i| {
+                Some(IngestWindow {
+                    version: versions.value(i),
+                    begin_at: Utc.timestamp_millis_opt(begins.value(i)).single()?,
+                    end_at: Utc.timestamp_millis_opt(ends.value(i)).single()?,
+                    rows_before: rows_before.value(i) as usize,
+                    rows: rows.value(i) as usize,
+                })
+            }));
+        }
+    }
+    Ok(windows)
+}
+
+/// A scanner over the rows of the buffers that overlap the wall clock range, i.e.
+/// "14:00 to 14:05 yesterday", None if no buffer does.
+/// Buffers are appended in time order so the rows are one contiguous run, rows of a buffer
+/// that only partly overlaps are all included
+pub async fn scan_time_range(
+    storage_uri: &str,
+    range: Range<DateTime<Utc>>,
+) -> Result<Option<Scanner>> {
+    let covering: Vec<_> = ingest_windows(storage_uri)
+        .await?
+        .into_iter()
+        .filter(|w| w.begin_at < range.end && w.end_at > range.start)
+        .collect();
+    let (Some(start), Some(end)) = (
+        covering.iter().map(|w| w.rows_before).min(),
+        covering.iter().map(|w| w.rows_before + w.rows).max(),
+    ) else {
+        return Ok(None);
+    };
+
+    let dataset = Dataset::open(storage_uri).await?;
+    let mut scanner = dataset.scan();
+    scanner.limit((end - start) as i64, Some(start as i64))?;
+    Ok(Some(scanner))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use futures::TryStreamExt;
+
+    use katniss_test::{protos::spacecorp::Packet, test_util::ProtoBatch};
+
+    use super::*;
+    use crate::{temporal_rotator::TemporalBuffer, LanceIngestor};
+
+    #[tokio::test]
+    async fn it_scans_the_buffers_covering_a_range() -> anyhow::Result<()> {
+        let mut path = std::env::current_dir()?;
+        let now = Utc::now().timestamp_micros();
+        path.push(format!("test_lance_time_travel_{now}.lance"));
+        let uri = format!("file://{}", path.to_str().unwrap());
+
+        let schema = ProtoBatch::SpaceCorp(&[Packet::default()])
+            .arrow_batch()?
+            .schema();
+        let ingestor = LanceIngestor::new(&uri, schema)?;
+
+        let start = Utc.timestamp_millis_opt(0).unwrap();
+        for rows in 1..=3 {
+            let packets = vec![Packet::default(); rows];
+            let batch = ProtoBatch::SpaceCorp(&packets).arrow_batch()?;
+
+            let begin_at = start + Duration::minutes(rows as i64 - 1);
+            ingestor
+                .write(TemporalBuffer {
+                    begin_at,
+                    end_at: begin_at + Duration::minutes(1),
+                    batches: vec![batch],
+                })
+                .await?;
+        }
+
+        let windows = ingest_windows(&uri).await?;
+        let rows_before: Vec<_> = windows.iter().map(|w| w.rows_before).collect();
+        assert_eq!(rows_before, [0, 1, 3]);
+
+        let range = start + Duration::seconds(70)..start + Duration::seconds(130);
+        let scanner = scan_time_range(&uri, range).await?.unwrap();
+        let batches: Vec<RecordBatch> = scanner.try_into_stream().await?.try_collect().await?;
+        let rows = batches.iter().map(RecordBatch::num_rows).sum::<usize>();
+        assert_eq!(rows, 5);
+
+        let before = start - Duration::minutes(10)..start;
+        assert!(scan_time_range(&uri, before).await?.is_none());
+        Ok(())
+    }
+}
@@ -0,0 +1,245 @@
+use std::sync::Arc;
+
+use arrow_array::{
+    builder::{FixedSizeListBuilder, Float32Builder},
+    Array, ArrayRef, Float32Array, ListArray,
+};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use lance::{
+    dataset::Dataset,
+    index::{
+        vector::{MetricType, VectorIndexParams},
+        IndexType,
+    },
+};
+
+use katniss_pb2arrow::exports::RecordBatch;
+
+use crate::{errors::KatinssIngestorError, Result};
+
+/// A repeated float field stored as a FixedSizeList<Float32> of `dimension` values, the layout
+/// lance's vector search and indexes need. Only top level columns can be vectors
+#[derive(Debug, Clone)]
+pub(crate) struct VectorColumn {
+    name: String,
+    dimension: i32,
+}
+
+impl VectorColumn {
+    pub(crate) fn new(name: &str, dimension: i32) -> Self {
+        Self {
+            name: name.to_owned(),
+            dimension,
+        }
+    }
+
+    /// The schema with this column's list swapped for a fixed size list
+    pub(crate) fn apply_to_schema(&self, schema: &Schema) -> Result<SchemaRef> {
+        let invalid = || KatinssIngestorError::InvalidVectorColumn(self.name.clone());
+        let (i, field) = schema.column_with_name(&self.name).ok_or_else(invalid)?;
+        match field.data_type() {
+            DataType::List(item) if item.data_type() == &DataType::Float32 => {}
+            _ => return Err(invalid()),
+        }
+
+        let mut fields: Vec<_> = schema.fields().iter().cloned().collect();
+        fields[i] = Arc::new(Field::new(
+            field.name(),
+            DataType::FixedSizeList(
+                Arc::new(Field::new("item", DataType::Float32, true)),
+                self.dimension,
+            ),
+            field.is_nullable(),
+        ));
+        Ok(Arc::new(Schema::new_with_metadata(
+            fields,
+            schema.metadata().clone(),
+        )))
+    }
+
+    /// The batch with this column converted, every (non null) row needs `dimension` values
+    pub(crate) fn apply_to_batch(&self, batch: &RecordBatch) -> Result<RecordBatch> {
+        let invalid = || KatinssIngestorError::InvalidVectorColumn(self.name.clone());
+        let (i, _) = batch
+            .schema()
+            .column_with_name(&self.name)
+            .ok_or_else(invalid)?;
+        let list = batch
+            .column(i)
+            .as_any()
+            .downcast_ref::<ListArray>()
+            .ok_or_else(invalid)?;
+        let values = list
+            .values()
+            .as_any()
+            .downcast_ref::<Float32Array>()
+            .ok_or_else(invalid)?;
+
+        let dimension = self.dimension as usize;
+        let mut builder = FixedSizeListBuilder::with_capacity(
+            Float32Builder::with_capacity(list.len() * dimension),
+            self.dimension,
+            list.len(),
+        );
+        let offsets = list.value_offsets();
+        for row in 0..list.len() {
+            if list.is_null(row) {
+                (0..dimension).for_each(|_| builder.values().append_null());
+                builder.append(false);
+                continue;
+            }
+
+            let (start, end) = (offsets[row] as usize, offsets[row + 1] as usize);
+            if end - start != dimension {
+                return Err(KatinssIngestorError::VectorDimension(
+                    self.name.clone(),
+                    dimension,
+                    end - start,
+                ));
+            }
+            for v in start..end {
+                builder
+                    .values()
+                    .append_option(values.is_valid(v).then(|| values.value(v)));
+            }
+            builder.append(true);
+        }
+
+        let mut columns = batch.columns().to_vec();
+        columns[i] = Arc::new(builder.finish()) as ArrayRef;
+        let schema = self.apply_to_schema(&batch.schema())?;
+        Ok(RecordBatch::try_new(schema, columns)?)
+    }
+}
+
+/// An IVF_PQ index on a vector column that's rebuilt as data arrives,
+/// see LanceIngestor::with_vector_index
+#[derive(Debug, Clone)]
+pub struct VectorIndex {
+    column: String,
+    num_partitions: usize,
+    num_sub_vectors: usize,
+    metric: MetricType,
+    min_rows: usize,
+    rebuild_every: usize,
+}
+
+impl VectorIndex {
+    /// 256 partitions, 16 sub vectors and L2 distance, built once the dataset has 65536 rows
+    /// and rebuilt every 10 buffers
+    pub fn new(column: &str) -> Self {
+        Self {
+            column: column.to_owned(),
+            num_partitions: 256,
+            num_sub_vectors: 16,
+            metric: MetricType::L2,
+            min_rows: 256 * 256,
+            rebuild_every: 10,
+        }
+    }
+
+    /// IVF partitions, needs a few hundred rows per partition to train well
+    pub fn with_partitions(mut self, num_partitions: usize) -> Self {
+        self.num_partitions = num_partitions;
+        self
+    }
+
+    /// PQ sub vectors, has to divide the dimension
+    pub fn with_sub_vectors(mut self, num_sub_vectors: usize) -> Self {
+        self.num_sub_vectors = num_sub_vectors;
+        self
+    }
+
+    pub fn with_metric(mut self, metric: MetricType) -> Self {
+        self.metric = metric;
+        self
+    }
+
+    /// Rows the dataset needs before the index is first built
+    pub fn with_min_rows(mut self, min_rows: usize) -> Self {
+        self.min_rows = min_rows;
+        self
+    }
+
+    /// Buffers written between rebuilds, rows since the last one are searched without the index
+    pub fn with_rebuild_every(mut self, buffers: usize) -> Self {
+        self.rebuild_every = buffers.max(1);
+        self
+    }
+
+    pub(crate) fn rebuild_every(&self) -> usize {
+        self.rebuild_every
+    }
+
+    /// Builds (or replaces) the index, false if the dataset is still too small
+    pub(crate) async fn build(&self, dataset: &Dataset) -> Result<bool> {
+        if dataset.count_rows().await? < self.min_rows {
+            return Ok(false);
+        }
+
+        let params = VectorIndexParams::ivf_pq(
+            self.num_partitions,
+            8,
+            self.num_sub_vectors,
+            false,
+            self.metric,
+            50,
+        );
+        dataset
+            .create_index(
+                &[self.column.as_str()],
+                IndexType::Vector,
+                None,
+                &params,
+                true,
+            )
+            .await?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::{builder::ListBuilder, FixedSizeListArray};
+
+    use super::*;
+
+    #[test]
+    fn float_lists_become_fixed_size_lists() -> anyhow::Result<()> {
+        let mut embeddings = ListBuilder::new(Float32Builder::new());
+        embeddings.append_value([Some(1.0), Some(2.0)]);
+        embeddings.append_null();
+        embeddings.append_value([Some(3.0), None]);
+        let embeddings: ArrayRef = Arc::new(embeddings.finish());
+
+        let schema = Schema::new(vec![Field::new(
+            "embedding",
+            embeddings.data_type().clone(),
+            true,
+        )]);
+        let batch = RecordBatch::try_new(Arc::new(schema), vec![embeddings])?;
+
+        let vectors = VectorColumn::new("embedding", 2).apply_to_batch(&batch)?;
+        let column = vectors
+            .column(0)
+            .as_any()
+            .downcast_ref::<FixedSizeListArray>()
+            .unwrap();
+        assert_eq!(column.value_length(), 2);
+        assert!(column.is_null(1));
+        let values = column
+            .values()
+            .as_any()
+            .downcast_ref::<Float32Array>()
+            .unwrap();
+        assert_eq!(values.value(4), 3.0);
+        assert!(values.is_null(5));
+
+        let err = VectorColumn::new("embedding", 3).apply_to_batch(&batch);
+        assert!(matches!(
+            err,
+            Err(KatinssIngestorError::VectorDimension(_, 3, 2))
+        ));
+        Ok(())
+    }
+}
@@ -1,8 +1,106 @@
+mod ack;
 mod arrow;
+mod backfill;
+#[cfg(feature = "datafusion")]
+mod catalog;
+mod compaction;
+mod dedup;
+mod event_time;
+mod export;
+mod fan_in;
+mod field_path;
+mod filter;
+#[cfg(feature = "flight")]
+mod flight_source;
+#[cfg(feature = "flight-sql")]
+mod flight_sql;
+mod follow_source;
+mod ipc_source;
+#[cfg(feature = "kafka")]
+mod kafka_source;
 mod lance_ingestion;
+mod lance_migration;
+mod lance_retention;
+mod lance_source;
+mod lance_time_travel;
+mod lance_vectors;
+mod live_buffers;
+#[cfg(feature = "datafusion")]
+mod live_table;
+mod load_shed;
+#[cfg(feature = "mcap")]
+mod mcap_source;
+mod oneof_fanout;
+mod parquet_source;
+mod pipeline;
+mod pipeline_config;
+mod proto_generation;
+mod rate_limit;
+mod reorder;
+mod schema_guard;
+mod schema_reload;
+mod stats;
+mod status;
+mod supervisor;
+mod tcp_source;
 mod temporal_rotator;
+#[cfg(feature = "websocket")]
+mod websocket_source;
 
 pub mod errors;
+pub mod sinks;
 pub type Result<T> = core::result::Result<T, errors::KatinssIngestorError>;
-pub use lance_ingestion::{lance_ingestion_pipeline, LanceIngestor, LoopJoinSet};
+pub use ack::Ack;
+pub use backfill::{backfill, Backfill, BackfillReport, DryRunReport, Framing};
+#[cfg(feature = "datafusion")]
+pub use catalog::IngestedCatalog;
+pub use compaction::{CompactionReport, Compactor};
+pub use dedup::{DedupSnapshot, Deduplicator};
+pub use event_time::{EventTime, EventTimeRotator, LatenessPolicy};
+#[cfg(feature = "mcap")]
+pub use export::McapWriter;
+pub use export::{export, DelimitedWriter, ExportReport, MessageWriter};
+pub use fan_in::{source_tagged_schema, FanIn, SourceSender};
+pub use filter::{CompareOp, Literal, Predicate};
+#[cfg(feature = "flight")]
+pub use flight_source::{FlightSource, MirrorReport};
+#[cfg(feature = "flight-sql")]
+pub use flight_sql::{FlightSqlEndpoint, DEFAULT_FLIGHT_SQL_ADDR};
+pub use follow_source::follow_file;
+pub use ipc_source::IpcMessageReader;
+#[cfg(feature = "kafka")]
+pub use kafka_source::{consume_kafka, kafka_consumer};
+pub use lance::dataset::{WriteMode, WriteParams};
+pub use lance::index::vector::MetricType;
+pub use lance_ingestion::{
+    lance_descriptor, lance_ingestion_pipeline, lance_oneof_fanout_pipeline, LanceIngestor,
+};
+pub use lance_migration::{migrate_parquet_to_lance, MigrationReport};
+pub use lance_retention::{LanceRetention, RetentionReport};
+pub use lance_source::LanceMessageReader;
+pub use lance_time_travel::{ingest_windows, scan_time_range, IngestWindow, INGEST_WINDOWS_DIR};
+pub use lance_vectors::VectorIndex;
+pub use live_buffers::LiveBuffers;
+#[cfg(feature = "datafusion")]
+pub use live_table::LiveBuffersTable;
+pub use load_shed::{LoadShedding, ShedPolicy};
+#[cfg(feature = "mcap")]
+pub use mcap_source::{ingest_mcap, McapReport};
+pub use oneof_fanout::{oneof_fanout_pipeline, oneof_variant_props};
+pub use parquet_source::ParquetMessageReader;
+pub use pipeline::{ingestion_pipeline, LoopJoinSet, PipelineBuilder};
+pub use pipeline_config::PipelineConfig;
+pub use proto_generation::proto_from_schema;
+pub use rate_limit::{RateLimit, ThrottlePolicy};
+pub use reorder::Reorder;
+pub use schema_guard::{compare_messages, compare_pools, ChangePolicy, SchemaChange, SchemaGuard};
+pub use schema_reload::SchemaReloader;
+pub use stats::{column_stats, column_stats_schema};
+pub use status::{PipelineEvent, PipelineHandle, PipelineStatus, Stage, StageStatus};
+pub use supervisor::{supervise, RestartPolicy};
+pub use tcp_source::serve_tcp;
 pub use temporal_rotator::TemporalBuffer;
+#[cfg(feature = "bench")]
+pub use temporal_rotator::TemporalRotator;
+#[cfg(feature = "websocket")]
+pub use websocket_source::{serve_websocket, FrameContents};
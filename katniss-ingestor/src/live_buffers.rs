@@ -0,0 +1,230 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::{Arc, RwLock};
+
+use arrow_schema::SchemaRef;
+
+use katniss_pb2arrow::exports::RecordBatch;
+
+use crate::temporal_rotator::TemporalBuffer;
+
+/// Shared, cheaply cloneable view of the data a pipeline is holding in memory:
+/// the batches of the buffer currently being filled plus the last few rotated buffers,
+/// bounded by count and optionally by their size in memory.
+/// Rows still sitting in the arrow converter (< records_per_arrow_batch) aren't visible yet.
+#[derive(Debug, Clone)]
+pub struct LiveBuffers {
+    schema: SchemaRef,
+    inner: Arc<RwLock<Inner>>,
+    on_evict: Option<OnEvict>,
+}
+
+#[derive(Clone)]
+struct OnEvict(Arc<dyn Fn(TemporalBuffer) + Send + Sync>);
+
+impl fmt::Debug for OnEvict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("OnEvict")
+    }
+}
+
+#[derive(Debug)]
+struct Inner {
+    current: Vec<RecordBatch>,
+    recent: VecDeque<TemporalBuffer>,
+    max_recent: usize,
+    recent_bytes: usize,
+    max_bytes: Option<usize>,
+}
+
+impl Inner {
+    fn pop_oldest(&mut self) -> Option<TemporalBuffer> {
+        let oldest = self.recent.pop_front()?;
+        self.recent_bytes -= memory_size(&oldest);
+        Some(oldest)
+    }
+}
+
+fn memory_size(buffer: &TemporalBuffer) -> usize {
+    buffer
+        .batches
+        .iter()
+        .map(RecordBatch::get_array_memory_size)
+        .sum()
+}
+
+impl LiveBuffers {
+    /// Keeps up to max_recent rotated buffers around after they've been handed off to the sink
+    pub fn new(schema: SchemaRef, max_recent: usize) -> Self {
+        Self {
+            schema,
+            inner: Arc::new(RwLock::new(Inner {
+                current: Vec::new(),
+                recent: VecDeque::with_capacity(max_recent),
+                max_recent,
+                recent_bytes: 0,
+                max_bytes: None,
+            })),
+            on_evict: None,
+        }
+    }
+
+    /// Also drop the oldest rotated buffers once together they take more than max_bytes,
+    /// a buffer bigger than that on its own isn't kept at all
+    pub fn with_max_bytes(self, max_bytes: usize) -> Self {
+        self.inner
+            .write()
+            .expect("live buffers lock poisoned")
+            .max_bytes = Some(max_bytes);
+        self
+    }
+
+    /// Hand every buffer that stops being kept to f, oldest first, i.e. to spill it to disk.
+    /// Called after the lock is released, from the pipeline's rotation task, so f shouldn't block
+    pub fn with_on_evict(mut self, f: impl Fn(TemporalBuffer) + Send + Sync + 'static) -> Self {
+        self.on_evict = Some(OnEvict(Arc::new(f)));
+        self
+    }
+
+    pub fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    /// Every batch held, oldest first
+    pub fn batches(&self) -> Vec<RecordBatch> {
+        let inner = self.inner.read().expect("live buffers lock poisoned");
+        inner
+            .recent
+            .iter()
+            .flat_map(|b| b.batches.iter())
+            .chain(inner.current.iter())
+            .cloned()
+            .collect()
+    }
+
+    /// Recently rotated buffers, oldest first
+    pub fn recent(&self) -> Vec<TemporalBuffer> {
+        let inner = self.inner.read().expect("live buffers lock poisoned");
+        inner.recent.iter().cloned().collect()
+    }
+
+    /// Mirror the rotator's state after an ingest, cheap when nothing has changed
+    pub(crate) fn sync(&self, current: &[RecordBatch], finished: &[TemporalBuffer]) {
+        if finished.is_empty()
+            && self
+                .inner
+                .read()
+                .expect("live buffers lock poisoned")
+                .current
+                .len()
+                == current.len()
+        {
+            return;
+        }
+
+        let mut inner = self.inner.write().expect("live buffers lock poisoned");
+        let mut evicted = Vec::new();
+        for buffer in finished {
+            inner.recent_bytes += memory_size(buffer);
+            inner.recent.push_back(buffer.clone());
+            if inner.recent.len() > inner.max_recent {
+                evicted.extend(inner.pop_oldest());
+            }
+        }
+        if let Some(max_bytes) = inner.max_bytes {
+            while inner.recent_bytes > max_bytes {
+                match inner.pop_oldest() {
+                    Some(oldest) => evicted.push(oldest),
+                    None => break,
+                }
+            }
+        }
+        inner.current = current.to_vec();
+        drop(inner);
+
+        if let Some(OnEvict(on_evict)) = &self.on_evict {
+            for buffer in evicted {
+                on_evict(buffer);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use katniss_test::{protos::spacecorp::Packet, test_util::ProtoBatch};
+
+    use super::*;
+
+    fn buffer(rows: usize) -> anyhow::Result<TemporalBuffer> {
+        let packets = vec![Packet::default(); rows];
+        Ok(TemporalBuffer {
+            begin_at: Utc::now(),
+            end_at: Utc::now(),
+            batches: vec![ProtoBatch::SpaceCorp(&packets).arrow_batch()?],
+        })
+    }
+
+    fn rows(live: &LiveBuffers) -> Vec<usize> {
+        live.batches().iter().map(|b| b.num_rows()).collect()
+    }
+
+    #[test]
+    fn it_keeps_a_bounded_number_of_recent_buffers() -> anyhow::Result<()> {
+        let current = buffer(1)?;
+        let live = LiveBuffers::new(current.batches[0].schema(), 2);
+
+        live.sync(&current.batches, &[]);
+        assert_eq!(rows(&live), vec![1]);
+
+        live.sync(&buffer(4)?.batches, &[buffer(2)?]);
+        live.sync(&buffer(5)?.batches, &[buffer(3)?]);
+        assert_eq!(rows(&live), vec![2, 3, 5]);
+
+        live.sync(&buffer(6)?.batches, &[buffer(4)?]);
+        assert_eq!(rows(&live), vec![3, 4, 6]);
+        assert_eq!(live.recent().len(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn it_drops_recent_buffers_over_max_bytes() -> anyhow::Result<()> {
+        let current = buffer(1)?;
+        let two_buffers = 2 * memory_size(&buffer(2)?);
+        let live = LiveBuffers::new(current.batches[0].schema(), 8).with_max_bytes(two_buffers);
+
+        live.sync(&current.batches, &[buffer(2)?, buffer(2)?]);
+        assert_eq!(live.recent().len(), 2);
+
+        live.sync(&current.batches, &[buffer(2)?]);
+        assert_eq!(rows(&live), vec![2, 2, 1]);
+
+        // too big to keep on its own, and it pushes out everything older
+        live.sync(&current.batches, &[buffer(10_000)?]);
+        assert!(live.recent().is_empty());
+        assert_eq!(rows(&live), vec![1]);
+        Ok(())
+    }
+
+    #[test]
+    fn it_hands_off_buffers_it_stops_keeping() -> anyhow::Result<()> {
+        let current = buffer(1)?;
+        let handed_off = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = handed_off.clone();
+        let live = LiveBuffers::new(current.batches[0].schema(), 1)
+            .with_on_evict(move |buffer| seen.lock().unwrap().push(buffer.num_rows()));
+
+        live.sync(&current.batches, &[buffer(2)?, buffer(3)?]);
+        live.sync(&current.batches, &[buffer(4)?]);
+        assert_eq!(rows(&live), vec![4, 1]);
+        assert_eq!(*handed_off.lock().unwrap(), vec![2, 3]);
+
+        // clones share the callback
+        live.clone().sync(&current.batches, &[buffer(5)?]);
+        assert_eq!(*handed_off.lock().unwrap(), vec![2, 3, 4]);
+        Ok(())
+    }
+}
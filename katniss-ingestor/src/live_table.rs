@@ -0,0 +1,155 @@
+use std::any::Any;
+use std::sync::Arc;
+
+use arrow_schema::SchemaRef;
+use async_trait::async_trait;
+use datafusion::{
+    datasource::{MemTable, TableProvider},
+    error::Result as DataFusionResult,
+    execution::context::{SessionContext, SessionState},
+    logical_expr::{Expr, TableType},
+    physical_plan::{memory::MemoryExec, ExecutionPlan},
+};
+
+use katniss_pb2arrow::exports::RecordBatch;
+
+use crate::{live_buffers::LiveBuffers, temporal_rotator::TemporalBuffer, Result};
+
+/// DataFusion table over a pipeline's in memory buffers, so operators can run sql
+/// against the last few minutes of data before it hits storage
+pub struct LiveBuffersTable {
+    live_buffers: LiveBuffers,
+}
+
+impl LiveBuffersTable {
+    pub fn new(live_buffers: LiveBuffers) -> Self {
+        Self { live_buffers }
+    }
+}
+
+impl LiveBuffers {
+    /// Register these buffers as a table named `name` in the session
+    pub fn register(&self, ctx: &SessionContext, name: &str) -> Result<()> {
+        ctx.register_table(name, Arc::new(LiveBuffersTable::new(self.clone())))?;
+        Ok(())
+    }
+}
+
+impl TemporalBuffer {
+    /// Run a one-off sql query against this buffer's batches, available as the table `buffer`.
+    /// Unlike TemporalBuffer::query this takes any sql DataFusion does, i.e. aggregates
+    pub async fn sql(&self, sql: &str) -> Result<Vec<RecordBatch>> {
+        let schema = match self.batches.first() {
+            Some(batch) => batch.schema(),
+            None => return Ok(Vec::new()),
+        };
+
+        let ctx = SessionContext::new();
+        ctx.register_table(
+            "buffer",
+            Arc::new(MemTable::try_new(schema, vec![self.batches.clone()])?),
+        )?;
+
+        Ok(ctx.sql(sql).await?.collect().await?)
+    }
+}
+
+#[async_trait]
+impl TableProvider for LiveBuffersTable {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.live_buffers.schema()
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::Temporary
+    }
+
+    /// Snapshots the buffers at scan time, later rotations don't affect a running query
+    async fn scan(
+        &self,
+        _state: &SessionState,
+        projection: Option<&Vec<usize>>,
+        _filters: &[Expr],
+        _limit: Option<usize>,
+    ) -> DataFusionResult<Arc<dyn ExecutionPlan>> {
+        let batches = self.live_buffers.batches();
+        Ok(Arc::new(MemoryExec::try_new(
+            &[batches],
+            self.schema(),
+            projection.cloned(),
+        )?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::{
+        cast::AsArray,
+        types::{Int64Type, UInt64Type},
+    };
+    use chrono::Utc;
+
+    use katniss_test::{protos::spacecorp::Packet, test_util::ProtoBatch};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn it_queries_live_buffers_with_sql() -> anyhow::Result<()> {
+        let packets = (0..10)
+            .map(|i| Packet {
+                sender_uid: i,
+                ..Default::default()
+            })
+            .collect::<Vec<_>>();
+
+        let buffer = TemporalBuffer {
+            begin_at: Utc::now(),
+            end_at: Utc::now(),
+            batches: vec![ProtoBatch::SpaceCorp(&packets).arrow_batch()?],
+        };
+
+        let live = LiveBuffers::new(buffer.batches[0].schema(), 1);
+        live.sync(&buffer.batches, &[]);
+
+        let ctx = SessionContext::new();
+        live.register(&ctx, "packets")?;
+
+        let batches = ctx
+            .sql("select count(*) from packets where sender_uid >= 5")
+            .await?
+            .collect()
+            .await?;
+
+        assert_eq!(batches[0].column(0).as_primitive::<Int64Type>().value(0), 5);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_queries_a_single_buffer() -> anyhow::Result<()> {
+        let packets = (0..4)
+            .map(|i| Packet {
+                sender_uid: i,
+                ..Default::default()
+            })
+            .collect::<Vec<_>>();
+
+        let buffer = TemporalBuffer {
+            begin_at: Utc::now(),
+            end_at: Utc::now(),
+            batches: vec![ProtoBatch::SpaceCorp(&packets).arrow_batch()?],
+        };
+
+        let batches = buffer
+            .sql("select max(sender_uid) as max_uid from buffer")
+            .await?;
+        assert_eq!(
+            batches[0].column(0).as_primitive::<UInt64Type>().value(0),
+            3
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,149 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use tokio::sync::Notify;
+
+/// Which messages to throw away once the pipeline's queue is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum ShedPolicy {
+    /// Make room by dropping the message that's been waiting longest
+    #[default]
+    DropOldest,
+    /// Drop incoming messages until there's room again
+    DropNewest,
+    /// Keep 1 in every n messages that arrive while full (displacing the oldest), drop the rest
+    Sample(u32),
+}
+
+/// Caps how many messages can be waiting for the encoder so a producer that outpaces it
+/// can't grow the pipeline's memory without bound, i.e. on edge devices that must never OOM
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LoadShedding {
+    capacity: usize,
+    #[cfg_attr(feature = "serde", serde(default))]
+    policy: ShedPolicy,
+}
+
+impl LoadShedding {
+    pub fn new(capacity: usize, policy: ShedPolicy) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            policy,
+        }
+    }
+}
+
+/// Bounded queue between the pipeline's head channel and the encoder that sheds on push
+pub(crate) struct ShedQueue<T> {
+    inner: Mutex<Inner<T>>,
+    notify: Notify,
+}
+
+struct Inner<T> {
+    queue: VecDeque<T>,
+    shedding: LoadShedding,
+    /// Messages that arrived while full, for sampling
+    arrived_full: u64,
+    closed: bool,
+}
+
+impl<T> ShedQueue<T> {
+    pub(crate) fn new(shedding: LoadShedding) -> Self {
+        Self {
+            inner: Mutex::new(Inner {
+                queue: VecDeque::with_capacity(shedding.capacity),
+                shedding,
+                arrived_full: 0,
+                closed: false,
+            }),
+            notify: Notify::new(),
+        }
+    }
+
+    /// Queues the item, returns the message that had to be dropped to do so if any
+    pub(crate) fn push(&self, item: T) -> Option<T> {
+        let mut inner = self.inner.lock().expect("shed queue lock poisoned");
+        let mut shed = None;
+        if inner.queue.len() >= inner.shedding.capacity {
+            inner.arrived_full += 1;
+            let keep = match inner.shedding.policy {
+                ShedPolicy::DropOldest => true,
+                ShedPolicy::DropNewest => false,
+                ShedPolicy::Sample(n) => inner.arrived_full % n.max(1) as u64 == 0,
+            };
+            if !keep {
+                return Some(item);
+            }
+            shed = inner.queue.pop_front();
+        } else {
+            inner.arrived_full = 0;
+        }
+
+        inner.queue.push_back(item);
+        drop(inner);
+        self.notify.notify_one();
+        shed
+    }
+
+    /// No more items will be pushed, pop returns None once the queue has drained
+    pub(crate) fn close(&self) {
+        self.inner.lock().expect("shed queue lock poisoned").closed = true;
+        self.notify.notify_one();
+    }
+
+    pub(crate) async fn pop(&self) -> Option<T> {
+        loop {
+            {
+                let mut inner = self.inner.lock().expect("shed queue lock poisoned");
+                if let Some(item) = inner.queue.pop_front() {
+                    return Some(item);
+                }
+                if inner.closed {
+                    return None;
+                }
+            }
+            self.notify.notified().await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fill(policy: ShedPolicy, items: impl IntoIterator<Item = u32>) -> (Vec<u32>, usize) {
+        let queue = ShedQueue::new(LoadShedding::new(3, policy));
+        let dropped = items.into_iter().filter_map(|i| queue.push(i)).count();
+        let kept = queue
+            .inner
+            .into_inner()
+            .unwrap()
+            .queue
+            .into_iter()
+            .collect();
+        (kept, dropped)
+    }
+
+    #[test]
+    fn each_policy_sheds_different_messages() {
+        assert_eq!(fill(ShedPolicy::DropOldest, 0..6), (vec![3, 4, 5], 3));
+        assert_eq!(fill(ShedPolicy::DropNewest, 0..6), (vec![0, 1, 2], 3));
+        // every other message that arrives while full displaces the oldest
+        assert_eq!(fill(ShedPolicy::Sample(2), 0..9), (vec![4, 6, 8], 6));
+    }
+
+    #[tokio::test]
+    async fn pop_drains_before_reporting_closed() {
+        let queue = ShedQueue::new(LoadShedding::new(2, ShedPolicy::default()));
+        queue.push(1);
+        queue.close();
+        assert_eq!(queue.pop().await, Some(1));
+        assert_eq!(queue.pop().await, None);
+    }
+}
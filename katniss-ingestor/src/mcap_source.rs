@@ -0,0 +1,268 @@
+use std::collections::{hash_map::Entry, BTreeMap, HashMap};
+use std::path::Path;
+
+use mcap::{Channel, MessageStream};
+use memmap2::Mmap;
+use tokio::sync::mpsc::UnboundedSender;
+
+use katniss_pb2arrow::{
+    exports::{
+        prost_reflect::{DescriptorPool, MessageDescriptor},
+        DynamicMessage,
+    },
+    ArrowBatchProps,
+};
+
+use crate::{
+    errors::KatinssIngestorError,
+    pipeline::{LoopJoinSet, PipelineBuilder},
+    sinks::BufferSink,
+    Result,
+};
+
+/// What was read out of a recording, see ingest_mcap
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct McapReport {
+    /// Messages sent to each topic's pipeline
+    pub messages: BTreeMap<String, u64>,
+    /// Messages on channels that aren't protobuf or don't embed their schema
+    pub skipped: u64,
+}
+
+type Route = (MessageDescriptor, UnboundedSender<DynamicMessage>);
+
+/// Sends each protobuf channel of an MCAP recording through its own pipeline, using the
+/// schema embedded in the recording. make_pipeline is called once per topic with props for
+/// the topic's message type. Once the whole recording has been sent the pipelines flush
+/// whatever they're holding and exit, so wait for the returned tasks to finish.
+/// The recording is memory mapped rather than read in, so it mustn't change while it's ingested.
+/// If a message can't be read or decoded the pipelines are closed, flushing what they got
+/// before it, and joined before the error is returned
+pub async fn ingest_mcap<S, F>(
+    path: &Path,
+    mut make_pipeline: F,
+) -> Result<(McapReport, LoopJoinSet)>
+where
+    S: BufferSink + 'static,
+    F: FnMut(&str, ArrowBatchProps) -> Result<PipelineBuilder<S>>,
+{
+    let file = std::fs::File::open(path)?;
+    // safety: only ever read, see above for the file changing underneath it
+    let recording = unsafe { Mmap::map(&file)? };
+    let mut tasks = LoopJoinSet::new();
+    let mut routes: HashMap<String, Option<Route>> = HashMap::new();
+    let mut report = McapReport::default();
+
+    let sent = send_messages(
+        &recording,
+        &mut make_pipeline,
+        &mut tasks,
+        &mut routes,
+        &mut report,
+    );
+    if let Err(err) = sent {
+        // dropping the heads closes the pipelines
+        drop(routes);
+        while tasks.join_next().await.is_some() {}
+        return Err(err);
+    }
+    Ok((report, tasks))
+}
+
+fn send_messages<S, F>(
+    recording: &[u8],
+    make_pipeline: &mut F,
+    tasks: &mut LoopJoinSet,
+    routes: &mut HashMap<String, Option<Route>>,
+    report: &mut McapReport,
+) -> Result<()>
+where
+    S: BufferSink + 'static,
+    F: FnMut(&str, ArrowBatchProps) -> Result<PipelineBuilder<S>>,
+{
+    for message in MessageStream::new(recording)? {
+        let message = message?;
+        let route = match routes.entry(message.channel.topic.clone()) {
+            Entry::Occupied(o) => o.into_mut(),
+            Entry::Vacant(v) => v.insert(channel_route(&message.channel, make_pipeline, tasks)?),
+        };
+
+        let Some((descriptor, head)) = route else {
+            report.skipped += 1;
+            continue;
+        };
+
+        let msg = DynamicMessage::decode(descriptor.clone(), &message.data[..])?;
+        head.send(msg)
+            .map_err(|_| KatinssIngestorError::PipelineClosed)?;
+        *report
+            .messages
+            .entry(message.channel.topic.clone())
+            .or_default() += 1;
+    }
+    Ok(())
+}
+
+/// Spawns a pipeline for the channel, None if its messages can't be decoded as protobuf
+fn channel_route<S, F>(
+    channel: &Channel,
+    make_pipeline: &mut F,
+    tasks: &mut LoopJoinSet,
+) -> Result<Option<Route>>
+where
+    S: BufferSink + 'static,
+    F: FnMut(&str, ArrowBatchProps) -> Result<PipelineBuilder<S>>,
+{
+    let Some(schema) = &channel.schema else {
+        return Ok(None);
+    };
+    if channel.message_encoding != "protobuf" || schema.encoding != "protobuf" {
+        return Ok(None);
+    }
+
+    let pool = DescriptorPool::decode(&schema.data[..])?;
+    let props = ArrowBatchProps::try_new(pool, schema.name.clone())?;
+    let descriptor = props.descriptor.clone();
+    let head = make_pipeline(&channel.topic, props)?.spawn_into(tasks)?;
+    Ok(Some((descriptor, head)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+    use std::io::BufWriter;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use async_trait::async_trait;
+    use mcap::{records::MessageHeader, Schema, Writer};
+    use prost::Message;
+
+    use katniss_test::protos::{spacecorp::Packet, FILE_DESCRIPTOR_BYTES};
+
+    use super::*;
+    use crate::temporal_rotator::TemporalBuffer;
+
+    #[derive(Clone, Default)]
+    struct Collect(Arc<Mutex<Vec<TemporalBuffer>>>);
+
+    #[async_trait]
+    impl BufferSink for Collect {
+        async fn write_buffer(&mut self, buffer: TemporalBuffer) -> Result<()> {
+            self.0.lock().unwrap().push(buffer);
+            Ok(())
+        }
+    }
+
+    /// Three packets and three json logs, the last packet isn't protobuf if it's corrupt
+    fn write_recording(path: &Path, corrupt: bool) -> anyhow::Result<()> {
+        let mut writer = Writer::new(BufWriter::new(std::fs::File::create(path)?))?;
+
+        let packets = writer.add_channel(&Channel {
+            topic: "/packets".to_owned(),
+            schema: Some(Arc::new(Schema {
+                name: "eto.pb2arrow.tests.spacecorp.Packet".to_owned(),
+                encoding: "protobuf".to_owned(),
+                data: Cow::Borrowed(FILE_DESCRIPTOR_BYTES),
+            })),
+            message_encoding: "protobuf".to_owned(),
+            metadata: BTreeMap::new(),
+        })?;
+        let logs = writer.add_channel(&Channel {
+            topic: "/logs".to_owned(),
+            schema: None,
+            message_encoding: "json".to_owned(),
+            metadata: BTreeMap::new(),
+        })?;
+
+        for sequence in 0..3 {
+            let header = |channel_id| MessageHeader {
+                channel_id,
+                sequence,
+                log_time: sequence as u64,
+                publish_time: sequence as u64,
+            };
+            let packet = Packet {
+                sender_uid: sequence as u64,
+                ..Default::default()
+            };
+            let data = if corrupt && sequence == 2 {
+                vec![0xff]
+            } else {
+                packet.encode_to_vec()
+            };
+            writer.write_to_known_channel(&header(packets), &data)?;
+            writer.write_to_known_channel(&header(logs), b"{}")?;
+        }
+
+        writer.finish()?;
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn protobuf_channels_get_a_pipeline_each() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("recording.mcap");
+        write_recording(&path, false)?;
+
+        let sink = Collect::default();
+        let (report, mut tasks) = ingest_mcap(&path, |topic, props| {
+            assert_eq!(topic, "/packets");
+            Ok(PipelineBuilder::new(
+                props,
+                Duration::from_secs(60),
+                sink.clone(),
+            ))
+        })
+        .await?;
+
+        assert_eq!(
+            report.messages,
+            BTreeMap::from([("/packets".to_owned(), 3)])
+        );
+        assert_eq!(report.skipped, 3);
+
+        while tasks.join_next().await.is_some() {}
+        let rows = sink
+            .0
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|b| b.num_rows())
+            .sum::<usize>();
+        assert_eq!(rows, 3);
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn decode_errors_flush_and_join_the_pipelines() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("recording.mcap");
+        write_recording(&path, true)?;
+
+        let sink = Collect::default();
+        let result = ingest_mcap(&path, |_, props| {
+            Ok(PipelineBuilder::new(
+                props,
+                Duration::from_secs(60),
+                sink.clone(),
+            ))
+        })
+        .await;
+        assert!(matches!(
+            result,
+            Err(KatinssIngestorError::ProtoDecodeError(_))
+        ));
+
+        // the pipeline was done with the packets before the bad one by the time it returned
+        let rows = sink
+            .0
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|b| b.num_rows())
+            .sum::<usize>();
+        assert_eq!(rows, 2);
+        Ok(())
+    }
+}
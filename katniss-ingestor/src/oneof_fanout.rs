@@ -0,0 +1,131 @@
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+
+use katniss_pb2arrow::{exports::DynamicMessage, ArrowBatchProps};
+
+use crate::{
+    errors::KatinssIngestorError,
+    pipeline::{LoopJoinSet, PipelineBuilder},
+    sinks::BufferSink,
+    Result,
+};
+
+/// Props for each variant of an envelope's oneof, keyed by the variant's field name.
+/// Each variant's schema is the envelope's fields outside of the oneof plus that one variant,
+/// i.e. spacecorp.Packet's `msg` gives `jump_drive_status` -> (timestamp, sender_uid, jump_drive_status)
+pub fn oneof_variant_props(
+    props: &ArrowBatchProps,
+    oneof: &str,
+) -> Result<Vec<(String, ArrowBatchProps)>> {
+    let descriptor = &props.descriptor;
+    let oneof_desc = descriptor
+        .oneofs()
+        .find(|o| o.name() == oneof)
+        .ok_or_else(|| KatinssIngestorError::UnknownOneof(oneof.to_owned()))?;
+
+    let shared = descriptor
+        .fields()
+        .filter(|f| !f.containing_oneof().is_some_and(|o| o.name() == oneof))
+        .map(|f| f.name().to_owned())
+        .collect::<Vec<_>>();
+
+    oneof_desc
+        .fields()
+        .map(|variant| {
+            let projection = shared
+                .iter()
+                .map(String::as_str)
+                .chain([variant.name()])
+                .collect::<Vec<_>>();
+            let variant_props = ArrowBatchProps::try_new_projected(
+                descriptor.parent_pool().clone(),
+                descriptor.full_name().to_owned(),
+                &projection,
+            )?
+            .with_records_per_arrow_batch(props.records_per_arrow_batch);
+            Ok((variant.name().to_owned(), variant_props))
+        })
+        .collect()
+}
+
+/// Splits an envelope message into a pipeline per oneof variant instead of one sparse, wide table.
+/// make_pipeline is called with each variant's name and props (see oneof_variant_props).
+/// Returns a single head that routes messages by which variant is set,
+/// messages without any variant set are dropped.
+pub fn oneof_fanout_pipeline<S, F>(
+    props: &ArrowBatchProps,
+    oneof: &str,
+    mut make_pipeline: F,
+) -> Result<(UnboundedSender<DynamicMessage>, LoopJoinSet)>
+where
+    S: BufferSink + 'static,
+    F: FnMut(&str, ArrowBatchProps) -> Result<PipelineBuilder<S>>,
+{
+    let mut tasks = LoopJoinSet::new();
+
+    let routes = oneof_variant_props(props, oneof)?
+        .into_iter()
+        .map(|(variant, variant_props)| {
+            let field = props
+                .descriptor
+                .get_field_by_name(&variant)
+                .expect("variant came from this descriptor");
+            let head = make_pipeline(&variant, variant_props)?.spawn_into(&mut tasks)?;
+            Ok((field, head))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let (head, mut rx_msg) = unbounded_channel::<DynamicMessage>();
+    tasks.spawn(async move {
+        loop {
+            let msg = rx_msg
+                .recv()
+                .await
+                .ok_or_else(|| KatinssIngestorError::PipelineClosed)?;
+
+            if let Some((_, route)) = routes.iter().find(|(field, _)| msg.has_field(field)) {
+                route
+                    .send(msg)
+                    .map_err(|_| KatinssIngestorError::PipelineClosed)?;
+            }
+        }
+    });
+
+    Ok((head, tasks))
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_schema::Schema;
+
+    use katniss_test::descriptor_pool;
+
+    use super::*;
+
+    fn names(schema: &Schema) -> Vec<&str> {
+        schema.fields().iter().map(|f| f.name().as_str()).collect()
+    }
+
+    #[test]
+    fn each_variant_gets_the_shared_fields_and_itself() -> anyhow::Result<()> {
+        let props = ArrowBatchProps::try_new(
+            descriptor_pool()?,
+            "eto.pb2arrow.tests.spacecorp.Packet".to_owned(),
+        )?;
+
+        let variants = oneof_variant_props(&props, "msg")?;
+        assert_eq!(variants.len(), 5);
+
+        let (name, jump_drive) = &variants[1];
+        assert_eq!(name, "jump_drive_status");
+        assert_eq!(
+            names(&jump_drive.schema),
+            vec!["timestamp", "sender_uid", "jump_drive_status"]
+        );
+
+        assert!(matches!(
+            oneof_variant_props(&props, "nope"),
+            Err(KatinssIngestorError::UnknownOneof(_))
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,132 @@
+use parquet::{
+    arrow::arrow_reader::{ParquetRecordBatchReader, ParquetRecordBatchReaderBuilder},
+    file::reader::ChunkReader,
+};
+
+use katniss_pb2arrow::{
+    exports::{prost_reflect::MessageDescriptor, DynamicMessage},
+    ArrowToProtoConverter,
+};
+
+use crate::{errors::KatinssIngestorError, sinks::descriptor_from_metadata, Result};
+
+/// Reads a parquet file back into protobuf messages, i.e. to check a round trip or to replay
+/// archived data. Files written with ParquetEncoder::with_descriptor know their own message type
+pub struct ParquetMessageReader {
+    converter: ArrowToProtoConverter,
+    batches: ParquetRecordBatchReader,
+    pending: std::vec::IntoIter<DynamicMessage>,
+}
+
+impl ParquetMessageReader {
+    /// Uses the descriptor embedded in the file's footer
+    pub fn try_new<R: ChunkReader + 'static>(file: R) -> Result<Self> {
+        let builder = ParquetRecordBatchReaderBuilder::try_new(file)?;
+        let key_value_metadata = builder.metadata().file_metadata().key_value_metadata();
+        let descriptor = descriptor_from_metadata(key_value_metadata)?
+            .ok_or(KatinssIngestorError::NoEmbeddedDescriptor)?;
+        Self::from_builder(builder, descriptor)
+    }
+
+    /// For files without an embedded descriptor
+    pub fn try_new_with_descriptor<R: ChunkReader + 'static>(
+        file: R,
+        descriptor: MessageDescriptor,
+    ) -> Result<Self> {
+        Self::from_builder(ParquetRecordBatchReaderBuilder::try_new(file)?, descriptor)
+    }
+
+    fn from_builder<R: ChunkReader + 'static>(
+        builder: ParquetRecordBatchReaderBuilder<R>,
+        descriptor: MessageDescriptor,
+    ) -> Result<Self> {
+        Ok(Self {
+            converter: ArrowToProtoConverter::new(descriptor),
+            batches: builder.build()?,
+            pending: Vec::new().into_iter(),
+        })
+    }
+
+    pub fn descriptor(&self) -> &MessageDescriptor {
+        self.converter.descriptor()
+    }
+}
+
+impl Iterator for ParquetMessageReader {
+    type Item = Result<DynamicMessage>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(msg) = self.pending.next() {
+                return Some(Ok(msg));
+            }
+
+            let batch = match self.batches.next()? {
+                Ok(batch) => batch,
+                Err(err) => return Some(Err(err.into())),
+            };
+            match self.converter.messages(&batch) {
+                Ok(messages) => self.pending = messages.into_iter(),
+                Err(err) => return Some(Err(err.into())),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use object_store::{memory::InMemory, path::Path};
+
+    use katniss_pb2arrow::ArrowBatchProps;
+    use katniss_test::{
+        descriptor_pool,
+        protos::spacecorp::{Packet, Timestamp},
+        test_util::ProtoBatch,
+    };
+
+    use super::*;
+    use crate::{
+        sinks::{ParquetEncoder, StoreSink},
+        temporal_rotator::TemporalBuffer,
+    };
+
+    #[tokio::test]
+    async fn it_reads_back_what_was_written() -> anyhow::Result<()> {
+        let props = ArrowBatchProps::try_new(
+            descriptor_pool()?,
+            "eto.pb2arrow.tests.spacecorp.Packet".to_owned(),
+        )?;
+        let packets: Vec<_> = (0..5)
+            .map(|sender_uid| Packet {
+                timestamp: Some(Timestamp::system_now()),
+                sender_uid,
+                msg: None,
+            })
+            .collect();
+        let batch = ProtoBatch::SpaceCorp(&packets).arrow_batch()?;
+
+        let sink = StoreSink::new(
+            Box::new(InMemory::new()),
+            Path::from("packets"),
+            batch.schema(),
+            ParquetEncoder::new().with_descriptor(&props.descriptor),
+        );
+        let location = sink
+            .write(TemporalBuffer {
+                begin_at: Utc.timestamp_millis_opt(1000).unwrap(),
+                end_at: Utc.timestamp_millis_opt(2000).unwrap(),
+                batches: vec![batch],
+            })
+            .await?;
+        let bytes = sink.store().get(&location).await?.bytes().await?;
+
+        let reader = ParquetMessageReader::try_new(bytes)?;
+        assert_eq!(reader.descriptor(), &props.descriptor);
+        let read = reader
+            .map(|msg| Ok(msg?.transcode_to::<Packet>()?))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        assert_eq!(read, packets);
+        Ok(())
+    }
+}
@@ -0,0 +1,691 @@
+use std::borrow::Cow;
+use std::convert::Infallible;
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use prost::Message;
+use tokio::{
+    select,
+    sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+    task::{block_in_place, JoinSet},
+    time::{interval, sleep, Instant, MissedTickBehavior},
+};
+
+use katniss_pb2arrow::exports::{
+    prost_reflect::{DynamicMessage, MessageDescriptor},
+    RecordBatch,
+};
+use katniss_pb2arrow::{ArrowBatchProps, KatnissArrowError};
+
+use crate::ack::{Ack, PendingAcks};
+use crate::dedup::{DedupSnapshot, Deduplicator};
+use crate::errors::KatinssIngestorError;
+use crate::event_time::{EventTime, EventTimeRotator};
+use crate::fan_in::{source_tagged_schema, FanIn, IntoSourced, Sourced};
+use crate::filter::Predicate;
+use crate::live_buffers::LiveBuffers;
+use crate::load_shed::{LoadShedding, ShedQueue};
+use crate::rate_limit::{RateLimit, RateLimiter};
+use crate::reorder::Reorder;
+use crate::schema_guard::SchemaGuard;
+use crate::schema_reload::SchemaReloader;
+use crate::sinks::BufferSink;
+use crate::status::{PipelineEvent, PipelineHandle, Stage, StageStatus};
+use crate::supervisor::RestartPolicy;
+use crate::temporal_rotator::{TemporalBuffer, TemporalRotator};
+use crate::Result;
+
+/// Set Of Tokio Tasks that never return unless they error
+pub type LoopJoinSet = JoinSet<Result<Infallible>>; // (Infallible used in place of !)
+
+/// Start a pipeline that ingests dynamic messages into a sink
+/// Returns:
+/// * a channel that functions as the head of the pipeline
+/// * A Set of Infinite Loop Futures for:
+///     - ArrowEncoding
+///     - Sink Writing (i.e. Lance, Flight)
+pub async fn ingestion_pipeline<S: BufferSink + 'static>(
+    props: ArrowBatchProps,
+    batch_period: std::time::Duration,
+    sink: S,
+) -> Result<(UnboundedSender<DynamicMessage>, LoopJoinSet)> {
+    PipelineBuilder::new(props, batch_period, sink).build()
+}
+
+/// Configures everything between the head channel and the sink
+pub struct PipelineBuilder<S: BufferSink> {
+    props: ArrowBatchProps,
+    batch_period: std::time::Duration,
+    sink: S,
+    live_buffers: Option<LiveBuffers>,
+    event_time: Option<EventTime>,
+    aligned_windows: bool,
+    dedup: Option<Deduplicator>,
+    filters: Vec<Predicate>,
+    rate_limit: Option<RateLimit>,
+    load_shedding: Option<LoadShedding>,
+    reorder: Option<Reorder>,
+    restart_policy: RestartPolicy,
+    status: PipelineHandle,
+    reloader: SchemaReloader,
+    reloads: UnboundedReceiver<ArrowBatchProps>,
+    previous_descriptor: Option<MessageDescriptor>,
+}
+
+impl<S: BufferSink + 'static> PipelineBuilder<S> {
+    pub fn new(props: ArrowBatchProps, batch_period: std::time::Duration, sink: S) -> Self {
+        let (reload_tx, reloads) = unbounded_channel();
+        let status = PipelineHandle::default();
+        Self {
+            reloader: SchemaReloader::new(reload_tx, &props, status.clone()),
+            reloads,
+            previous_descriptor: None,
+            props,
+            batch_period,
+            sink,
+            live_buffers: None,
+            event_time: None,
+            aligned_windows: false,
+            dedup: None,
+            filters: Vec::new(),
+            rate_limit: None,
+            load_shedding: None,
+            reorder: None,
+            restart_policy: RestartPolicy::default(),
+            status,
+        }
+    }
+
+    /// Only ingest messages that match the predicate, checked before anything else so
+    /// uninteresting traffic is as cheap as possible. Adding several filters ANDs them together.
+    pub fn with_filter(mut self, predicate: Predicate) -> Self {
+        self.filters.push(predicate);
+        self
+    }
+
+    /// Handle for observing the pipeline once it's been built
+    pub fn handle(&self) -> PipelineHandle {
+        self.status.clone()
+    }
+
+    /// Handle for swapping in a new version of the message once the pipeline's running,
+    /// see SchemaReloader::reload
+    pub fn schema_reloader(&self) -> SchemaReloader {
+        self.reloader.clone()
+    }
+
+    /// Which changes to the message are accepted on reload and at startup,
+    /// see SchemaGuard::default for what's accepted without one
+    pub fn with_schema_guard(self, guard: SchemaGuard) -> Self {
+        self.reloader.set_guard(guard);
+        self
+    }
+
+    /// The version of the message the sink's existing data was written with, i.e. from
+    /// lance_descriptor or descriptor_from_metadata. The pipeline won't start if the
+    /// schema guard rejects the changes since
+    pub fn with_previous_descriptor(mut self, descriptor: MessageDescriptor) -> Self {
+        self.previous_descriptor = Some(descriptor);
+        self
+    }
+
+    /// Throttle messages after filtering, so a misbehaving producer can't starve the encoder
+    pub fn with_rate_limit(mut self, rate_limit: RateLimit) -> Self {
+        self.rate_limit = Some(rate_limit);
+        self
+    }
+
+    /// Bound how many messages can queue up ahead of the encoder, shedding the excess
+    pub fn with_load_shedding(mut self, load_shedding: LoadShedding) -> Self {
+        self.load_shedding = Some(load_shedding);
+        self
+    }
+
+    /// Drop messages whose id has already been seen, the dedup window rotates with the buffers
+    pub fn with_dedup(mut self, dedup: Deduplicator) -> Self {
+        self.dedup = Some(dedup);
+        self
+    }
+
+    /// Retry failed sink writes instead of letting the first one take the pipeline down
+    pub fn with_restart_policy(mut self, restart_policy: RestartPolicy) -> Self {
+        self.restart_policy = restart_policy;
+        self
+    }
+
+    /// Sort messages by event time (within the reorder's window) before they're batched
+    pub fn with_reorder(mut self, reorder: Reorder) -> Self {
+        self.reorder = Some(reorder);
+        self
+    }
+
+    /// Window messages by a timestamp field on the message rather than by when they arrive
+    pub fn with_event_time(mut self, event_time: EventTime) -> Self {
+        self.event_time = Some(event_time);
+        self
+    }
+
+    /// Rotate arrival time buffers on multiples of the batch period since the epoch,
+    /// event time windows always are
+    pub fn with_aligned_windows(mut self) -> Self {
+        self.aligned_windows = true;
+        self
+    }
+
+    /// Keep live_buffers up to date with data that hasn't been written to the sink yet
+    pub fn with_live_buffers(mut self, live_buffers: LiveBuffers) -> Self {
+        self.live_buffers = Some(live_buffers);
+        self
+    }
+
+    /// Spawns the pipeline's tasks, must be called from within a tokio runtime
+    pub fn build(self) -> Result<(UnboundedSender<DynamicMessage>, LoopJoinSet)> {
+        let mut tasks = JoinSet::new();
+        let head = self.spawn_into(&mut tasks)?;
+        Ok((head, tasks))
+    }
+
+    /// Like build but spawns the tasks onto an existing set, i.e. to run several pipelines together
+    pub fn spawn_into(self, tasks: &mut LoopJoinSet) -> Result<UnboundedSender<DynamicMessage>> {
+        let (head, rx_msg) = unbounded_channel();
+        self.spawn_with_head(rx_msg, None, tasks)?;
+        Ok(head)
+    }
+
+    /// Like build but every message is sent with an Ack that's fired once the sink has written it
+    pub fn build_acked(self) -> Result<(UnboundedSender<(DynamicMessage, Ack)>, LoopJoinSet)> {
+        let mut tasks = JoinSet::new();
+        let (head, rx_msg) = unbounded_channel();
+        self.spawn_with_head(rx_msg, None, &mut tasks)?;
+        Ok((head, tasks))
+    }
+
+    /// Like build but messages from every source are merged into one rotator, with
+    /// each row tagged in source_column. The sink needs to expect the extra column,
+    /// see source_tagged_schema.
+    pub fn build_fan_in(self, source_column: &str) -> Result<(FanIn, LoopJoinSet)> {
+        let mut tasks = JoinSet::new();
+        let (head, rx_msg) = unbounded_channel();
+        self.spawn_with_head(rx_msg, Some(source_column), &mut tasks)?;
+        Ok((FanIn::new(head), tasks))
+    }
+
+    fn spawn_with_head<T: IntoSourced>(
+        self,
+        mut rx_msg: UnboundedReceiver<T>,
+        source_column: Option<&str>,
+        tasks: &mut LoopJoinSet,
+    ) -> Result<()> {
+        if self.batch_period.is_zero() {
+            // tokio's interval panics on a zero period, after the other tasks are spawned
+            return Err(KatinssIngestorError::InvalidConfig(
+                "batch period must be longer than 0".to_owned(),
+            ));
+        }
+        let Self {
+            props,
+            batch_period,
+            mut sink,
+            live_buffers,
+            event_time,
+            aligned_windows,
+            mut dedup,
+            filters,
+            rate_limit,
+            load_shedding,
+            mut reorder,
+            restart_policy,
+            status,
+            reloader,
+            mut reloads,
+            previous_descriptor,
+        } = self;
+
+        props.check_fingerprint()?;
+        // before anything is spawned so a bad limit doesn't leave a task running
+        let mut rate_limiter = rate_limit.as_ref().map(RateLimiter::new).transpose()?;
+        if let Some(previous) = &previous_descriptor {
+            let warnings = reloader.guard().check(previous, &props.descriptor)?;
+            status.update(|s| s.schema_warnings.extend(warnings));
+        }
+
+        let mut head = match load_shedding {
+            None => Head::Channel(rx_msg),
+            Some(load_shedding) => {
+                let queue = Arc::new(ShedQueue::new(load_shedding));
+                let (intake, handle) = (queue.clone(), status.clone());
+                tasks.spawn(async move {
+                    while let Some(msg) = rx_msg.recv().await {
+                        if let Some(shed) = intake.push(msg) {
+                            shed.into_sourced().acknowledge();
+                            handle.update(|s| s.dropped_shed += 1);
+                        }
+                    }
+                    intake.close();
+                    Err(KatinssIngestorError::PipelineClosed)
+                });
+                Head::Shedding(queue)
+            }
+        };
+
+        let mut rotator = Rotator::new(
+            &props,
+            event_time.clone(),
+            aligned_windows,
+            source_column,
+            batch_period,
+        )?;
+        let mut descriptor = props.descriptor;
+        let source_column = source_column.map(str::to_owned);
+        // rotators start their drop counts over, these are from the ones before a reload
+        let mut dropped_before_reload = (0, 0);
+
+        let mut acks = PendingAcks::default();
+        let (tx_buffer, mut rx_buffer) = unbounded_channel::<ToSink>();
+
+        let handle = status.clone();
+        tasks.spawn(async move {
+            handle
+                .run_stage(Stage::Encoder, async {
+                    let mut rotation_timer = interval(batch_period);
+                    rotation_timer.set_missed_tick_behavior(MissedTickBehavior::Delay);
+                    let mut last_message_at = Instant::now();
+
+                    loop {
+                        let next = select! {
+                            // so messages sent after a reload never beat it to the encoder
+                            biased;
+                            Some(props) = reloads.recv() => Next::Reload(props),
+                            msg = head.recv() => msg.map_or(Next::Closed, Next::Message),
+                            _ = rotation_timer.tick() => Next::Tick,
+                        };
+                        let closed = matches!(next, Next::Closed);
+                        let mut reloaded = None;
+
+                        let finished = match next {
+                            Next::Message(msg) => {
+                                let mut incoming = msg.into_sourced();
+                                if incoming.msg.descriptor() != descriptor {
+                                    incoming.msg = transcode(&incoming.msg, &descriptor)?;
+                                }
+                                last_message_at = Instant::now();
+                                handle.update(|s| mark_active(&mut s.encoder));
+
+                                if !filters.iter().all(|f| f.matches(&incoming.msg)) {
+                                    incoming.acknowledge();
+                                    handle.update(|s| s.dropped_filtered += 1);
+                                    continue;
+                                }
+
+                                if let Some(limiter) = &mut rate_limiter {
+                                    if !limiter.admit(incoming.msg.encoded_len()).await {
+                                        incoming.acknowledge();
+                                        handle.update(|s| s.dropped_rate_limited += 1);
+                                        continue;
+                                    }
+                                }
+
+                                if let Some(dedup) = &mut dedup {
+                                    if dedup.check_and_insert(&incoming.msg) {
+                                        incoming.acknowledge();
+                                        handle.update(|s| s.dropped_duplicates += 1);
+                                        continue;
+                                    }
+                                }
+
+                                let ready = match &mut reorder {
+                                    Some(reorder) => reorder.push(incoming)?,
+                                    None => vec![incoming],
+                                };
+
+                                block_in_place(|| rotator.ingest_all(ready, &mut acks))?
+                            }
+                            // Nothing arrived for a while, rotate anyway so a quiet stream still
+                            // gets flushed. Only give up on stragglers once it's been a whole period,
+                            // or once the head has been dropped and nothing else can arrive.
+                            Next::Tick | Next::Closed => {
+                                let idle = closed || last_message_at.elapsed() >= batch_period;
+                                let held = match &mut reorder {
+                                    Some(reorder) if idle => reorder.drain(),
+                                    _ => Vec::new(),
+                                };
+
+                                block_in_place(|| -> Result<Vec<TemporalBuffer>> {
+                                    let mut finished = rotator.ingest_all(held, &mut acks)?;
+                                    if closed {
+                                        finished.extend(rotator.flush_all()?);
+                                    } else {
+                                        finished.extend(rotator.rotate_if_due(Utc::now(), idle)?);
+                                    }
+                                    Ok(finished)
+                                })?
+                            }
+                            // Finish what's buffered with the old schema before starting over
+                            // with the new one, the sink hears about it in between
+                            Next::Reload(props) => {
+                                let held = reorder.as_mut().map_or_else(Vec::new, Reorder::drain);
+                                let finished =
+                                    block_in_place(|| -> Result<Vec<TemporalBuffer>> {
+                                        let mut finished = rotator.ingest_all(held, &mut acks)?;
+                                        finished.extend(rotator.flush_all()?);
+                                        Ok(finished)
+                                    })?;
+
+                                report_warnings(&handle, rotator.take_warnings());
+                                let (late, missing) = rotator.dropped();
+                                dropped_before_reload.0 += late;
+                                dropped_before_reload.1 += missing;
+                                rotator = Rotator::new(
+                                    &props,
+                                    event_time.clone(),
+                                    aligned_windows,
+                                    source_column.as_deref(),
+                                    batch_period,
+                                )?;
+                                descriptor = props.descriptor.clone();
+
+                                reloaded = Some(match &source_column {
+                                    Some(column) => ArrowBatchProps {
+                                        schema: source_tagged_schema(&props.schema, column),
+                                        ..props
+                                    },
+                                    None => props,
+                                });
+                                finished
+                            }
+                        };
+
+                        let mut dedup_snapshot = match &mut dedup {
+                            Some(dedup) if !finished.is_empty() => dedup.rotate(),
+                            _ => None,
+                        };
+
+                        if let Some(live_buffers) = &live_buffers {
+                            live_buffers.sync(&rotator.current_batches(), &finished);
+                        }
+
+                        handle.update(|s| {
+                            s.buffered_rows =
+                                rotator.buffered_rows() + reorder.as_ref().map_or(0, Reorder::len);
+                            s.pending_buffers += finished.len();
+                            let (late, missing) = rotator.dropped();
+                            s.dropped_late = dropped_before_reload.0 + late;
+                            s.dropped_missing_time = dropped_before_reload.1 + missing;
+                        });
+                        report_warnings(&handle, rotator.take_warnings());
+
+                        let last = finished.len().saturating_sub(1);
+                        for (i, last_batch) in finished.into_iter().enumerate() {
+                            let batch_acks = acks.release(&last_batch);
+                            // persisted once the last of the rotation's buffers is written
+                            let snapshot = if i == last {
+                                dedup_snapshot.take()
+                            } else {
+                                None
+                            };
+                            tx_buffer
+                                .send(ToSink::Buffer(last_batch, batch_acks, snapshot))
+                                .map_err(|_| KatinssIngestorError::PipelineClosed)?;
+                        }
+                        if let Some(props) = reloaded {
+                            tx_buffer
+                                .send(ToSink::Reload(props))
+                                .map_err(|_| KatinssIngestorError::PipelineClosed)?;
+                        }
+
+                        // everything's been handed to the sink, which exits once it's written it all
+                        if closed {
+                            return Err(KatinssIngestorError::PipelineClosed);
+                        }
+                    }
+                })
+                .await
+        });
+
+        let handle = status;
+        tasks.spawn(async move {
+            handle
+                .run_stage(Stage::Sink, async {
+                    loop {
+                        let (buf, acks, dedup_snapshot) = match rx_buffer.recv().await {
+                            Some(ToSink::Buffer(buf, acks, snapshot)) => (buf, acks, snapshot),
+                            Some(ToSink::Reload(props)) => {
+                                sink.reload_schema(&props).await?;
+                                continue;
+                            }
+                            None => {
+                                sink.close().await?;
+                                return Err(KatinssIngestorError::PipelineClosed);
+                            }
+                        };
+
+                        let mut attempt = 0;
+                        while let Err(err) = sink.write_buffer(buf.clone()).await {
+                            let Some(wait) = restart_policy.backoff(attempt) else {
+                                return Err(err);
+                            };
+                            handle.update(|s| {
+                                s.sink.errors += 1;
+                                s.sink.last_error = Some(err.to_string());
+                                s.sink.restarts += 1;
+                            });
+                            handle.emit(PipelineEvent::SinkRestarting {
+                                attempt,
+                                error: err.to_string(),
+                                backoff: wait,
+                            });
+                            sleep(wait).await;
+                            attempt += 1;
+                        }
+                        if let Some(snapshot) = dedup_snapshot {
+                            block_in_place(|| snapshot.persist())?;
+                        }
+                        acks.into_iter().for_each(Ack::fire);
+                        handle.update(|s| {
+                            mark_active(&mut s.sink);
+                            s.last_flush_at = s.sink.last_active_at;
+                            s.pending_buffers = s.pending_buffers.saturating_sub(1);
+                        });
+                    }
+                })
+                .await
+        });
+
+        Ok(())
+    }
+}
+
+/// What woke the encoder up
+enum Next<T> {
+    Message(T),
+    Tick,
+    /// Every sender for the head has been dropped
+    Closed,
+    /// A new schema from a SchemaReloader
+    Reload(ArrowBatchProps),
+}
+
+/// What the encoder hands the sink task
+enum ToSink {
+    /// With the dedup index to persist once it's written
+    Buffer(TemporalBuffer, Vec<Ack>, Option<DedupSnapshot>),
+    /// Buffers before this have the old schema, buffers after it the new one
+    Reload(ArrowBatchProps),
+}
+
+/// Re-decodes a message built with another version of the descriptor, fields are matched by number
+fn transcode(msg: &DynamicMessage, descriptor: &MessageDescriptor) -> Result<DynamicMessage> {
+    Ok(DynamicMessage::decode(
+        descriptor.clone(),
+        msg.encode_to_vec().as_slice(),
+    )?)
+}
+
+/// Where the encoder takes messages from
+enum Head<T> {
+    Channel(UnboundedReceiver<T>),
+    Shedding(Arc<ShedQueue<T>>),
+}
+
+impl<T> Head<T> {
+    async fn recv(&mut self) -> Option<T> {
+        match self {
+            Self::Channel(rx) => rx.recv().await,
+            Self::Shedding(queue) => queue.pop().await,
+        }
+    }
+}
+
+fn mark_active(stage: &mut StageStatus) {
+    stage.processed += 1;
+    stage.last_active_at = Some(Utc::now());
+}
+
+fn report_warnings(handle: &PipelineHandle, warnings: Vec<KatnissArrowError>) {
+    if warnings.is_empty() {
+        return;
+    }
+    handle.update(|s| s.conversion_warnings += warnings.len() as u64);
+    for warning in warnings {
+        handle.emit(PipelineEvent::ConversionWarning(warning.to_string()));
+    }
+}
+
+/// Arrival time rotation unless the pipeline has been given an EventTime
+enum Rotator {
+    ArrivalTime(TemporalRotator),
+    EventTime(EventTimeRotator),
+}
+
+impl Rotator {
+    fn new(
+        props: &ArrowBatchProps,
+        event_time: Option<EventTime>,
+        aligned: bool,
+        source_column: Option<&str>,
+        batch_period: std::time::Duration,
+    ) -> Result<Self> {
+        if let Some(event_time) = event_time {
+            let rotator = EventTimeRotator::new(props, event_time, batch_period);
+            return Ok(Self::EventTime(match source_column {
+                Some(column) => rotator.with_source_column(column),
+                None => rotator,
+            }));
+        }
+
+        let mut rotator = TemporalRotator::new(props, Utc::now(), batch_period)?;
+        if let Some(column) = source_column {
+            rotator = rotator.with_source_column(props, column);
+        }
+        if aligned {
+            rotator = rotator.with_aligned_windows()?;
+        }
+        Ok(Self::ArrivalTime(rotator))
+    }
+
+    fn ingest_potentially_blocking(
+        &mut self,
+        msg: DynamicMessage,
+        source: Option<&str>,
+    ) -> Result<Vec<TemporalBuffer>> {
+        match self {
+            Self::ArrivalTime(rotator) => Ok(rotator
+                .ingest_sourced_potentially_blocking(msg, source, Utc::now())?
+                .into_iter()
+                .collect()),
+            Self::EventTime(rotator) => rotator.ingest_sourced_potentially_blocking(msg, source),
+        }
+    }
+
+    /// Ingests messages in order, holding on to their acks until their buffer is finished
+    fn ingest_all(
+        &mut self,
+        messages: Vec<Sourced>,
+        acks: &mut PendingAcks,
+    ) -> Result<Vec<TemporalBuffer>> {
+        let mut finished = Vec::new();
+        for Sourced { source, msg, ack } in messages {
+            finished.extend(self.ingest_potentially_blocking(msg, source.as_deref())?);
+            if let Some(ack) = ack {
+                match self.last_window() {
+                    Some(begin_at) => acks.hold(begin_at, ack),
+                    None => ack.fire(),
+                }
+            }
+        }
+        Ok(finished)
+    }
+
+    /// Rotation that doesn't wait for the next message. Event time windows can't tell
+    /// time has passed without new events, so they're only flushed once the stream has gone idle.
+    fn rotate_if_due(&mut self, now: DateTime<Utc>, idle: bool) -> Result<Vec<TemporalBuffer>> {
+        match self {
+            Self::ArrivalTime(rotator) => Ok(rotator.rotate_if_due(now)?.into_iter().collect()),
+            Self::EventTime(rotator) if idle => rotator.flush_all(),
+            Self::EventTime(_) => Ok(Vec::new()),
+        }
+    }
+
+    /// begin_at of the buffer the last ingested message went into, None if it was dropped
+    fn last_window(&self) -> Option<DateTime<Utc>> {
+        match self {
+            Self::ArrivalTime(rotator) => Some(rotator.current.begin_at),
+            Self::EventTime(rotator) => rotator.last_window(),
+        }
+    }
+
+    /// Finish every buffer that has anything in it, i.e. when the pipeline is shutting down
+    fn flush_all(&mut self) -> Result<Vec<TemporalBuffer>> {
+        match self {
+            Self::ArrivalTime(rotator) => Ok(rotator.rotate(Utc::now())?.into_iter().collect()),
+            Self::EventTime(rotator) => rotator.flush_all(),
+        }
+    }
+
+    fn current_batches(&self) -> Cow<'_, [RecordBatch]> {
+        match self {
+            Self::ArrivalTime(rotator) => Cow::Borrowed(&rotator.current.batches),
+            Self::EventTime(rotator) => Cow::Owned(rotator.open_batches()),
+        }
+    }
+
+    /// Rows in buffers that are still open, including ones not yet encoded to arrow
+    fn buffered_rows(&self) -> usize {
+        match self {
+            Self::ArrivalTime(rotator) => rotator.current.num_rows() + rotator.converter.len(),
+            Self::EventTime(rotator) => rotator.buffered_rows(),
+        }
+    }
+
+    fn take_warnings(&mut self) -> Vec<KatnissArrowError> {
+        match self {
+            Self::ArrivalTime(rotator) => rotator.take_warnings(),
+            Self::EventTime(rotator) => rotator.take_warnings(),
+        }
+    }
+
+    /// (late, missing event time)
+    fn dropped(&self) -> (u64, u64) {
+        match self {
+            Self::ArrivalTime(_) => (0, 0),
+            Self::EventTime(rotator) => (rotator.dropped_late, rotator.dropped_missing_time),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use katniss_test::descriptor_pool;
+
+    use super::*;
+    use crate::sinks::JsonLinesSink;
+
+    #[tokio::test]
+    async fn zero_periods_are_rejected_before_spawning() -> anyhow::Result<()> {
+        let props = ArrowBatchProps::try_new(descriptor_pool()?, "spacecorp.Packet".to_owned())?;
+        let sink = JsonLinesSink::new(Vec::new());
+        let built = PipelineBuilder::new(props, std::time::Duration::ZERO, sink).build();
+        assert!(matches!(built, Err(KatinssIngestorError::InvalidConfig(_))));
+        Ok(())
+    }
+}
@@ -0,0 +1,263 @@
+use std::time::Duration;
+
+use katniss_pb2arrow::{exports::prost_reflect::DescriptorPool, ArrowBatchConfig, ArrowBatchProps};
+
+use crate::{
+    errors::KatinssIngestorError,
+    event_time::EventTime,
+    load_shed::LoadShedding,
+    pipeline::PipelineBuilder,
+    rate_limit::RateLimit,
+    schema_guard::SchemaGuard,
+    sinks::{BufferSink, SinkConfig, TeeSink},
+    supervisor::RestartPolicy,
+    Result,
+};
+
+/// The settings of a PipelineBuilder and its sinks that aren't code, so with the "serde" feature
+/// they can be loaded from a config file and logged or diffed. Durations are seconds, i.e.
+///
+/// ```toml
+/// message = "spacecorp.Packet"
+/// conversion_mode = "lenient"
+/// period = 60
+///
+/// [restart_policy]
+/// max_restarts = 5
+/// backoff = 0.5
+///
+/// [load_shedding]
+/// capacity = 100000
+/// policy = { sample = 10 }
+///
+/// [[sink]]
+/// output = "file:///data/packets.lance"
+/// ```
+///
+/// Deserializing doesn't check the values, builder does, see validate
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PipelineConfig {
+    #[cfg_attr(feature = "serde", serde(flatten))]
+    pub batch: ArrowBatchConfig,
+    /// How long a buffer collects messages before it's rotated out to the sink
+    #[cfg_attr(feature = "serde", serde(with = "secs"))]
+    pub period: Duration,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub event_time: Option<EventTime>,
+    /// Rotate on multiples of period since the epoch, i.e. on the minute for period = 60
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub align_windows: bool,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub rate_limit: Option<RateLimit>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub load_shedding: Option<LoadShedding>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub restart_policy: RestartPolicy,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub schema_guard: SchemaGuard,
+    /// Written to in order by sink, see SinkConfig
+    #[cfg_attr(feature = "serde", serde(default, rename = "sink"))]
+    pub sinks: Vec<SinkConfig>,
+}
+
+impl PipelineConfig {
+    pub fn new(batch: ArrowBatchConfig, period: Duration) -> Self {
+        Self {
+            batch,
+            period,
+            event_time: None,
+            align_windows: false,
+            rate_limit: None,
+            load_shedding: None,
+            restart_policy: RestartPolicy::default(),
+            schema_guard: SchemaGuard::default(),
+            sinks: Vec::new(),
+        }
+    }
+
+    pub fn with_sink(mut self, sink: SinkConfig) -> Self {
+        self.sinks.push(sink);
+        self
+    }
+
+    /// Errors for the settings a running pipeline would panic on or never get past,
+    /// i.e. a zero period or a rate limit of 0 messages per second
+    pub fn validate(&self) -> Result<()> {
+        if self.period.is_zero() {
+            return Err(KatinssIngestorError::InvalidConfig(
+                "period must be longer than 0".to_owned(),
+            ));
+        }
+        if let Some(rate_limit) = &self.rate_limit {
+            rate_limit.validate()?;
+        }
+        Ok(())
+    }
+
+    /// The configured sinks behind one TeeSink, for the props builder is given
+    pub fn sink(&self, props: &ArrowBatchProps) -> Result<TeeSink> {
+        let sinks = self
+            .sinks
+            .iter()
+            .map(|sink| sink.sink(props))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(TeeSink::from(sinks))
+    }
+
+    /// A builder for the configured message in the pool, ready for the rest of the with_ methods.
+    /// Fails if the config doesn't validate
+    pub fn builder<S: BufferSink + 'static>(
+        &self,
+        pool: DescriptorPool,
+        sink: S,
+    ) -> Result<PipelineBuilder<S>> {
+        self.builder_with_props(self.batch.props(pool)?, sink)
+    }
+
+    /// Like builder for props made some other way, i.e. projected further by the caller
+    pub fn builder_with_props<S: BufferSink + 'static>(
+        &self,
+        props: ArrowBatchProps,
+        sink: S,
+    ) -> Result<PipelineBuilder<S>> {
+        self.validate()?;
+        let mut builder = PipelineBuilder::new(props, self.period, sink)
+            .with_restart_policy(self.restart_policy.clone())
+            .with_schema_guard(self.schema_guard);
+        if let Some(event_time) = &self.event_time {
+            builder = builder.with_event_time(event_time.clone());
+        }
+        if self.align_windows {
+            builder = builder.with_aligned_windows();
+        }
+        if let Some(rate_limit) = &self.rate_limit {
+            builder = builder.with_rate_limit(rate_limit.clone());
+        }
+        if let Some(load_shedding) = self.load_shedding {
+            builder = builder.with_load_shedding(load_shedding);
+        }
+        Ok(builder)
+    }
+}
+
+/// Durations as (fractional) seconds, for `#[serde(with = "secs")]`
+#[cfg(feature = "serde")]
+pub(crate) mod secs {
+    use std::time::Duration;
+
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        duration: &Duration,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_f64(duration.as_secs_f64())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<Duration, D::Error> {
+        Duration::try_from_secs_f64(f64::deserialize(deserializer)?).map_err(D::Error::custom)
+    }
+
+    pub mod option {
+        use super::*;
+
+        pub fn serialize<S: Serializer>(
+            duration: &Option<Duration>,
+            serializer: S,
+        ) -> std::result::Result<S::Ok, S::Error> {
+            match duration {
+                Some(duration) => serializer.serialize_some(&duration.as_secs_f64()),
+                None => serializer.serialize_none(),
+            }
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> std::result::Result<Option<Duration>, D::Error> {
+            Option::<f64>::deserialize(deserializer)?
+                .map(|secs| Duration::try_from_secs_f64(secs).map_err(D::Error::custom))
+                .transpose()
+        }
+    }
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+    use katniss_pb2arrow::ConversionMode;
+    use katniss_test::descriptor_pool;
+
+    use super::*;
+    use crate::sinks::Codec;
+
+    #[tokio::test]
+    async fn pipelines_can_be_configured_from_json() -> anyhow::Result<()> {
+        let json = r#"{
+            "message": "spacecorp.Packet",
+            "conversion_mode": "lenient",
+            "period": 0.5,
+            "align_windows": true,
+            "event_time": {"field_path": "timestamp.seconds", "unit": "second"},
+            "restart_policy": {"max_restarts": 3},
+            "load_shedding": {"capacity": 10, "policy": {"sample": 4}},
+            "sink": [
+                {"output": "file:///replaced", "format": "parquet", "compression": "zstd",
+                 "statistics": {"sender_uid": "page"}, "partition_by": ["time:hour"]}
+            ]
+        }"#;
+        let config: PipelineConfig = serde_json::from_str(json)?;
+        assert_eq!(config.period, Duration::from_millis(500));
+        assert!(config.align_windows);
+        assert_eq!(config.batch.conversion_mode, ConversionMode::Lenient);
+        assert_eq!(
+            config.restart_policy.backoff(0),
+            Some(Duration::from_millis(100))
+        );
+        assert_eq!(config.schema_guard, SchemaGuard::default());
+
+        let logged = serde_json::to_value(&config)?;
+        assert_eq!(logged["load_shedding"]["policy"]["sample"], 4);
+        assert_eq!(logged["event_time"]["field_path"], "timestamp.seconds");
+        assert_eq!(logged["event_time"]["unit"], "second");
+        assert_eq!(logged["sink"][0]["partition_by"][0], "time:hour");
+        assert_eq!(config.sinks[0].compression, Codec::Zstd);
+        let reloaded: PipelineConfig = serde_json::from_value(logged.clone())?;
+        assert_eq!(serde_json::to_value(&reloaded)?, logged);
+
+        let (_head, tasks) = config
+            .builder(descriptor_pool()?, TeeSink::new())?
+            .build()?;
+        drop(tasks);
+
+        let dir = tempfile::tempdir()?;
+        let mut config = config;
+        config.sinks[0].output = format!("file://{}", dir.path().display());
+        let props = config.batch.props(descriptor_pool()?)?;
+        config.sink(&props)?;
+
+        let unknown = PipelineConfig::new(ArrowBatchConfig::new("Nope"), config.period);
+        assert!(unknown.builder(descriptor_pool()?, TeeSink::new()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn configs_that_would_panic_dont_build() -> anyhow::Result<()> {
+        for json in [
+            r#"{"message": "spacecorp.Packet", "period": 0}"#,
+            r#"{"message": "spacecorp.Packet", "period": 1, "rate_limit": {"messages_per_sec": 0}}"#,
+            r#"{"message": "spacecorp.Packet", "period": 1, "rate_limit": {"bytes_per_sec": -5}}"#,
+        ] {
+            let config: PipelineConfig = serde_json::from_str(json)?;
+            assert!(matches!(
+                config.builder(descriptor_pool()?, TeeSink::new()),
+                Err(KatinssIngestorError::InvalidConfig(_))
+            ));
+        }
+
+        let sink = r#"{"output": "file:///tmp", "partition_by": ["time:week"]}"#;
+        assert!(serde_json::from_str::<SinkConfig>(sink).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,446 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use arrow_schema::{DataType, Field, Fields, Schema};
+
+use katniss_pb2arrow::exports::prost_reflect::{
+    EnumDescriptor, FieldDescriptor, Kind, MessageDescriptor,
+};
+
+use crate::{errors::KatinssIngestorError, sinks::descriptor_from_schema, Result};
+
+/// Generates a proto3 file with a message for the schema, i.e. to bootstrap definitions for a
+/// parquet dataset that wasn't written from protobuf. `message` is fully qualified, i.e.
+/// acme.telemetry.Reading puts Reading in package acme.telemetry. Structs become nested messages,
+/// lists repeated fields and timestamps google.protobuf.Timestamp. Schemas written with their
+/// descriptor keep its field numbers, scalar types, enums and oneofs, other columns are numbered
+/// in order. Names that would clash are numbered too, i.e. speed_2 or GpsFix2
+pub fn proto_from_schema(schema: &Schema, message: &str) -> Result<String> {
+    let descriptor = descriptor_from_schema(schema)?;
+    let (package, name) = match message.rsplit_once('.') {
+        Some((package, name)) => (Some(package), name),
+        None => (None, message),
+    };
+
+    let mut imports = BTreeSet::new();
+    let body = message_definition(
+        &identifier(name),
+        schema.fields(),
+        descriptor.as_ref(),
+        0,
+        &mut imports,
+    )?;
+
+    let mut proto = String::from("syntax = \"proto3\";\n\n");
+    if let Some(package) = package {
+        proto += &format!("package {package};\n\n");
+    }
+    for import in &imports {
+        proto += &format!("import \"{import}\";\n");
+    }
+    if !imports.is_empty() {
+        proto += "\n";
+    }
+    Ok(proto + &body)
+}
+
+enum Line {
+    Field(String),
+    Oneof(String, Vec<String>),
+}
+
+fn message_definition(
+    name: &str,
+    fields: &Fields,
+    descriptor: Option<&MessageDescriptor>,
+    depth: usize,
+    imports: &mut BTreeSet<&'static str>,
+) -> Result<String> {
+    let mut lines = Vec::new();
+    let mut nested = Nested::default();
+    let mut field_names = HashSet::new();
+    // columns the descriptor doesn't know go after its highest number so they can't collide
+    let mut next_number = descriptor
+        .and_then(|d| d.fields().map(|f| f.number()).max())
+        .unwrap_or(0)
+        + 1;
+
+    for column in fields {
+        let field = descriptor.and_then(|d| d.get_field_by_name(column.name()));
+        let (repeated, item) = match column.data_type() {
+            DataType::List(item) | DataType::LargeList(item) | DataType::FixedSizeList(item, _) => {
+                (true, item.as_ref())
+            }
+            _ => (false, column),
+        };
+        let ty = nested.type_name(item, column.name(), field.as_ref(), depth, imports)?;
+
+        let number = match &field {
+            Some(field) => field.number(),
+            None => {
+                let number = next_number;
+                next_number += 1;
+                number
+            }
+        };
+        let oneof = field
+            .as_ref()
+            .and_then(|f| f.containing_oneof())
+            .filter(|oneof| !oneof.is_synthetic());
+        let label = match &field {
+            _ if repeated => "repeated ",
+            Some(field) if oneof.is_none() && is_optional_scalar(field) => "optional ",
+            _ => "",
+        };
+        let field_name = numbered(&identifier(column.name()), "_", |name| {
+            field_names.insert(name.to_owned())
+        });
+        let line = format!("{label}{ty} {field_name} = {number};");
+
+        match oneof {
+            Some(oneof) => match lines
+                .iter_mut()
+                .find(|l| matches!(l, Line::Oneof(name, _) if *name == oneof.name()))
+            {
+                Some(Line::Oneof(_, members)) => members.push(line),
+                _ => lines.push(Line::Oneof(oneof.name().to_owned(), vec![line])),
+            },
+            None => lines.push(Line::Field(line)),
+        }
+    }
+
+    let pad = "  ".repeat(depth);
+    let mut definition = format!("{pad}message {name} {{\n");
+    for nested in &nested.definitions {
+        definition += nested;
+    }
+    for line in lines {
+        match line {
+            Line::Field(field) => definition += &format!("{pad}  {field}\n"),
+            Line::Oneof(name, members) => {
+                definition += &format!("{pad}  oneof {name} {{\n");
+                for member in members {
+                    definition += &format!("{pad}    {member}\n");
+                }
+                definition += &format!("{pad}  }}\n");
+            }
+        }
+    }
+    definition += &format!("{pad}}}\n");
+    Ok(definition)
+}
+
+/// Messages and enums defined inside the message being generated, each name once
+#[derive(Default)]
+struct Nested {
+    /// Index of each name's definition
+    names: HashMap<String, usize>,
+    definitions: Vec<String>,
+}
+
+impl Nested {
+    /// The name `define` was given, `base` unless it's taken by a different definition, then
+    /// numbered from 2, i.e. two different structs that are both GpsFix make GpsFix and GpsFix2.
+    /// The same definition twice (i.e. one message type used by two fields) is only added once
+    fn define(
+        &mut self,
+        base: &str,
+        mut define: impl FnMut(&str) -> Result<String>,
+    ) -> Result<String> {
+        for n in 1.. {
+            let name = match n {
+                1 => base.to_owned(),
+                n => format!("{base}{n}"),
+            };
+            let definition = define(&name)?;
+            match self.names.get(&name) {
+                Some(&i) if self.definitions[i] != definition => continue,
+                Some(_) => {}
+                None => {
+                    self.names.insert(name.clone(), self.definitions.len());
+                    self.definitions.push(definition);
+                }
+            }
+            return Ok(name);
+        }
+        unreachable!("numbers run out before names do")
+    }
+
+    fn type_name(
+        &mut self,
+        item: &Field,
+        column: &str,
+        field: Option<&FieldDescriptor>,
+        depth: usize,
+        imports: &mut BTreeSet<&'static str>,
+    ) -> Result<String> {
+        let kind = field.map(FieldDescriptor::kind);
+        Ok(match (item.data_type(), kind) {
+            (DataType::Struct(children), kind) => {
+                let descriptor = kind.as_ref().and_then(Kind::as_message);
+                let name = descriptor
+                    .map(|d| d.name().to_owned())
+                    .unwrap_or_else(|| camel_case(column));
+                self.define(&name, |name| {
+                    message_definition(name, children, descriptor, depth + 1, imports)
+                })?
+            }
+            // unit messages are stored as a bool of whether they're set
+            (DataType::Boolean, Some(Kind::Message(descriptor))) => {
+                let pad = "  ".repeat(depth + 1);
+                self.define(descriptor.name(), |name| {
+                    Ok(format!("{pad}message {name} {{}}\n"))
+                })?
+            }
+            (_, Some(Kind::Enum(descriptor))) => self.define(descriptor.name(), |name| {
+                Ok(enum_definition(name, &descriptor, depth + 1))
+            })?,
+            (_, Some(Kind::Message(_))) | (_, None) => {
+                scalar_type(item.data_type(), imports)?.to_owned()
+            }
+            (_, Some(kind)) => format!("{kind:?}").to_lowercase(),
+        })
+    }
+}
+
+/// proto3 enums have to start with 0, so the zero value goes first and proto2 enums without
+/// one get a NAME_UNSPECIFIED placeholder
+fn enum_definition(name: &str, descriptor: &EnumDescriptor, depth: usize) -> String {
+    let pad = "  ".repeat(depth);
+    let mut definition = format!("{pad}enum {name} {{\n");
+    let (zero, rest): (Vec<_>, Vec<_>) = descriptor.values().partition(|v| v.number() == 0);
+    if zero.is_empty() {
+        let taken: HashSet<_> = rest.iter().map(|v| v.name()).collect();
+        let placeholder = numbered(&format!("{}_UNSPECIFIED", screaming_case(name)), "_", |n| {
+            !taken.contains(n)
+        });
+        definition += &format!("{pad}  {placeholder} = 0;\n");
+    }
+    for value in zero.iter().chain(&rest) {
+        definition += &format!("{pad}  {} = {};\n", value.name(), value.number());
+    }
+    definition + &format!("{pad}}}\n")
+}
+
+/// The proto type for a column without a descriptor field
+fn scalar_type(data_type: &DataType, imports: &mut BTreeSet<&'static str>) -> Result<&'static str> {
+    Ok(match data_type {
+        DataType::Boolean => "bool",
+        DataType::Int8 | DataType::Int16 | DataType::Int32 => "int32",
+        DataType::UInt8 | DataType::UInt16 | DataType::UInt32 => "uint32",
+        DataType::Int64 => "int64",
+        DataType::UInt64 => "uint64",
+        DataType::Float16 | DataType::Float32 => "float",
+        DataType::Float64 => "double",
+        DataType::Utf8 | DataType::LargeUtf8 => "string",
+        DataType::Binary | DataType::LargeBinary | DataType::FixedSizeBinary(_) => "bytes",
+        DataType::Dictionary(_, values) => scalar_type(values, imports)?,
+        DataType::Timestamp(_, _) | DataType::Date32 | DataType::Date64 => {
+            imports.insert("google/protobuf/timestamp.proto");
+            "google.protobuf.Timestamp"
+        }
+        DataType::Duration(_) => {
+            imports.insert("google/protobuf/duration.proto");
+            "google.protobuf.Duration"
+        }
+        other => {
+            return Err(KatinssIngestorError::UnsupportedColumnType(
+                other.to_string(),
+                "proto",
+            ))
+        }
+    })
+}
+
+/// proto3 scalars only keep whether they were set when they're marked optional
+fn is_optional_scalar(field: &FieldDescriptor) -> bool {
+    field.supports_presence() && !matches!(field.kind(), Kind::Message(_))
+}
+
+/// Column names with anything but letters, digits and underscores replaced, i.e. "speed (m/s)"
+/// is speed__m_s_
+fn identifier(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    match name.chars().next() {
+        Some(c) if c.is_ascii_alphabetic() => name,
+        _ => format!("f{name}"),
+    }
+}
+
+/// `base`, or the first of base{sep}2, base{sep}3.. that `accept` takes
+fn numbered(base: &str, sep: &str, mut accept: impl FnMut(&str) -> bool) -> String {
+    if accept(base) {
+        return base.to_owned();
+    }
+    (2..)
+        .map(|n| format!("{base}{sep}{n}"))
+        .find(|name| accept(name))
+        .expect("runs until a name is accepted")
+}
+
+/// JumpDriveMode is JUMP_DRIVE_MODE
+fn screaming_case(name: &str) -> String {
+    let mut screaming = String::with_capacity(name.len() + 4);
+    for (i, c) in name.chars().enumerate() {
+        if c.is_ascii_uppercase() && i > 0 && !screaming.ends_with('_') {
+            screaming.push('_');
+        }
+        screaming.push(c.to_ascii_uppercase());
+    }
+    screaming
+}
+
+/// jump_drive_status is JumpDriveStatus
+fn camel_case(name: &str) -> String {
+    identifier(name)
+        .split('_')
+        .map(|part| {
+            let mut chars = part.chars();
+            match chars.next() {
+                Some(first) => first.to_ascii_uppercase().to_string() + chars.as_str(),
+                None => String::new(),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use katniss_pb2arrow::ArrowBatchProps;
+    use katniss_test::{descriptor_pool, protos::spacecorp::Packet, test_util::ProtoBatch};
+
+    use super::*;
+    use crate::sinks::descriptor_metadata;
+
+    #[test]
+    fn schemas_without_descriptors_are_numbered_in_order() -> anyhow::Result<()> {
+        let schema = Schema::new(vec![
+            Field::new(
+                "recorded at",
+                DataType::Timestamp(arrow_schema::TimeUnit::Microsecond, None),
+                true,
+            ),
+            Field::new("speed", DataType::Float32, true),
+            Field::new(
+                "gps_fix",
+                DataType::Struct(vec![Field::new("lat", DataType::Float64, true)].into()),
+                true,
+            ),
+            Field::new(
+                "tags",
+                DataType::List(Arc::new(Field::new("item", DataType::Utf8, true))),
+                true,
+            ),
+        ]);
+        let proto = proto_from_schema(&schema, "acme.telemetry.Reading")?;
+        assert_eq!(
+            proto,
+            r#"syntax = "proto3";
+
+package acme.telemetry;
+
+import "google/protobuf/timestamp.proto";
+
+message Reading {
+  message GpsFix {
+    double lat = 1;
+  }
+  google.protobuf.Timestamp recorded_at = 1;
+  float speed = 2;
+  GpsFix gps_fix = 3;
+  repeated string tags = 4;
+}
+"#
+        );
+
+        let nested = Schema::new(vec![Field::new(
+            "matrix",
+            DataType::List(Arc::new(Field::new(
+                "item",
+                DataType::List(Arc::new(Field::new("item", DataType::Float32, true))),
+                true,
+            ))),
+            true,
+        )]);
+        assert!(proto_from_schema(&nested, "Matrix").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn clashing_names_are_numbered() -> anyhow::Result<()> {
+        let gps_fix = |lat| DataType::Struct(vec![Field::new("lat", lat, true)].into());
+        let schema = Schema::new(vec![
+            Field::new("gps fix", gps_fix(DataType::Float64), true),
+            Field::new("gps_fix", gps_fix(DataType::Float32), true),
+            Field::new("gps-fix", gps_fix(DataType::Float64), true),
+        ]);
+        assert_eq!(
+            proto_from_schema(&schema, "Reading")?,
+            r#"syntax = "proto3";
+
+message Reading {
+  message GpsFix {
+    double lat = 1;
+  }
+  message GpsFix2 {
+    float lat = 1;
+  }
+  GpsFix gps_fix = 1;
+  GpsFix2 gps_fix_2 = 2;
+  GpsFix gps_fix_3 = 3;
+}
+"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn proto2_enums_get_a_zero_value() -> anyhow::Result<()> {
+        let props =
+            ArrowBatchProps::try_new(descriptor_pool()?, "eto.pb2arrow.tests.v2.Dial".to_owned())?;
+        let schema = props
+            .schema
+            .as_ref()
+            .clone()
+            .with_metadata(descriptor_metadata(&props.descriptor).into_iter().collect());
+
+        let proto = proto_from_schema(&schema, "eto.pb2arrow.tests.v2.Dial")?;
+        assert!(
+            proto.contains(
+                "\n  enum Setting {\n    SETTING_UNSPECIFIED = 0;\n    LOW = 1;\n    HIGH = 2;\n  }\n"
+            ),
+            "{proto}"
+        );
+        assert!(
+            proto.contains("\n  optional Setting setting = 1;\n"),
+            "{proto}"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn embedded_descriptors_keep_numbers_enums_and_oneofs() -> anyhow::Result<()> {
+        let props = ArrowBatchProps::try_new(
+            descriptor_pool()?,
+            "eto.pb2arrow.tests.spacecorp.Packet".to_owned(),
+        )?;
+        let packets = [Packet::default()];
+        let schema = ProtoBatch::SpaceCorp(&packets).arrow_batch()?.schema();
+        let schema = schema
+            .as_ref()
+            .clone()
+            .with_metadata(descriptor_metadata(&props.descriptor).into_iter().collect());
+
+        let proto = proto_from_schema(&schema, "eto.pb2arrow.tests.spacecorp.Packet")?;
+        assert!(proto.contains("\n  uint64 sender_uid = 2;\n"), "{proto}");
+        assert!(proto.contains("\n  oneof msg {\n    JumpDriveControl jump_drive_control = 100;\n"));
+        assert!(proto.contains("\n    enum JumpDriveMode {\n      OFF = 0;\n"));
+        assert!(proto.contains("\n  message NewComponentControl {}\n"));
+        assert!(proto.contains("\n      repeated double vxs = 3;\n"));
+        Ok(())
+    }
+}
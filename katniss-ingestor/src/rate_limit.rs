@@ -0,0 +1,235 @@
+use std::time::{Duration, Instant};
+
+use crate::errors::KatinssIngestorError;
+
+/// What to do with messages that arrive faster than the limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum ThrottlePolicy {
+    /// Drop messages until there's room again
+    #[default]
+    Drop,
+    /// Wait for room before encoding, messages queue up in the pipeline's head channel meanwhile
+    Backpressure,
+}
+
+/// Token bucket limits for the pipeline head, in messages and/or encoded protobuf bytes per second
+#[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct RateLimit {
+    messages_per_sec: Option<f64>,
+    bytes_per_sec: Option<f64>,
+    #[cfg_attr(feature = "serde", serde(with = "crate::pipeline_config::secs"))]
+    burst: Duration,
+    policy: ThrottlePolicy,
+}
+
+impl Default for RateLimit {
+    fn default() -> Self {
+        Self {
+            messages_per_sec: None,
+            bytes_per_sec: None,
+            burst: Duration::from_secs(1),
+            policy: ThrottlePolicy::default(),
+        }
+    }
+}
+
+impl RateLimit {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn messages_per_sec(mut self, rate: f64) -> Self {
+        self.messages_per_sec = Some(rate);
+        self
+    }
+
+    pub fn bytes_per_sec(mut self, rate: f64) -> Self {
+        self.bytes_per_sec = Some(rate);
+        self
+    }
+
+    /// How much unused rate can be saved up, defaults to one second's worth
+    pub fn with_burst(mut self, burst: Duration) -> Self {
+        self.burst = burst;
+        self
+    }
+
+    pub fn with_policy(mut self, policy: ThrottlePolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Rates have to be positive and finite, a rate of 0 would never admit anything
+    pub fn validate(&self) -> crate::Result<()> {
+        let rates = [
+            ("messages_per_sec", self.messages_per_sec),
+            ("bytes_per_sec", self.bytes_per_sec),
+        ];
+        for (name, rate) in rates {
+            match rate {
+                Some(rate) if !(rate.is_finite() && rate > 0.0) => {
+                    return Err(KatinssIngestorError::InvalidConfig(format!(
+                        "{name} must be a positive number, got {rate}"
+                    )))
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+pub(crate) struct RateLimiter {
+    messages: Option<TokenBucket>,
+    bytes: Option<TokenBucket>,
+    policy: ThrottlePolicy,
+}
+
+impl RateLimiter {
+    /// Fails for rates that aren't positive and finite, see RateLimit::validate
+    pub(crate) fn new(limit: &RateLimit) -> crate::Result<Self> {
+        limit.validate()?;
+        let now = Instant::now();
+        let bucket = |rate| TokenBucket::new(rate, limit.burst, now);
+        Ok(Self {
+            messages: limit.messages_per_sec.map(bucket),
+            bytes: limit.bytes_per_sec.map(bucket),
+            policy: limit.policy,
+        })
+    }
+
+    /// False if the message should be dropped, waits first under the backpressure policy
+    pub(crate) async fn admit(&mut self, num_bytes: usize) -> bool {
+        loop {
+            match self.try_admit(num_bytes, Instant::now()) {
+                Ok(()) => return true,
+                Err(_) if self.policy == ThrottlePolicy::Drop => return false,
+                Err(wait) => tokio::time::sleep(wait).await,
+            }
+        }
+    }
+
+    /// Takes tokens for the message if both buckets have room, otherwise how long until they will
+    fn try_admit(&mut self, num_bytes: usize, now: Instant) -> Result<(), Duration> {
+        let wait = [
+            (&mut self.messages, 1.0),
+            (&mut self.bytes, num_bytes as f64),
+        ]
+        .into_iter()
+        .filter_map(|(bucket, cost)| bucket.as_mut().map(|b| b.wait_for(cost, now)))
+        .max()
+        .unwrap_or(Duration::ZERO);
+
+        if !wait.is_zero() {
+            return Err(wait);
+        }
+
+        if let Some(bucket) = &mut self.messages {
+            bucket.take(1.0);
+        }
+        if let Some(bucket) = &mut self.bytes {
+            bucket.take(num_bytes as f64);
+        }
+        Ok(())
+    }
+}
+
+struct TokenBucket {
+    rate: f64,
+    capacity: f64,
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: f64, burst: Duration, now: Instant) -> Self {
+        let capacity = (rate * burst.as_secs_f64()).max(1.0);
+        Self {
+            rate,
+            capacity,
+            tokens: capacity,
+            refilled_at: now,
+        }
+    }
+
+    /// Refills then returns how long until cost tokens are available
+    /// Costs bigger than the whole bucket only need a full bucket so they can't stall forever
+    fn wait_for(&mut self, cost: f64, now: Instant) -> Duration {
+        let elapsed = now.saturating_duration_since(self.refilled_at);
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * self.rate).min(self.capacity);
+        self.refilled_at = now;
+
+        let missing = cost.min(self.capacity) - self.tokens;
+        if missing <= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(missing / self.rate)
+        }
+    }
+
+    fn take(&mut self, cost: f64) {
+        self.tokens = (self.tokens - cost.min(self.capacity)).max(0.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_limits_messages_and_bytes() {
+        let limit = RateLimit::new().messages_per_sec(10.0).bytes_per_sec(100.0);
+        let mut limiter = RateLimiter::new(&limit).unwrap();
+        let start = Instant::now();
+
+        for _ in 0..5 {
+            assert_eq!(limiter.try_admit(20, start), Ok(()));
+        }
+        // out of bytes, but not messages
+        let wait = limiter.try_admit(20, start).unwrap_err();
+        assert_eq!(wait, Duration::from_millis(200));
+
+        let later = start + wait;
+        assert_eq!(limiter.try_admit(20, later), Ok(()));
+        // a message bigger than the bucket goes through once it's full
+        assert!(limiter.try_admit(1_000, later).is_err());
+        assert_eq!(
+            limiter.try_admit(1_000, later + Duration::from_secs(1)),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn rates_must_be_positive_and_finite() {
+        for rate in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            assert!(RateLimiter::new(&RateLimit::new().messages_per_sec(rate)).is_err());
+            assert!(RateLimiter::new(&RateLimit::new().bytes_per_sec(rate)).is_err());
+        }
+        assert!(RateLimiter::new(&RateLimit::new().bytes_per_sec(0.5)).is_ok());
+    }
+
+    #[tokio::test]
+    async fn backpressure_waits_instead_of_dropping() {
+        let mut dropping = RateLimiter::new(&RateLimit::new().messages_per_sec(1.0)).unwrap();
+        assert!(dropping.admit(0).await);
+        assert!(!dropping.admit(0).await);
+
+        let limit = RateLimit::new()
+            .messages_per_sec(1_000.0)
+            .with_burst(Duration::ZERO)
+            .with_policy(ThrottlePolicy::Backpressure);
+        let mut waiting = RateLimiter::new(&limit).unwrap();
+        assert!(waiting.admit(0).await);
+        assert!(waiting.admit(0).await);
+    }
+}
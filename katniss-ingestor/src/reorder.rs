@@ -0,0 +1,169 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+
+use crate::{event_time::EventTime, fan_in::Sourced, Result};
+
+/// Holds messages for up to a bounded window of event time and releases them in event time order,
+/// so interleaved sources (i.e. a FanIn) produce roughly time sorted output files.
+/// Messages are released once the newest event time seen is more than window past theirs,
+/// anything arriving even later than that is passed straight through, as are messages without a time.
+pub struct Reorder {
+    event_time: EventTime,
+    window: Duration,
+    pending: BinaryHeap<Reverse<Pending>>,
+    newest_event: Option<DateTime<Utc>>,
+    /// keeps messages with the same event time in arrival order
+    sequence: u64,
+}
+
+impl Reorder {
+    pub fn new(event_time: EventTime, window: Duration) -> Self {
+        Self {
+            event_time,
+            window,
+            pending: BinaryHeap::new(),
+            newest_event: None,
+            sequence: 0,
+        }
+    }
+
+    /// Number of messages being held
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Takes a message and returns any that are ready, oldest first
+    pub(crate) fn push(&mut self, incoming: Sourced) -> Result<Vec<Sourced>> {
+        let Some(event_at) = self.event_time.extract(&incoming.msg) else {
+            return Ok(vec![incoming]);
+        };
+
+        self.newest_event = self.newest_event.max(Some(event_at));
+        let release_through = self.newest_event.unwrap() - chrono::Duration::from_std(self.window)?;
+
+        self.sequence += 1;
+        self.pending.push(Reverse(Pending {
+            event_at,
+            sequence: self.sequence,
+            incoming,
+        }));
+
+        let mut ready = Vec::new();
+        while let Some(Reverse(next)) = self.pending.peek() {
+            if next.event_at > release_through {
+                break;
+            }
+            let Reverse(next) = self.pending.pop().expect("just peeked");
+            ready.push(next.incoming);
+        }
+        Ok(ready)
+    }
+
+    /// Everything being held, oldest first
+    pub(crate) fn drain(&mut self) -> Vec<Sourced> {
+        std::iter::from_fn(|| self.pending.pop())
+            .map(|Reverse(p)| p.incoming)
+            .collect()
+    }
+}
+
+struct Pending {
+    event_at: DateTime<Utc>,
+    sequence: u64,
+    incoming: Sourced,
+}
+
+impl Pending {
+    fn key(&self) -> (DateTime<Utc>, u64) {
+        (self.event_at, self.sequence)
+    }
+}
+
+impl PartialEq for Pending {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for Pending {}
+
+impl PartialOrd for Pending {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Pending {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use katniss_test::{
+        protos::spacecorp::{Packet, Timestamp},
+        test_util::to_dynamic,
+    };
+
+    use super::*;
+
+    fn packet_at(seconds: i64) -> anyhow::Result<Sourced> {
+        let msg = to_dynamic(
+            &Packet {
+                timestamp: Some(Timestamp { seconds, nanos: 0 }),
+                sender_uid: seconds as u64,
+                ..Default::default()
+            },
+            "eto.pb2arrow.tests.spacecorp.Packet",
+        )?;
+        Ok(Sourced::new(None, msg))
+    }
+
+    fn uids(messages: Vec<Sourced>) -> Vec<u64> {
+        messages
+            .iter()
+            .map(|s| {
+                s.msg
+                    .get_field_by_name("sender_uid")
+                    .unwrap()
+                    .as_u64()
+                    .unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn it_releases_messages_in_event_time_order() -> anyhow::Result<()> {
+        let mut reorder = Reorder::new(EventTime::new("timestamp"), Duration::from_secs(5));
+
+        assert!(reorder.push(packet_at(10)?)?.is_empty());
+        assert!(reorder.push(packet_at(8)?)?.is_empty());
+        assert!(reorder.push(packet_at(12)?)?.is_empty());
+        assert_eq!(reorder.len(), 3);
+
+        // watermark is 9
+        assert_eq!(uids(reorder.push(packet_at(14)?)?), vec![8]);
+        // watermark is 15
+        let from_b = Sourced {
+            source: Some("b".into()),
+            ..packet_at(20)?
+        };
+        assert_eq!(uids(reorder.push(from_b)?), vec![10, 12, 14]);
+        // too late to be reordered, goes straight through
+        assert_eq!(uids(reorder.push(packet_at(1)?)?), vec![1]);
+
+        let drained = reorder.drain();
+        assert_eq!(drained[0].source.as_deref(), Some("b"));
+        assert_eq!(uids(drained), vec![20]);
+        assert!(reorder.is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,362 @@
+use std::collections::HashSet;
+use std::fmt::{self, Display};
+
+use katniss_pb2arrow::exports::prost_reflect::{
+    Cardinality, DescriptorPool, FieldDescriptor, Kind, MessageDescriptor,
+};
+
+use crate::{errors::KatinssIngestorError, Result};
+
+/// One difference between two versions of a message, fields are dot separated paths from the
+/// top level message, i.e. "header.stamp"
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum SchemaChange {
+    Added {
+        field: String,
+        number: u32,
+    },
+    Removed {
+        field: String,
+        number: u32,
+    },
+    /// Type or cardinality, i.e. "int32" to "repeated int64"
+    TypeChanged {
+        field: String,
+        from: String,
+        to: String,
+    },
+    /// Same name under a different number, old data for it won't be read into the new field
+    Renumbered {
+        field: String,
+        from: u32,
+        to: u32,
+    },
+}
+
+impl Display for SchemaChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Added { field, number } => write!(f, "{field} = {number} was added"),
+            Self::Removed { field, number } => write!(f, "{field} = {number} was removed"),
+            Self::TypeChanged { field, from, to } => {
+                write!(f, "{field} changed type from {from} to {to}")
+            }
+            Self::Renumbered { field, from, to } => {
+                write!(f, "{field} was renumbered from {from} to {to}")
+            }
+        }
+    }
+}
+
+/// What a SchemaGuard does about a kind of change
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum ChangePolicy {
+    Allow,
+    /// Let it through but report it, see PipelineStatus::schema_warnings
+    Warn,
+    Reject,
+}
+
+/// Decides which changes between versions of a message a pipeline accepts, when it's
+/// reloaded (see SchemaReloader) or started over data written with an older version
+/// (see PipelineBuilder::with_previous_descriptor).
+/// By default added fields are allowed, removed fields are warned about, and type changes
+/// and renumbering are rejected since existing data can't be read as the new fields
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct SchemaGuard {
+    added: ChangePolicy,
+    removed: ChangePolicy,
+    type_changed: ChangePolicy,
+    renumbered: ChangePolicy,
+}
+
+impl Default for SchemaGuard {
+    fn default() -> Self {
+        Self {
+            added: ChangePolicy::Allow,
+            removed: ChangePolicy::Warn,
+            type_changed: ChangePolicy::Reject,
+            renumbered: ChangePolicy::Reject,
+        }
+    }
+}
+
+impl SchemaGuard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_added(mut self, policy: ChangePolicy) -> Self {
+        self.added = policy;
+        self
+    }
+
+    pub fn with_removed(mut self, policy: ChangePolicy) -> Self {
+        self.removed = policy;
+        self
+    }
+
+    /// Allowing these in a running pipeline means messages that can't be re-decoded
+    /// with the new descriptor take it down
+    pub fn with_type_changed(mut self, policy: ChangePolicy) -> Self {
+        self.type_changed = policy;
+        self
+    }
+
+    pub fn with_renumbered(mut self, policy: ChangePolicy) -> Self {
+        self.renumbered = policy;
+        self
+    }
+
+    pub fn policy(&self, change: &SchemaChange) -> ChangePolicy {
+        match change {
+            SchemaChange::Added { .. } => self.added,
+            SchemaChange::Removed { .. } => self.removed,
+            SchemaChange::TypeChanged { .. } => self.type_changed,
+            SchemaChange::Renumbered { .. } => self.renumbered,
+        }
+    }
+
+    /// The changes from old to new that are let through with a warning,
+    /// fails with every rejected change if there are any
+    pub fn check(
+        &self,
+        old: &MessageDescriptor,
+        new: &MessageDescriptor,
+    ) -> Result<Vec<SchemaChange>> {
+        let mut warnings = Vec::new();
+        let mut rejected = Vec::new();
+        for change in compare_messages(old, new)? {
+            match self.policy(&change) {
+                ChangePolicy::Allow => {}
+                ChangePolicy::Warn => warnings.push(change),
+                ChangePolicy::Reject => rejected.push(change.to_string()),
+            }
+        }
+
+        if rejected.is_empty() {
+            Ok(warnings)
+        } else {
+            Err(KatinssIngestorError::IncompatibleSchema(
+                rejected.join(", "),
+            ))
+        }
+    }
+}
+
+/// Every change to a message between two descriptor pools, see compare_messages
+pub fn compare_pools(
+    old: &DescriptorPool,
+    new: &DescriptorPool,
+    message: &str,
+) -> Result<Vec<SchemaChange>> {
+    let find = |pool: &DescriptorPool| {
+        pool.get_message_by_name(message)
+            .ok_or_else(|| KatinssIngestorError::UnknownMessage(message.to_owned()))
+    };
+    compare_messages(&find(old)?, &find(new)?)
+}
+
+/// Every change between two versions of a message, fields are matched by number like protobuf
+/// decodes them, and fields of nested messages are compared too.
+/// Renaming a field isn't a change. Fails if they aren't the same message
+pub fn compare_messages(
+    old: &MessageDescriptor,
+    new: &MessageDescriptor,
+) -> Result<Vec<SchemaChange>> {
+    if old.full_name() != new.full_name() {
+        return Err(KatinssIngestorError::IncompatibleSchema(format!(
+            "{} can't be replaced by {}",
+            old.full_name(),
+            new.full_name()
+        )));
+    }
+
+    let mut changes = Vec::new();
+    compare_fields(old, new, "", &mut changes, &mut HashSet::new());
+    Ok(changes)
+}
+
+fn compare_fields(
+    old: &MessageDescriptor,
+    new: &MessageDescriptor,
+    prefix: &str,
+    changes: &mut Vec<SchemaChange>,
+    seen: &mut HashSet<String>,
+) {
+    // recursive messages only need comparing once
+    if !seen.insert(old.full_name().to_owned()) {
+        return;
+    }
+
+    for field in old.fields() {
+        let path = format!("{prefix}{}", field.name());
+        if let Some(renumbered) = new.get_field_by_name(field.name()) {
+            if renumbered.number() != field.number() {
+                changes.push(SchemaChange::Renumbered {
+                    field: path,
+                    from: field.number(),
+                    to: renumbered.number(),
+                });
+                continue;
+            }
+        }
+
+        let Some(updated) = new.get_field(field.number()) else {
+            changes.push(SchemaChange::Removed {
+                field: path,
+                number: field.number(),
+            });
+            continue;
+        };
+        let (from, to) = (type_name(&field), type_name(&updated));
+        if from != to {
+            changes.push(SchemaChange::TypeChanged {
+                field: path,
+                from,
+                to,
+            });
+        } else if let (Kind::Message(old), Kind::Message(new)) = (field.kind(), updated.kind()) {
+            compare_fields(&old, &new, &format!("{path}."), changes, seen);
+        }
+    }
+
+    for field in new.fields() {
+        // renumbered fields are already accounted for by name
+        if old.get_field(field.number()).is_none() && old.get_field_by_name(field.name()).is_none()
+        {
+            changes.push(SchemaChange::Added {
+                field: format!("{prefix}{}", field.name()),
+                number: field.number(),
+            });
+        }
+    }
+}
+
+/// i.e. "int32", "repeated eto.pb2arrow.tests.v3.Foo"
+fn type_name(field: &FieldDescriptor) -> String {
+    let kind = match field.kind() {
+        Kind::Message(message) => message.full_name().to_owned(),
+        Kind::Enum(enum_type) => enum_type.full_name().to_owned(),
+        scalar => format!("{scalar:?}").to_lowercase(),
+    };
+    match field.cardinality() {
+        Cardinality::Optional => kind,
+        Cardinality::Required => format!("required {kind}"),
+        Cardinality::Repeated => format!("repeated {kind}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use katniss_test::test_util::{reading_props, READING};
+
+    use super::*;
+    use crate::{pipeline::PipelineBuilder, sinks::JsonLinesSink};
+
+    #[test]
+    fn changes_are_classified() -> anyhow::Result<()> {
+        let old = reading_props("int32 id = 1; string name = 2; bool ok = 3; double value = 4;")?;
+        let new = reading_props("int32 id = 1; int64 name = 2; double value = 5; bytes raw = 6;")?;
+
+        let changes = compare_pools(
+            old.descriptor.parent_pool(),
+            new.descriptor.parent_pool(),
+            READING,
+        )?;
+        assert_eq!(
+            changes,
+            vec![
+                SchemaChange::TypeChanged {
+                    field: "name".to_owned(),
+                    from: "string".to_owned(),
+                    to: "int64".to_owned(),
+                },
+                SchemaChange::Removed {
+                    field: "ok".to_owned(),
+                    number: 3,
+                },
+                SchemaChange::Renumbered {
+                    field: "value".to_owned(),
+                    from: 4,
+                    to: 5,
+                },
+                SchemaChange::Added {
+                    field: "raw".to_owned(),
+                    number: 6,
+                },
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn the_guard_applies_its_policies() -> anyhow::Result<()> {
+        let v1 = reading_props("int32 id = 1; string name = 2;")?.descriptor;
+        let added = reading_props("int32 id = 1; string name = 2; double value = 3;")?.descriptor;
+        let removed = reading_props("int32 id = 1;")?.descriptor;
+        let retyped = reading_props("int32 id = 1; repeated string name = 2;")?.descriptor;
+
+        let guard = SchemaGuard::default();
+        assert!(guard.check(&v1, &added)?.is_empty());
+        assert_eq!(
+            guard.check(&v1, &removed)?,
+            vec![SchemaChange::Removed {
+                field: "name".to_owned(),
+                number: 2
+            }]
+        );
+        assert!(matches!(
+            guard.check(&v1, &retyped),
+            Err(KatinssIngestorError::IncompatibleSchema(_))
+        ));
+
+        let strict = guard.with_added(ChangePolicy::Reject);
+        assert!(strict.check(&v1, &added).is_err());
+        let lenient = guard.with_type_changed(ChangePolicy::Warn);
+        assert_eq!(lenient.check(&v1, &retyped)?.len(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn pipelines_check_the_previous_descriptor_at_startup() -> anyhow::Result<()> {
+        let previous = reading_props("int32 id = 1; string name = 2;")?.descriptor;
+        let period = std::time::Duration::from_secs(60);
+        let start = |fields: &str| -> anyhow::Result<_> {
+            let builder = PipelineBuilder::new(
+                reading_props(fields)?,
+                period,
+                JsonLinesSink::new(Vec::new()),
+            )
+            .with_previous_descriptor(previous.clone());
+            let handle = builder.handle();
+            Ok((builder.build(), handle))
+        };
+
+        let (started, handle) = start("int32 id = 1;")?;
+        assert!(started.is_ok());
+        assert_eq!(handle.status().schema_warnings.len(), 1);
+
+        let (started, _) = start("int64 id = 1; string name = 2;")?;
+        assert!(matches!(
+            started,
+            Err(KatinssIngestorError::IncompatibleSchema(_))
+        ));
+        Ok(())
+    }
+}
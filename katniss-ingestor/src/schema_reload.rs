@@ -0,0 +1,186 @@
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::mpsc::UnboundedSender;
+
+use katniss_pb2arrow::exports::prost_reflect::MessageDescriptor;
+use katniss_pb2arrow::{ArrowBatchProps, SchemaFingerprint};
+
+use crate::{
+    errors::KatinssIngestorError,
+    schema_guard::{SchemaChange, SchemaGuard},
+    status::PipelineHandle,
+    Result,
+};
+
+/// Swaps the schema of a running pipeline, see PipelineBuilder::schema_reloader
+#[derive(Clone)]
+pub struct SchemaReloader {
+    tx: UnboundedSender<ArrowBatchProps>,
+    state: Arc<Mutex<ReloadState>>,
+    status: PipelineHandle,
+}
+
+struct ReloadState {
+    current: MessageDescriptor,
+    guard: SchemaGuard,
+    /// see ArrowBatchProps::with_expected_fingerprint
+    pinned: Option<SchemaFingerprint>,
+}
+
+impl SchemaReloader {
+    pub(crate) fn new(
+        tx: UnboundedSender<ArrowBatchProps>,
+        props: &ArrowBatchProps,
+        status: PipelineHandle,
+    ) -> Self {
+        Self {
+            tx,
+            state: Arc::new(Mutex::new(ReloadState {
+                current: props.descriptor.clone(),
+                guard: SchemaGuard::default(),
+                pinned: props.expected_fingerprint.clone(),
+            })),
+            status,
+        }
+    }
+
+    /// Buffers that are open when the encoder picks this up are finished with the old schema,
+    /// everything after goes into buffers with the new one. Messages sources already decoded
+    /// with the old descriptor are re-decoded with the new one, so sources can switch over
+    /// whenever they like. Returns the changes the pipeline's SchemaGuard warned about,
+    /// nothing changes if it rejects any. Props without an expected fingerprint have to match
+    /// the pipeline's, if it was started with one
+    pub fn reload(&self, props: ArrowBatchProps) -> Result<Vec<SchemaChange>> {
+        let mut state = self.lock();
+        let props = match (&props.expected_fingerprint, &state.pinned) {
+            (None, Some(pinned)) => props.with_expected_fingerprint(pinned.clone())?,
+            _ => {
+                props.check_fingerprint()?;
+                props
+            }
+        };
+        let warnings = state.guard.check(&state.current, &props.descriptor)?;
+
+        let (descriptor, pinned) = (props.descriptor.clone(), props.expected_fingerprint.clone());
+        self.tx
+            .send(props)
+            .map_err(|_| KatinssIngestorError::PipelineClosed)?;
+        state.current = descriptor;
+        state.pinned = pinned;
+        self.status
+            .update(|s| s.schema_warnings.extend(warnings.iter().cloned()));
+        Ok(warnings)
+    }
+
+    /// The descriptor of the last accepted reload, or the one the pipeline started with
+    pub fn current(&self) -> MessageDescriptor {
+        self.lock().current.clone()
+    }
+
+    pub(crate) fn guard(&self) -> SchemaGuard {
+        self.lock().guard
+    }
+
+    pub(crate) fn set_guard(&self, guard: SchemaGuard) {
+        self.lock().guard = guard;
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, ReloadState> {
+        self.state.lock().expect("schema reloader poisoned")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+    use katniss_pb2arrow::exports::prost_reflect::{DynamicMessage, Value};
+    use katniss_test::test_util::reading_props;
+
+    use super::*;
+    use crate::{pipeline::PipelineBuilder, sinks::BufferSink, temporal_rotator::TemporalBuffer};
+
+    struct Collect(tokio::sync::mpsc::UnboundedSender<TemporalBuffer>);
+
+    #[async_trait]
+    impl BufferSink for Collect {
+        async fn write_buffer(&mut self, buffer: TemporalBuffer) -> crate::Result<()> {
+            self.0
+                .send(buffer)
+                .map_err(|_| KatinssIngestorError::PipelineClosed)
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn reloads_finish_the_open_buffer_with_the_old_schema() -> anyhow::Result<()> {
+        let v1 = reading_props("int32 id = 1;")?;
+        let v2 = reading_props("int32 id = 1; double value = 2;")?;
+
+        let (tx, mut written) = tokio::sync::mpsc::unbounded_channel();
+        let period = std::time::Duration::from_secs(3600);
+        let builder = PipelineBuilder::new(v1.clone(), period, Collect(tx));
+        let (reloader, handle) = (builder.schema_reloader(), builder.handle());
+        let (head, _tasks) = builder.build()?;
+
+        let mut old = DynamicMessage::new(v1.descriptor.clone());
+        old.set_field_by_name("id", Value::I32(1));
+        head.send(old.clone())?;
+        while handle.status().encoder.processed == 0 {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+
+        assert!(reloader.reload(v2.clone())?.is_empty());
+        // a source that's still on the old descriptor
+        head.send(old)?;
+        let mut new = DynamicMessage::new(v2.descriptor.clone());
+        new.set_field_by_name("id", Value::I32(2));
+        new.set_field_by_name("value", Value::F64(0.5));
+        head.send(new)?;
+        drop(head);
+
+        let before = written.recv().await.expect("buffer before the reload");
+        assert_eq!(before.num_rows(), 1);
+        assert_eq!(before.batches[0].schema(), v1.schema);
+
+        let after = written.recv().await.expect("buffer after the reload");
+        assert_eq!(after.num_rows(), 2);
+        assert!(after.batches.iter().all(|b| b.schema() == v2.schema));
+
+        assert!(matches!(
+            reloader.reload(reading_props("int64 id = 1;")?),
+            Err(KatinssIngestorError::IncompatibleSchema(_))
+        ));
+        assert_eq!(reloader.current(), v2.descriptor);
+
+        let warnings = reloader.reload(reading_props("double value = 2;")?)?;
+        assert_eq!(handle.status().schema_warnings, warnings);
+        assert_eq!(warnings.len(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn reloads_keep_the_pinned_fingerprint() -> anyhow::Result<()> {
+        let v1 = reading_props("int32 id = 1;")?;
+        let pinned = SchemaFingerprint::of(&v1.schema);
+        let v1 = v1.with_expected_fingerprint(pinned)?;
+
+        let period = std::time::Duration::from_secs(3600);
+        let (tx, _written) = tokio::sync::mpsc::unbounded_channel();
+        let builder = PipelineBuilder::new(v1, period, Collect(tx));
+        let reloader = builder.schema_reloader();
+        let (_head, _tasks) = builder.build()?;
+
+        let renamed = reading_props("int32 reading_id = 1;")?;
+        assert!(matches!(
+            reloader.reload(renamed),
+            Err(KatinssIngestorError::Pb2ArrowArror(
+                katniss_pb2arrow::KatnissArrowError::SchemaDrift(_)
+            ))
+        ));
+
+        let v2 = reading_props("int32 id = 1; double value = 2;")?;
+        let repinned = SchemaFingerprint::of(&v2.schema);
+        reloader.reload(v2.with_expected_fingerprint(repinned)?)?;
+        assert!(reloader.reload(reading_props("int32 id = 1;")?).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,78 @@
+//! Destinations for TemporalBuffers once they've been rotated out of the pipeline
+
+use async_trait::async_trait;
+
+use katniss_pb2arrow::ArrowBatchProps;
+
+use crate::{temporal_rotator::TemporalBuffer, Result};
+
+#[cfg(feature = "avro")]
+mod avro;
+mod config;
+mod csv;
+#[cfg(feature = "flight")]
+mod flight;
+mod ipc;
+mod json;
+pub(crate) mod manifest;
+mod naming;
+mod parquet;
+mod partitioned;
+#[cfg(feature = "postgres")]
+mod postgres;
+mod store;
+mod tee;
+
+pub(crate) use self::parquet::{column_at, decode_descriptor, descriptor_metadata};
+pub use self::parquet::{
+    descriptor_from_metadata, descriptor_from_schema, parse_encoding, ParquetColumns,
+    ParquetEncoder, ParquetStoreSink, DESCRIPTOR_SET_KEY, ENCODING_OPTION, MESSAGE_NAME_KEY,
+};
+#[cfg(feature = "avro")]
+pub use avro::AvroEncoder;
+pub use config::{Codec, ColumnStatistics, LanceMode, PartitionBy, SinkConfig, SinkFormat};
+pub use csv::CsvEncoder;
+#[cfg(feature = "flight")]
+pub use flight::FlightSink;
+pub use ipc::{IpcFileEncoder, IpcStreamSink};
+pub use json::{JsonLinesEncoder, JsonLinesSink};
+pub use manifest::{manifest_schema, MANIFEST_FILENAME, SUCCESS_FILENAME};
+pub use naming::{FileMeta, FileNaming};
+pub use partitioned::{PartitionedSink, NULL_PARTITION};
+#[cfg(feature = "postgres")]
+pub use postgres::{postgres_type, PostgresSink};
+pub use store::{store_from_uri, BufferEncoder, StoreSink};
+pub use tee::TeeSink;
+
+/// Anything that can durably (or not so durably) put away a finished TemporalBuffer
+#[async_trait]
+pub trait BufferSink: Send {
+    async fn write_buffer(&mut self, buffer: TemporalBuffer) -> Result<()>;
+
+    /// Called once every buffer has been written and no more are coming
+    async fn close(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// The pipeline's schema was reloaded, buffers from here on have props.schema,
+    /// see SchemaReloader. Sinks that can't change schema mid stream should return an error
+    async fn reload_schema(&mut self, _props: &ArrowBatchProps) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// So the sink can be picked at runtime, i.e. from a cli flag
+#[async_trait]
+impl<S: BufferSink + ?Sized> BufferSink for Box<S> {
+    async fn write_buffer(&mut self, buffer: TemporalBuffer) -> Result<()> {
+        (**self).write_buffer(buffer).await
+    }
+
+    async fn close(&mut self) -> Result<()> {
+        (**self).close().await
+    }
+
+    async fn reload_schema(&mut self, props: &ArrowBatchProps) -> Result<()> {
+        (**self).reload_schema(props).await
+    }
+}
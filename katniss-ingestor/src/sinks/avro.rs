@@ -0,0 +1,359 @@
+use std::collections::HashMap;
+
+use apache_avro::{types::Value, Codec, Schema as AvroSchema, Writer};
+use arrow_array::{cast::AsArray, types::*, Array, ArrayRef, RecordBatch};
+use arrow_cast::{cast, cast_with_options, CastOptions};
+use arrow_schema::{DataType, Field, Schema, TimeUnit};
+use serde_json::json;
+
+use katniss_pb2arrow::ArrowBatchProps;
+
+use crate::{errors::KatinssIngestorError, sinks::store::BufferEncoder, Result};
+
+/// Encodes each buffer as an Avro object container file, for downstream systems that only
+/// take Avro. Every field is a union with null, nested messages are records and enums are
+/// enums with the descriptor's value names. Repeated enums are strings since their columns
+/// don't carry the values. uint64s that don't fit in a long fail the encode
+#[derive(Debug, Clone)]
+pub struct AvroEncoder {
+    schema: AvroSchema,
+    /// Enum symbols by dot separated column path
+    enums: HashMap<String, Vec<String>>,
+    codec: Codec,
+}
+
+impl AvroEncoder {
+    /// The record is named after the message, i.e. eto.pb2arrow.tests.spacecorp.Packet
+    pub fn try_new(props: &ArrowBatchProps) -> Result<Self> {
+        let mut enums = HashMap::new();
+        let fields = props
+            .schema
+            .fields()
+            .iter()
+            .map(|field| avro_field(field, "", props, &mut enums))
+            .collect::<Result<Vec<_>>>()?;
+        let record = json!({
+            "type": "record",
+            "name": props.descriptor.name(),
+            "namespace": props.descriptor.package_name(),
+            "fields": fields,
+        });
+
+        Ok(Self {
+            schema: AvroSchema::parse(&record)?,
+            enums,
+            codec: Codec::Null,
+        })
+    }
+
+    /// Block compression, uncompressed by default
+    pub fn with_codec(mut self, codec: Codec) -> Self {
+        self.codec = codec;
+        self
+    }
+
+    pub fn avro_schema(&self) -> &AvroSchema {
+        &self.schema
+    }
+
+    fn records(&self, batch: &RecordBatch) -> Result<Vec<Value>> {
+        let schema = batch.schema();
+        let mut columns = schema
+            .fields()
+            .iter()
+            .zip(batch.columns())
+            .map(|(field, column)| Ok(self.values(column, field.name())?.into_iter()))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok((0..batch.num_rows())
+            .map(|_| {
+                let fields = schema
+                    .fields()
+                    .iter()
+                    .zip(columns.iter_mut())
+                    .map(|(field, values)| (field.name().to_owned(), values.next().unwrap()))
+                    .collect();
+                Value::Record(fields)
+            })
+            .collect())
+    }
+
+    /// Every row of the array as the null or value branch of its union
+    fn values(&self, array: &ArrayRef, path: &str) -> Result<Vec<Value>> {
+        // safe: false so a uint64 too big for a long fails instead of becoming null
+        let options = CastOptions {
+            safe: false,
+            ..Default::default()
+        };
+        let values: Vec<Option<Value>> = match array.data_type() {
+            DataType::Boolean => array
+                .as_boolean()
+                .iter()
+                .map(|v| v.map(Value::Boolean))
+                .collect(),
+            DataType::Int8
+            | DataType::Int16
+            | DataType::Int32
+            | DataType::UInt8
+            | DataType::UInt16 => cast(array, &DataType::Int32)?
+                .as_primitive::<Int32Type>()
+                .iter()
+                .map(|v| v.map(Value::Int))
+                .collect(),
+            DataType::Int64 | DataType::UInt32 | DataType::UInt64 => {
+                cast_with_options(array, &DataType::Int64, &options)?
+                    .as_primitive::<Int64Type>()
+                    .iter()
+                    .map(|v| v.map(Value::Long))
+                    .collect()
+            }
+            DataType::Float32 => array
+                .as_primitive::<Float32Type>()
+                .iter()
+                .map(|v| v.map(Value::Float))
+                .collect(),
+            DataType::Float64 => array
+                .as_primitive::<Float64Type>()
+                .iter()
+                .map(|v| v.map(Value::Double))
+                .collect(),
+            DataType::Utf8 | DataType::LargeUtf8 => cast(array, &DataType::Utf8)?
+                .as_string::<i32>()
+                .iter()
+                .map(|v| v.map(|s| Value::String(s.to_owned())))
+                .collect(),
+            DataType::Dictionary(_, _) => {
+                let strings = cast(array, &DataType::Utf8)?;
+                let strings = strings.as_string::<i32>().iter();
+                match self.enums.get(path) {
+                    Some(symbols) => strings
+                        .map(|v| v.map(|s| enum_value(path, symbols, s)).transpose())
+                        .collect::<Result<_>>()?,
+                    None => strings
+                        .map(|v| v.map(|s| Value::String(s.to_owned())))
+                        .collect(),
+                }
+            }
+            DataType::Binary | DataType::LargeBinary => cast(array, &DataType::Binary)?
+                .as_binary::<i32>()
+                .iter()
+                .map(|v| v.map(|b| Value::Bytes(b.to_vec())))
+                .collect(),
+            DataType::Timestamp(_, _) => {
+                cast(array, &DataType::Timestamp(TimeUnit::Microsecond, None))?
+                    .as_primitive::<TimestampMicrosecondType>()
+                    .iter()
+                    .map(|v| v.map(Value::TimestampMicros))
+                    .collect()
+            }
+            DataType::Struct(fields) => {
+                let array = array.as_struct();
+                let mut children = fields
+                    .iter()
+                    .zip(array.columns())
+                    .map(|(field, child)| {
+                        let path = format!("{path}.{}", field.name());
+                        Ok(self.values(child, &path)?.into_iter())
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                (0..array.len())
+                    .map(|row| {
+                        let record = fields
+                            .iter()
+                            .zip(children.iter_mut())
+                            .map(|(f, values)| (f.name().to_owned(), values.next().unwrap()))
+                            .collect();
+                        array.is_valid(row).then_some(Value::Record(record))
+                    })
+                    .collect()
+            }
+            DataType::List(_) => {
+                let array = array.as_list::<i32>();
+                (0..array.len())
+                    .map(|row| -> Result<Option<Value>> {
+                        if array.is_null(row) {
+                            return Ok(None);
+                        }
+                        Ok(Some(Value::Array(self.values(&array.value(row), path)?)))
+                    })
+                    .collect::<Result<_>>()?
+            }
+            other => {
+                return Err(KatinssIngestorError::UnsupportedColumnType(
+                    other.to_string(),
+                    "avro",
+                ))
+            }
+        };
+
+        Ok(values
+            .into_iter()
+            .map(|v| match v {
+                Some(v) => Value::Union(1, Box::new(v)),
+                None => Value::Union(0, Box::new(Value::Null)),
+            })
+            .collect())
+    }
+}
+
+fn enum_value(path: &str, symbols: &[String], symbol: &str) -> Result<Value> {
+    let index = symbols.iter().position(|s| s == symbol).ok_or_else(|| {
+        KatinssIngestorError::UnsupportedColumnType(format!("{path} value {symbol}"), "avro")
+    })?;
+    Ok(Value::Enum(index as u32, symbol.to_owned()))
+}
+
+/// `{"name": .., "type": ["null", ..], "default": null}`
+fn avro_field(
+    field: &Field,
+    parent: &str,
+    props: &ArrowBatchProps,
+    enums: &mut HashMap<String, Vec<String>>,
+) -> Result<serde_json::Value> {
+    let path = match parent {
+        "" => field.name().to_owned(),
+        parent => format!("{parent}.{}", field.name()),
+    };
+    Ok(json!({
+        "name": field.name(),
+        "type": ["null", avro_type(field, &path, props, enums)?],
+        "default": null,
+    }))
+}
+
+fn avro_type(
+    field: &Field,
+    path: &str,
+    props: &ArrowBatchProps,
+    enums: &mut HashMap<String, Vec<String>>,
+) -> Result<serde_json::Value> {
+    // named types have to be unique within the schema, so they're named by path
+    let name = path.replace('.', "_");
+    Ok(match field.data_type() {
+        DataType::Boolean => json!("boolean"),
+        DataType::Int8 | DataType::Int16 | DataType::Int32 | DataType::UInt8 | DataType::UInt16 => {
+            json!("int")
+        }
+        DataType::Int64 | DataType::UInt32 | DataType::UInt64 => json!("long"),
+        DataType::Float32 => json!("float"),
+        DataType::Float64 => json!("double"),
+        DataType::Utf8 | DataType::LargeUtf8 => json!("string"),
+        DataType::Binary | DataType::LargeBinary => json!("bytes"),
+        DataType::Timestamp(_, _) => json!({"type": "long", "logicalType": "timestamp-micros"}),
+        DataType::Dictionary(_, _) => {
+            let values = field
+                .dict_id()
+                .and_then(|id| props.dictionaries.get_dict_values(id));
+            match values {
+                Some(values) => {
+                    let symbols: Vec<String> = values.iter().flatten().map(str::to_owned).collect();
+                    enums.insert(path.to_owned(), symbols.clone());
+                    json!({"type": "enum", "name": name, "symbols": symbols})
+                }
+                None => json!("string"),
+            }
+        }
+        DataType::Struct(fields) => {
+            let fields = fields
+                .iter()
+                .map(|f| avro_field(f, path, props, enums))
+                .collect::<Result<Vec<_>>>()?;
+            json!({"type": "record", "name": name, "fields": fields})
+        }
+        DataType::List(item) => {
+            json!({"type": "array", "items": ["null", avro_type(item, path, props, enums)?]})
+        }
+        other => {
+            return Err(KatinssIngestorError::UnsupportedColumnType(
+                other.to_string(),
+                "avro",
+            ))
+        }
+    })
+}
+
+impl BufferEncoder for AvroEncoder {
+    fn extension(&self) -> &str {
+        "avro"
+    }
+
+    fn encode(&self, _schema: &Schema, batches: &[RecordBatch]) -> Result<Vec<u8>> {
+        let mut writer = Writer::with_codec(&self.schema, Vec::new(), self.codec);
+        for batch in batches {
+            for record in self.records(batch)? {
+                writer.append(record)?;
+            }
+        }
+        Ok(writer.into_inner()?)
+    }
+
+    fn reload_schema(&mut self, props: &ArrowBatchProps) -> Result<()> {
+        *self = Self::try_new(props)?.with_codec(self.codec);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use apache_avro::Reader;
+    use katniss_pb2arrow::exports::prost_reflect::DescriptorPool;
+
+    use katniss_test::{
+        descriptor_pool,
+        protos::spacecorp::{climate_status::Species, packet::Msg, ClimateStatus, Packet},
+        test_util::ProtoBatch,
+    };
+
+    use super::*;
+
+    fn props(pool: DescriptorPool) -> Result<ArrowBatchProps> {
+        Ok(ArrowBatchProps::try_new(
+            pool,
+            "eto.pb2arrow.tests.spacecorp.Packet".to_owned(),
+        )?)
+    }
+
+    #[test]
+    fn buffers_are_written_as_avro_files() -> anyhow::Result<()> {
+        let packets = (0..3)
+            .map(|i| Packet {
+                sender_uid: i,
+                msg: Some(Msg::ClimateStatus(ClimateStatus {
+                    species: Species::Timelord as i32,
+                    ..Default::default()
+                })),
+                ..Default::default()
+            })
+            .collect::<Vec<_>>();
+        let batch = ProtoBatch::SpaceCorp(&packets).arrow_batch()?;
+
+        let encoder = AvroEncoder::try_new(&props(descriptor_pool()?)?)?.with_codec(Codec::Deflate);
+        let bytes = encoder.encode(&batch.schema(), &[batch])?;
+
+        let records = Reader::new(&bytes[..])?.collect::<std::result::Result<Vec<_>, _>>()?;
+        assert_eq!(records.len(), 3);
+        let Value::Record(fields) = &records[2] else {
+            panic!("expected a record, got {:?}", records[2]);
+        };
+        let field = |name: &str| &fields.iter().find(|(n, _)| n == name).unwrap().1;
+        assert_eq!(
+            field("sender_uid"),
+            &Value::Union(1, Box::new(Value::Long(2)))
+        );
+        assert_eq!(
+            field("jump_drive_status"),
+            &Value::Union(0, Box::new(Value::Null))
+        );
+        let Value::Union(1, climate_status) = field("climate_status") else {
+            panic!("climate_status wasn't set");
+        };
+        let Value::Record(climate_status) = climate_status.as_ref() else {
+            panic!("expected a record, got {climate_status:?}");
+        };
+        assert!(climate_status.contains(&(
+            "species".to_owned(),
+            Value::Union(1, Box::new(Value::Enum(2, "TIMELORD".to_owned())))
+        )));
+        Ok(())
+    }
+}
@@ -0,0 +1,395 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use parquet::{
+    basic::{Compression, GzipLevel, ZstdLevel},
+    file::properties::EnabledStatistics,
+};
+
+use katniss_pb2arrow::ArrowBatchProps;
+
+use crate::{
+    errors::KatinssIngestorError,
+    lance_ingestion::LanceIngestor,
+    lance_retention::LanceRetention,
+    sinks::{
+        parse_encoding, BufferEncoder, BufferSink, CsvEncoder, FileNaming, IpcFileEncoder,
+        JsonLinesEncoder, ParquetColumns, ParquetEncoder, ParquetStoreSink, PartitionedSink,
+        StoreSink,
+    },
+    Result, WriteMode,
+};
+
+/// Where rotated buffers get written and how, the same settings as `katniss serve`'s output
+/// flags so a config file can describe a pipeline's sinks, i.e.
+///
+/// ```toml
+/// output = "s3://archive/packets"
+/// format = "parquet"
+/// compression = "zstd"
+/// statistics = { payload = "none" }
+/// partition_by = ["field:header.device_id", "time:hour"]
+/// manifests = true
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SinkConfig {
+    /// Storage uri, i.e. file:///data/packets or s3://bucket/packets
+    pub output: String,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub format: SinkFormat,
+    /// How the first buffer is written to a lance dataset, later ones always append
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub lance_mode: LanceMode,
+    /// Delete all but this many of the newest lance dataset versions after each write
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub lance_max_versions: Option<usize>,
+    /// Delete lance rows from buffers that ended longer ago than this
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, with = "crate::pipeline_config::secs::option")
+    )]
+    pub lance_row_ttl: Option<Duration>,
+    /// Parquet compression codec
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub compression: Codec,
+    /// Level for zstd (1-22) or gzip (0-10), the codec's default if not given
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub compression_level: Option<u32>,
+    /// Parquet columns to write bloom filters for, i.e. header.device_id
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub bloom_filter: Vec<String>,
+    /// Parquet column statistics levels by column
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub statistics: BTreeMap<String, ColumnStatistics>,
+    /// Parquet column encodings by column, i.e. counter = "DELTA_BINARY_PACKED".
+    /// Added to encodings from katniss.parquet_encoding field options
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub encoding: BTreeMap<String, String>,
+    /// Sort each parquet file's rows by this column, i.e. timestamp or header.stamp
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub sort_column: Option<String>,
+    /// Finish each output directory with a _manifest.jsonl and a _SUCCESS marker
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub manifests: bool,
+    /// File names (and so partitions) relative to the output, i.e. "{date}/{hour}/{start}.{ext}",
+    /// see FileNaming::template for the placeholders
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub file_naming: Option<String>,
+    /// Field partitions nest in the order given with a time partition innermost
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub partition_by: Vec<PartitionBy>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum SinkFormat {
+    #[default]
+    Lance,
+    Parquet,
+    Arrow,
+    Jsonl,
+    Csv,
+    #[cfg(feature = "avro")]
+    Avro,
+}
+
+/// WriteMode for the first buffer of a LanceIngestor
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum LanceMode {
+    Create,
+    #[default]
+    Append,
+    Overwrite,
+}
+
+impl From<LanceMode> for WriteMode {
+    fn from(mode: LanceMode) -> Self {
+        match mode {
+            LanceMode::Create => WriteMode::Create,
+            LanceMode::Append => WriteMode::Append,
+            LanceMode::Overwrite => WriteMode::Overwrite,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum Codec {
+    None,
+    #[default]
+    Snappy,
+    Lz4,
+    Zstd,
+    Gzip,
+}
+
+impl Codec {
+    /// Level is for zstd or gzip, the codec's default if not given
+    pub fn compression(self, level: Option<u32>) -> Result<Compression> {
+        Ok(match self {
+            Self::None => Compression::UNCOMPRESSED,
+            Self::Snappy => Compression::SNAPPY,
+            Self::Lz4 => Compression::LZ4_RAW,
+            Self::Zstd => Compression::ZSTD(match level {
+                Some(level) => ZstdLevel::try_new(level as i32)?,
+                None => ZstdLevel::default(),
+            }),
+            Self::Gzip => Compression::GZIP(match level {
+                Some(level) => GzipLevel::try_new(level)?,
+                None => GzipLevel::default(),
+            }),
+        })
+    }
+}
+
+/// EnabledStatistics for a parquet column
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum ColumnStatistics {
+    None,
+    Chunk,
+    Page,
+}
+
+impl From<ColumnStatistics> for EnabledStatistics {
+    fn from(statistics: ColumnStatistics) -> Self {
+        match statistics {
+            ColumnStatistics::None => EnabledStatistics::None,
+            ColumnStatistics::Chunk => EnabledStatistics::Chunk,
+            ColumnStatistics::Page => EnabledStatistics::Page,
+        }
+    }
+}
+
+/// Hive style partitions, written time:day or time:hour for date=2024-06-01/hour=13/
+/// directories (not with a file naming) and field:<path> for header.device_id=7/ directories
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "String", into = "String")
+)]
+pub enum PartitionBy {
+    Day,
+    Hour,
+    Field(String),
+}
+
+impl FromStr for PartitionBy {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some(("time", "day")) => Ok(Self::Day),
+            Some(("time", "hour")) => Ok(Self::Hour),
+            Some(("field", path)) if !path.is_empty() => Ok(Self::Field(path.to_owned())),
+            _ => Err(format!(
+                "expected time:day, time:hour or field:<path>, got {s}"
+            )),
+        }
+    }
+}
+
+impl TryFrom<String> for PartitionBy {
+    type Error = String;
+
+    fn try_from(s: String) -> std::result::Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for PartitionBy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Day => write!(f, "time:day"),
+            Self::Hour => write!(f, "time:hour"),
+            Self::Field(path) => write!(f, "field:{path}"),
+        }
+    }
+}
+
+impl From<PartitionBy> for String {
+    fn from(partition: PartitionBy) -> Self {
+        partition.to_string()
+    }
+}
+
+impl SinkConfig {
+    pub fn new<U: Into<String>>(output: U, format: SinkFormat) -> Self {
+        Self {
+            output: output.into(),
+            format,
+            lance_mode: LanceMode::default(),
+            lance_max_versions: None,
+            lance_row_ttl: None,
+            compression: Codec::default(),
+            compression_level: None,
+            bloom_filter: Vec::new(),
+            statistics: BTreeMap::new(),
+            encoding: BTreeMap::new(),
+            sort_column: None,
+            manifests: false,
+            file_naming: None,
+            partition_by: Vec::new(),
+        }
+    }
+
+    /// The configured sink for the props' schema, field partitions wrap it in a PartitionedSink
+    pub fn sink(&self, props: &ArrowBatchProps) -> Result<Box<dyn BufferSink>> {
+        let invalid = |msg: &str| Err(KatinssIngestorError::InvalidConfig(msg.to_owned()));
+        let time_partitions = self
+            .partition_by
+            .iter()
+            .filter(|p| !matches!(p, PartitionBy::Field(_)))
+            .count();
+        if time_partitions > 1 {
+            return invalid("only one of time:day and time:hour partitions");
+        }
+        if time_partitions == 1 && self.file_naming.is_some() {
+            return invalid(
+                "a file naming decides directories, it can't be used with time partitions",
+            );
+        }
+        if time_partitions == 1 && self.format == SinkFormat::Lance {
+            return invalid("lance datasets can't be partitioned by time");
+        }
+
+        let field = self
+            .partition_by
+            .iter()
+            .position(|p| matches!(p, PartitionBy::Field(_)));
+        let Some(field) = field else {
+            return self.unpartitioned_sink(props);
+        };
+        let mut rest = self.clone();
+        let PartitionBy::Field(column) = rest.partition_by.remove(field) else {
+            unreachable!("position found a field partition");
+        };
+        let props = props.clone();
+        Ok(Box::new(PartitionedSink::new(&column, move |directory| {
+            let mut partition = rest.clone();
+            partition.output = format!("{}/{directory}", rest.output.trim_end_matches('/'));
+            partition.sink(&props)
+        })))
+    }
+
+    fn unpartitioned_sink(&self, props: &ArrowBatchProps) -> Result<Box<dyn BufferSink>> {
+        let uri = self.output.as_str();
+        let schema = props.schema.clone();
+        Ok(match self.format {
+            SinkFormat::Lance => {
+                let ingestor = LanceIngestor::new(uri, schema)?
+                    .with_mode(self.lance_mode.into())
+                    .with_descriptor(&props.descriptor);
+                Box::new(match self.lance_retention() {
+                    Some(retention) => ingestor.with_retention(retention),
+                    None => ingestor,
+                })
+            }
+            SinkFormat::Parquet => {
+                let encoder = ParquetEncoder::new()
+                    .with_compression(self.compression.compression(self.compression_level)?)
+                    .with_columns(&self.parquet_columns(props)?)
+                    .with_descriptor(&props.descriptor);
+                let encoder = match &self.sort_column {
+                    Some(column) => encoder.with_sort_column(&schema, column)?,
+                    None => encoder,
+                };
+                let sink = ParquetStoreSink::from_uri(uri, schema, encoder)?;
+                let sink = match self.file_naming(props) {
+                    Some(naming) => sink.with_file_naming(naming),
+                    None => sink,
+                };
+                Box::new(if self.manifests {
+                    sink.with_manifests()
+                } else {
+                    sink
+                })
+            }
+            SinkFormat::Arrow => self.store_sink(props, IpcFileEncoder)?,
+            SinkFormat::Jsonl => self.store_sink(props, JsonLinesEncoder)?,
+            SinkFormat::Csv => {
+                let encoder = CsvEncoder::try_new(&schema)?;
+                self.store_sink(props, encoder)?
+            }
+            #[cfg(feature = "avro")]
+            SinkFormat::Avro => self.store_sink(props, super::AvroEncoder::try_new(props)?)?,
+        })
+    }
+
+    fn parquet_columns(&self, props: &ArrowBatchProps) -> Result<ParquetColumns> {
+        let mut columns = ParquetColumns::from_options(&props.descriptor)?;
+        for column in &self.bloom_filter {
+            columns = columns.with_bloom_filter(column);
+        }
+        for (column, level) in &self.statistics {
+            columns = columns.with_statistics(column, (*level).into());
+        }
+        for (column, encoding) in &self.encoding {
+            columns = columns.with_encoding(column, parse_encoding(encoding)?);
+        }
+        Ok(columns)
+    }
+
+    fn lance_retention(&self) -> Option<LanceRetention> {
+        if self.lance_max_versions.is_none() && self.lance_row_ttl.is_none() {
+            return None;
+        }
+        let retention = LanceRetention::new();
+        let retention = match self.lance_max_versions {
+            Some(n) => retention.with_max_versions(n),
+            None => retention,
+        };
+        Some(match self.lance_row_ttl {
+            Some(ttl) => retention.with_row_ttl(ttl),
+            None => retention,
+        })
+    }
+
+    fn file_naming(&self, props: &ArrowBatchProps) -> Option<FileNaming> {
+        let time = self.partition_by.iter().find_map(|p| match p {
+            PartitionBy::Day => Some("date={date}/{start}_{end}.{ext}"),
+            PartitionBy::Hour => Some("date={date}/hour={hour}/{start}_{end}.{ext}"),
+            PartitionBy::Field(_) => None,
+        });
+        let template = self.file_naming.as_deref().or(time)?;
+        Some(FileNaming::template(template).with_message(props.descriptor.full_name()))
+    }
+
+    fn store_sink<E: BufferEncoder + 'static>(
+        &self,
+        props: &ArrowBatchProps,
+        encoder: E,
+    ) -> Result<Box<dyn BufferSink>> {
+        let sink = StoreSink::from_uri(&self.output, props.schema.clone(), encoder)?;
+        let sink = match self.file_naming(props) {
+            Some(naming) => sink.with_file_naming(naming),
+            None => sink,
+        };
+        Ok(Box::new(if self.manifests {
+            sink.with_manifests()
+        } else {
+            sink
+        }))
+    }
+}
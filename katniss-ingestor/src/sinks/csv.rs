@@ -0,0 +1,167 @@
+use std::sync::Arc;
+
+use arrow_array::{ArrayRef, RecordBatch};
+use arrow_cast::cast;
+use arrow_csv::WriterBuilder;
+use arrow_schema::{DataType, Field, Schema};
+
+use crate::{errors::KatinssIngestorError, sinks::store::BufferEncoder, Result};
+
+/// Encodes each buffer as a csv file, only works for flat schemas (no structs or lists)
+/// Enum (dictionary) columns are written out as their string values
+#[derive(Debug, Clone)]
+pub struct CsvEncoder {
+    delimiter: u8,
+    has_headers: bool,
+}
+
+impl CsvEncoder {
+    /// Fails if the schema has any nested columns as csv has no way to represent them
+    pub fn try_new(schema: &Schema) -> Result<Self> {
+        let nested = schema
+            .fields()
+            .iter()
+            .filter(|f| {
+                matches!(
+                    f.data_type(),
+                    DataType::Struct(_)
+                        | DataType::List(_)
+                        | DataType::LargeList(_)
+                        | DataType::FixedSizeList(_, _)
+                        | DataType::Map(_, _)
+                        | DataType::Union(_, _)
+                )
+            })
+            .map(|f| f.name().to_owned())
+            .collect::<Vec<_>>();
+
+        if !nested.is_empty() {
+            return Err(KatinssIngestorError::NonFlatSchema(nested));
+        }
+
+        Ok(Self {
+            delimiter: b',',
+            has_headers: true,
+        })
+    }
+
+    pub fn with_delimiter(mut self, delimiter: u8) -> Self {
+        self.delimiter = delimiter;
+        self
+    }
+
+    pub fn with_headers(mut self, has_headers: bool) -> Self {
+        self.has_headers = has_headers;
+        self
+    }
+}
+
+impl BufferEncoder for CsvEncoder {
+    fn extension(&self) -> &str {
+        "csv"
+    }
+
+    fn encode(&self, _schema: &Schema, batches: &[RecordBatch]) -> Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        let mut writer = WriterBuilder::new()
+            .has_headers(self.has_headers)
+            .with_delimiter(self.delimiter)
+            .build(&mut bytes);
+
+        for batch in batches {
+            writer.write(&hydrate_dictionaries(batch)?)?;
+        }
+        drop(writer);
+
+        Ok(bytes)
+    }
+}
+
+/// Swap dictionary columns for plain columns of their values
+fn hydrate_dictionaries(batch: &RecordBatch) -> Result<RecordBatch> {
+    let (fields, columns): (Vec<Field>, Vec<ArrayRef>) = batch
+        .schema()
+        .fields()
+        .iter()
+        .zip(batch.columns())
+        .map(|(field, column)| -> Result<(Field, ArrayRef)> {
+            match field.data_type() {
+                DataType::Dictionary(_, value_type) => Ok((
+                    Field::new(
+                        field.name(),
+                        value_type.as_ref().clone(),
+                        field.is_nullable(),
+                    ),
+                    cast(column, value_type)?,
+                )),
+                _ => Ok((field.as_ref().clone(), column.clone())),
+            }
+        })
+        .collect::<Result<Vec<_>>>()?
+        .into_iter()
+        .unzip();
+
+    Ok(RecordBatch::try_new(
+        Arc::new(Schema::new(fields)),
+        columns,
+    )?)
+}
+
+#[cfg(test)]
+mod tests {
+    use katniss_test::{
+        protos::{
+            spacecorp::Packet,
+            v3::{Foo, MessageWithNestedEnum, SomeRandomEnum},
+        },
+        test_util::ProtoBatch,
+    };
+
+    use super::*;
+
+    #[test]
+    fn it_writes_flat_messages() -> anyhow::Result<()> {
+        let batch = ProtoBatch::V3(&[
+            Foo {
+                key: 1,
+                str_val: "one".into(),
+            },
+            Foo {
+                key: 2,
+                str_val: "two".into(),
+            },
+        ])
+        .arrow_batch()?;
+
+        let encoder = CsvEncoder::try_new(&batch.schema())?.with_delimiter(b'|');
+        let csv = String::from_utf8(encoder.encode(&batch.schema(), &[batch])?)?;
+
+        assert_eq!(csv, "key|str_val\n1|one\n2|two\n");
+        Ok(())
+    }
+
+    #[test]
+    fn it_writes_enums_as_strings() -> anyhow::Result<()> {
+        let batch = ProtoBatch::V3(&[MessageWithNestedEnum {
+            status: SomeRandomEnum::Failing.into(),
+        }])
+        .arrow_batch()?;
+
+        let encoder = CsvEncoder::try_new(&batch.schema())?.with_headers(false);
+        let csv = String::from_utf8(encoder.encode(&batch.schema(), &[batch])?)?;
+
+        assert_eq!(csv, "FAILING\n");
+        Ok(())
+    }
+
+    #[test]
+    fn it_rejects_nested_schemas() -> anyhow::Result<()> {
+        let batch = ProtoBatch::SpaceCorp(&[Packet::default()]).arrow_batch()?;
+
+        assert!(matches!(
+            CsvEncoder::try_new(&batch.schema()),
+            Err(KatinssIngestorError::NonFlatSchema(_))
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,242 @@
+use arrow_flight::{
+    encode::FlightDataEncoderBuilder, error::FlightError, FlightClient, FlightDescriptor,
+};
+use async_trait::async_trait;
+use futures::{stream, StreamExt, TryStreamExt};
+use tonic::transport::Endpoint;
+
+use crate::{sinks::BufferSink, temporal_rotator::TemporalBuffer, Result};
+
+/// Streams each rotated buffer's batches to a remote Arrow Flight server via DoPut.
+/// Lets katniss do the encoding at the edge while a central Flight server handles storage.
+pub struct FlightSink {
+    client: FlightClient,
+    descriptor: FlightDescriptor,
+}
+
+impl FlightSink {
+    /// Connect to a flight endpoint i.e. http://collector:50051
+    /// Every DoPut is tagged with a FlightDescriptor made from `path`
+    pub async fn connect<E: Into<String>>(endpoint: E, path: Vec<String>) -> Result<Self> {
+        let channel = Endpoint::try_from(endpoint.into())?.connect().await?;
+
+        Ok(Self::new(
+            FlightClient::new(channel),
+            FlightDescriptor::new_path(path),
+        ))
+    }
+
+    pub fn new(client: FlightClient, descriptor: FlightDescriptor) -> Self {
+        Self { client, descriptor }
+    }
+
+    /// DoPut all of the buffer's batches as a single stream, waits for the server to ack them
+    pub async fn write(&mut self, buffer: TemporalBuffer) -> Result<()> {
+        let batches = stream::iter(buffer.batches.into_iter().map(Ok::<_, FlightError>));
+
+        // The descriptor only needs to ride along on the first message of the stream
+        let descriptor = self.descriptor.clone();
+        let flight_data = FlightDataEncoderBuilder::new()
+            .build(batches)
+            .enumerate()
+            .map(move |(i, data)| {
+                data.map(|mut data| {
+                    if i == 0 {
+                        data.flight_descriptor = Some(descriptor.clone());
+                    }
+                    data
+                })
+            });
+
+        self.client
+            .do_put(flight_data)
+            .await?
+            .try_collect::<Vec<_>>()
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl BufferSink for FlightSink {
+    async fn write_buffer(&mut self, buffer: TemporalBuffer) -> Result<()> {
+        self.write(buffer).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use arrow_array::{cast::AsArray, types::UInt64Type};
+    use arrow_flight::{
+        decode::FlightRecordBatchStream,
+        flight_service_server::{FlightService, FlightServiceServer},
+        Action, ActionType, Criteria, Empty, FlightData, FlightInfo, HandshakeRequest,
+        HandshakeResponse, PutResult, SchemaResult, Ticket,
+    };
+    use chrono::Utc;
+    use futures::stream::BoxStream;
+    use tokio::net::TcpListener;
+    use tonic::{transport::Server, Request, Response, Status, Streaming};
+
+    use katniss_pb2arrow::exports::RecordBatch;
+    use katniss_test::{protos::spacecorp::Packet, test_util::ProtoBatch};
+
+    use super::*;
+
+    type Puts = Arc<Mutex<Vec<(Option<FlightDescriptor>, Vec<RecordBatch>)>>>;
+    type Responses<T> = BoxStream<'static, std::result::Result<T, Status>>;
+
+    /// Keeps whatever is DoPut to it, everything else is unimplemented
+    #[derive(Clone, Default)]
+    struct Collector {
+        puts: Puts,
+    }
+
+    #[tonic::async_trait]
+    impl FlightService for Collector {
+        type HandshakeStream = Responses<HandshakeResponse>;
+        type ListFlightsStream = Responses<FlightInfo>;
+        type DoGetStream = Responses<FlightData>;
+        type DoPutStream = Responses<PutResult>;
+        type DoActionStream = Responses<arrow_flight::Result>;
+        type ListActionsStream = Responses<ActionType>;
+        type DoExchangeStream = Responses<FlightData>;
+
+        async fn do_put(
+            &self,
+            request: Request<Streaming<FlightData>>,
+        ) -> std::result::Result<Response<Self::DoPutStream>, Status> {
+            let data: Vec<FlightData> = request.into_inner().try_collect().await?;
+            let descriptor = data.first().and_then(|d| d.flight_descriptor.clone());
+            let data = stream::iter(data.into_iter().map(Ok::<_, FlightError>));
+            let batches: Vec<RecordBatch> = FlightRecordBatchStream::new_from_flight_data(data)
+                .try_collect()
+                .await
+                .map_err(|e| Status::invalid_argument(e.to_string()))?;
+
+            self.puts.lock().unwrap().push((descriptor, batches));
+            Ok(Response::new(
+                stream::iter([Ok(PutResult::default())]).boxed(),
+            ))
+        }
+
+        async fn handshake(
+            &self,
+            _: Request<Streaming<HandshakeRequest>>,
+        ) -> std::result::Result<Response<Self::HandshakeStream>, Status> {
+            Err(Status::unimplemented("handshake"))
+        }
+
+        async fn list_flights(
+            &self,
+            _: Request<Criteria>,
+        ) -> std::result::Result<Response<Self::ListFlightsStream>, Status> {
+            Err(Status::unimplemented("list_flights"))
+        }
+
+        async fn get_flight_info(
+            &self,
+            _: Request<FlightDescriptor>,
+        ) -> std::result::Result<Response<FlightInfo>, Status> {
+            Err(Status::unimplemented("get_flight_info"))
+        }
+
+        async fn get_schema(
+            &self,
+            _: Request<FlightDescriptor>,
+        ) -> std::result::Result<Response<SchemaResult>, Status> {
+            Err(Status::unimplemented("get_schema"))
+        }
+
+        async fn do_get(
+            &self,
+            _: Request<Ticket>,
+        ) -> std::result::Result<Response<Self::DoGetStream>, Status> {
+            Err(Status::unimplemented("do_get"))
+        }
+
+        async fn do_action(
+            &self,
+            _: Request<Action>,
+        ) -> std::result::Result<Response<Self::DoActionStream>, Status> {
+            Err(Status::unimplemented("do_action"))
+        }
+
+        async fn list_actions(
+            &self,
+            _: Request<Empty>,
+        ) -> std::result::Result<Response<Self::ListActionsStream>, Status> {
+            Err(Status::unimplemented("list_actions"))
+        }
+
+        async fn do_exchange(
+            &self,
+            _: Request<Streaming<FlightData>>,
+        ) -> std::result::Result<Response<Self::DoExchangeStream>, Status> {
+            Err(Status::unimplemented("do_exchange"))
+        }
+    }
+
+    #[tokio::test]
+    async fn it_puts_buffers_to_a_flight_server() -> anyhow::Result<()> {
+        let collector = Collector::default();
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let incoming = stream::unfold(listener, |listener| async move {
+            let conn = listener.accept().await.map(|(conn, _)| conn);
+            Some((conn, listener))
+        });
+        tokio::spawn(
+            Server::builder()
+                .add_service(FlightServiceServer::new(collector.clone()))
+                .serve_with_incoming(incoming),
+        );
+
+        let packets = (0..5)
+            .map(|i| Packet {
+                sender_uid: i,
+                ..Default::default()
+            })
+            .collect::<Vec<_>>();
+        let batches = vec![
+            ProtoBatch::SpaceCorp(&packets[..2]).arrow_batch()?,
+            ProtoBatch::SpaceCorp(&packets[2..]).arrow_batch()?,
+        ];
+
+        let path = vec!["robots".to_owned(), "packets".to_owned()];
+        let mut sink = FlightSink::connect(format!("http://{addr}"), path.clone()).await?;
+        for _ in 0..2 {
+            sink.write_buffer(TemporalBuffer {
+                begin_at: Utc::now(),
+                end_at: Utc::now(),
+                batches: batches.clone(),
+            })
+            .await?;
+        }
+
+        let puts = collector.puts.lock().unwrap();
+        assert_eq!(puts.len(), 2);
+        for (descriptor, received) in puts.iter() {
+            assert_eq!(
+                descriptor.as_ref(),
+                Some(&FlightDescriptor::new_path(path.clone()))
+            );
+            // batches can come back re-chunked with dictionaries hydrated, the rows can't change
+            let senders = received
+                .iter()
+                .flat_map(|b| {
+                    b.column_by_name("sender_uid")
+                        .unwrap()
+                        .as_primitive::<UInt64Type>()
+                        .values()
+                        .to_vec()
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(senders, (0..5).collect::<Vec<_>>());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,167 @@
+use std::io::Write;
+use std::net::{TcpStream, ToSocketAddrs};
+
+use arrow_ipc::writer::{FileWriter, StreamWriter};
+use arrow_schema::Schema;
+use async_trait::async_trait;
+use tokio::task::block_in_place;
+
+use katniss_pb2arrow::{exports::RecordBatch, ArrowBatchProps};
+
+use crate::{
+    errors::KatinssIngestorError,
+    sinks::{store::BufferEncoder, BufferSink},
+    temporal_rotator::TemporalBuffer,
+    Result,
+};
+
+/// Encodes each buffer as an Arrow IPC file (aka Feather v2)
+/// Cheapest interchange format for downstream processes that don't want to decode parquet
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IpcFileEncoder;
+
+impl BufferEncoder for IpcFileEncoder {
+    fn extension(&self) -> &str {
+        "arrow"
+    }
+
+    fn encode(&self, schema: &Schema, batches: &[RecordBatch]) -> Result<Vec<u8>> {
+        let mut writer = FileWriter::try_new(Vec::new(), schema)?;
+        for batch in batches {
+            writer.write(batch)?;
+        }
+        Ok(writer.into_inner()?)
+    }
+}
+
+/// Writes every batch into one long running Arrow IPC stream i.e. over a socket
+/// The stream is only finished by into_inner, readers should treat it as unbounded
+pub struct IpcStreamSink<W: Write + Send> {
+    writer: StreamWriter<W>,
+    schema: Schema,
+}
+
+impl IpcStreamSink<TcpStream> {
+    /// Open a tcp connection and start an IPC stream over it
+    pub fn connect<A: ToSocketAddrs>(addr: A, schema: &Schema) -> Result<Self> {
+        Self::try_new(TcpStream::connect(addr)?, schema)
+    }
+}
+
+impl<W: Write + Send> IpcStreamSink<W> {
+    /// Writes the stream's schema message immediately
+    pub fn try_new(writer: W, schema: &Schema) -> Result<Self> {
+        Ok(Self {
+            writer: StreamWriter::try_new(writer, schema)?,
+            schema: schema.clone(),
+        })
+    }
+
+    pub fn write(&mut self, buffer: TemporalBuffer) -> Result<()> {
+        for batch in &buffer.batches {
+            self.writer.write(batch)?;
+        }
+        Ok(())
+    }
+
+    pub fn into_inner(self) -> Result<W> {
+        Ok(self.writer.into_inner()?)
+    }
+}
+
+#[async_trait]
+impl<W: Write + Send> BufferSink for IpcStreamSink<W> {
+    async fn write_buffer(&mut self, buffer: TemporalBuffer) -> Result<()> {
+        block_in_place(|| self.write(buffer))
+    }
+
+    /// The stream's schema was sent up front, so its columns can't change
+    async fn reload_schema(&mut self, props: &ArrowBatchProps) -> Result<()> {
+        if props.schema.fields() == self.schema.fields() {
+            Ok(())
+        } else {
+            Err(KatinssIngestorError::SchemaChangeUnsupported(
+                "an arrow ipc stream".to_owned(),
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use arrow_ipc::reader::{FileReader, StreamReader};
+    use chrono::{TimeZone, Utc};
+    use object_store::{memory::InMemory, path::Path};
+
+    use katniss_test::{
+        protos::spacecorp::{packet, JumpDriveStatus, Packet},
+        test_util::ProtoBatch,
+    };
+
+    use super::*;
+    use crate::sinks::StoreSink;
+
+    fn packets() -> Vec<Packet> {
+        vec![
+            Packet::default(),
+            Packet {
+                msg: Some(packet::Msg::JumpDriveStatus(JumpDriveStatus::default())),
+                ..Default::default()
+            },
+        ]
+    }
+
+    fn buffer() -> anyhow::Result<TemporalBuffer> {
+        Ok(TemporalBuffer {
+            begin_at: Utc.timestamp_millis_opt(1000).unwrap(),
+            end_at: Utc.timestamp_millis_opt(2000).unwrap(),
+            batches: vec![
+                ProtoBatch::SpaceCorp(&packets()).arrow_batch()?,
+                ProtoBatch::SpaceCorp(&packets()).arrow_batch()?,
+            ],
+        })
+    }
+
+    #[tokio::test]
+    async fn it_writes_ipc_files_to_a_store() -> anyhow::Result<()> {
+        let buffer = buffer()?;
+        let schema = buffer.batches[0].schema();
+
+        let sink = StoreSink::new(
+            Box::new(InMemory::new()),
+            Path::from("packets"),
+            schema.clone(),
+            IpcFileEncoder,
+        );
+
+        let location = sink.write(buffer).await?;
+        assert_eq!(location, Path::from("packets/1000_2000.arrow"));
+
+        let bytes = sink.store().get(&location).await?.bytes().await?;
+        let reader = FileReader::try_new(Cursor::new(bytes.to_vec()), None)?;
+        assert_eq!(reader.schema().fields().len(), schema.fields().len());
+
+        let rows: usize = reader.map(|b| b.unwrap().num_rows()).sum();
+        assert_eq!(rows, 4);
+
+        Ok(())
+    }
+
+    #[test]
+    fn it_streams_buffers_continuously() -> anyhow::Result<()> {
+        let schema = buffer()?.batches[0].schema();
+        let mut sink = IpcStreamSink::try_new(Vec::new(), &schema)?;
+
+        sink.write(buffer()?)?;
+        sink.write(buffer()?)?;
+
+        let bytes = sink.into_inner()?;
+        let reader = StreamReader::try_new(Cursor::new(bytes), None)?;
+        let rows: usize = reader.map(|b| b.unwrap().num_rows()).sum();
+        assert_eq!(rows, 8);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,126 @@
+use std::fs::File;
+use std::io::{stdout, Stdout, Write};
+use std::path::Path;
+
+use arrow_json::LineDelimitedWriter;
+use arrow_schema::Schema;
+use async_trait::async_trait;
+use tokio::task::block_in_place;
+
+use katniss_pb2arrow::exports::RecordBatch;
+
+use crate::{
+    sinks::{store::BufferEncoder, BufferSink},
+    temporal_rotator::TemporalBuffer,
+    Result,
+};
+
+/// Encodes each buffer as newline delimited json, one object per row
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonLinesEncoder;
+
+impl BufferEncoder for JsonLinesEncoder {
+    fn extension(&self) -> &str {
+        "jsonl"
+    }
+
+    fn encode(&self, _schema: &Schema, batches: &[RecordBatch]) -> Result<Vec<u8>> {
+        let mut writer = LineDelimitedWriter::new(Vec::new());
+        for batch in batches {
+            writer.write(batch)?;
+        }
+        writer.finish()?;
+        Ok(writer.into_inner())
+    }
+}
+
+/// Debugging sink that renders every row it receives as a json line
+/// Handy for figuring out where a proto field actually landed without opening a notebook
+pub struct JsonLinesSink<W: Write + Send> {
+    writer: LineDelimitedWriter<W>,
+}
+
+impl JsonLinesSink<Stdout> {
+    pub fn stdout() -> Self {
+        Self::new(stdout())
+    }
+}
+
+impl JsonLinesSink<File> {
+    /// Creates (or truncates) the file at path
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Ok(Self::new(File::create(path)?))
+    }
+}
+
+impl<W: Write + Send> JsonLinesSink<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer: LineDelimitedWriter::new(writer),
+        }
+    }
+
+    pub fn write(&mut self, buffer: TemporalBuffer) -> Result<()> {
+        for batch in &buffer.batches {
+            self.writer.write(batch)?;
+        }
+        Ok(())
+    }
+
+    pub fn into_inner(mut self) -> Result<W> {
+        self.writer.finish()?;
+        Ok(self.writer.into_inner())
+    }
+}
+
+#[async_trait]
+impl<W: Write + Send> BufferSink for JsonLinesSink<W> {
+    async fn write_buffer(&mut self, buffer: TemporalBuffer) -> Result<()> {
+        block_in_place(|| self.write(buffer))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use katniss_test::{
+        protos::spacecorp::{packet, ClimateStatus, Packet},
+        test_util::ProtoBatch,
+    };
+
+    use super::*;
+
+    #[test]
+    fn it_writes_a_line_per_row() -> anyhow::Result<()> {
+        let packets = [
+            Packet {
+                sender_uid: 7,
+                ..Default::default()
+            },
+            Packet {
+                msg: Some(packet::Msg::ClimateStatus(ClimateStatus {
+                    room_id: 42,
+                    ..Default::default()
+                })),
+                ..Default::default()
+            },
+        ];
+        let buffer = TemporalBuffer {
+            begin_at: Utc::now(),
+            end_at: Utc::now(),
+            batches: vec![ProtoBatch::SpaceCorp(&packets).arrow_batch()?],
+        };
+
+        let mut sink = JsonLinesSink::new(Vec::new());
+        sink.write(buffer)?;
+        let output = String::from_utf8(sink.into_inner()?)?;
+
+        let lines = output.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].contains(r#""sender_uid":7"#));
+        assert!(lines[1].contains(r#""room_id":42"#));
+
+        Ok(())
+    }
+}
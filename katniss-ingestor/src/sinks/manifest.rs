@@ -0,0 +1,295 @@
+use std::sync::Arc;
+
+use arrow_array::{ArrayRef, Int64Array, StringArray, UInt64Array};
+use arrow_json::ReaderBuilder;
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use chrono::{DateTime, Utc};
+use object_store::{path::Path, ObjectStore};
+
+use katniss_pb2arrow::exports::RecordBatch;
+
+use crate::{
+    errors::KatinssIngestorError,
+    sinks::{BufferEncoder, JsonLinesEncoder},
+    Result,
+};
+
+/// Lists the files of a complete partition, one json line per file
+pub const MANIFEST_FILENAME: &str = "_manifest.jsonl";
+/// Written after the manifest, a partition with one won't get any more files. StoreSink
+/// refuses buffers that would land in a sealed partition rather than rewriting its manifest,
+/// only Compactor touches it afterwards (same rows, fewer files)
+pub const SUCCESS_FILENAME: &str = "_SUCCESS";
+
+/// path, rows, begin_millis, end_millis
+pub fn manifest_schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        Field::new("path", DataType::Utf8, false),
+        Field::new("rows", DataType::UInt64, false),
+        Field::new("begin_millis", DataType::Int64, false),
+        Field::new("end_millis", DataType::Int64, false),
+    ]))
+}
+
+/// Keeps track of the files written into the current partition, the directory FileNaming puts
+/// them in. Buffers come out of the pipeline in time order, so once a file lands in another
+/// partition (or the sink closes) the current one is done and gets its manifest and marker.
+#[derive(Debug, Default)]
+pub(crate) struct Manifests {
+    partition: Option<Path>,
+    files: Vec<ManifestEntry>,
+}
+
+#[derive(Debug)]
+pub(crate) struct ManifestEntry {
+    pub(crate) filename: String,
+    pub(crate) rows: usize,
+    pub(crate) begin_millis: i64,
+    pub(crate) end_millis: i64,
+}
+
+impl Manifests {
+    /// Errors if `location` would land in a partition that's already been sealed, by this sink
+    /// or an earlier run, so late files can't change a manifest readers may already trust
+    pub(crate) async fn check_open(&self, store: &dyn ObjectStore, location: &Path) -> Result<()> {
+        let partition = partition_of(location);
+        if self.partition.as_ref() == Some(&partition) {
+            return Ok(());
+        }
+        match store.head(&partition.child(SUCCESS_FILENAME)).await {
+            Ok(_) => Err(KatinssIngestorError::SealedPartition(partition.to_string())),
+            Err(object_store::Error::NotFound { .. }) => Ok(()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub(crate) async fn record(
+        &mut self,
+        store: &dyn ObjectStore,
+        location: &Path,
+        rows: usize,
+        begin_at: DateTime<Utc>,
+        end_at: DateTime<Utc>,
+    ) -> Result<()> {
+        let partition = partition_of(location);
+        if self.partition.as_ref() != Some(&partition) {
+            self.finish(store).await?;
+            self.partition = Some(partition);
+        }
+        self.files.push(ManifestEntry {
+            filename: location.filename().unwrap_or_default().to_owned(),
+            rows,
+            begin_millis: begin_at.timestamp_millis(),
+            end_millis: end_at.timestamp_millis(),
+        });
+        Ok(())
+    }
+
+    /// Writes the current partition's manifest and marker
+    pub(crate) async fn finish(&mut self, store: &dyn ObjectStore) -> Result<()> {
+        let Some(partition) = self.partition.take() else {
+            return Ok(());
+        };
+        let files = std::mem::take(&mut self.files);
+        write_manifest(store, &partition, &files).await?;
+        store
+            .put(&partition.child(SUCCESS_FILENAME), Vec::new().into())
+            .await?;
+        Ok(())
+    }
+}
+
+fn partition_of(location: &Path) -> Path {
+    let mut parts: Vec<_> = location.parts().collect();
+    parts.pop();
+    Path::from_iter(parts)
+}
+
+/// Updates a finished partition's manifest after Compactor merged `merged` into the first of
+/// them, partitions without a manifest are left alone
+pub(crate) async fn merge_entries(
+    store: &dyn ObjectStore,
+    partition: &Path,
+    merged: &[String],
+) -> Result<()> {
+    let Some((into, rest)) = merged.split_first() else {
+        return Ok(());
+    };
+    let Some(mut files) = read_entries(store, partition).await? else {
+        return Ok(());
+    };
+
+    let merged_away: Vec<_> = files
+        .iter()
+        .filter(|f| rest.contains(&f.filename))
+        .collect();
+    let rows = merged_away.iter().map(|f| f.rows).sum::<usize>();
+    let begin_millis = merged_away.iter().map(|f| f.begin_millis).min();
+    let end_millis = merged_away.iter().map(|f| f.end_millis).max();
+
+    if let Some(entry) = files.iter_mut().find(|f| &f.filename == into) {
+        entry.rows += rows;
+        entry.begin_millis = entry.begin_millis.min(begin_millis.unwrap_or(i64::MAX));
+        entry.end_millis = entry.end_millis.max(end_millis.unwrap_or(i64::MIN));
+    }
+    files.retain(|f| !rest.contains(&f.filename));
+    write_manifest(store, partition, &files).await
+}
+
+/// The partition's manifest entries, None if it doesn't have a manifest (yet)
+pub(crate) async fn read_entries(
+    store: &dyn ObjectStore,
+    partition: &Path,
+) -> Result<Option<Vec<ManifestEntry>>> {
+    let bytes = match store.get(&partition.child(MANIFEST_FILENAME)).await {
+        Ok(manifest) => manifest.bytes().await?,
+        Err(object_store::Error::NotFound { .. }) => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+
+    let mut files: Vec<ManifestEntry> = Vec::new();
+    for batch in ReaderBuilder::new(manifest_schema()).build(bytes.as_ref())? {
+        let batch = batch?;
+        let column = |i| batch.column(i).as_any();
+        let (Some(paths), Some(rows), Some(begins), Some(ends)) = (
+            column(0).downcast_ref::<StringArray>(),
+            column(1).downcast_ref::<UInt64Array>(),
+            column(2).downcast_ref::<Int64Array>(),
+            column(3).downcast_ref::<Int64Array>(),
+        ) else {
+            unreachable!("batches are read with the manifest schema");
+        };
+        files.extend((0..batch.num_rows()).map(|i| ManifestEntry {
+            filename: paths.value(i).to_owned(),
+            rows: rows.value(i) as usize,
+            begin_millis: begins.value(i),
+            end_millis: ends.value(i),
+        }));
+    }
+    Ok(Some(files))
+}
+
+async fn write_manifest(
+    store: &dyn ObjectStore,
+    partition: &Path,
+    files: &[ManifestEntry],
+) -> Result<()> {
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(StringArray::from_iter_values(
+            files.iter().map(|f| f.filename.as_str()),
+        )),
+        Arc::new(UInt64Array::from_iter_values(
+            files.iter().map(|f| f.rows as u64),
+        )),
+        Arc::new(Int64Array::from_iter_values(
+            files.iter().map(|f| f.begin_millis),
+        )),
+        Arc::new(Int64Array::from_iter_values(
+            files.iter().map(|f| f.end_millis),
+        )),
+    ];
+    let schema = manifest_schema();
+    let manifest = RecordBatch::try_new(schema.clone(), columns)?;
+    let bytes = JsonLinesEncoder.encode(&schema, &[manifest])?;
+
+    store
+        .put(&partition.child(MANIFEST_FILENAME), bytes.into())
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone};
+    use object_store::memory::InMemory;
+
+    use katniss_test::{protos::spacecorp::Packet, test_util::ProtoBatch};
+
+    use super::*;
+    use crate::{
+        sinks::{BufferSink, FileNaming, IpcFileEncoder, StoreSink},
+        temporal_rotator::TemporalBuffer,
+    };
+
+    #[tokio::test]
+    async fn partitions_get_a_manifest_once_theyre_done() -> anyhow::Result<()> {
+        let batch = ProtoBatch::SpaceCorp(&[Packet::default()]).arrow_batch()?;
+        let mut sink = StoreSink::new(
+            Box::new(InMemory::new()),
+            Path::from("packets"),
+            batch.schema(),
+            IpcFileEncoder,
+        )
+        .with_file_naming(FileNaming::template("{hour}/{start}.{ext}"))
+        .with_manifests();
+
+        let start = Utc.timestamp_millis_opt(0).unwrap();
+        for minutes in [0, 30, 60] {
+            let begin_at = start + Duration::minutes(minutes);
+            sink.write_buffer(TemporalBuffer {
+                begin_at,
+                end_at: begin_at + Duration::minutes(30),
+                batches: vec![batch.clone()],
+            })
+            .await?;
+        }
+
+        let marker = |hour| Path::from(format!("packets/{hour}/{SUCCESS_FILENAME}"));
+        assert!(sink.store().head(&marker("00")).await.is_ok());
+        assert!(sink.store().head(&marker("01")).await.is_err());
+
+        let manifest = Path::from(format!("packets/00/{MANIFEST_FILENAME}"));
+        let manifest = sink.store().get(&manifest).await?.bytes().await?;
+        let lines: Vec<_> = std::str::from_utf8(&manifest)?.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].contains(r#""path":"0.arrow""#));
+        assert!(lines[1].contains(r#""path":"1800000.arrow""#));
+        assert!(lines[1].contains(r#""end_millis":3600000"#));
+
+        sink.close().await?;
+        assert!(sink.store().head(&marker("01")).await.is_ok());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn late_buffers_dont_reopen_sealed_partitions() -> anyhow::Result<()> {
+        let batch = ProtoBatch::SpaceCorp(&[Packet::default()]).arrow_batch()?;
+        let mut sink = StoreSink::new(
+            Box::new(InMemory::new()),
+            Path::from("packets"),
+            batch.schema(),
+            IpcFileEncoder,
+        )
+        .with_file_naming(FileNaming::template("{hour}/{start}.{ext}"))
+        .with_manifests();
+
+        let start = Utc.timestamp_millis_opt(0).unwrap();
+        let buffer = |minutes| {
+            let begin_at = start + Duration::minutes(minutes);
+            TemporalBuffer {
+                begin_at,
+                end_at: begin_at + Duration::minutes(30),
+                batches: vec![batch.clone()],
+            }
+        };
+        sink.write_buffer(buffer(0)).await?;
+        sink.write_buffer(buffer(60)).await?;
+
+        let manifest = Path::from(format!("packets/00/{MANIFEST_FILENAME}"));
+        let sealed = sink.store().get(&manifest).await?.bytes().await?;
+
+        let late = sink.write_buffer(buffer(30)).await;
+        assert!(matches!(late, Err(KatinssIngestorError::SealedPartition(p)) if p == "packets/00"));
+        assert!(sink
+            .store()
+            .head(&Path::from("packets/00/1800000.arrow"))
+            .await
+            .is_err());
+        assert_eq!(sink.store().get(&manifest).await?.bytes().await?, sealed);
+
+        // the open partition still takes files
+        sink.write_buffer(buffer(90)).await?;
+        sink.close().await?;
+        Ok(())
+    }
+}
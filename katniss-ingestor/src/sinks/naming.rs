@@ -0,0 +1,155 @@
+use std::fmt;
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+
+use crate::temporal_rotator::{timestamp_string, TemporalBuffer};
+
+/// Everything known about a file when it's being named
+#[derive(Debug, Clone)]
+pub struct FileMeta<'a> {
+    pub message: Option<&'a str>,
+    pub begin_at: DateTime<Utc>,
+    pub end_at: DateTime<Utc>,
+    /// Files the sink has written before this one
+    pub seq: u64,
+    /// Index of the file within its buffer, when a buffer is split into several
+    pub part: usize,
+    pub extension: &'a str,
+}
+
+/// How a sink names the files it writes, relative to its prefix
+#[derive(Clone)]
+pub struct FileNaming {
+    rule: Rule,
+    message: Option<String>,
+}
+
+#[derive(Clone)]
+enum Rule {
+    Template(String),
+    Callback(Arc<dyn Fn(&FileMeta<'_>) -> String + Send + Sync>),
+}
+
+impl FileNaming {
+    /// Placeholders are replaced with the file's metadata:
+    /// {message}, {start} and {end} (epoch millis), {start_time} and {end_time}
+    /// (i.e. 2023-03-08-203901_utc), {date} and {hour} of the start (2023-03-08 and 20),
+    /// {seq}, {part} and {ext}.
+    /// i.e. "{message}_{start}_{end}_{seq}.{ext}"
+    pub fn template<T: Into<String>>(template: T) -> Self {
+        Self {
+            rule: Rule::Template(template.into()),
+            message: None,
+        }
+    }
+
+    pub fn callback<F>(callback: F) -> Self
+    where
+        F: Fn(&FileMeta<'_>) -> String + Send + Sync + 'static,
+    {
+        Self {
+            rule: Rule::Callback(Arc::new(callback)),
+            message: None,
+        }
+    }
+
+    /// Name of the message being written, for {message}
+    pub fn with_message<M: Into<String>>(mut self, message: M) -> Self {
+        self.message = Some(message.into());
+        self
+    }
+
+    pub(crate) fn name(
+        &self,
+        buffer: &TemporalBuffer,
+        seq: u64,
+        part: usize,
+        extension: &str,
+    ) -> String {
+        self.name_meta(&FileMeta {
+            message: self.message.as_deref(),
+            begin_at: buffer.begin_at,
+            end_at: buffer.end_at,
+            seq,
+            part,
+            extension,
+        })
+    }
+
+    fn name_meta(&self, meta: &FileMeta<'_>) -> String {
+        let template = match &self.rule {
+            Rule::Template(template) => template,
+            Rule::Callback(callback) => return callback(meta),
+        };
+
+        [
+            ("{message}", meta.message.unwrap_or_default().to_owned()),
+            ("{start}", meta.begin_at.timestamp_millis().to_string()),
+            ("{end}", meta.end_at.timestamp_millis().to_string()),
+            ("{start_time}", timestamp_string(meta.begin_at)),
+            ("{end_time}", timestamp_string(meta.end_at)),
+            ("{date}", meta.begin_at.format("%Y-%m-%d").to_string()),
+            ("{hour}", meta.begin_at.format("%H").to_string()),
+            ("{seq}", meta.seq.to_string()),
+            ("{part}", meta.part.to_string()),
+            ("{ext}", meta.extension.to_owned()),
+        ]
+        .iter()
+        .fold(template.clone(), |name, (placeholder, value)| {
+            name.replace(placeholder, value)
+        })
+    }
+}
+
+impl Default for FileNaming {
+    /// {start}_{end}.{ext}
+    fn default() -> Self {
+        Self::template("{start}_{end}.{ext}")
+    }
+}
+
+impl fmt::Debug for FileNaming {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("FileNaming");
+        match &self.rule {
+            Rule::Template(template) => debug.field("template", template),
+            Rule::Callback(_) => debug.field("callback", &"Fn"),
+        };
+        debug.field("message", &self.message).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn templates_fill_in_file_metadata() {
+        let buffer = TemporalBuffer {
+            begin_at: Utc.timestamp_millis_opt(1678307941000).unwrap(),
+            end_at: Utc.timestamp_millis_opt(1678308001000).unwrap(),
+            batches: Vec::new(),
+        };
+
+        let naming = FileNaming::template("{message}/{start_time}_{seq}-{part}.{ext}")
+            .with_message("Packet");
+        assert_eq!(
+            naming.name(&buffer, 7, 1, "parquet"),
+            "Packet/2023-03-08-203901_utc_7-1.parquet"
+        );
+        assert_eq!(
+            FileNaming::template("{date}/{hour}/{start}.{ext}").name(&buffer, 7, 1, "parquet"),
+            "2023-03-08/20/1678307941000.parquet"
+        );
+        assert_eq!(
+            FileNaming::default().name(&buffer, 7, 1, "parquet"),
+            "1678307941000_1678308001000.parquet"
+        );
+
+        let callback = FileNaming::callback(|m| format!("{}.{}", m.seq, m.extension));
+        assert_eq!(callback.name(&buffer, 7, 1, "parquet"), "7.parquet");
+    }
+}
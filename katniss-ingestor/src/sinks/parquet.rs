@@ -0,0 +1,1387 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::ops::Range;
+use std::pin::Pin;
+use std::sync::{
+    atomic::{AtomicU64, AtomicUsize, Ordering},
+    Arc,
+};
+use std::task::{Context, Poll};
+
+use arrow_array::{Array, ArrayRef, StructArray, UInt32Array};
+use arrow_row::{RowConverter, SortField};
+use arrow_schema::{DataType, Fields, Schema, SchemaRef};
+use arrow_select::{concat::concat_batches, take::take};
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use futures::future::try_join_all;
+use object_store::{path::Path, ObjectStore};
+use parquet::{
+    arrow::{ArrowWriter, AsyncArrowWriter, ARROW_SCHEMA_META_KEY},
+    basic::{Compression, Encoding},
+    file::{
+        metadata::KeyValue,
+        properties::{EnabledStatistics, WriterProperties, WriterPropertiesBuilder},
+    },
+    format::SortingColumn,
+    schema::types::ColumnPath,
+};
+use tokio::io::AsyncWrite;
+
+use katniss_pb2arrow::exports::{
+    prost_reflect::{DescriptorPool, ExtensionDescriptor, Kind, MessageDescriptor},
+    RecordBatch,
+};
+use katniss_pb2arrow::ArrowBatchProps;
+
+use crate::{
+    errors::KatinssIngestorError,
+    sinks::{
+        manifest::Manifests,
+        store::{child_path, store_from_uri, BufferEncoder},
+        BufferSink, FileNaming,
+    },
+    temporal_rotator::TemporalBuffer,
+    Result,
+};
+
+/// Bytes of encoded row groups held before they're uploaded
+const UPLOAD_BUFFER_SIZE: usize = 8 * 1024 * 1024;
+
+/// Footer key for the base64 encoded FileDescriptorSet, see ParquetEncoder::with_descriptor
+pub const DESCRIPTOR_SET_KEY: &str = "katniss.descriptor_set";
+/// Footer key for the fully qualified name of the message the rows came from
+pub const MESSAGE_NAME_KEY: &str = "katniss.message";
+
+/// Encodes each buffer as a single parquet file
+#[derive(Debug, Clone)]
+pub struct ParquetEncoder {
+    /// What with_writer_properties was given, the rest is applied on top of it
+    base_properties: WriterProperties,
+    properties: WriterProperties,
+    compression: Option<Compression>,
+    columns: ParquetColumns,
+    sort: Option<SortColumn>,
+    key_value_metadata: Vec<KeyValue>,
+}
+
+/// Column the rows are sorted by and the parquet leaf columns it's stored in
+#[derive(Debug, Clone)]
+struct SortColumn {
+    path: Vec<String>,
+    leaves: Range<usize>,
+}
+
+impl Default for ParquetEncoder {
+    fn default() -> Self {
+        Self {
+            base_properties: WriterProperties::builder().build(),
+            properties: WriterProperties::builder().build(),
+            compression: None,
+            columns: ParquetColumns::default(),
+            sort: None,
+            key_value_metadata: Vec::new(),
+        }
+    }
+}
+
+impl ParquetEncoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Compression, dictionary encoding, statistics, row group size etc. with_compression,
+    /// with_columns and with_sort_column only override their own settings on top of these,
+    /// in whatever order they're called
+    pub fn with_writer_properties(mut self, properties: WriterProperties) -> Self {
+        self.base_properties = properties;
+        self.rebuild_properties()
+    }
+
+    /// Codec for every column, i.e. Compression::ZSTD(ZstdLevel::try_new(3)?),
+    /// the writer properties' compression (uncompressed by default) otherwise
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.compression = Some(compression);
+        self.rebuild_properties()
+    }
+
+    /// Writer properties plus the column settings
+    pub fn with_columns(mut self, columns: &ParquetColumns) -> Self {
+        self.columns = columns.clone();
+        self.rebuild_properties()
+    }
+
+    /// Sorts each file's rows by a column, i.e. "timestamp" or "header.stamp", and records it as
+    /// the row groups' sorting_columns so readers can prune and merge on it.
+    /// Message columns sort by their fields in order, i.e. seconds then nanos
+    pub fn with_sort_column(mut self, schema: &Schema, column: &str) -> Result<Self> {
+        let path: Vec<String> = column.split('.').map(str::to_owned).collect();
+        let (data_type, leaves) = leaf_columns(schema.fields(), &path, 0)
+            .ok_or_else(|| KatinssIngestorError::UnknownField(column.to_owned()))?;
+        if !RowConverter::supports_fields(&[SortField::new(data_type.clone())]) {
+            return Err(KatinssIngestorError::UnsortableColumn(column.to_owned()));
+        }
+        self.sort = Some(SortColumn { path, leaves });
+        Ok(self.rebuild_properties())
+    }
+
+    fn rebuild_properties(mut self) -> Self {
+        let sorting_columns = self.sort.as_ref().map(|sort| {
+            sort.leaves
+                .clone()
+                .map(|leaf| SortingColumn {
+                    column_idx: leaf as i32,
+                    descending: false,
+                    nulls_first: true,
+                })
+                .collect()
+        });
+        let mut builder = self.base_properties.clone().into_builder();
+        if let Some(compression) = self.compression {
+            builder = builder.set_compression(compression);
+        }
+        if sorting_columns.is_some() {
+            builder = builder.set_sorting_columns(sorting_columns);
+        }
+        self.properties = self.columns.apply(builder).build();
+        self
+    }
+
+    pub fn writer_properties(&self) -> &WriterProperties {
+        &self.properties
+    }
+
+    /// Put the message's descriptor set and name in every file's footer, so readers can
+    /// get the proto schema back from the file alone with descriptor_from_metadata
+    pub fn with_descriptor(mut self, descriptor: &MessageDescriptor) -> Self {
+        self.key_value_metadata.extend(
+            descriptor_metadata(descriptor)
+                .into_iter()
+                .map(|(key, value)| KeyValue::new(key, value)),
+        );
+        self
+    }
+
+    /// Carries over another file's footer metadata, the arrow schema is left to ArrowWriter
+    pub(crate) fn with_key_value_metadata<'a, I>(mut self, key_value_metadata: I) -> Self
+    where
+        I: IntoIterator<Item = &'a KeyValue>,
+    {
+        for kv in key_value_metadata {
+            let known = self.key_value_metadata.iter().any(|k| k.key == kv.key);
+            if kv.key != ARROW_SCHEMA_META_KEY && !known {
+                self.key_value_metadata.push(kv.clone());
+            }
+        }
+        self
+    }
+
+    /// The batches sorted by the sort column, sliced back into batches of the same lengths.
+    /// None without a sort column
+    pub(crate) fn sort_batches(&self, batches: &[RecordBatch]) -> Result<Option<Vec<RecordBatch>>> {
+        let (Some(sort), Some(first)) = (&self.sort, batches.first()) else {
+            return Ok(None);
+        };
+        let batch = concat_batches(&first.schema(), batches)?;
+        let column = column_at(&batch, &sort.path)
+            .ok_or_else(|| KatinssIngestorError::UnknownField(sort.path.join(".")))?;
+
+        let mut converter = RowConverter::new(vec![SortField::new(column.data_type().clone())])?;
+        let rows = converter.convert_columns(&[column])?;
+        // stable, so rows with the same key stay in arrival order
+        let mut indices: Vec<u32> = (0..batch.num_rows() as u32).collect();
+        indices.sort_by(|&a, &b| rows.row(a as usize).cmp(&rows.row(b as usize)));
+        let indices = UInt32Array::from(indices);
+
+        let columns = batch
+            .columns()
+            .iter()
+            .map(|c| take(c.as_ref(), &indices, None))
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        let sorted = RecordBatch::try_new(batch.schema(), columns)?;
+
+        let mut offset = 0;
+        Ok(Some(
+            batches
+                .iter()
+                .map(|b| {
+                    let slice = sorted.slice(offset, b.num_rows());
+                    offset += b.num_rows();
+                    slice
+                })
+                .collect(),
+        ))
+    }
+
+    /// Writes the batches as they are, see BufferEncoder::encode
+    fn write_file(&self, schema: &Schema, batches: &[RecordBatch]) -> Result<Vec<u8>> {
+        let mut files = self.write_parts(schema, batches, None)?;
+        Ok(files.pop().expect("always writes a file").0)
+    }
+
+    /// Writes the batches as they are into files of roughly the target size, like
+    /// ParquetStoreSink::with_target_file_size, and one file without a target.
+    /// Returns each file's bytes and rows, no batches still gives an empty file
+    fn write_parts(
+        &self,
+        schema: &Schema,
+        batches: &[RecordBatch],
+        target: Option<usize>,
+    ) -> Result<Vec<(Vec<u8>, usize)>> {
+        let schema = Arc::new(schema.clone());
+        let mut batches = batches.iter().peekable();
+        let mut files = Vec::new();
+        loop {
+            let mut bytes = Vec::new();
+            let mut writer =
+                ArrowWriter::try_new(&mut bytes, schema.clone(), Some(self.properties.clone()))?;
+            for kv in &self.key_value_metadata {
+                writer.append_key_value_metadata(kv.clone());
+            }
+            let mut rows = 0;
+            for batch in batches.by_ref() {
+                writer.write(batch)?;
+                rows += batch.num_rows();
+                // only row groups already flushed have a size
+                let written = writer
+                    .flushed_row_groups()
+                    .iter()
+                    .map(|rg| rg.compressed_size() as usize)
+                    .sum::<usize>();
+                if target.is_some_and(|target| written >= target) {
+                    break;
+                }
+            }
+            writer.close()?;
+            files.push((bytes, rows));
+            if batches.peek().is_none() {
+                return Ok(files);
+            }
+        }
+    }
+}
+
+/// Number of parquet leaf columns a column of this type is stored in
+fn leaf_count(data_type: &DataType) -> usize {
+    match data_type {
+        DataType::Struct(fields) => fields.iter().map(|f| leaf_count(f.data_type())).sum(),
+        DataType::List(item)
+        | DataType::LargeList(item)
+        | DataType::FixedSizeList(item, _)
+        | DataType::Map(item, _) => leaf_count(item.data_type()),
+        _ => 1,
+    }
+}
+
+/// Type of the column at the path and the parquet leaf columns it's stored in
+fn leaf_columns<'a>(
+    fields: &'a Fields,
+    path: &[String],
+    mut offset: usize,
+) -> Option<(&'a DataType, Range<usize>)> {
+    let (name, rest) = path.split_first()?;
+    for field in fields.iter() {
+        if field.name() != name {
+            offset += leaf_count(field.data_type());
+            continue;
+        }
+        return match field.data_type() {
+            data_type if rest.is_empty() => {
+                Some((data_type, offset..offset + leaf_count(data_type)))
+            }
+            DataType::Struct(children) => leaf_columns(children, rest, offset),
+            _ => None,
+        };
+    }
+    None
+}
+
+pub(crate) fn column_at(batch: &RecordBatch, path: &[String]) -> Option<ArrayRef> {
+    let (first, rest) = path.split_first()?;
+    let mut column = batch.column_by_name(first)?.clone();
+    for name in rest {
+        let parent = column.as_any().downcast_ref::<StructArray>()?;
+        column = parent.column_by_name(name)?.clone();
+    }
+    Some(column)
+}
+
+/// The message descriptor embedded by ParquetEncoder::with_descriptor, None for files without one
+pub fn descriptor_from_metadata(
+    key_value_metadata: Option<&Vec<KeyValue>>,
+) -> Result<Option<MessageDescriptor>> {
+    let value = |key: &str| {
+        key_value_metadata?
+            .iter()
+            .find(|kv| kv.key == key)
+            .and_then(|kv| kv.value.as_deref())
+    };
+    let (Some(descriptor_set), Some(message)) =
+        (value(DESCRIPTOR_SET_KEY), value(MESSAGE_NAME_KEY))
+    else {
+        return Ok(None);
+    };
+    decode_descriptor(descriptor_set, message).map(Some)
+}
+
+/// The message descriptor in an arrow schema's metadata, i.e. a lance dataset's or the schema
+/// of a parquet file read with its footer, None if it doesn't have one
+pub fn descriptor_from_schema(schema: &Schema) -> Result<Option<MessageDescriptor>> {
+    let metadata = schema.metadata();
+    let (Some(descriptor_set), Some(message)) = (
+        metadata.get(DESCRIPTOR_SET_KEY),
+        metadata.get(MESSAGE_NAME_KEY),
+    ) else {
+        return Ok(None);
+    };
+    decode_descriptor(descriptor_set, message).map(Some)
+}
+
+/// The DESCRIPTOR_SET_KEY and MESSAGE_NAME_KEY entries for a message
+pub(crate) fn descriptor_metadata(descriptor: &MessageDescriptor) -> [(String, String); 2] {
+    let descriptor_set = descriptor.parent_pool().encode_to_vec();
+    [
+        (DESCRIPTOR_SET_KEY.to_owned(), BASE64.encode(descriptor_set)),
+        (
+            MESSAGE_NAME_KEY.to_owned(),
+            descriptor.full_name().to_owned(),
+        ),
+    ]
+}
+
+pub(crate) fn decode_descriptor(descriptor_set: &str, message: &str) -> Result<MessageDescriptor> {
+    let bytes = BASE64
+        .decode(descriptor_set)
+        .map_err(|err| KatinssIngestorError::CorruptDescriptorMetadata(err.to_string()))?;
+    let pool = DescriptorPool::decode(bytes.as_slice())?;
+    let descriptor = pool
+        .get_message_by_name(message)
+        .ok_or_else(|| KatinssIngestorError::UnknownMessage(message.to_owned()))?;
+    Ok(descriptor)
+}
+
+/// Field option naming a parquet encoding for the field's column, see ParquetColumns::from_options
+pub const ENCODING_OPTION: &str = "katniss.parquet_encoding";
+
+/// Bloom filters, statistics levels and encodings for individual columns, on top of the writer's
+/// defaults.
+/// Columns are dot separated parquet column paths, i.e. "header.device_id"
+#[derive(Debug, Clone, Default)]
+pub struct ParquetColumns {
+    columns: BTreeMap<String, ColumnSettings>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct ColumnSettings {
+    bloom_filter: bool,
+    bloom_filter_fpp: Option<f64>,
+    bloom_filter_ndv: Option<u64>,
+    statistics: Option<EnabledStatistics>,
+    encoding: Option<Encoding>,
+}
+
+impl ParquetColumns {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Encodings declared on the message's fields with the katniss.parquet_encoding option
+    /// (protos/katniss/options.proto), i.e.
+    /// `uint64 count = 1 [(katniss.parquet_encoding) = "DELTA_BINARY_PACKED"];`
+    pub fn from_options(descriptor: &MessageDescriptor) -> Result<Self> {
+        let mut columns = Self::default();
+        if let Some(option) = descriptor
+            .parent_pool()
+            .get_extension_by_name(ENCODING_OPTION)
+        {
+            columns.add_option_encodings(descriptor, &option, "")?;
+        }
+        Ok(columns)
+    }
+
+    fn add_option_encodings(
+        &mut self,
+        descriptor: &MessageDescriptor,
+        option: &ExtensionDescriptor,
+        prefix: &str,
+    ) -> Result<()> {
+        for field in descriptor.fields() {
+            let mut path = format!("{prefix}{}", field.name());
+            match field.kind() {
+                _ if field.is_map() => continue,
+                Kind::Message(child) if !field.is_list() && child.fields().len() > 0 => {
+                    self.add_option_encodings(&child, option, &format!("{path}."))?;
+                    continue;
+                }
+                _ if field.is_list() => path.push_str(".list.item"),
+                _ => {}
+            }
+
+            let options = field.options();
+            if options.has_extension(option) {
+                let value = options.get_extension(option);
+                let name = value.as_str().unwrap_or_default();
+                self.column(&path).encoding = Some(parse_encoding(name)?);
+            }
+        }
+        Ok(())
+    }
+
+    /// Write a bloom filter for the column, worth it for ids that get looked up by value
+    pub fn with_bloom_filter(mut self, column: &str) -> Self {
+        self.column(column).bloom_filter = true;
+        self
+    }
+
+    /// Bloom filter sized for ndv distinct values per row group at a false positive rate of fpp
+    pub fn with_bloom_filter_sized(mut self, column: &str, fpp: f64, ndv: u64) -> Self {
+        let settings = self.column(column);
+        settings.bloom_filter = true;
+        settings.bloom_filter_fpp = Some(fpp);
+        settings.bloom_filter_ndv = Some(ndv);
+        self
+    }
+
+    /// None, Chunk (per row group) or Page level min/max statistics for the column
+    pub fn with_statistics(mut self, column: &str, statistics: EnabledStatistics) -> Self {
+        self.column(column).statistics = Some(statistics);
+        self
+    }
+
+    /// i.e. DELTA_BINARY_PACKED for counters, BYTE_STREAM_SPLIT for floats.
+    /// Other encodings turn off dictionary encoding for the column, RLE_DICTIONARY turns it on
+    pub fn with_encoding(mut self, column: &str, encoding: Encoding) -> Self {
+        self.column(column).encoding = Some(encoding);
+        self
+    }
+
+    /// Add the column settings to the rest of the writer properties
+    pub fn apply(&self, mut builder: WriterPropertiesBuilder) -> WriterPropertiesBuilder {
+        for (column, settings) in &self.columns {
+            let path = || ColumnPath::new(column.split('.').map(str::to_owned).collect());
+            if settings.bloom_filter {
+                builder = builder.set_column_bloom_filter_enabled(path(), true);
+            }
+            if let Some(fpp) = settings.bloom_filter_fpp {
+                builder = builder.set_column_bloom_filter_fpp(path(), fpp);
+            }
+            if let Some(ndv) = settings.bloom_filter_ndv {
+                builder = builder.set_column_bloom_filter_ndv(path(), ndv);
+            }
+            if let Some(statistics) = settings.statistics {
+                builder = builder.set_column_statistics_enabled(path(), statistics);
+            }
+            match settings.encoding {
+                // dictionaries can't be set as an encoding, only turned on
+                Some(Encoding::RLE_DICTIONARY | Encoding::PLAIN_DICTIONARY) => {
+                    builder = builder.set_column_dictionary_enabled(path(), true);
+                }
+                Some(encoding) => {
+                    builder = builder
+                        .set_column_dictionary_enabled(path(), false)
+                        .set_column_encoding(path(), encoding);
+                }
+                None => {}
+            }
+        }
+        builder
+    }
+
+    fn column(&mut self, column: &str) -> &mut ColumnSettings {
+        self.columns.entry(column.to_owned()).or_default()
+    }
+}
+
+/// Parquet encoding by its name in the spec, i.e. DELTA_BINARY_PACKED
+pub fn parse_encoding(name: &str) -> Result<Encoding> {
+    Ok(match name.to_ascii_uppercase().as_str() {
+        "PLAIN" => Encoding::PLAIN,
+        "RLE" => Encoding::RLE,
+        "DELTA_BINARY_PACKED" => Encoding::DELTA_BINARY_PACKED,
+        "DELTA_LENGTH_BYTE_ARRAY" => Encoding::DELTA_LENGTH_BYTE_ARRAY,
+        "DELTA_BYTE_ARRAY" => Encoding::DELTA_BYTE_ARRAY,
+        "BYTE_STREAM_SPLIT" => Encoding::BYTE_STREAM_SPLIT,
+        "RLE_DICTIONARY" => Encoding::RLE_DICTIONARY,
+        "PLAIN_DICTIONARY" => Encoding::PLAIN_DICTIONARY,
+        _ => return Err(KatinssIngestorError::UnknownEncoding(name.to_owned())),
+    })
+}
+
+impl BufferEncoder for ParquetEncoder {
+    fn extension(&self) -> &str {
+        "parquet"
+    }
+
+    fn encode(&self, schema: &Schema, batches: &[RecordBatch]) -> Result<Vec<u8>> {
+        match self.sort_batches(batches)? {
+            Some(sorted) => self.write_file(schema, &sorted),
+            None => self.write_file(schema, batches),
+        }
+    }
+
+    /// Points the footer descriptor (if there is one) at the new message and finds the sort
+    /// column's leaves again, fails if the sort column is gone
+    fn reload_schema(&mut self, props: &ArrowBatchProps) -> Result<()> {
+        if self
+            .key_value_metadata
+            .iter()
+            .any(|kv| kv.key == DESCRIPTOR_SET_KEY)
+        {
+            for (key, value) in descriptor_metadata(&props.descriptor) {
+                if let Some(kv) = self.key_value_metadata.iter_mut().find(|kv| kv.key == key) {
+                    kv.value = Some(value);
+                }
+            }
+        }
+        if let Some(sort) = &self.sort {
+            let column = sort.path.join(".");
+            *self = self.clone().with_sort_column(&props.schema, &column)?;
+        }
+        Ok(())
+    }
+}
+
+/// Like StoreSink with a ParquetEncoder, but streams row groups to the store as a multipart
+/// upload as they're encoded, so big buffers never sit in memory as a whole file
+/// and don't need a local filesystem
+pub struct ParquetStoreSink {
+    store: Box<dyn ObjectStore>,
+    prefix: Path,
+    schema: SchemaRef,
+    encoder: ParquetEncoder,
+    target_file_size: Option<usize>,
+    parallel_files: usize,
+    naming: Option<FileNaming>,
+    staging: Option<Path>,
+    files_written: AtomicU64,
+    manifests: Option<Manifests>,
+}
+
+impl ParquetStoreSink {
+    pub fn new(
+        store: Box<dyn ObjectStore>,
+        prefix: Path,
+        schema: SchemaRef,
+        encoder: ParquetEncoder,
+    ) -> Self {
+        Self {
+            store,
+            prefix,
+            schema,
+            encoder,
+            target_file_size: None,
+            parallel_files: 1,
+            naming: None,
+            staging: None,
+            files_written: AtomicU64::new(0),
+            manifests: None,
+        }
+    }
+
+    /// See StoreSink::from_uri
+    pub fn from_uri(uri: &str, schema: SchemaRef, encoder: ParquetEncoder) -> Result<Self> {
+        let (store, prefix) = store_from_uri(uri)?;
+        Ok(Self::new(store, prefix, schema, encoder))
+    }
+
+    /// Split buffers into files of roughly this many compressed bytes,
+    /// named {start}_{end}_{part}.parquet unless there's a FileNaming.
+    /// Sizes are only known once row groups are encoded, so files overshoot by up to a row group,
+    /// lower the writer properties' max_row_group_size for a tighter fit.
+    pub fn with_target_file_size(mut self, bytes: usize) -> Self {
+        self.target_file_size = Some(bytes.max(1));
+        self
+    }
+
+    /// Encode each buffer as this many runs of batches at once on the blocking thread pool,
+    /// named {start}_{end}_{part}.parquet unless there's a FileNaming. For buffers too big to
+    /// encode on one thread before the next rotation. Runs are split along batch boundaries,
+    /// and further into files of the target size if there is one. Files are held in memory
+    /// until uploaded instead of streamed
+    pub fn with_parallel_files(mut self, files: usize) -> Self {
+        self.parallel_files = files.max(1);
+        self
+    }
+
+    /// Defaults to {start}_{end}.{ext}, see FileNaming::template. Include {part} or {seq}
+    /// when there's a target file size so the parts of a buffer don't overwrite each other.
+    pub fn with_file_naming(mut self, naming: FileNaming) -> Self {
+        self.naming = Some(naming);
+        self
+    }
+
+    /// Upload files under staging and only move them into the prefix once every part of the
+    /// buffer is closed, so anything watching the prefix never sees a partial file or buffer.
+    /// Staging should be on the same store and outside of the prefix, i.e. data/_staging
+    /// Local files, gcs and azure move each file atomically and never overwrite one that's
+    /// already in the prefix. S3 can't, there it's a server side copy (the file still appears
+    /// whole) then a delete, so a crash in between leaves the staged copy behind
+    pub fn with_staging(mut self, staging: Path) -> Self {
+        self.staging = Some(staging);
+        self
+    }
+
+    /// See StoreSink::with_manifests
+    pub fn with_manifests(mut self) -> Self {
+        self.manifests = Some(Manifests::default());
+        self
+    }
+
+    pub fn store(&self) -> &dyn ObjectStore {
+        self.store.as_ref()
+    }
+
+    /// Upload the buffer to {prefix}/{name}, or several parts if there's a target file size.
+    /// An upload is aborted if encoding fails part way.
+    /// Returns the locations of the new files
+    pub async fn write(&self, buffer: TemporalBuffer) -> Result<Vec<Path>> {
+        let files = self.write_files(&buffer).await?;
+        Ok(files.into_iter().map(|(location, _)| location).collect())
+    }
+
+    /// Locations and row counts of the new files
+    async fn write_files(&self, buffer: &TemporalBuffer) -> Result<Vec<(Path, usize)>> {
+        // sorted once for the whole buffer, rather than per file
+        let sorted;
+        let buffer = match self.encoder.sort_batches(&buffer.batches)? {
+            Some(batches) => {
+                sorted = TemporalBuffer {
+                    begin_at: buffer.begin_at,
+                    end_at: buffer.end_at,
+                    batches,
+                };
+                &sorted
+            }
+            None => buffer,
+        };
+
+        let Some(staging) = &self.staging else {
+            return self.upload_all(&self.prefix, buffer).await;
+        };
+
+        let staged = self.upload_all(staging, buffer).await?;
+        let mut files = Vec::with_capacity(staged.len());
+        for (from, rows) in staged {
+            let relative = from.prefix_match(staging).into_iter().flatten();
+            let to = relative.fold(self.prefix.clone(), |path, part| path.child(part));
+            move_into_place(self.store.as_ref(), &from, &to).await?;
+            files.push((to, rows));
+        }
+        Ok(files)
+    }
+
+    fn file_naming(&self) -> FileNaming {
+        self.naming.clone().unwrap_or_else(|| {
+            if self.target_file_size.is_some() || self.parallel_files > 1 {
+                FileNaming::template("{start}_{end}_{part}.{ext}")
+            } else {
+                FileNaming::default()
+            }
+        })
+    }
+
+    /// Every part of the buffer under prefix, parts already uploaded are deleted if one fails
+    async fn upload_all(
+        &self,
+        prefix: &Path,
+        buffer: &TemporalBuffer,
+    ) -> Result<Vec<(Path, usize)>> {
+        let naming = self.file_naming();
+        if self.parallel_files > 1 {
+            return self.upload_parallel(prefix, buffer, &naming).await;
+        }
+
+        let mut files = Vec::new();
+        let mut batches = buffer.batches.iter().peekable();
+
+        loop {
+            let seq = self.files_written.fetch_add(1, Ordering::Relaxed);
+            let filename = naming.name(buffer, seq, files.len(), self.encoder.extension());
+            let location = child_path(prefix, &filename);
+            let (upload_id, upload) = self.store.put_multipart(&location).await?;
+
+            let rows = match self.upload(upload, &mut batches).await {
+                Ok(rows) => rows,
+                Err(err) => {
+                    self.store.abort_multipart(&location, &upload_id).await?;
+                    if self.staging.is_some() {
+                        for (uploaded, _) in &files {
+                            self.store.delete(uploaded).await?;
+                        }
+                    }
+                    return Err(err);
+                }
+            };
+            files.push((location, rows));
+
+            if batches.peek().is_none() {
+                return Ok(files);
+            }
+        }
+    }
+
+    /// Encodes contiguous runs of batches on the blocking pool, then uploads them in order
+    async fn upload_parallel(
+        &self,
+        prefix: &Path,
+        buffer: &TemporalBuffer,
+        naming: &FileNaming,
+    ) -> Result<Vec<(Path, usize)>> {
+        let encoding = split_batches(&buffer.batches, self.parallel_files)
+            .into_iter()
+            .map(|batches| {
+                let encoder = self.encoder.clone();
+                let schema = self.schema.clone();
+                let target = self.target_file_size;
+                tokio::task::spawn_blocking(move || encoder.write_parts(&schema, &batches, target))
+            });
+        let encoded = try_join_all(encoding)
+            .await?
+            .into_iter()
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
+            .flatten();
+
+        let mut files = Vec::new();
+        for (bytes, rows) in encoded {
+            let seq = self.files_written.fetch_add(1, Ordering::Relaxed);
+            let filename = naming.name(buffer, seq, files.len(), self.encoder.extension());
+            let location = child_path(prefix, &filename);
+
+            if let Err(err) = self.store.put(&location, bytes.into()).await {
+                if self.staging.is_some() {
+                    for (uploaded, _) in &files {
+                        self.store.delete(uploaded).await?;
+                    }
+                }
+                return Err(err.into());
+            }
+            files.push((location, rows));
+        }
+        Ok(files)
+    }
+
+    /// Writes batches until they run out or the file reaches its target size,
+    /// returns how many rows were written
+    async fn upload<'a>(
+        &self,
+        upload: Box<dyn AsyncWrite + Send + Unpin>,
+        batches: &mut impl Iterator<Item = &'a RecordBatch>,
+    ) -> Result<usize> {
+        let written = Arc::new(AtomicUsize::new(0));
+        let upload = CountingWrite {
+            inner: upload,
+            written: written.clone(),
+        };
+        let buffer_size = self
+            .target_file_size
+            .map_or(UPLOAD_BUFFER_SIZE, |t| UPLOAD_BUFFER_SIZE.min(t / 4));
+
+        let mut writer = AsyncArrowWriter::try_new(
+            upload,
+            self.schema.clone(),
+            buffer_size,
+            Some(self.encoder.properties.clone()),
+        )?;
+        for kv in &self.encoder.key_value_metadata {
+            writer.append_key_value_metadata(kv.clone());
+        }
+        let mut rows = 0;
+        for batch in batches {
+            writer.write(batch).await?;
+            rows += batch.num_rows();
+            if self
+                .target_file_size
+                .is_some_and(|target| written.load(Ordering::Relaxed) >= target)
+            {
+                break;
+            }
+        }
+        writer.close().await?;
+        Ok(rows)
+    }
+}
+
+/// rename_if_not_exists where the store has it, a plain rename (copy + delete) otherwise
+async fn move_into_place(store: &dyn ObjectStore, from: &Path, to: &Path) -> Result<()> {
+    match store.rename_if_not_exists(from, to).await {
+        Err(object_store::Error::NotImplemented | object_store::Error::NotSupported { .. }) => {
+            store.rename(from, to).await?
+        }
+        result => result?,
+    }
+    Ok(())
+}
+
+/// Up to n contiguous runs of batches with about as many rows each, never splitting a batch
+fn split_batches(batches: &[RecordBatch], n: usize) -> Vec<Vec<RecordBatch>> {
+    let total = batches
+        .iter()
+        .map(RecordBatch::num_rows)
+        .sum::<usize>()
+        .max(1);
+    let mut runs: Vec<Vec<RecordBatch>> = Vec::new();
+    let mut rows_before = 0;
+    let mut last_run = None;
+
+    for batch in batches {
+        let run = rows_before * n / total;
+        if last_run != Some(run) {
+            runs.push(Vec::new());
+            last_run = Some(run);
+        }
+        runs.last_mut().unwrap().push(batch.clone());
+        rows_before += batch.num_rows();
+    }
+    runs
+}
+
+/// Keeps track of how much has actually been handed to the upload
+struct CountingWrite<W> {
+    inner: W,
+    written: Arc<AtomicUsize>,
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for CountingWrite<W> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let poll = Pin::new(&mut self.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(n)) = &poll {
+            self.written.fetch_add(*n, Ordering::Relaxed);
+        }
+        poll
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[async_trait]
+impl BufferSink for ParquetStoreSink {
+    async fn write_buffer(&mut self, buffer: TemporalBuffer) -> Result<()> {
+        if let Some(manifests) = &self.manifests {
+            // the parts of a buffer all go into the partition of the first
+            let seq = self.files_written.load(Ordering::Relaxed);
+            let filename = self
+                .file_naming()
+                .name(&buffer, seq, 0, self.encoder.extension());
+            let location = child_path(&self.prefix, &filename);
+            manifests.check_open(self.store.as_ref(), &location).await?;
+        }
+        let files = self.write_files(&buffer).await?;
+
+        if let Some(manifests) = &mut self.manifests {
+            for (location, rows) in &files {
+                manifests
+                    .record(
+                        self.store.as_ref(),
+                        location,
+                        *rows,
+                        buffer.begin_at,
+                        buffer.end_at,
+                    )
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn close(&mut self) -> Result<()> {
+        match &mut self.manifests {
+            Some(manifests) => manifests.finish(self.store.as_ref()).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn reload_schema(&mut self, props: &ArrowBatchProps) -> Result<()> {
+        self.encoder.reload_schema(props)?;
+        self.schema = props.schema.clone();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use object_store::{memory::InMemory, path::Path};
+    use parquet::{arrow::arrow_reader::ParquetRecordBatchReaderBuilder, basic::ZstdLevel};
+    use prost::bytes::Bytes;
+
+    use katniss_pb2arrow::ArrowBatchProps;
+    use katniss_test::{
+        descriptor_pool,
+        protos::spacecorp::{Packet, Timestamp},
+        test_util::ProtoBatch,
+    };
+
+    use super::*;
+    use crate::{sinks::StoreSink, temporal_rotator::TemporalBuffer, ParquetMessageReader};
+
+    #[tokio::test]
+    async fn it_writes_parquet_with_the_given_properties() -> anyhow::Result<()> {
+        let batch =
+            ProtoBatch::SpaceCorp(&[Packet::default(), Packet::default(), Packet::default()])
+                .arrow_batch()?;
+        let properties = WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .set_max_row_group_size(2)
+            .build();
+
+        let sink = StoreSink::new(
+            Box::new(InMemory::new()),
+            Path::from("packets"),
+            batch.schema(),
+            ParquetEncoder::new().with_writer_properties(properties),
+        );
+        let location = sink
+            .write(TemporalBuffer {
+                begin_at: Utc.timestamp_millis_opt(1000).unwrap(),
+                end_at: Utc.timestamp_millis_opt(2000).unwrap(),
+                batches: vec![batch],
+            })
+            .await?;
+        assert_eq!(location, Path::from("packets/1000_2000.parquet"));
+
+        let bytes = sink.store().get(&location).await?.bytes().await?;
+        let reader = ParquetRecordBatchReaderBuilder::try_new(bytes)?;
+        let metadata = reader.metadata().clone();
+        assert_eq!(metadata.num_row_groups(), 2);
+        assert_eq!(
+            metadata.row_group(0).column(0).compression(),
+            Compression::SNAPPY
+        );
+
+        let rows: usize = reader.build()?.map(|b| b.unwrap().num_rows()).sum();
+        assert_eq!(rows, 3);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_streams_parquet_to_a_store() -> anyhow::Result<()> {
+        let packets = vec![Packet::default(); 5];
+        let batch = ProtoBatch::SpaceCorp(&packets).arrow_batch()?;
+        let sink = ParquetStoreSink::new(
+            Box::new(InMemory::new()),
+            Path::from("packets"),
+            batch.schema(),
+            ParquetEncoder::new(),
+        );
+
+        let buffer = TemporalBuffer {
+            begin_at: Utc.timestamp_millis_opt(1000).unwrap(),
+            end_at: Utc.timestamp_millis_opt(2000).unwrap(),
+            batches: vec![batch; 4],
+        };
+
+        let locations = sink.write(buffer.clone()).await?;
+        assert_eq!(locations, vec![Path::from("packets/1000_2000.parquet")]);
+        assert_eq!(rows_at(&sink, &locations).await?, 20);
+
+        // every batch is its own row group, so every file can end after one
+        let properties = WriterProperties::builder()
+            .set_max_row_group_size(5)
+            .build();
+        let sink = ParquetStoreSink::new(
+            Box::new(InMemory::new()),
+            Path::from("packets"),
+            buffer.batches[0].schema(),
+            ParquetEncoder::new().with_writer_properties(properties),
+        )
+        .with_target_file_size(1);
+
+        let locations = sink.write(buffer).await?;
+        assert_eq!(locations.len(), 4);
+        assert_eq!(locations[3], Path::from("packets/1000_2000_3.parquet"));
+        assert_eq!(rows_at(&sink, &locations).await?, 20);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_writes_bloom_filters_and_statistics_per_column() -> anyhow::Result<()> {
+        let packets: Vec<_> = (0..10)
+            .map(|sender_uid| Packet {
+                sender_uid,
+                ..Default::default()
+            })
+            .collect();
+        let batch = ProtoBatch::SpaceCorp(&packets).arrow_batch()?;
+        let columns = ParquetColumns::new()
+            .with_bloom_filter("sender_uid")
+            .with_statistics("timestamp.seconds", EnabledStatistics::None);
+
+        let sink = StoreSink::new(
+            Box::new(InMemory::new()),
+            Path::from("packets"),
+            batch.schema(),
+            ParquetEncoder::new().with_columns(&columns),
+        );
+        let location = sink
+            .write(TemporalBuffer {
+                begin_at: Utc.timestamp_millis_opt(1000).unwrap(),
+                end_at: Utc.timestamp_millis_opt(2000).unwrap(),
+                batches: vec![batch],
+            })
+            .await?;
+
+        let bytes = sink.store().get(&location).await?.bytes().await?;
+        let metadata = ParquetRecordBatchReaderBuilder::try_new(bytes)?
+            .metadata()
+            .clone();
+        for column in metadata.row_group(0).columns() {
+            let path = column.column_path().string();
+            assert_eq!(
+                column.bloom_filter_offset().is_some(),
+                path == "sender_uid",
+                "{path}"
+            );
+            assert_eq!(
+                column.statistics().is_some(),
+                path != "timestamp.seconds",
+                "{path}"
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn compression_applies_on_top_of_column_settings() -> anyhow::Result<()> {
+        let packets = vec![Packet::default(); 5];
+        let batch = ProtoBatch::SpaceCorp(&packets).arrow_batch()?;
+        let encoder = ParquetEncoder::new()
+            .with_compression(Compression::ZSTD(ZstdLevel::try_new(3)?))
+            .with_columns(&ParquetColumns::new().with_bloom_filter("sender_uid"));
+
+        let bytes = encoder.encode(&batch.schema(), &[batch])?;
+        let reader = ParquetRecordBatchReaderBuilder::try_new(Bytes::from(bytes))?;
+        for column in reader.metadata().row_group(0).columns() {
+            assert_eq!(
+                column.compression(),
+                Compression::ZSTD(ZstdLevel::try_new(3)?)
+            );
+        }
+        let sender_uid = reader
+            .metadata()
+            .row_group(0)
+            .columns()
+            .iter()
+            .find(|c| c.column_path().string() == "sender_uid")
+            .unwrap();
+        assert!(sender_uid.bloom_filter_offset().is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn settings_apply_on_top_of_the_writer_properties() -> anyhow::Result<()> {
+        let packets = vec![Packet::default(); 5];
+        let batch = ProtoBatch::SpaceCorp(&packets).arrow_batch()?;
+        let properties = WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .set_max_row_group_size(2)
+            .set_created_by("katniss tests".into())
+            .build();
+        let encoder = ParquetEncoder::new()
+            .with_columns(&ParquetColumns::new().with_bloom_filter("sender_uid"))
+            .with_writer_properties(properties)
+            .with_sort_column(&batch.schema(), "sender_uid")?;
+
+        let bytes = encoder.encode(&batch.schema(), &[batch])?;
+        let reader = ParquetRecordBatchReaderBuilder::try_new(Bytes::from(bytes))?;
+        let metadata = reader.metadata();
+        assert_eq!(metadata.num_row_groups(), 3);
+        assert_eq!(metadata.file_metadata().created_by(), Some("katniss tests"));
+        let row_group = metadata.row_group(0);
+        assert!(row_group.sorting_columns().is_some());
+        for column in row_group.columns() {
+            assert_eq!(column.compression(), Compression::SNAPPY);
+            assert_eq!(
+                column.bloom_filter_offset().is_some(),
+                column.column_path().string() == "sender_uid"
+            );
+        }
+
+        let zstd = Compression::ZSTD(ZstdLevel::try_new(3)?);
+        let encoder = encoder.with_compression(zstd);
+        assert_eq!(encoder.writer_properties().max_row_group_size(), 2);
+        assert_eq!(
+            encoder
+                .writer_properties()
+                .compression(&ColumnPath::from("sender_uid")),
+            zstd
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn encodings_come_from_field_options() -> anyhow::Result<()> {
+        let descriptor = descriptor_pool()?
+            .get_message_by_name("eto.pb2arrow.tests.encodings.Counters")
+            .unwrap();
+        let properties = ParquetColumns::from_options(&descriptor)?
+            .with_encoding("source.name", Encoding::RLE_DICTIONARY)
+            .apply(WriterProperties::builder().set_dictionary_enabled(false))
+            .build();
+        let path = |p: &str| ColumnPath::new(p.split('.').map(str::to_owned).collect());
+
+        for (column, encoding) in [
+            ("count", Encoding::DELTA_BINARY_PACKED),
+            ("readings.list.item", Encoding::BYTE_STREAM_SPLIT),
+            ("source.sequence", Encoding::DELTA_BINARY_PACKED),
+        ] {
+            assert_eq!(properties.encoding(&path(column)), Some(encoding));
+            assert!(!properties.dictionary_enabled(&path(column)));
+        }
+        assert_eq!(properties.encoding(&path("source.name")), None);
+        assert!(properties.dictionary_enabled(&path("source.name")));
+
+        assert!(matches!(
+            parse_encoding("LZW"),
+            Err(KatinssIngestorError::UnknownEncoding(_))
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_encodes_files_in_parallel() -> anyhow::Result<()> {
+        let packets = vec![Packet::default(); 5];
+        let batch = ProtoBatch::SpaceCorp(&packets).arrow_batch()?;
+        let sink = ParquetStoreSink::new(
+            Box::new(InMemory::new()),
+            Path::from("packets"),
+            batch.schema(),
+            ParquetEncoder::new(),
+        )
+        .with_parallel_files(3);
+
+        let locations = sink
+            .write(TemporalBuffer {
+                begin_at: Utc.timestamp_millis_opt(1000).unwrap(),
+                end_at: Utc.timestamp_millis_opt(2000).unwrap(),
+                batches: vec![batch; 4],
+            })
+            .await?;
+        assert_eq!(
+            locations,
+            (0..3)
+                .map(|part| Path::from(format!("packets/1000_2000_{part}.parquet")))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(rows_at(&sink, &locations).await?, 20);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_splits_parallel_files_by_target_size() -> anyhow::Result<()> {
+        let packets = vec![Packet::default(); 5];
+        let batch = ProtoBatch::SpaceCorp(&packets).arrow_batch()?;
+        let properties = WriterProperties::builder()
+            .set_max_row_group_size(5)
+            .build();
+        let sink = ParquetStoreSink::new(
+            Box::new(InMemory::new()),
+            Path::from("packets"),
+            batch.schema(),
+            ParquetEncoder::new().with_writer_properties(properties),
+        )
+        .with_parallel_files(2)
+        .with_target_file_size(1);
+
+        let locations = sink
+            .write(TemporalBuffer {
+                begin_at: Utc.timestamp_millis_opt(1000).unwrap(),
+                end_at: Utc.timestamp_millis_opt(2000).unwrap(),
+                batches: vec![batch; 4],
+            })
+            .await?;
+        assert_eq!(
+            locations,
+            (0..4)
+                .map(|part| Path::from(format!("packets/1000_2000_{part}.parquet")))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(rows_at(&sink, &locations).await?, 20);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_embeds_the_descriptor_in_the_footer() -> anyhow::Result<()> {
+        let props = ArrowBatchProps::try_new(
+            descriptor_pool()?,
+            "eto.pb2arrow.tests.spacecorp.Packet".to_owned(),
+        )?;
+        let batch = ProtoBatch::SpaceCorp(&[Packet::default()]).arrow_batch()?;
+        let sink = ParquetStoreSink::new(
+            Box::new(InMemory::new()),
+            Path::from("packets"),
+            batch.schema(),
+            ParquetEncoder::new().with_descriptor(&props.descriptor),
+        );
+
+        let locations = sink
+            .write(TemporalBuffer {
+                begin_at: Utc.timestamp_millis_opt(1000).unwrap(),
+                end_at: Utc.timestamp_millis_opt(2000).unwrap(),
+                batches: vec![batch],
+            })
+            .await?;
+
+        let bytes = sink.store().get(&locations[0]).await?.bytes().await?;
+        let reader = ParquetRecordBatchReaderBuilder::try_new(bytes)?;
+        let descriptor =
+            descriptor_from_metadata(reader.metadata().file_metadata().key_value_metadata())?;
+        assert_eq!(descriptor, Some(props.descriptor));
+
+        assert!(descriptor_from_metadata(None)?.is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn rows_are_sorted_by_the_sort_column() -> anyhow::Result<()> {
+        let props = ArrowBatchProps::try_new(
+            descriptor_pool()?,
+            "eto.pb2arrow.tests.spacecorp.Packet".to_owned(),
+        )?;
+        let packet = |seconds, nanos| Packet {
+            timestamp: Some(Timestamp { seconds, nanos }),
+            sender_uid: seconds as u64,
+            msg: None,
+        };
+        let late = vec![packet(3, 0), packet(1, 5)];
+        let early = vec![packet(1, 0), packet(2, 0)];
+        let batches = vec![
+            ProtoBatch::SpaceCorp(&late).arrow_batch()?,
+            ProtoBatch::SpaceCorp(&early).arrow_batch()?,
+        ];
+        let schema = batches[0].schema();
+
+        assert!(ParquetEncoder::new()
+            .with_sort_column(&schema, "timestamp.minutes")
+            .is_err());
+        let encoder = ParquetEncoder::new().with_sort_column(&schema, "timestamp")?;
+        let sink = ParquetStoreSink::new(
+            Box::new(InMemory::new()),
+            Path::from("packets"),
+            schema,
+            encoder,
+        );
+        let locations = sink
+            .write(TemporalBuffer {
+                begin_at: Utc.timestamp_millis_opt(1000).unwrap(),
+                end_at: Utc.timestamp_millis_opt(4000).unwrap(),
+                batches,
+            })
+            .await?;
+
+        let bytes = sink.store().get(&locations[0]).await?.bytes().await?;
+        let reader = ParquetRecordBatchReaderBuilder::try_new(bytes.clone())?;
+        let sorting_columns = reader.metadata().row_group(0).sorting_columns();
+        let leaves: Vec<_> = sorting_columns
+            .into_iter()
+            .flatten()
+            .map(|c| c.column_idx)
+            .collect();
+        assert_eq!(leaves, [0, 1]);
+
+        let read = ParquetMessageReader::try_new_with_descriptor(bytes, props.descriptor)?
+            .map(|msg| Ok(msg?.transcode_to::<Packet>()?))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        assert_eq!(
+            read,
+            [packet(1, 0), packet(1, 5), packet(2, 0), packet(3, 0)]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn staged_files_are_moved_into_place_once_closed() -> anyhow::Result<()> {
+        let packets = vec![Packet::default(); 5];
+        let batch = ProtoBatch::SpaceCorp(&packets).arrow_batch()?;
+        let properties = WriterProperties::builder()
+            .set_max_row_group_size(5)
+            .build();
+        let sink = ParquetStoreSink::new(
+            Box::new(InMemory::new()),
+            Path::from("data/packets"),
+            batch.schema(),
+            ParquetEncoder::new().with_writer_properties(properties),
+        )
+        .with_target_file_size(1)
+        .with_staging(Path::from("data/_staging"));
+
+        let locations = sink
+            .write(TemporalBuffer {
+                begin_at: Utc.timestamp_millis_opt(1000).unwrap(),
+                end_at: Utc.timestamp_millis_opt(2000).unwrap(),
+                batches: vec![batch; 2],
+            })
+            .await?;
+        assert_eq!(
+            locations,
+            vec![
+                Path::from("data/packets/1000_2000_0.parquet"),
+                Path::from("data/packets/1000_2000_1.parquet"),
+            ]
+        );
+        assert_eq!(rows_at(&sink, &locations).await?, 10);
+
+        let staged = sink
+            .store()
+            .list_with_delimiter(Some(&Path::from("data/_staging")))
+            .await?;
+        assert!(staged.objects.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn staged_files_dont_overwrite_ones_already_in_place() -> anyhow::Result<()> {
+        let batch = ProtoBatch::SpaceCorp(&[Packet::default()]).arrow_batch()?;
+        let sink = ParquetStoreSink::new(
+            Box::new(InMemory::new()),
+            Path::from("data/packets"),
+            batch.schema(),
+            ParquetEncoder::new(),
+        )
+        .with_staging(Path::from("data/_staging"));
+
+        let existing = Path::from("data/packets/1000_2000.parquet");
+        sink.store()
+            .put(&existing, Bytes::from("a crashed run"))
+            .await?;
+
+        let written = sink
+            .write(TemporalBuffer {
+                begin_at: Utc.timestamp_millis_opt(1000).unwrap(),
+                end_at: Utc.timestamp_millis_opt(2000).unwrap(),
+                batches: vec![batch],
+            })
+            .await;
+        assert!(matches!(
+            written,
+            Err(KatinssIngestorError::ObjectStoreError(
+                object_store::Error::AlreadyExists { .. }
+            ))
+        ));
+        let bytes = sink.store().get(&existing).await?.bytes().await?;
+        assert_eq!(bytes, Bytes::from("a crashed run"));
+        Ok(())
+    }
+
+    async fn rows_at(sink: &ParquetStoreSink, locations: &[Path]) -> anyhow::Result<usize> {
+        let mut rows = 0;
+        for location in locations {
+            let bytes = sink.store().get(location).await?.bytes().await?;
+            rows += ParquetRecordBatchReaderBuilder::try_new(bytes)?
+                .build()?
+                .map(|b| b.unwrap().num_rows())
+                .sum::<usize>();
+        }
+        Ok(rows)
+    }
+}
@@ -0,0 +1,287 @@
+use std::collections::BTreeMap;
+
+use arrow_array::{Array, BooleanArray, StringArray};
+use arrow_cast::cast;
+use arrow_schema::DataType;
+use arrow_select::filter::filter_record_batch;
+use async_trait::async_trait;
+
+use katniss_pb2arrow::{exports::RecordBatch, ArrowBatchProps};
+
+use crate::{
+    errors::KatinssIngestorError,
+    sinks::{parquet::column_at, BufferSink},
+    temporal_rotator::TemporalBuffer,
+    Result,
+};
+
+/// Directory value for rows where the partition column is null, same as hive's
+pub const NULL_PARTITION: &str = "__HIVE_DEFAULT_PARTITION__";
+
+/// Partition sinks kept open by default, see PartitionedSink::with_max_open_partitions
+const MAX_OPEN_PARTITIONS: usize = 256;
+
+/// Splits every buffer by the value of a column and hands each part to the sink for that
+/// value, made on first use from its hive style directory, i.e. "header.device_id=7".
+/// Combine with a FileNaming like "date={date}/hour={hour}/{start}_{end}.{ext}" to also
+/// partition by time
+pub struct PartitionedSink<S: BufferSink> {
+    column: String,
+    path: Vec<String>,
+    make_sink: Box<dyn FnMut(&str) -> Result<S> + Send>,
+    /// Open sinks by directory, with the write they were last used for
+    sinks: BTreeMap<String, (S, u64)>,
+    max_open: usize,
+    writes: u64,
+    /// Passed on to sinks made after a schema reload
+    reloaded: Option<ArrowBatchProps>,
+}
+
+impl<S: BufferSink> PartitionedSink<S> {
+    /// Column is dot separated for nested fields, its values are written as strings
+    pub fn new<F>(column: &str, make_sink: F) -> Self
+    where
+        F: FnMut(&str) -> Result<S> + Send + 'static,
+    {
+        Self {
+            column: column.to_owned(),
+            path: column.split('.').map(str::to_owned).collect(),
+            make_sink: Box::new(make_sink),
+            sinks: BTreeMap::new(),
+            max_open: MAX_OPEN_PARTITIONS,
+            writes: 0,
+            reloaded: None,
+        }
+    }
+
+    /// Close the least recently written partition's sink once this many are open, 256 by
+    /// default. Rows for it later get a new sink, so with manifests they're refused
+    /// (the partition was sealed when it closed), i.e. keep it above the live partitions
+    pub fn with_max_open_partitions(mut self, partitions: usize) -> Self {
+        self.max_open = partitions.max(1);
+        self
+    }
+
+    async fn close_least_recent(&mut self) -> Result<()> {
+        let least_recent = self
+            .sinks
+            .iter()
+            .min_by_key(|(_, (_, last_used))| *last_used)
+            .map(|(directory, _)| directory.clone());
+        if let Some((mut sink, _)) = least_recent.and_then(|d| self.sinks.remove(&d)) {
+            sink.close().await?;
+        }
+        Ok(())
+    }
+
+    /// Rows of the batch grouped by their partition directory
+    fn split(&self, batch: &RecordBatch) -> Result<BTreeMap<String, RecordBatch>> {
+        let column = column_at(batch, &self.path)
+            .ok_or_else(|| KatinssIngestorError::UnknownField(self.column.clone()))?;
+        let values = cast(&column, &DataType::Utf8)?;
+        let values = values
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .expect("cast to utf8");
+
+        let mut directories: BTreeMap<String, Vec<bool>> = BTreeMap::new();
+        for (i, value) in values.iter().enumerate() {
+            let value = match value {
+                Some(value) => escape(value)?,
+                None => NULL_PARTITION.to_owned(),
+            };
+            let directory = format!("{}={value}", self.column);
+            directories
+                .entry(directory)
+                .or_insert_with(|| vec![false; values.len()])[i] = true;
+        }
+        directories
+            .into_iter()
+            .map(|(directory, rows)| {
+                let rows = BooleanArray::from(rows);
+                Ok((directory, filter_record_batch(batch, &rows)?))
+            })
+            .collect()
+    }
+}
+
+#[async_trait]
+impl<S: BufferSink> BufferSink for PartitionedSink<S> {
+    async fn write_buffer(&mut self, buffer: TemporalBuffer) -> Result<()> {
+        let mut parts: BTreeMap<String, Vec<RecordBatch>> = BTreeMap::new();
+        for batch in &buffer.batches {
+            for (directory, rows) in self.split(batch)? {
+                parts.entry(directory).or_default().push(rows);
+            }
+        }
+
+        for (directory, batches) in parts {
+            self.writes += 1;
+            if !self.sinks.contains_key(&directory) {
+                if self.sinks.len() >= self.max_open {
+                    self.close_least_recent().await?;
+                }
+                let mut sink = (self.make_sink)(&directory)?;
+                if let Some(props) = &self.reloaded {
+                    sink.reload_schema(props).await?;
+                }
+                self.sinks.insert(directory.clone(), (sink, 0));
+            }
+            let (sink, last_used) = self.sinks.get_mut(&directory).expect("opened above");
+            *last_used = self.writes;
+            sink.write_buffer(TemporalBuffer {
+                begin_at: buffer.begin_at,
+                end_at: buffer.end_at,
+                batches,
+            })
+            .await?;
+        }
+        Ok(())
+    }
+
+    async fn close(&mut self) -> Result<()> {
+        for (sink, _) in self.sinks.values_mut() {
+            sink.close().await?;
+        }
+        Ok(())
+    }
+
+    async fn reload_schema(&mut self, props: &ArrowBatchProps) -> Result<()> {
+        for (sink, _) in self.sinks.values_mut() {
+            sink.reload_schema(props).await?;
+        }
+        self.reloaded = Some(props.clone());
+        Ok(())
+    }
+}
+
+/// Percent encodes anything that isn't safe in a path segment, i.e. "a/b" is "a%2Fb".
+/// Empty values, "." and ".." are refused rather than made into a directory
+fn escape(value: &str) -> Result<String> {
+    if matches!(value, "" | "." | "..") {
+        return Err(KatinssIngestorError::InvalidPartitionValue(
+            value.to_owned(),
+        ));
+    }
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') {
+            escaped.push(c);
+        } else {
+            let mut bytes = [0; 4];
+            for byte in c.encode_utf8(&mut bytes).bytes() {
+                escaped.push_str(&format!("%{byte:02X}"));
+            }
+        }
+    }
+    Ok(escaped)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use chrono::Utc;
+
+    use katniss_test::{protos::spacecorp::Packet, test_util::ProtoBatch};
+
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct Collect(Arc<Mutex<BTreeMap<String, (usize, bool)>>>, String);
+
+    #[async_trait]
+    impl BufferSink for Collect {
+        async fn write_buffer(&mut self, buffer: TemporalBuffer) -> Result<()> {
+            let mut seen = self.0.lock().unwrap();
+            seen.entry(self.1.clone()).or_default().0 += buffer.num_rows();
+            Ok(())
+        }
+
+        async fn close(&mut self) -> Result<()> {
+            self.0.lock().unwrap().entry(self.1.clone()).or_default().1 = true;
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn rows_go_to_the_sink_for_their_value() -> anyhow::Result<()> {
+        let collect = Collect::default();
+        let seen = collect.0.clone();
+        let mut sink = PartitionedSink::new("sender_uid", move |directory| {
+            Ok(Collect(collect.0.clone(), directory.to_owned()))
+        });
+
+        let packets: Vec<_> = [1, 2, 1]
+            .into_iter()
+            .map(|sender_uid| Packet {
+                sender_uid,
+                ..Default::default()
+            })
+            .collect();
+        for _ in 0..2 {
+            sink.write_buffer(TemporalBuffer {
+                begin_at: Utc::now(),
+                end_at: Utc::now(),
+                batches: vec![ProtoBatch::SpaceCorp(&packets).arrow_batch()?],
+            })
+            .await?;
+        }
+        sink.close().await?;
+
+        let seen = seen.lock().unwrap();
+        assert_eq!(seen["sender_uid=1"], (4, true));
+        assert_eq!(seen["sender_uid=2"], (2, true));
+        assert_eq!(seen.len(), 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn least_recently_written_partitions_are_closed() -> anyhow::Result<()> {
+        let collect = Collect::default();
+        let seen = collect.0.clone();
+        let mut sink = PartitionedSink::new("sender_uid", move |directory| {
+            Ok(Collect(collect.0.clone(), directory.to_owned()))
+        })
+        .with_max_open_partitions(2);
+
+        for sender_uid in [1, 2, 1, 3] {
+            let packets = [Packet {
+                sender_uid,
+                ..Default::default()
+            }];
+            sink.write_buffer(TemporalBuffer {
+                begin_at: Utc::now(),
+                end_at: Utc::now(),
+                batches: vec![ProtoBatch::SpaceCorp(&packets).arrow_batch()?],
+            })
+            .await?;
+        }
+        assert_eq!(sink.sinks.len(), 2);
+        {
+            let seen = seen.lock().unwrap();
+            assert_eq!(seen["sender_uid=1"], (2, false));
+            assert_eq!(seen["sender_uid=2"], (1, true));
+            assert_eq!(seen["sender_uid=3"], (1, false));
+        }
+
+        sink.close().await?;
+        assert!(seen.lock().unwrap().values().all(|(_, closed)| *closed));
+        Ok(())
+    }
+
+    #[test]
+    fn values_are_escaped_into_one_path_segment() -> anyhow::Result<()> {
+        assert_eq!(escape("robot-7_a.b")?, "robot-7_a.b");
+        assert_eq!(escape("a/b c=d")?, "a%2Fb%20c%3Dd");
+        assert_eq!(escape("é")?, "%C3%A9");
+        assert_eq!(escape("...")?, "...");
+        for value in ["", ".", ".."] {
+            assert!(matches!(
+                escape(value),
+                Err(KatinssIngestorError::InvalidPartitionValue(_))
+            ));
+        }
+        Ok(())
+    }
+}
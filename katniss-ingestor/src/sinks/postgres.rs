@@ -0,0 +1,370 @@
+use std::sync::Arc;
+
+use arrow_array::{cast::AsArray, types::*, Array, ArrayRef, RecordBatch};
+use arrow_cast::{cast_with_options, CastOptions};
+use arrow_json::writer::record_batches_to_json_rows;
+use arrow_schema::{DataType, Schema, TimeUnit};
+use async_trait::async_trait;
+use chrono::{TimeZone, Utc};
+use futures::pin_mut;
+use tokio_postgres::{
+    binary_copy::BinaryCopyInWriter,
+    types::{ToSql, Type},
+    Client, NoTls,
+};
+
+use crate::{
+    errors::KatinssIngestorError, sinks::BufferSink, temporal_rotator::TemporalBuffer, Result,
+};
+
+type Cell = Box<dyn ToSql + Sync + Send>;
+
+/// Writes each rotated buffer into a Postgres table with a binary COPY, for teams whose data
+/// lake is still a Postgres instance. Every top level field is a column: integers are widened to
+/// the next signed type (failing if a uint64 doesn't fit), enums are text, timestamps are
+/// timestamptz and nested messages and lists are jsonb
+pub struct PostgresSink {
+    client: Client,
+    /// Set when connected with PostgresSink::connect, so a closed connection can be reopened
+    config: Option<String>,
+    table: String,
+    columns: Vec<PostgresColumn>,
+}
+
+struct PostgresColumn {
+    name: String,
+    ty: Type,
+    /// What the arrow column is cast to before writing, None for jsonb
+    cast_to: Option<DataType>,
+}
+
+impl PostgresSink {
+    /// Connect with a libpq style config, i.e. "host=localhost user=katniss dbname=telemetry",
+    /// the connection is driven by a spawned task. If it closes, i.e. the server restarted,
+    /// the next write reconnects
+    pub async fn connect(config: &str, table: &str, schema: &Schema) -> Result<Self> {
+        let client = connect_client(config).await?;
+        let mut sink = Self::new(client, table, schema)?;
+        sink.config = Some(config.to_owned());
+        Ok(sink)
+    }
+
+    /// `table` can be schema qualified, i.e. "telemetry.packets"
+    pub fn new(client: Client, table: &str, schema: &Schema) -> Result<Self> {
+        let columns = schema
+            .fields()
+            .iter()
+            .map(|f| {
+                let (ty, cast_to) = storage_types(f.data_type())?;
+                Ok(PostgresColumn {
+                    name: f.name().to_owned(),
+                    ty,
+                    cast_to,
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            client,
+            config: None,
+            table: table.split('.').map(quote).collect::<Vec<_>>().join("."),
+            columns,
+        })
+    }
+
+    /// Create the table if it doesn't exist yet, see create_table_sql
+    pub async fn create_table(&self) -> Result<()> {
+        self.client.batch_execute(&self.create_table_sql()).await?;
+        Ok(())
+    }
+
+    /// The table definition for the schema, i.e.
+    /// `CREATE TABLE IF NOT EXISTS "packets" ("timestamp" jsonb, "sender_uid" int8)`
+    pub fn create_table_sql(&self) -> String {
+        let columns = self
+            .columns
+            .iter()
+            .map(|c| format!("{} {}", quote(&c.name), c.ty.name()))
+            .collect::<Vec<_>>();
+        format!(
+            "CREATE TABLE IF NOT EXISTS {} ({})",
+            self.table,
+            columns.join(", ")
+        )
+    }
+
+    /// One COPY per buffer, so a buffer is either all in the table or not at all
+    pub async fn write(&mut self, buffer: TemporalBuffer) -> Result<()> {
+        if let (true, Some(config)) = (self.client.is_closed(), &self.config) {
+            self.client = connect_client(config).await?;
+        }
+        let names = self
+            .columns
+            .iter()
+            .map(|c| quote(&c.name))
+            .collect::<Vec<_>>();
+        let copy = format!(
+            "COPY {} ({}) FROM STDIN (FORMAT binary)",
+            self.table,
+            names.join(", ")
+        );
+        let types: Vec<_> = self.columns.iter().map(|c| c.ty.clone()).collect();
+
+        let writer = BinaryCopyInWriter::new(self.client.copy_in(&copy).await?, &types);
+        pin_mut!(writer);
+        for batch in &buffer.batches {
+            let columns = self.cast_columns(batch)?;
+            for row in 0..batch.num_rows() {
+                let cells = columns
+                    .iter()
+                    .map(|column| column.cell(row))
+                    .collect::<Result<Vec<_>>>()?;
+                let cells: Vec<&(dyn ToSql + Sync)> = cells
+                    .iter()
+                    .map(|cell| cell.as_ref() as &(dyn ToSql + Sync))
+                    .collect();
+                writer.as_mut().write(&cells).await?;
+            }
+        }
+        writer.finish().await?;
+        Ok(())
+    }
+
+    /// The batch's columns cast to what their postgres types take
+    fn cast_columns(&self, batch: &RecordBatch) -> Result<Vec<Column>> {
+        let json_columns = self
+            .columns
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| c.cast_to.is_none())
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+        let json_rows = if json_columns.is_empty() {
+            None
+        } else {
+            let nested = batch.project(&json_columns)?;
+            Some(Arc::new(record_batches_to_json_rows(&[&nested])?))
+        };
+
+        // safe: false so a uint64 too big for an int8 fails instead of becoming null
+        let options = CastOptions {
+            safe: false,
+            ..Default::default()
+        };
+        self.columns
+            .iter()
+            .zip(batch.columns())
+            .map(|(column, array)| {
+                Ok(match &column.cast_to {
+                    Some(to) => Column::Array(cast_with_options(array, to, &options)?),
+                    None => Column::Json(
+                        column.name.clone(),
+                        json_rows.clone().expect("json rows for jsonb columns"),
+                    ),
+                })
+            })
+            .collect()
+    }
+}
+
+async fn connect_client(config: &str) -> Result<Client> {
+    let (client, connection) = tokio_postgres::connect(config, NoTls).await?;
+    tokio::spawn(async move {
+        if let Err(err) = connection.await {
+            tracing::warn!("postgres connection closed: {err}");
+        }
+    });
+    Ok(client)
+}
+
+/// Postgres column type for an arrow type, nested types are jsonb
+pub fn postgres_type(data_type: &DataType) -> Result<Type> {
+    Ok(storage_types(data_type)?.0)
+}
+
+/// The postgres type and the arrow type that's cast to for writing it, None for jsonb
+fn storage_types(data_type: &DataType) -> Result<(Type, Option<DataType>)> {
+    let cast_to = |ty: Type, to: DataType| Ok((ty, Some(to)));
+    match data_type {
+        DataType::Boolean => cast_to(Type::BOOL, DataType::Boolean),
+        DataType::Int8 | DataType::UInt8 | DataType::Int16 => cast_to(Type::INT2, DataType::Int16),
+        DataType::UInt16 | DataType::Int32 => cast_to(Type::INT4, DataType::Int32),
+        DataType::UInt32 | DataType::Int64 | DataType::UInt64 => {
+            cast_to(Type::INT8, DataType::Int64)
+        }
+        DataType::Float32 => cast_to(Type::FLOAT4, DataType::Float32),
+        DataType::Float64 => cast_to(Type::FLOAT8, DataType::Float64),
+        DataType::Utf8 | DataType::LargeUtf8 => cast_to(Type::TEXT, DataType::Utf8),
+        DataType::Dictionary(_, value) if value.as_ref() == &DataType::Utf8 => {
+            cast_to(Type::TEXT, DataType::Utf8)
+        }
+        DataType::Binary | DataType::LargeBinary => cast_to(Type::BYTEA, DataType::Binary),
+        DataType::Timestamp(_, _) => cast_to(
+            Type::TIMESTAMPTZ,
+            DataType::Timestamp(TimeUnit::Microsecond, None),
+        ),
+        DataType::Struct(_) | DataType::List(_) | DataType::LargeList(_) | DataType::Map(_, _) => {
+            Ok((Type::JSONB, None))
+        }
+        other => Err(KatinssIngestorError::UnsupportedColumnType(
+            other.to_string(),
+            "postgres",
+        )),
+    }
+}
+
+/// Identifiers are quoted so field names keep their case and can't clash with keywords
+fn quote(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+enum Column {
+    Array(ArrayRef),
+    Json(String, Arc<Vec<serde_json::Map<String, serde_json::Value>>>),
+}
+
+impl Column {
+    fn cell(&self, row: usize) -> Result<Cell> {
+        let array = match self {
+            Self::Json(name, rows) => return Ok(Box::new(rows[row].get(name).cloned())),
+            Self::Array(array) => array,
+        };
+        let valid = array.is_valid(row);
+        Ok(match array.data_type() {
+            DataType::Boolean => Box::new(valid.then(|| array.as_boolean().value(row))),
+            DataType::Int16 => {
+                Box::new(valid.then(|| array.as_primitive::<Int16Type>().value(row)))
+            }
+            DataType::Int32 => {
+                Box::new(valid.then(|| array.as_primitive::<Int32Type>().value(row)))
+            }
+            DataType::Int64 => {
+                Box::new(valid.then(|| array.as_primitive::<Int64Type>().value(row)))
+            }
+            DataType::Float32 => {
+                Box::new(valid.then(|| array.as_primitive::<Float32Type>().value(row)))
+            }
+            DataType::Float64 => {
+                Box::new(valid.then(|| array.as_primitive::<Float64Type>().value(row)))
+            }
+            DataType::Utf8 => {
+                Box::new(valid.then(|| array.as_string::<i32>().value(row).to_owned()))
+            }
+            DataType::Binary => {
+                Box::new(valid.then(|| array.as_binary::<i32>().value(row).to_vec()))
+            }
+            DataType::Timestamp(TimeUnit::Microsecond, _) => {
+                let micros = array.as_primitive::<TimestampMicrosecondType>().value(row);
+                let secs = micros.div_euclid(1_000_000);
+                let nanos = micros.rem_euclid(1_000_000) as u32 * 1_000;
+                let at = valid
+                    .then(|| Utc.timestamp_opt(secs, nanos).single())
+                    .map(|at| at.ok_or(KatinssIngestorError::TimestampOutOfRange(micros)))
+                    .transpose()?;
+                Box::new(at)
+            }
+            other => {
+                return Err(KatinssIngestorError::UnsupportedColumnType(
+                    other.to_string(),
+                    "postgres",
+                ))
+            }
+        })
+    }
+}
+
+#[async_trait]
+impl BufferSink for PostgresSink {
+    async fn write_buffer(&mut self, buffer: TemporalBuffer) -> Result<()> {
+        self.write(buffer).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use katniss_test::{protos::spacecorp::Packet, test_util::ProtoBatch};
+
+    use super::*;
+
+    #[test]
+    fn arrow_types_map_to_postgres_columns() -> anyhow::Result<()> {
+        let packets = [Packet::default()];
+        let schema = ProtoBatch::SpaceCorp(&packets).arrow_batch()?.schema();
+        let types: Vec<_> = schema
+            .fields()
+            .iter()
+            .map(|f| postgres_type(f.data_type()))
+            .collect::<Result<_>>()?;
+        assert_eq!(types[0], Type::JSONB);
+        assert_eq!(types[1], Type::INT8);
+
+        let enums = DataType::Dictionary(Box::new(DataType::Int32), Box::new(DataType::Utf8));
+        assert_eq!(postgres_type(&enums)?, Type::TEXT);
+        assert!(matches!(
+            postgres_type(&DataType::Float16),
+            Err(KatinssIngestorError::UnsupportedColumnType(_, "postgres"))
+        ));
+        assert_eq!(quote("weird \"name\""), "\"weird \"\"name\"\"\"");
+        Ok(())
+    }
+
+    #[test]
+    fn out_of_range_timestamps_are_errors() {
+        let column = |micros| {
+            Column::Array(Arc::new(arrow_array::TimestampMicrosecondArray::from(
+                vec![micros],
+            )))
+        };
+        assert!(column(1_700_000_000_000_000).cell(0).is_ok());
+        assert!(matches!(
+            column(i64::MAX).cell(0),
+            Err(KatinssIngestorError::TimestampOutOfRange(i64::MAX))
+        ));
+    }
+
+    /// Needs a server, i.e. KATNISS_TEST_POSTGRES="host=localhost user=postgres"
+    #[tokio::test]
+    async fn it_copies_buffers_into_postgres() -> anyhow::Result<()> {
+        let Ok(config) = std::env::var("KATNISS_TEST_POSTGRES") else {
+            return Ok(());
+        };
+        let packets = (0..3)
+            .map(|sender_uid| Packet {
+                sender_uid,
+                ..Default::default()
+            })
+            .collect::<Vec<_>>();
+        let batch = ProtoBatch::SpaceCorp(&packets).arrow_batch()?;
+        let table = format!("katniss_test_{}", std::process::id());
+        let buffer = TemporalBuffer {
+            begin_at: Utc::now(),
+            end_at: Utc::now(),
+            batches: vec![batch.clone()],
+        };
+
+        let mut sink = PostgresSink::connect(&config, &table, &batch.schema()).await?;
+        sink.create_table().await?;
+        sink.write(buffer.clone()).await?;
+
+        // the server dropping the connection doesn't lose the next buffer
+        let _ = sink
+            .client
+            .batch_execute("SELECT pg_terminate_backend(pg_backend_pid())")
+            .await;
+        while !sink.client.is_closed() {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        sink.write(buffer).await?;
+
+        let rows = sink
+            .client
+            .query(&format!("SELECT sender_uid FROM {}", quote(&table)), &[])
+            .await?;
+        let mut senders = rows.iter().map(|r| r.get::<_, i64>(0)).collect::<Vec<_>>();
+        senders.sort();
+        assert_eq!(senders, [0, 0, 1, 1, 2, 2]);
+        sink.client
+            .batch_execute(&format!("DROP TABLE {}", quote(&table)))
+            .await?;
+        Ok(())
+    }
+}
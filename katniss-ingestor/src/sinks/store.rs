@@ -0,0 +1,270 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use arrow_schema::{Schema, SchemaRef};
+use async_trait::async_trait;
+use object_store::{
+    aws::AmazonS3Builder, azure::MicrosoftAzureBuilder, gcp::GoogleCloudStorageBuilder,
+    local::LocalFileSystem, path::Path, ObjectStore,
+};
+
+use katniss_pb2arrow::{exports::RecordBatch, ArrowBatchProps};
+
+use crate::{
+    errors::KatinssIngestorError,
+    sinks::{manifest::Manifests, BufferSink, FileNaming, JsonLinesEncoder},
+    stats::{column_stats, column_stats_schema},
+    temporal_rotator::TemporalBuffer,
+    Result,
+};
+
+/// Turns the batches of a TemporalBuffer into the bytes of a single file
+pub trait BufferEncoder: Send + Sync {
+    /// File extension (without the dot) for files made by this encoder
+    fn extension(&self) -> &str;
+
+    fn encode(&self, schema: &Schema, batches: &[RecordBatch]) -> Result<Vec<u8>>;
+
+    /// Catch up with a schema reload, see BufferSink::reload_schema
+    fn reload_schema(&mut self, _props: &ArrowBatchProps) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Writes one file per rotated buffer into an object store (local fs, gcs, etc)
+pub struct StoreSink<E: BufferEncoder> {
+    store: Box<dyn ObjectStore>,
+    prefix: Path,
+    schema: SchemaRef,
+    encoder: E,
+    naming: FileNaming,
+    files_written: AtomicU64,
+    stats_sidecar: bool,
+    manifests: Option<Manifests>,
+}
+
+impl<E: BufferEncoder> StoreSink<E> {
+    pub fn new(store: Box<dyn ObjectStore>, prefix: Path, schema: SchemaRef, encoder: E) -> Self {
+        Self {
+            store,
+            prefix,
+            schema,
+            encoder,
+            naming: FileNaming::default(),
+            files_written: AtomicU64::new(0),
+            stats_sidecar: false,
+            manifests: None,
+        }
+    }
+
+    /// Defaults to {start}_{end}.{ext}, see FileNaming::template
+    pub fn with_file_naming(mut self, naming: FileNaming) -> Self {
+        self.naming = naming;
+        self
+    }
+
+    /// Finish each partition (the directory files are named into, see FileNaming) with a
+    /// _manifest.jsonl listing its files and a _SUCCESS marker, once files move on to the next
+    /// partition or the sink is closed, i.e. with "{date}/{hour}/{start}_{end}.{ext}"
+    /// Sealed partitions are never rewritten, a late buffer that would land in one is an error
+    pub fn with_manifests(mut self) -> Self {
+        self.manifests = Some(Manifests::default());
+        self
+    }
+
+    /// Also write each file's column_stats next to it as {file}.stats.jsonl
+    pub fn with_stats_sidecar(mut self) -> Self {
+        self.stats_sidecar = true;
+        self
+    }
+
+    /// Build the object store from a connection string, credentials come from the environment
+    /// Supports s3://bucket/prefix, gs://bucket/prefix, az://container/prefix and file:///some/dir
+    pub fn from_uri(uri: &str, schema: SchemaRef, encoder: E) -> Result<Self> {
+        let (store, prefix) = store_from_uri(uri)?;
+        Ok(Self::new(store, prefix, schema, encoder))
+    }
+
+    pub fn store(&self) -> &dyn ObjectStore {
+        self.store.as_ref()
+    }
+
+    /// Encode the buffer and put it at {prefix}/{name}, named by the sink's FileNaming
+    /// Returns the location of the new file
+    pub async fn write(&self, buffer: TemporalBuffer) -> Result<Path> {
+        let filename = self.next_filename(&buffer);
+        self.put(&filename, buffer).await
+    }
+
+    fn next_filename(&self, buffer: &TemporalBuffer) -> String {
+        let seq = self.files_written.fetch_add(1, Ordering::Relaxed);
+        self.naming.name(buffer, seq, 0, self.encoder.extension())
+    }
+
+    async fn put(&self, filename: &str, buffer: TemporalBuffer) -> Result<Path> {
+        let bytes = self.encoder.encode(&self.schema, &buffer.batches)?;
+        let location = child_path(&self.prefix, filename);
+
+        self.store.put(&location, bytes.into()).await?;
+
+        if self.stats_sidecar {
+            let stats = column_stats(&buffer)?;
+            let stats_bytes = JsonLinesEncoder.encode(&column_stats_schema(), &[stats])?;
+            let stats_location = child_path(&self.prefix, &format!("{filename}.stats.jsonl"));
+            self.store.put(&stats_location, stats_bytes.into()).await?;
+        }
+        Ok(location)
+    }
+}
+
+#[async_trait]
+impl<E: BufferEncoder> BufferSink for StoreSink<E> {
+    async fn write_buffer(&mut self, buffer: TemporalBuffer) -> Result<()> {
+        let rows = buffer.num_rows();
+        let (begin_at, end_at) = (buffer.begin_at, buffer.end_at);
+        let filename = self.next_filename(&buffer);
+        if let Some(manifests) = &self.manifests {
+            let location = child_path(&self.prefix, &filename);
+            manifests.check_open(self.store.as_ref(), &location).await?;
+        }
+        let location = self.put(&filename, buffer).await?;
+
+        if let Some(manifests) = &mut self.manifests {
+            manifests
+                .record(self.store.as_ref(), &location, rows, begin_at, end_at)
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn close(&mut self) -> Result<()> {
+        match &mut self.manifests {
+            Some(manifests) => manifests.finish(self.store.as_ref()).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn reload_schema(&mut self, props: &ArrowBatchProps) -> Result<()> {
+        self.encoder.reload_schema(props)?;
+        self.schema = props.schema.clone();
+        Ok(())
+    }
+}
+
+/// prefix/name, where slashes in name make subdirectories
+pub(crate) fn child_path(prefix: &Path, name: &str) -> Path {
+    name.split('/')
+        .filter(|part| !part.is_empty())
+        .fold(prefix.clone(), |path, part| path.child(part))
+}
+
+/// Split an object store uri into the store (bucket or root dir) and the prefix within it
+pub fn store_from_uri(uri: &str) -> Result<(Box<dyn ObjectStore>, Path)> {
+    let invalid = || KatinssIngestorError::InvalidStorageUri(uri.to_owned());
+
+    let (scheme, rest) = uri.split_once("://").ok_or_else(invalid)?;
+
+    if scheme == "file" {
+        std::fs::create_dir_all(rest)?;
+        return Ok((
+            Box::new(LocalFileSystem::new_with_prefix(rest)?),
+            Path::default(),
+        ));
+    }
+
+    let (bucket, prefix) = rest.split_once('/').unwrap_or((rest, ""));
+    if bucket.is_empty() {
+        return Err(invalid());
+    }
+
+    let store: Box<dyn ObjectStore> = match scheme {
+        "s3" | "s3a" => Box::new(
+            AmazonS3Builder::from_env()
+                .with_bucket_name(bucket)
+                .build()?,
+        ),
+        "gs" => Box::new(
+            GoogleCloudStorageBuilder::from_env()
+                .with_bucket_name(bucket)
+                .build()?,
+        ),
+        "az" | "azure" | "abfs" => Box::new(
+            MicrosoftAzureBuilder::from_env()
+                .with_container_name(bucket)
+                .build()?,
+        ),
+        _ => return Err(invalid()),
+    };
+
+    Ok((store, Path::from(prefix)))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use katniss_test::{protos::spacecorp::Packet, test_util::ProtoBatch};
+
+    use super::*;
+    use crate::sinks::IpcFileEncoder;
+
+    #[tokio::test]
+    async fn it_writes_to_file_uris() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let root = dir.path().join("packets");
+        let batch = ProtoBatch::SpaceCorp(&[Packet::default()]).arrow_batch()?;
+
+        let sink = StoreSink::from_uri(
+            &format!("file://{}", root.to_str().unwrap()),
+            batch.schema(),
+            IpcFileEncoder,
+        )?
+        .with_stats_sidecar();
+
+        sink.write(TemporalBuffer {
+            begin_at: Utc.timestamp_millis_opt(10).unwrap(),
+            end_at: Utc.timestamp_millis_opt(20).unwrap(),
+            batches: vec![batch],
+        })
+        .await?;
+
+        assert!(root.join("10_20.arrow").is_file());
+        assert!(root.join("10_20.arrow.stats.jsonl").is_file());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn templates_can_make_subdirectories() -> anyhow::Result<()> {
+        let batch = ProtoBatch::SpaceCorp(&[Packet::default()]).arrow_batch()?;
+        let sink = StoreSink::new(
+            Box::new(object_store::memory::InMemory::new()),
+            Path::from("data"),
+            batch.schema(),
+            IpcFileEncoder,
+        )
+        .with_file_naming(
+            FileNaming::template("{message}/{start}_{seq}.{ext}").with_message("Packet"),
+        );
+
+        for seq in 0..2 {
+            let location = sink
+                .write(TemporalBuffer {
+                    begin_at: Utc.timestamp_millis_opt(10).unwrap(),
+                    end_at: Utc.timestamp_millis_opt(20).unwrap(),
+                    batches: vec![batch.clone()],
+                })
+                .await?;
+            assert_eq!(location, Path::from(format!("data/Packet/10_{seq}.arrow")));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn it_rejects_unknown_uris() {
+        for uri in ["packets", "ftp://bucket/packets", "gs:///packets"] {
+            assert!(matches!(
+                store_from_uri(uri),
+                Err(KatinssIngestorError::InvalidStorageUri(_))
+            ));
+        }
+    }
+}
@@ -0,0 +1,102 @@
+use async_trait::async_trait;
+
+use katniss_pb2arrow::ArrowBatchProps;
+
+use crate::{sinks::BufferSink, temporal_rotator::TemporalBuffer, Result};
+
+/// Writes every buffer to each of its sinks in turn, i.e. lance for queries and parquet for
+/// the archive. A failing sink fails the write, sinks before it will have the buffer already
+#[derive(Default)]
+pub struct TeeSink {
+    sinks: Vec<Box<dyn BufferSink>>,
+}
+
+impl TeeSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_sink<S: BufferSink + 'static>(mut self, sink: S) -> Self {
+        self.sinks.push(Box::new(sink));
+        self
+    }
+}
+
+impl From<Vec<Box<dyn BufferSink>>> for TeeSink {
+    fn from(sinks: Vec<Box<dyn BufferSink>>) -> Self {
+        Self { sinks }
+    }
+}
+
+#[async_trait]
+impl BufferSink for TeeSink {
+    async fn write_buffer(&mut self, buffer: TemporalBuffer) -> Result<()> {
+        if let Some((last, rest)) = self.sinks.split_last_mut() {
+            for sink in rest {
+                sink.write_buffer(buffer.clone()).await?;
+            }
+            last.write_buffer(buffer).await?;
+        }
+        Ok(())
+    }
+
+    async fn close(&mut self) -> Result<()> {
+        for sink in &mut self.sinks {
+            sink.close().await?;
+        }
+        Ok(())
+    }
+
+    async fn reload_schema(&mut self, props: &ArrowBatchProps) -> Result<()> {
+        for sink in &mut self.sinks {
+            sink.reload_schema(props).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use chrono::Utc;
+
+    use katniss_test::{protos::spacecorp::Packet, test_util::ProtoBatch};
+
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct Collect(Arc<Mutex<(usize, bool)>>);
+
+    #[async_trait]
+    impl BufferSink for Collect {
+        async fn write_buffer(&mut self, buffer: TemporalBuffer) -> Result<()> {
+            self.0.lock().unwrap().0 += buffer.num_rows();
+            Ok(())
+        }
+
+        async fn close(&mut self) -> Result<()> {
+            self.0.lock().unwrap().1 = true;
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn every_sink_gets_every_buffer() -> anyhow::Result<()> {
+        let (a, b) = (Collect::default(), Collect::default());
+        let mut tee = TeeSink::new().with_sink(a.clone()).with_sink(b.clone());
+
+        let packets = vec![Packet::default(); 3];
+        tee.write_buffer(TemporalBuffer {
+            begin_at: Utc::now(),
+            end_at: Utc::now(),
+            batches: vec![ProtoBatch::SpaceCorp(&packets).arrow_batch()?],
+        })
+        .await?;
+        tee.close().await?;
+
+        assert_eq!(*a.0.lock().unwrap(), (3, true));
+        assert_eq!(*b.0.lock().unwrap(), (3, true));
+        Ok(())
+    }
+}
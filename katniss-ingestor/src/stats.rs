@@ -0,0 +1,156 @@
+use std::sync::Arc;
+
+use arrow_array::{
+    builder::{StringBuilder, UInt64Builder},
+    ArrayRef,
+};
+use arrow_cast::display::{ArrayFormatter, FormatOptions};
+use arrow_row::{OwnedRow, RowConverter, SortField};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+
+use katniss_pb2arrow::exports::RecordBatch;
+
+use crate::{temporal_rotator::TemporalBuffer, Result};
+
+/// One row per column of the stats batch, see column_stats
+pub fn column_stats_schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        Field::new("column", DataType::Utf8, false),
+        Field::new("row_count", DataType::UInt64, false),
+        Field::new("null_count", DataType::UInt64, false),
+        Field::new("min", DataType::Utf8, true),
+        Field::new("max", DataType::Utf8, true),
+    ]))
+}
+
+/// Min, max and null count for each top level column of a finished buffer, so downstream
+/// planners can prune files by time and value without opening them.
+/// Min and max are rendered as strings and are null for columns that are all null
+/// or whose type can't be ordered (i.e. maps).
+pub fn column_stats(buffer: &TemporalBuffer) -> Result<RecordBatch> {
+    let Some(schema) = buffer.batches.first().map(|b| b.schema()) else {
+        return Ok(RecordBatch::new_empty(column_stats_schema()));
+    };
+
+    let num_columns = schema.fields().len();
+    let mut names = StringBuilder::with_capacity(num_columns, num_columns * 16);
+    let mut row_counts = UInt64Builder::with_capacity(num_columns);
+    let mut null_counts = UInt64Builder::with_capacity(num_columns);
+    let mut mins = StringBuilder::with_capacity(num_columns, num_columns * 16);
+    let mut maxes = StringBuilder::with_capacity(num_columns, num_columns * 16);
+
+    for (i, field) in schema.fields().iter().enumerate() {
+        let columns = buffer
+            .batches
+            .iter()
+            .map(|b| b.column(i).clone())
+            .collect::<Vec<_>>();
+
+        names.append_value(field.name());
+        row_counts.append_value(columns.iter().map(|c| c.len() as u64).sum());
+        null_counts.append_value(columns.iter().map(|c| c.null_count() as u64).sum());
+
+        let (min, max) = min_max(field.data_type(), &columns)?.unzip();
+        mins.append_option(min);
+        maxes.append_option(max);
+    }
+
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(names.finish()),
+        Arc::new(row_counts.finish()),
+        Arc::new(null_counts.finish()),
+        Arc::new(mins.finish()),
+        Arc::new(maxes.finish()),
+    ];
+    Ok(RecordBatch::try_new(column_stats_schema(), columns)?)
+}
+
+/// Compares values in the arrow row format so every orderable type is handled the same way
+fn min_max(data_type: &DataType, columns: &[ArrayRef]) -> Result<Option<(String, String)>> {
+    let sort_fields = vec![SortField::new(data_type.clone())];
+    if !RowConverter::supports_fields(&sort_fields) {
+        return Ok(None);
+    }
+
+    let mut converter = RowConverter::new(sort_fields)?;
+    let mut bounds: Option<(OwnedRow, OwnedRow)> = None;
+    for column in columns {
+        let rows = converter.convert_columns(&[column.clone()])?;
+        for (i, row) in rows.iter().enumerate() {
+            if column.is_null(i) {
+                continue;
+            }
+            bounds = Some(match bounds {
+                None => (row.owned(), row.owned()),
+                Some((min, max)) => (
+                    if row < min.row() { row.owned() } else { min },
+                    if row > max.row() { row.owned() } else { max },
+                ),
+            });
+        }
+    }
+
+    let Some((min, max)) = bounds else {
+        return Ok(None);
+    };
+    let values = converter.convert_rows([min.row(), max.row()])?;
+    let formatter = ArrayFormatter::try_new(values[0].as_ref(), &FormatOptions::default())?;
+    Ok(Some((
+        formatter.value(0).to_string(),
+        formatter.value(1).to_string(),
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::{cast::AsArray, types::UInt64Type};
+    use chrono::Utc;
+
+    use katniss_test::{
+        protos::spacecorp::{Packet, Timestamp},
+        test_util::ProtoBatch,
+    };
+
+    use super::*;
+
+    #[test]
+    fn it_summarizes_each_column() -> anyhow::Result<()> {
+        let packet = |sender_uid, timestamp| Packet {
+            sender_uid,
+            timestamp,
+            ..Default::default()
+        };
+        let first = [packet(7, None), packet(3, None)];
+        let second = [packet(12, Some(Timestamp::default()))];
+
+        let stats = column_stats(&TemporalBuffer {
+            begin_at: Utc::now(),
+            end_at: Utc::now(),
+            batches: vec![
+                ProtoBatch::SpaceCorp(&first).arrow_batch()?,
+                ProtoBatch::SpaceCorp(&second).arrow_batch()?,
+            ],
+        })?;
+
+        let strings = |name| stats.column_by_name(name).unwrap().as_string::<i32>();
+        let row_of = |name| {
+            strings("column")
+                .iter()
+                .position(|c| c == Some(name))
+                .unwrap()
+        };
+
+        let sender = row_of("sender_uid");
+        assert_eq!(strings("min").value(sender), "3");
+        assert_eq!(strings("max").value(sender), "12");
+
+        let null_counts = stats.column_by_name("null_count").unwrap();
+        let null_counts = null_counts.as_primitive::<UInt64Type>();
+        assert_eq!(null_counts.value(row_of("timestamp")), 2);
+        assert_eq!(null_counts.value(sender), 0);
+
+        let empty = TemporalBuffer::new(Utc::now(), std::time::Duration::from_secs(1))?;
+        assert_eq!(column_stats(&empty)?.num_rows(), 0);
+        Ok(())
+    }
+}
@@ -0,0 +1,277 @@
+use std::any::Any;
+use std::panic::{resume_unwind, AssertUnwindSafe};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use futures::FutureExt;
+use tokio::sync::broadcast;
+
+use crate::{errors::KatinssIngestorError, schema_guard::SchemaChange, Result};
+
+/// How many events a subscriber can fall behind before it misses the oldest ones
+const EVENT_CAPACITY: usize = 1024;
+
+/// Health of one of the pipeline's tasks
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StageStatus {
+    pub alive: bool,
+    /// Messages received for the encoder, buffers written for the sink
+    pub processed: u64,
+    pub last_active_at: Option<DateTime<Utc>>,
+    pub errors: u64,
+    pub last_error: Option<String>,
+    /// Times the stage recovered from an error, see RestartPolicy
+    pub restarts: u64,
+}
+
+/// Snapshot of a running pipeline, see PipelineHandle::status
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PipelineStatus {
+    pub encoder: StageStatus,
+    pub sink: StageStatus,
+    /// When the sink last finished writing a buffer
+    pub last_flush_at: Option<DateTime<Utc>>,
+    /// Rows held in buffers that haven't been handed to the sink yet
+    pub buffered_rows: usize,
+    /// Finished buffers waiting on the sink
+    pub pending_buffers: usize,
+    pub dropped_filtered: u64,
+    pub dropped_rate_limited: u64,
+    /// Messages thrown away because the pipeline was overloaded, see LoadShedding
+    pub dropped_shed: u64,
+    pub dropped_duplicates: u64,
+    pub dropped_late: u64,
+    pub dropped_missing_time: u64,
+    /// Records a source couldn't decode and skipped, i.e. see consume_kafka
+    pub dropped_malformed: u64,
+    /// Values ConversionMode::Lenient replaced with nulls, see PipelineEvent::ConversionWarning
+    pub conversion_warnings: u64,
+    /// Changes a SchemaGuard let through with a warning, at startup or on reload
+    pub schema_warnings: Vec<SchemaChange>,
+}
+
+impl PipelineStatus {
+    /// Every stage is running
+    pub fn is_healthy(&self) -> bool {
+        self.encoder.alive && self.sink.alive
+    }
+}
+
+/// Something that went wrong in a running pipeline, sent as it happens rather than when
+/// the pipeline's tasks are joined, see PipelineHandle::events
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PipelineEvent {
+    /// A message couldn't be converted to arrow, the encoder exits with the error
+    ConversionFailed(String),
+    /// ConversionMode::Lenient appended a null in place of something it couldn't convert
+    ConversionWarning(String),
+    /// The sink failed a write and tries it again after backoff, see RestartPolicy
+    SinkRestarting {
+        attempt: u32,
+        error: String,
+        backoff: Duration,
+    },
+    /// A stage exited with an error or panicked, taking the pipeline down
+    StageFailed { stage: Stage, error: String },
+}
+
+/// Cheaply cloneable view of a pipeline's status for supervisors and readiness probes
+#[derive(Debug, Clone)]
+pub struct PipelineHandle {
+    inner: Arc<Mutex<PipelineStatus>>,
+    events: broadcast::Sender<PipelineEvent>,
+}
+
+impl Default for PipelineHandle {
+    fn default() -> Self {
+        Self {
+            inner: Default::default(),
+            events: broadcast::channel(EVENT_CAPACITY).0,
+        }
+    }
+}
+
+impl PipelineHandle {
+    pub fn status(&self) -> PipelineStatus {
+        self.inner.lock().expect("status lock poisoned").clone()
+    }
+
+    /// Events from now on, a subscriber that falls behind by more than 1024 gets
+    /// RecvError::Lagged and misses the oldest
+    pub fn events(&self) -> broadcast::Receiver<PipelineEvent> {
+        self.events.subscribe()
+    }
+
+    pub(crate) fn emit(&self, event: PipelineEvent) {
+        // no one's subscribed
+        let _ = self.events.send(event);
+    }
+
+    pub(crate) fn update<F: FnOnce(&mut PipelineStatus)>(&self, f: F) {
+        f(&mut self.inner.lock().expect("status lock poisoned"))
+    }
+
+    /// Runs a stage's loop with its alive flag set, recording the error it exits with.
+    /// Panics are recorded and sent as StageFailed too before they carry on to the task's
+    /// JoinHandle
+    pub(crate) async fn run_stage<T, F>(&self, stage: Stage, f: F) -> Result<T>
+    where
+        F: std::future::Future<Output = Result<T>>,
+    {
+        self.update(|s| stage.of(s).alive = true);
+        let result = match AssertUnwindSafe(f).catch_unwind().await {
+            Ok(result) => result,
+            Err(panic) => {
+                let error = format!("panicked: {}", panic_message(panic.as_ref()));
+                self.update(|s| {
+                    let stage = stage.of(s);
+                    stage.alive = false;
+                    stage.errors += 1;
+                    stage.last_error = Some(error.clone());
+                });
+                self.emit(PipelineEvent::StageFailed { stage, error });
+                resume_unwind(panic);
+            }
+        };
+        self.update(|s| {
+            let stage = stage.of(s);
+            stage.alive = false;
+            if let Err(e) = &result {
+                stage.errors += 1;
+                stage.last_error = Some(e.to_string());
+            }
+        });
+
+        match (stage, &result) {
+            (_, Ok(_) | Err(KatinssIngestorError::PipelineClosed)) => {}
+            (Stage::Encoder, Err(e @ KatinssIngestorError::Pb2ArrowArror(_))) => {
+                self.emit(PipelineEvent::ConversionFailed(e.to_string()))
+            }
+            (stage, Err(e)) => self.emit(PipelineEvent::StageFailed {
+                stage,
+                error: e.to_string(),
+            }),
+        }
+        result
+    }
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    match (panic.downcast_ref::<&str>(), panic.downcast_ref::<String>()) {
+        (Some(message), _) => message,
+        (_, Some(message)) => message.as_str(),
+        _ => "Box<dyn Any>",
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Encoder,
+    Sink,
+}
+
+impl Stage {
+    pub(crate) fn of(self, status: &mut PipelineStatus) -> &mut StageStatus {
+        match self {
+            Self::Encoder => &mut status.encoder,
+            Self::Sink => &mut status.sink,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use katniss_pb2arrow::KatnissArrowError;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn stages_report_why_they_died() {
+        let handle = PipelineHandle::default();
+        assert!(!handle.status().is_healthy());
+
+        let result: Result<()> = handle
+            .run_stage(Stage::Sink, async {
+                assert!(handle.status().sink.alive);
+                Err(KatinssIngestorError::PipelineClosed)
+            })
+            .await;
+
+        assert!(result.is_err());
+        let sink = handle.status().sink;
+        assert!(!sink.alive);
+        assert_eq!(sink.errors, 1);
+        assert_eq!(sink.last_error.as_deref(), Some("Pipeline Channel Closed"));
+    }
+
+    #[tokio::test]
+    async fn failures_are_sent_as_they_happen() {
+        let handle = PipelineHandle::default();
+        let mut events = handle.events();
+
+        let conversion = KatnissArrowError::NoEnumValue("mode".to_owned(), 9);
+        let _: Result<()> = handle
+            .run_stage(Stage::Encoder, async { Err(conversion.into()) })
+            .await;
+        let _: Result<()> = handle
+            .run_stage(Stage::Sink, async {
+                Err(KatinssIngestorError::UnknownField("nope".to_owned()))
+            })
+            .await;
+        let _: Result<()> = handle
+            .run_stage(Stage::Sink, async {
+                Err(KatinssIngestorError::PipelineClosed)
+            })
+            .await;
+
+        assert!(matches!(
+            events.try_recv(),
+            Ok(PipelineEvent::ConversionFailed(error)) if error.contains("mode")
+        ));
+        assert!(matches!(
+            events.try_recv(),
+            Ok(PipelineEvent::StageFailed {
+                stage: Stage::Sink,
+                ..
+            })
+        ));
+        // shutting down isn't a failure
+        assert!(events.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn panics_are_stage_failures() {
+        let handle = PipelineHandle::default();
+        let mut events = handle.events();
+
+        let stage = handle.clone();
+        let task = tokio::spawn(async move {
+            stage
+                .run_stage(Stage::Encoder, async {
+                    if stage.status().encoder.alive {
+                        panic!("encoder fell over");
+                    }
+                    Ok(())
+                })
+                .await
+        });
+        assert!(task.await.is_err_and(|err| err.is_panic()));
+
+        let encoder = handle.status().encoder;
+        assert!(!encoder.alive);
+        assert_eq!(
+            encoder.last_error.as_deref(),
+            Some("panicked: encoder fell over")
+        );
+        assert!(matches!(
+            events.try_recv(),
+            Ok(PipelineEvent::StageFailed {
+                stage: Stage::Encoder,
+                error,
+            }) if error == "panicked: encoder fell over"
+        ));
+    }
+}
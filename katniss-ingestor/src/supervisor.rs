@@ -0,0 +1,109 @@
+use std::time::Duration;
+
+use crate::{errors::KatinssIngestorError, pipeline::LoopJoinSet, Result};
+
+/// How many times the sink stage retries a failed write before giving up and taking the
+/// pipeline down with it. The sink and any buffers queued for it are kept across restarts.
+/// See PipelineBuilder::with_restart_policy, by default the first failure is fatal.
+#[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct RestartPolicy {
+    max_restarts: u32,
+    #[cfg_attr(feature = "serde", serde(with = "crate::pipeline_config::secs"))]
+    backoff: Duration,
+    #[cfg_attr(feature = "serde", serde(with = "crate::pipeline_config::secs"))]
+    max_backoff: Duration,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        Self {
+            max_restarts: 0,
+            backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RestartPolicy {
+    /// Restart up to max_restarts times in a row, a successful write resets the count
+    pub fn new(max_restarts: u32) -> Self {
+        Self {
+            max_restarts,
+            ..Default::default()
+        }
+    }
+
+    /// Wait before the first restart, doubling each time up to max
+    pub fn with_backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.backoff = initial;
+        self.max_backoff = max;
+        self
+    }
+
+    /// How long to wait before restart number attempt (from 0), None once they're used up
+    pub(crate) fn backoff(&self, attempt: u32) -> Option<Duration> {
+        if attempt >= self.max_restarts {
+            return None;
+        }
+        let wait = self.backoff.saturating_mul(2u32.saturating_pow(attempt));
+        Some(wait.min(self.max_backoff))
+    }
+}
+
+/// Waits on a pipeline's tasks. If one of them fails (or panics) the rest are aborted
+/// so nothing is left silently stalled, and the failure is returned.
+/// Tasks exiting because the head was dropped is a clean shutdown, the others are left to
+/// finish flushing and Ok is returned once they have.
+pub async fn supervise(mut tasks: LoopJoinSet) -> Result<()> {
+    while let Some(exit) = tasks.join_next().await {
+        let err = match exit {
+            Ok(Err(KatinssIngestorError::PipelineClosed)) => continue,
+            Ok(Err(err)) => err,
+            Err(join_err) => join_err.into(),
+            Ok(Ok(never)) => match never {},
+        };
+
+        tasks.shutdown().await;
+        return Err(err);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::pending;
+
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_until_restarts_run_out() {
+        let policy =
+            RestartPolicy::new(4).with_backoff(Duration::from_secs(1), Duration::from_secs(5));
+        let secs = |s| Some(Duration::from_secs(s));
+        assert_eq!(
+            (0..5).map(|a| policy.backoff(a)).collect::<Vec<_>>(),
+            vec![secs(1), secs(2), secs(4), secs(5), None]
+        );
+        assert_eq!(RestartPolicy::default().backoff(0), None);
+    }
+
+    #[tokio::test]
+    async fn a_failed_task_tears_down_the_rest() {
+        let mut tasks = LoopJoinSet::new();
+        tasks.spawn(pending());
+        tasks.spawn(async { Err(KatinssIngestorError::UnknownField("nope".to_owned())) });
+        assert!(matches!(
+            supervise(tasks).await,
+            Err(KatinssIngestorError::UnknownField(_))
+        ));
+
+        let mut tasks = LoopJoinSet::new();
+        tasks.spawn(async { Err(KatinssIngestorError::PipelineClosed) });
+        assert!(supervise(tasks).await.is_ok());
+    }
+}
@@ -0,0 +1,197 @@
+use std::convert::Infallible;
+use std::future::Future;
+use std::io;
+use std::time::Duration;
+
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, BufReader},
+    net::{TcpListener, TcpStream},
+    select,
+    sync::mpsc::UnboundedSender,
+    task::JoinSet,
+    time::sleep,
+};
+
+use katniss_pb2arrow::exports::{prost_reflect::MessageDescriptor, DynamicMessage};
+
+use crate::{errors::KatinssIngestorError, Result};
+
+/// Bigger length prefixes are taken to mean the client isn't speaking the protocol
+const MAX_MESSAGE_BYTES: u64 = 64 * 1024 * 1024;
+/// Pause after a failed accept, doubled while they keep failing
+const MIN_ACCEPT_BACKOFF: Duration = Duration::from_millis(10);
+const MAX_ACCEPT_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Accepts connections until the pipeline behind head closes, each connection sending varint
+/// length delimited messages (protobuf's writeDelimitedTo) of the descriptor's type.
+/// A connection that sends something undecodable is dropped, the others carry on
+pub async fn serve_tcp(
+    listener: TcpListener,
+    descriptor: MessageDescriptor,
+    head: UnboundedSender<DynamicMessage>,
+) -> Result<Infallible> {
+    accept_connections(listener, |stream| {
+        let (descriptor, head) = (descriptor.clone(), head.clone());
+        read_messages(BufReader::new(stream), descriptor, head)
+    })
+    .await
+}
+
+/// Spawns a task per connection until one of them finds the pipeline closed.
+/// Failed accepts (i.e. out of file descriptors, a client resetting mid handshake) and
+/// connections that fail or panic are logged, the server keeps going
+pub(crate) async fn accept_connections<F, Fut>(
+    listener: TcpListener,
+    mut serve: F,
+) -> Result<Infallible>
+where
+    F: FnMut(TcpStream) -> Fut,
+    Fut: Future<Output = Result<()>> + Send + 'static,
+{
+    let mut connections: JoinSet<Result<()>> = JoinSet::new();
+    let mut backoff = MIN_ACCEPT_BACKOFF;
+    loop {
+        select! {
+            accepted = listener.accept() => match accepted {
+                Ok((stream, _)) => {
+                    backoff = MIN_ACCEPT_BACKOFF;
+                    connections.spawn(serve(stream));
+                }
+                Err(err) => {
+                    tracing::warn!("accepting a connection failed, retrying in {backoff:?}: {err}");
+                    sleep(backoff).await;
+                    backoff = (backoff * 2).min(MAX_ACCEPT_BACKOFF);
+                }
+            },
+            Some(finished) = connections.join_next() => match finished {
+                Ok(Ok(())) => {}
+                Ok(Err(KatinssIngestorError::PipelineClosed)) => {
+                    return Err(KatinssIngestorError::PipelineClosed)
+                }
+                Ok(Err(err)) => tracing::warn!("dropped a connection: {err}"),
+                Err(err) => tracing::warn!("connection task failed: {err}"),
+            }
+        }
+    }
+}
+
+pub(crate) async fn read_messages<R: AsyncRead + Unpin>(
+    mut reader: R,
+    descriptor: MessageDescriptor,
+    head: UnboundedSender<DynamicMessage>,
+) -> Result<()> {
+    let mut buf = Vec::new();
+    while let Some(len) = read_varint(&mut reader).await? {
+        if len > MAX_MESSAGE_BYTES {
+            let msg = format!("{len} byte message is over the {MAX_MESSAGE_BYTES} byte limit");
+            return Err(io::Error::new(io::ErrorKind::InvalidData, msg).into());
+        }
+        buf.resize(len as usize, 0);
+        reader.read_exact(&mut buf).await?;
+
+        let msg = DynamicMessage::decode(descriptor.clone(), buf.as_slice())?;
+        head.send(msg)
+            .map_err(|_| KatinssIngestorError::PipelineClosed)?;
+    }
+    Ok(())
+}
+
+/// None if the stream ended cleanly between messages
+pub(crate) async fn read_varint<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Option<u64>> {
+    let mut value = 0;
+    for i in 0..10 {
+        let byte = match reader.read_u8().await {
+            Ok(byte) => byte,
+            Err(err) if i == 0 && err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        value |= u64::from(byte & 0x7f) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Ok(Some(value));
+        }
+    }
+    Err(io::Error::new(io::ErrorKind::InvalidData, "varint longer than 10 bytes").into())
+}
+
+#[cfg(test)]
+mod tests {
+    use prost::Message;
+    use tokio::{io::AsyncWriteExt, net::TcpStream, sync::mpsc::unbounded_channel};
+
+    use katniss_pb2arrow::ArrowBatchProps;
+    use katniss_test::{descriptor_pool, protos::spacecorp::Packet};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn it_reads_delimited_messages_off_each_connection() -> anyhow::Result<()> {
+        let props = ArrowBatchProps::try_new(
+            descriptor_pool()?,
+            "eto.pb2arrow.tests.spacecorp.Packet".to_owned(),
+        )?;
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let (head, mut rx) = unbounded_channel();
+        let server = tokio::spawn(serve_tcp(listener, props.descriptor.clone(), head));
+
+        for sender_uid in [1, 2] {
+            let mut bytes = Vec::new();
+            for _ in 0..2 {
+                Packet {
+                    sender_uid,
+                    ..Default::default()
+                }
+                .encode_length_delimited(&mut bytes)?;
+            }
+            let mut stream = TcpStream::connect(addr).await?;
+            stream.write_all(&bytes).await?;
+            stream.shutdown().await?;
+        }
+
+        let mut senders = Vec::new();
+        for _ in 0..4 {
+            let msg = rx.recv().await.unwrap();
+            senders.push(msg.transcode_to::<Packet>()?.sender_uid);
+        }
+        senders.sort();
+        assert_eq!(senders, [1, 1, 2, 2]);
+
+        // a bad connection doesn't take the server down with it
+        let mut garbage = TcpStream::connect(addr).await?;
+        garbage.write_all(&[0xff; 11]).await?;
+        garbage.shutdown().await?;
+
+        let mut bytes = Vec::new();
+        Packet::default().encode_length_delimited(&mut bytes)?;
+        TcpStream::connect(addr).await?.write_all(&bytes).await?;
+        assert!(rx.recv().await.is_some());
+        server.abort();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn a_panicking_connection_doesnt_stop_the_server() -> anyhow::Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let (tx, mut rx) = unbounded_channel();
+        let mut accepted = 0;
+        let server = tokio::spawn(accept_connections(listener, move |_| {
+            accepted += 1;
+            let (first, tx) = (accepted == 1, tx.clone());
+            async move {
+                assert!(!first, "the first connection panics");
+                tx.send(())
+                    .map_err(|_| KatinssIngestorError::PipelineClosed)
+            }
+        }));
+
+        let _first = TcpStream::connect(addr).await?;
+        // long enough for the server to have joined the panicked task
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let _second = TcpStream::connect(addr).await?;
+        assert!(rx.recv().await.is_some());
+        assert!(!server.is_finished());
+        server.abort();
+        Ok(())
+    }
+}
@@ -1,14 +1,17 @@
 use std::time::Duration;
 
-use chrono::{DateTime, Utc};
+use arrow_select::filter::filter_record_batch;
+use chrono::{DateTime, TimeZone, Utc};
 
-use crate::{arrow::ProtobufBatchIngestor, Result};
+use crate::{
+    arrow::ProtobufBatchIngestor, errors::KatinssIngestorError, filter::Predicate, Result,
+};
 use katniss_pb2arrow::{
     exports::{DynamicMessage, RecordBatch},
-    ArrowBatchProps,
+    ArrowBatchProps, KatnissArrowError,
 };
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct TemporalBuffer {
     pub begin_at: DateTime<Utc>,
     pub end_at: DateTime<Utc>,
@@ -24,18 +27,148 @@ impl TemporalBuffer {
             batches: Vec::new(),
         })
     }
+
+    pub fn num_rows(&self) -> usize {
+        self.batches.iter().map(|b| b.num_rows()).sum()
+    }
+
+    /// Copy of this buffer with only the named top level columns, in the given order
+    pub fn project(&self, columns: &[&str]) -> Result<Self> {
+        let batches = self
+            .batches
+            .iter()
+            .map(|batch| -> Result<RecordBatch> {
+                let schema = batch.schema();
+                let indices = columns
+                    .iter()
+                    .map(|c| schema.index_of(c))
+                    .collect::<std::result::Result<Vec<_>, _>>()?;
+                Ok(batch.project(&indices)?)
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(self.with_batches(batches))
+    }
+
+    /// Copy of this buffer with only the rows matching the predicate, see Predicate::evaluate
+    /// i.e. for health checks and alerting inside the ingest process:
+    /// `buffer.filter(&"sender_uid >= 5".parse()?)`
+    pub fn filter(&self, predicate: &Predicate) -> Result<Self> {
+        let batches = self
+            .batches
+            .iter()
+            .map(|batch| -> Result<RecordBatch> {
+                Ok(filter_record_batch(batch, &predicate.evaluate(batch)?)?)
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(self.with_batches(batches))
+    }
+
+    /// Simple selects over this buffer's batches without DataFusion, i.e.
+    /// `select sender_uid, msg from buffer where sender_uid >= 5 and severity >= WARN`.
+    /// Columns are top level ones or *, conditions are Predicates joined by `and`.
+    /// The datafusion feature has TemporalBuffer::sql for anything more
+    pub fn query(&self, query: &str) -> Result<Vec<RecordBatch>> {
+        let invalid = || KatinssIngestorError::InvalidQuery(query.to_owned());
+        let query = query.trim();
+        let rest = query
+            .get(..7)
+            .filter(|s| s.eq_ignore_ascii_case("select "))
+            .map(|select| &query[select.len()..])
+            .ok_or_else(invalid)?;
+
+        let (columns, conditions) = match split_keyword(rest, "where") {
+            Some((columns, conditions)) => (columns, Some(conditions)),
+            None => (rest, None),
+        };
+        let (columns, table) = split_keyword(columns, "from").ok_or_else(invalid)?;
+        if table.trim() != "buffer" {
+            return Err(invalid());
+        }
+
+        let mut predicates = Vec::new();
+        let mut remaining = conditions;
+        while let Some(conditions) = remaining {
+            let (condition, rest) = match split_keyword(conditions, "and") {
+                Some((condition, rest)) => (condition, Some(rest)),
+                None => (conditions, None),
+            };
+            predicates.push(condition.parse::<Predicate>()?);
+            remaining = rest;
+        }
+        let filtered = if predicates.is_empty() {
+            self.clone()
+        } else {
+            self.filter(&Predicate::All(predicates))?
+        };
+
+        let columns = columns.split(',').map(str::trim).collect::<Vec<_>>();
+        if columns == ["*"] {
+            return Ok(filtered.batches);
+        }
+        if columns.iter().any(|c| c.is_empty()) {
+            return Err(invalid());
+        }
+        Ok(filtered.project(&columns)?.batches)
+    }
+
+    fn with_batches(&self, batches: Vec<RecordBatch>) -> Self {
+        Self {
+            begin_at: self.begin_at,
+            end_at: self.end_at,
+            batches,
+        }
+    }
+}
+
+/// Splits around the first ` keyword ` outside of quotes, ignoring case
+fn split_keyword<'a>(s: &'a str, keyword: &str) -> Option<(&'a str, &'a str)> {
+    let lower = s.to_ascii_lowercase();
+    let needle = format!(" {keyword} ");
+    let mut quote = None;
+    for (i, c) in lower.char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (None, '"' | '\'') => quote = Some(c),
+            (None, _) if lower[i..].starts_with(&needle) => {
+                return Some((&s[..i], &s[i + needle.len()..]))
+            }
+            _ => {}
+        }
+    }
+    None
 }
 
-#[allow(dead_code)]
 pub fn timestamp_string(time: DateTime<Utc>) -> String {
     time.format("%Y-%m-%d-%H%M%S_utc").to_string()
 }
 
+/// Start of the period long window since the epoch that `at` falls in, shared by arrival and
+/// event time rotation. None if the window or its end can't be represented, i.e. the year 3000
+pub(crate) fn aligned_window_start(
+    at: DateTime<Utc>,
+    period: Duration,
+) -> Result<Option<DateTime<Utc>>> {
+    let period = chrono::Duration::from_std(period)?;
+    let period_nanos = period.num_nanoseconds().unwrap_or(i64::MAX).max(1);
+    let at_nanos = at
+        .timestamp()
+        .checked_mul(1_000_000_000)
+        .and_then(|n| n.checked_add(at.timestamp_subsec_nanos() as i64));
+    let start = at_nanos
+        .and_then(|n| n.checked_sub(n.rem_euclid(period_nanos)))
+        .map(|n| Utc.timestamp_nanos(n));
+    Ok(start.filter(|start| start.checked_add_signed(period).is_some()))
+}
+
 /// Collects RecordBatches into buffers which get rotated every $batch_period of time.
 pub struct TemporalRotator {
     pub converter: ProtobufBatchIngestor,
     pub current: TemporalBuffer,
     batch_period: Duration,
+    aligned: bool,
+    warnings: Vec<KatnissArrowError>,
 }
 
 impl TemporalRotator {
@@ -44,9 +177,19 @@ impl TemporalRotator {
             converter: ProtobufBatchIngestor::try_new(props)?,
             current: TemporalBuffer::new(now, period)?,
             batch_period: period,
+            aligned: false,
+            warnings: Vec::new(),
         })
     }
 
+    /// Rotate on multiples of the period since the epoch instead of every period since the
+    /// first buffer, like EventTimeRotator's windows. The first buffer only runs to the next boundary
+    pub fn with_aligned_windows(mut self) -> Result<Self> {
+        self.aligned = true;
+        self.current = self.next_buffer(self.current.begin_at)?;
+        Ok(self)
+    }
+
     /// Receives dynamic protobuf messages and sends them in to a temporal buffer
     /// Rotates the temporal buffer if time boundary has been crossed
     /// Returns the previous buffer if it has been rotated
@@ -58,19 +201,75 @@ impl TemporalRotator {
         msg: DynamicMessage,
         now: DateTime<Utc>,
     ) -> Result<Option<TemporalBuffer>> {
-        let mut finished_batch = None;
-        if now > self.current.end_at {
-            let batch = self.converter.finish()?;
-            let new = TemporalBuffer::new(now, self.batch_period)?;
-            // constructing new before pushing as it's theoretically fallible to avoid memory leak
+        self.ingest_sourced_potentially_blocking(msg, None, now)
+    }
+
+    /// Tag each row with the source it came from, see FanIn
+    pub fn with_source_column(mut self, props: &ArrowBatchProps, name: &str) -> Self {
+        self.converter = self.converter.with_source_column(props, name);
+        self
+    }
+
+    /// Like ingest_potentially_blocking, source is ignored unless there's a source column
+    pub fn ingest_sourced_potentially_blocking(
+        &mut self,
+        msg: DynamicMessage,
+        source: Option<&str>,
+        now: DateTime<Utc>,
+    ) -> Result<Option<TemporalBuffer>> {
+        let finished_batch = self.rotate_if_due(now)?;
+
+        if let Some(batch) = self.converter.ingest_sourced(msg, source)? {
             self.current.batches.push(batch);
-            finished_batch = Some(std::mem::replace(&mut self.current, new));
+            self.warnings.extend(self.converter.take_warnings());
+        }
+        Ok(finished_batch)
+    }
+
+    /// What lenient conversion nulled out in the batches finished since the last call
+    pub fn take_warnings(&mut self) -> Vec<KatnissArrowError> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    /// Rotates the temporal buffer if the time boundary has been crossed, without needing a message
+    /// to arrive, so a quiet stream still gets flushed. An empty buffer is replaced but not returned.
+    /// Blocking: see ingest_potentially_blocking
+    pub fn rotate_if_due(&mut self, now: DateTime<Utc>) -> Result<Option<TemporalBuffer>> {
+        if now <= self.current.end_at {
+            return Ok(None);
         }
+        self.rotate(now)
+    }
 
-        if let Some(batch) = self.converter.ingest_message(msg)? {
-            self.current.batches.push(batch)
+    /// Finishes the current buffer early and starts a new one at now
+    /// An empty buffer is replaced but not returned
+    pub fn rotate(&mut self, now: DateTime<Utc>) -> Result<Option<TemporalBuffer>> {
+        let new = self.next_buffer(now)?;
+        if self.current.batches.is_empty() && self.converter.is_empty() {
+            self.current = new;
+            return Ok(None);
         }
-        Ok(finished_batch)
+
+        let batch = self.converter.finish()?;
+        self.warnings.extend(self.converter.take_warnings());
+        // constructing new before pushing as it's theoretically fallible to avoid memory leak
+        self.current.batches.push(batch);
+        Ok(Some(std::mem::replace(&mut self.current, new)))
+    }
+
+    fn next_buffer(&self, now: DateTime<Utc>) -> Result<TemporalBuffer> {
+        let mut next = TemporalBuffer::new(now, self.batch_period)?;
+        if !self.aligned {
+            return Ok(next);
+        }
+        if let Some(start) = aligned_window_start(now, self.batch_period)? {
+            // an early rotation stays in the current window, begin at now so file names don't clash
+            if start > self.current.begin_at {
+                next.begin_at = start;
+            }
+            next.end_at = start + chrono::Duration::from_std(self.batch_period)?;
+        }
+        Ok(next)
     }
 }
 
@@ -78,10 +277,15 @@ impl TemporalRotator {
 mod tests {
     use super::*;
 
+    use arrow_array::{cast::AsArray, types::UInt64Type};
     use chrono::{Duration, TimeZone};
-    use katniss_pb2arrow::ArrowBatchProps;
+    use katniss_pb2arrow::{ArrowBatchProps, ConversionMode};
 
-    use katniss_test::{descriptor_pool, protos::spacecorp::Packet, test_util::to_dynamic};
+    use katniss_test::{
+        descriptor_pool,
+        protos::spacecorp::{packet, JumpDriveControl, Packet},
+        test_util::{to_dynamic, ProtoBatch},
+    };
 
     const PACKET: &str = "eto.pb2arrow.tests.spacecorp.Packet";
 
@@ -138,6 +342,176 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn it_rotates_without_a_message() -> anyhow::Result<()> {
+        let start = Utc::now();
+        let mut rotator = TemporalRotator::new(
+            &ArrowBatchProps::try_new(descriptor_pool()?, PACKET.to_owned())?,
+            start,
+            std::time::Duration::from_millis(60),
+        )?;
+
+        rotator.ingest_potentially_blocking(
+            to_dynamic(&Packet::default(), PACKET)?,
+            start + Duration::milliseconds(1),
+        )?;
+        assert!(rotator
+            .rotate_if_due(start + Duration::milliseconds(30))?
+            .is_none());
+
+        let buf = rotator
+            .rotate_if_due(start + Duration::milliseconds(61))?
+            .unwrap();
+        assert_eq!(buf.num_rows(), 1);
+
+        // nothing arrived in the next window so there's nothing to flush, but it still moves on
+        assert!(rotator
+            .rotate_if_due(start + Duration::milliseconds(122))?
+            .is_none());
+        assert_eq!(
+            rotator.current.begin_at,
+            start + Duration::milliseconds(122)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn aligned_windows_rotate_on_period_boundaries() -> anyhow::Result<()> {
+        let start = Utc.with_ymd_and_hms(2023, 6, 1, 12, 0, 25).unwrap();
+        let mut rotator = TemporalRotator::new(
+            &ArrowBatchProps::try_new(descriptor_pool()?, PACKET.to_owned())?,
+            start,
+            std::time::Duration::from_secs(60),
+        )?
+        .with_aligned_windows()?;
+        assert_eq!(rotator.current.begin_at, start);
+        assert_eq!(
+            rotator.current.end_at,
+            Utc.with_ymd_and_hms(2023, 6, 1, 12, 1, 0).unwrap()
+        );
+
+        rotator.ingest_potentially_blocking(to_dynamic(&Packet::default(), PACKET)?, start)?;
+        let buf = rotator
+            .rotate_if_due(start + Duration::seconds(40))?
+            .unwrap();
+        assert_eq!(buf.num_rows(), 1);
+        assert_eq!(
+            rotator.current.begin_at,
+            Utc.with_ymd_and_hms(2023, 6, 1, 12, 1, 0).unwrap()
+        );
+
+        // flushing early keeps the window's end but not its start
+        rotator.ingest_potentially_blocking(
+            to_dynamic(&Packet::default(), PACKET)?,
+            start + Duration::seconds(50),
+        )?;
+        rotator.rotate(start + Duration::seconds(55))?.unwrap();
+        assert_eq!(rotator.current.begin_at, start + Duration::seconds(55));
+        assert_eq!(
+            rotator.current.end_at,
+            Utc.with_ymd_and_hms(2023, 6, 1, 12, 2, 0).unwrap()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn buffers_can_be_filtered_and_projected() -> anyhow::Result<()> {
+        let packets = (0..6)
+            .map(|i| Packet {
+                sender_uid: i,
+                ..Default::default()
+            })
+            .collect::<Vec<_>>();
+
+        let buffer = TemporalBuffer {
+            begin_at: Utc::now(),
+            end_at: Utc::now(),
+            batches: vec![
+                ProtoBatch::SpaceCorp(&packets[..3]).arrow_batch()?,
+                ProtoBatch::SpaceCorp(&packets[3..]).arrow_batch()?,
+            ],
+        };
+
+        let senders = buffer.project(&["sender_uid"])?;
+        assert_eq!(senders.num_rows(), 6);
+        assert_eq!(senders.batches[0].num_columns(), 1);
+
+        let odd = senders.filter(&"sender_uid in (1, 3, 5)".parse()?)?;
+        assert_eq!(
+            vec![1, 2],
+            odd.batches.iter().map(|b| b.num_rows()).collect::<Vec<_>>()
+        );
+
+        assert!(buffer.project(&["nope"]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn buffers_can_be_queried_without_datafusion() -> anyhow::Result<()> {
+        let packets = (0..6)
+            .map(|i| Packet {
+                sender_uid: i,
+                ..Default::default()
+            })
+            .collect::<Vec<_>>();
+        let buffer = TemporalBuffer {
+            begin_at: Utc::now(),
+            end_at: Utc::now(),
+            batches: vec![ProtoBatch::SpaceCorp(&packets).arrow_batch()?],
+        };
+
+        let batches = buffer
+            .query("SELECT sender_uid FROM buffer WHERE sender_uid >= 2 and sender_uid != 4")?;
+        assert_eq!(batches[0].num_columns(), 1);
+        assert_eq!(
+            batches[0]
+                .column(0)
+                .as_primitive::<UInt64Type>()
+                .values()
+                .to_vec(),
+            vec![2, 3, 5]
+        );
+
+        let everything = buffer.query("select * from buffer")?;
+        assert_eq!(everything[0].num_rows(), 6);
+        assert_eq!(everything[0].schema(), buffer.batches[0].schema());
+
+        for bad in [
+            "sender_uid from buffer",
+            "select sender_uid",
+            "select sender_uid from packets",
+            "select from buffer",
+            "select sender_uid from buffer where sender_uid",
+        ] {
+            assert!(buffer.query(bad).is_err(), "{bad} should not have run");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn lenient_warnings_are_taken_as_batches_finish() -> anyhow::Result<()> {
+        let props = ArrowBatchProps::try_new(descriptor_pool()?, PACKET.to_owned())?
+            .with_conversion_mode(ConversionMode::Lenient);
+        let start = Utc::now();
+        let mut rotator = TemporalRotator::new(&props, start, std::time::Duration::from_secs(60))?;
+
+        let unknown_mode = Packet {
+            msg: Some(packet::Msg::JumpDriveControl(JumpDriveControl {
+                requested_mode: 7,
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+        rotator.ingest_potentially_blocking(to_dynamic(&unknown_mode, PACKET)?, start)?;
+        // still in the builder
+        assert!(rotator.take_warnings().is_empty());
+
+        rotator.rotate(start + Duration::seconds(1))?;
+        assert_eq!(rotator.take_warnings().len(), 1);
+        assert!(rotator.take_warnings().is_empty());
+        Ok(())
+    }
+
     #[test]
     fn filenames_are_pretty() -> anyhow::Result<()> {
         let now = Utc.timestamp_nanos(1678307941000000000);
@@ -0,0 +1,158 @@
+use std::convert::Infallible;
+use std::io;
+
+use futures::StreamExt;
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::mpsc::UnboundedSender,
+};
+use tokio_tungstenite::{accept_async, tungstenite::Message};
+
+use katniss_pb2arrow::exports::{prost_reflect::MessageDescriptor, DynamicMessage};
+
+use crate::{
+    errors::KatinssIngestorError,
+    tcp_source::{accept_connections, read_messages},
+    Result,
+};
+
+/// What each binary frame of a websocket connection holds
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FrameContents {
+    /// One encoded message per frame, i.e. a browser's `ws.send(Packet.encode(p).finish())`
+    #[default]
+    Message,
+    /// Any number of varint length delimited messages per frame, for producers that batch
+    Delimited,
+}
+
+/// Same as serve_tcp but each connection is a websocket sending binary frames, for producers
+/// that can't open a raw socket i.e. browsers. Text frames aren't part of the protocol so they
+/// drop the connection along with anything undecodable
+pub async fn serve_websocket(
+    listener: TcpListener,
+    descriptor: MessageDescriptor,
+    head: UnboundedSender<DynamicMessage>,
+    contents: FrameContents,
+) -> Result<Infallible> {
+    accept_connections(listener, |stream| {
+        let (descriptor, head) = (descriptor.clone(), head.clone());
+        read_frames(stream, descriptor, head, contents)
+    })
+    .await
+}
+
+async fn read_frames(
+    stream: TcpStream,
+    descriptor: MessageDescriptor,
+    head: UnboundedSender<DynamicMessage>,
+    contents: FrameContents,
+) -> Result<()> {
+    // pings are answered by tungstenite as frames are read
+    let mut ws = accept_async(stream).await?;
+    while let Some(frame) = ws.next().await {
+        let bytes = match frame? {
+            Message::Binary(bytes) => bytes,
+            Message::Close(_) => break,
+            Message::Text(_) => {
+                let msg = "text frame, messages are sent as binary frames";
+                return Err(io::Error::new(io::ErrorKind::InvalidData, msg).into());
+            }
+            _ => continue,
+        };
+
+        match contents {
+            FrameContents::Message => {
+                let msg = DynamicMessage::decode(descriptor.clone(), bytes.as_slice())?;
+                head.send(msg)
+                    .map_err(|_| KatinssIngestorError::PipelineClosed)?;
+            }
+            FrameContents::Delimited => {
+                read_messages(bytes.as_slice(), descriptor.clone(), head.clone()).await?
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::SinkExt;
+    use prost::Message as _;
+    use tokio::sync::mpsc::unbounded_channel;
+    use tokio_tungstenite::connect_async;
+
+    use katniss_pb2arrow::ArrowBatchProps;
+    use katniss_test::{descriptor_pool, protos::spacecorp::Packet};
+
+    use super::*;
+
+    async fn server(
+        contents: FrameContents,
+    ) -> anyhow::Result<(String, tokio::sync::mpsc::UnboundedReceiver<DynamicMessage>)> {
+        let props = ArrowBatchProps::try_new(
+            descriptor_pool()?,
+            "eto.pb2arrow.tests.spacecorp.Packet".to_owned(),
+        )?;
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let url = format!("ws://{}", listener.local_addr()?);
+        let (head, rx) = unbounded_channel();
+        tokio::spawn(serve_websocket(listener, props.descriptor, head, contents));
+        Ok((url, rx))
+    }
+
+    fn packet(sender_uid: u64) -> Packet {
+        Packet {
+            sender_uid,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn it_reads_a_message_per_binary_frame() -> anyhow::Result<()> {
+        let (url, mut rx) = server(FrameContents::Message).await?;
+        let (mut ws, _) = connect_async(&url).await?;
+        for sender_uid in [1, 2] {
+            ws.send(Message::Binary(packet(sender_uid).encode_to_vec()))
+                .await?;
+        }
+        ws.close(None).await?;
+
+        for sender_uid in [1, 2] {
+            let msg = rx.recv().await.unwrap();
+            assert_eq!(msg.transcode_to::<Packet>()?.sender_uid, sender_uid);
+        }
+
+        // a text frame drops its connection but not the server
+        let (mut bad, _) = connect_async(&url).await?;
+        bad.send(Message::Text("hi".to_owned())).await?;
+        let (mut ws, _) = connect_async(&url).await?;
+        ws.send(Message::Binary(packet(3).encode_to_vec())).await?;
+        assert_eq!(
+            rx.recv()
+                .await
+                .unwrap()
+                .transcode_to::<Packet>()?
+                .sender_uid,
+            3
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_reads_delimited_batches_per_binary_frame() -> anyhow::Result<()> {
+        let (url, mut rx) = server(FrameContents::Delimited).await?;
+        let (mut ws, _) = connect_async(&url).await?;
+        let mut frame = Vec::new();
+        for sender_uid in 0..3 {
+            packet(sender_uid).encode_length_delimited(&mut frame)?;
+        }
+        ws.send(Message::Binary(frame)).await?;
+
+        for sender_uid in 0..3 {
+            let msg = rx.recv().await.unwrap();
+            assert_eq!(msg.transcode_to::<Packet>()?.sender_uid, sender_uid);
+        }
+        Ok(())
+    }
+}
@@ -1,39 +1,66 @@
-use arrow_schema::ArrowError;
+use arrow_schema::{ArrowError, DataType};
 use prost_reflect::{
     prost::{encoding::WireType, DecodeError},
     Value,
 };
 use thiserror::Error;
 
+use crate::message_conversion::FieldMismatch;
+
 #[derive(Error, Debug)]
 pub enum KatnissArrowError {
     #[error("file descriptor not found {0}")]
     DescriptorNotFound(String),
 
-    #[error("couldn't cast value {0:?} to correct type")]
-    TypeCastError(Value),
+    #[error("{0} could be any of {1:?}, use the full name")]
+    AmbiguousName(String, Vec<String>),
+
+    #[error("couldn't cast value {1:?} of {0} to correct type")]
+    TypeCastError(String, Value),
 
-    #[error("Field is not an enum")]
-    NonEnumField,
+    #[error("Field {0} is not an enum")]
+    NonEnumField(String),
 
-    #[error("No Enum Value {0}")]
-    NoEnumValue(i32),
+    #[error("No Enum Value {1} for {0}")]
+    NoEnumValue(String, i32),
+
+    #[error("No Enum Value named {0}")]
+    NoEnumName(String),
 
     #[error("Invalid Enum Value")]
     InvalidEnumValue(ArrowError),
 
+    #[error("More than one field of oneof {0} is set: {1:?}")]
+    OneofConflict(String, Vec<String>),
+
     #[error("Attempted to append a list to a non-list field")]
     NonListField,
 
+    #[cfg(feature = "protoc")]
     #[error("Couldn't find protoc on path")]
     ProtocError(#[from] which::Error),
 
+    #[error("protoc failed: {0}")]
+    ProtocFailed(String),
+
+    #[error("buf failed: {0}")]
+    BufFailed(String),
+
+    #[error("Invalid descriptor set: {0}")]
+    DescriptorError(#[from] prost_reflect::DescriptorError),
+
+    #[error("{0} is defined differently by the merged descriptors")]
+    ConflictingFile(String),
+
     #[error("Io Error")]
     IoError(#[from] std::io::Error),
 
     #[error("Batch Conversion Error: {0}")]
     BatchConversionError(ArrowError),
 
+    #[error("Converted {0} records don't match their schema: {1}")]
+    RecordsMismatch(String, ArrowError),
+
     #[error("Can only iterate over WireType::LengthDelimited but is {0:?}")]
     NotLengthDelimted(WireType),
 
@@ -45,6 +72,26 @@ pub enum KatnissArrowError {
 
     #[error("Arrow Dictionary Field must have dict_id")]
     DictNotFound,
+
+    #[error("Schema doesn't match the expected fingerprint: {0}")]
+    SchemaDrift(String),
+
+    #[error("Field {0} has unsupported type {1}")]
+    UnsupportedDataType(String, DataType),
+
+    #[error("Column for {0} has unexpected type {1}")]
+    ColumnTypeMismatch(String, DataType),
+
+    #[error("Columns don't match the message: {}", join_mismatches(.0))]
+    SchemaMismatch(Vec<FieldMismatch>),
+
+    #[error("Message {0} changed converting to arrow and back: {1:?}")]
+    RoundTripMismatch(usize, Vec<String>),
+}
+
+fn join_mismatches(mismatches: &[FieldMismatch]) -> String {
+    let mismatches: Vec<_> = mismatches.iter().map(ToString::to_string).collect();
+    mismatches.join(", ")
 }
 
 pub type Result<T> = core::result::Result<T, KatnissArrowError>;
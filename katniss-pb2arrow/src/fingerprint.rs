@@ -0,0 +1,151 @@
+use std::collections::HashSet;
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+use arrow_schema::{DataType, Field, Schema};
+
+use crate::KatnissArrowError;
+
+/// The shape of an Arrow schema, one line per column with nested ones flattened, i.e.
+/// `pose.position.x: Float64 (nullable)`. Displays as those lines and parses back from them
+/// so it can be pinned in a file, see ArrowBatchProps::with_expected_fingerprint.
+/// Dictionary values and metadata aren't part of it. Serializes as the list of columns
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
+pub struct SchemaFingerprint {
+    columns: Vec<String>,
+}
+
+impl SchemaFingerprint {
+    pub fn of(schema: &Schema) -> Self {
+        let mut columns = Vec::new();
+        for field in schema.fields() {
+            push_columns("", field, &mut columns);
+        }
+        Self { columns }
+    }
+
+    pub fn columns(&self) -> &[String] {
+        &self.columns
+    }
+
+    /// FNV-1a of the columns, stable across builds so it can be logged and compared
+    pub fn hash(&self) -> u64 {
+        let mut hash: u64 = 0xcbf29ce484222325;
+        for byte in self.columns.join("\n").bytes() {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+        hash
+    }
+
+    /// Columns only in self with a "-", then columns only in other with a "+", i.e.
+    /// `- value: Float64 (nullable)`, `+ value: Int64 (nullable)`
+    pub fn diff(&self, other: &SchemaFingerprint) -> Vec<String> {
+        let ours: HashSet<_> = self.columns.iter().collect();
+        let theirs: HashSet<_> = other.columns.iter().collect();
+        let removed = self.columns.iter().filter(|c| !theirs.contains(c));
+        let added = other.columns.iter().filter(|c| !ours.contains(c));
+        removed
+            .map(|c| format!("- {c}"))
+            .chain(added.map(|c| format!("+ {c}")))
+            .collect()
+    }
+}
+
+impl Display for SchemaFingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "# schema fingerprint {:016x}", self.hash())?;
+        for column in &self.columns {
+            writeln!(f, "{column}")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for SchemaFingerprint {
+    type Err = KatnissArrowError;
+
+    /// Blank lines and # comments are skipped
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let columns = s
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(str::to_owned)
+            .collect();
+        Ok(Self { columns })
+    }
+}
+
+fn push_columns(prefix: &str, field: &Field, columns: &mut Vec<String>) {
+    let path = format!("{prefix}{}", field.name());
+    let nullable = if field.is_nullable() {
+        " (nullable)"
+    } else {
+        ""
+    };
+    let data_type = match field.data_type() {
+        DataType::Struct(_) => "Struct".to_owned(),
+        DataType::List(_) | DataType::LargeList(_) => "List".to_owned(),
+        DataType::Map(_, _) => "Map".to_owned(),
+        data_type => format!("{data_type:?}"),
+    };
+    columns.push(format!("{path}: {data_type}{nullable}"));
+
+    match field.data_type() {
+        DataType::Struct(children) => {
+            for child in children {
+                push_columns(&format!("{path}."), child, columns);
+            }
+        }
+        // item names vary between writers, so items are always []
+        DataType::List(item) | DataType::LargeList(item) => {
+            let item = Field::new("", item.data_type().clone(), item.is_nullable());
+            push_columns(&format!("{path}[]"), &item, columns);
+        }
+        DataType::Map(entries, _) => push_columns(&format!("{path}."), entries, columns),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ArrowBatchProps, Result};
+
+    const PACKET: &str = "eto.pb2arrow.tests.spacecorp.Packet";
+
+    fn packet_props(projection: &[&str]) -> Result<ArrowBatchProps> {
+        let bytes = katniss_test::protos::FILE_DESCRIPTOR_BYTES;
+        let pool = prost_reflect::DescriptorPool::decode(bytes)?;
+        ArrowBatchProps::try_new_projected(pool, PACKET.to_owned(), projection)
+    }
+
+    #[test]
+    fn fingerprints_pin_the_schema() -> Result<()> {
+        let props = packet_props(&[])?;
+        let pinned: SchemaFingerprint = SchemaFingerprint::of(&props.schema).to_string().parse()?;
+        assert_eq!(pinned, SchemaFingerprint::of(&props.schema));
+        assert!(props
+            .clone()
+            .with_expected_fingerprint(pinned.clone())
+            .is_ok());
+
+        let first = props.schema.field(0).name().clone();
+        let drifted = packet_props(&[&first])?.with_expected_fingerprint(pinned);
+        match drifted {
+            Err(KatnissArrowError::SchemaDrift(diff)) => {
+                assert!(diff.starts_with("- "));
+                assert!(!diff.contains(&format!("- {first}:")));
+            }
+            Err(err) => panic!("expected drift, got {err}"),
+            Ok(_) => panic!("a projected schema matched the full one"),
+        }
+        Ok(())
+    }
+}
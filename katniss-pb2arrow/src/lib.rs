@@ -1,18 +1,29 @@
 //! Convert Protobuf schema and message into Apache Arrow Schema and Tables.
 //!
-//!
+//! Without the default "protoc" feature nothing shells out or touches a temp dir, so it
+//! builds for wasm32, schemas have to come from FileDescriptorSet bytes instead
 
 mod errors;
+mod fingerprint;
+mod message_conversion;
 mod record_conversion;
+mod round_trip;
 mod schema_conversion;
+#[cfg(feature = "protoc")]
+mod well_known_types;
 
+use std::path::Path;
 use std::sync::Arc;
 
 use arrow_schema::{Schema, SchemaRef};
 use prost_reflect::{DescriptorPool, MessageDescriptor};
 
 pub use errors::{KatnissArrowError, Result};
-pub use record_conversion::RecordConverter;
+pub use fingerprint::SchemaFingerprint;
+pub use message_conversion::{ArrowToProtoConverter, FieldMismatch};
+pub use record_conversion::{RecordConverter, ValidationReport};
+use round_trip::round_trip_schema;
+pub use round_trip::{verify_round_trip, FIELD_NUMBER_KEY, PROTO_TYPE_KEY, RESIDUAL_COLUMN};
 use schema_conversion::DictValuesContainer;
 pub use schema_conversion::SchemaConverter;
 
@@ -32,14 +43,113 @@ pub struct ArrowBatchProps {
     pub dictionaries: Arc<DictValuesContainer>,
     pub descriptor: MessageDescriptor,
     pub records_per_arrow_batch: usize,
+    /// The schema these were built with has to match it, see with_expected_fingerprint
+    pub expected_fingerprint: Option<SchemaFingerprint>,
+    pub conversion_mode: ConversionMode,
+}
+
+/// What RecordConverter does with values it can't convert, i.e. a string in a double field
+/// or an enum number the descriptor doesn't know
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum ConversionMode {
+    /// Fail the append
+    #[default]
+    Strict,
+    /// Append null instead and keep the error, see RecordConverter::take_warnings
+    Lenient,
+    /// Fail like Strict, except enum numbers the descriptor doesn't know are null in their
+    /// column. The schema gets RESIDUAL_COLUMN and each field's number and proto type in its
+    /// metadata so ArrowToProtoConverter gives back the exact message, see verify_round_trip
+    RoundTrip,
+}
+
+/// Everything that goes into ArrowBatchProps besides the descriptors, so it can be loaded from
+/// a config file (with the "serde" feature) and logged or diffed, i.e.
+/// `{"message": "spacecorp.Packet", "conversion_mode": "lenient"}`
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ArrowBatchConfig {
+    /// Full name of the message, or a short name see SchemaConverter::resolve_message
+    pub message: String,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub projection: Vec<String>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default = "ArrowBatchConfig::default_records_per_arrow_batch")
+    )]
+    pub records_per_arrow_batch: usize,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub conversion_mode: ConversionMode,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub expected_fingerprint: Option<SchemaFingerprint>,
+}
+
+impl ArrowBatchConfig {
+    pub fn new<M: Into<String>>(message: M) -> Self {
+        Self {
+            message: message.into(),
+            projection: Vec::new(),
+            records_per_arrow_batch: Self::default_records_per_arrow_batch(),
+            conversion_mode: ConversionMode::default(),
+            expected_fingerprint: None,
+        }
+    }
+
+    fn default_records_per_arrow_batch() -> usize {
+        1024
+    }
+
+    /// Props for the message in the pool, fails like with_expected_fingerprint if it's pinned
+    pub fn props(&self, pool: DescriptorPool) -> Result<ArrowBatchProps> {
+        let converter = SchemaConverter::new(pool);
+        let message = converter.resolve_message(&self.message)?;
+        let projection: Vec<&str> = self.projection.iter().map(String::as_str).collect();
+
+        let props = ArrowBatchProps::try_new_projected(
+            converter.descriptor_pool,
+            message.full_name().to_owned(),
+            &projection,
+        )?
+        .with_records_per_arrow_batch(self.records_per_arrow_batch)
+        .with_conversion_mode(self.conversion_mode);
+        match &self.expected_fingerprint {
+            Some(expected) => props.with_expected_fingerprint(expected.clone()),
+            None => Ok(props),
+        }
+    }
 }
 
 impl ArrowBatchProps {
     pub fn try_new(pool: DescriptorPool, msg_name: String) -> Result<Self> {
+        Self::try_new_projected(pool, msg_name, &[])
+    }
+
+    /// For a message in a serialized FileDescriptorSet, see SchemaConverter::from_descriptor_set_bytes
+    pub fn from_descriptor_set_bytes(bytes: &[u8], msg_name: String) -> Result<Self> {
+        Self::try_new(DescriptorPool::decode(bytes)?, msg_name)
+    }
+
+    /// For a message in a FileDescriptorSet file, i.e. a pre-built file_descriptor_set.bin
+    pub fn from_descriptor_set_file(path: impl AsRef<Path>, msg_name: String) -> Result<Self> {
+        Self::from_descriptor_set_bytes(&std::fs::read(path)?, msg_name)
+    }
+
+    /// Only the projected fields (dot separated for nested fields) become columns,
+    /// an empty projection keeps every field
+    pub fn try_new_projected(
+        pool: DescriptorPool,
+        msg_name: String,
+        projection: &[&str],
+    ) -> Result<Self> {
         let converter: SchemaConverter = SchemaConverter::new(pool);
 
         let (schema_opt, dictionaries_opt) =
-            converter.get_arrow_schema_with_dictionaries(&msg_name, &[])?;
+            converter.get_arrow_schema_with_dictionaries(&msg_name, projection)?;
 
         let schema = SchemaRef::new(
             schema_opt.ok_or_else(|| KatnissArrowError::DescriptorNotFound(msg_name.to_owned()))?,
@@ -55,7 +165,9 @@ impl ArrowBatchProps {
             schema,
             dictionaries,
             descriptor,
-            records_per_arrow_batch: 1024,
+            records_per_arrow_batch: ArrowBatchConfig::default_records_per_arrow_batch(),
+            expected_fingerprint: None,
+            conversion_mode: ConversionMode::Strict,
         })
     }
 
@@ -63,18 +175,64 @@ impl ArrowBatchProps {
         self.records_per_arrow_batch = size;
         self
     }
+
+    /// Switching to or from RoundTrip changes the schema, so pin a fingerprint after it
+    pub fn with_conversion_mode(mut self, mode: ConversionMode) -> Self {
+        if mode == ConversionMode::RoundTrip || self.conversion_mode == ConversionMode::RoundTrip {
+            let descriptor = (mode == ConversionMode::RoundTrip).then_some(&self.descriptor);
+            self.schema = Arc::new(round_trip_schema(&self.schema, descriptor));
+        }
+        self.conversion_mode = mode;
+        self
+    }
+
+    /// Pins the shape of the schema, fails with SchemaDrift and the differing columns if the
+    /// descriptors resolved to something else, i.e. a field was added to the .proto.
+    /// Pipelines keep the pin for reloads that don't bring their own
+    pub fn with_expected_fingerprint(mut self, expected: SchemaFingerprint) -> Result<Self> {
+        self.expected_fingerprint = Some(expected);
+        self.check_fingerprint()?;
+        Ok(self)
+    }
+
+    /// Ok if there's no expected fingerprint, see with_expected_fingerprint
+    pub fn check_fingerprint(&self) -> Result<()> {
+        let Some(expected) = &self.expected_fingerprint else {
+            return Ok(());
+        };
+        let actual = SchemaFingerprint::of(&self.schema);
+        if &actual == expected {
+            return Ok(());
+        }
+        Err(KatnissArrowError::SchemaDrift(
+            expected.diff(&actual).join(", "),
+        ))
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::path::PathBuf;
-
     use arrow_schema::{DataType, Field, Schema, SchemaRef};
+    use prost_reflect::{DynamicMessage, Value};
 
     use super::*;
 
+    /// Messages for these tests are in protos/test/fixtures.proto
+    const FIXTURES: &str = "eto.pb2arrow.tests.fixtures";
+
+    /// Everything katniss-test compiles from protos/test
+    fn fixtures() -> SchemaConverter {
+        SchemaConverter::from_descriptor_set_bytes(katniss_test::protos::FILE_DESCRIPTOR_BYTES)
+            .expect("katniss-test's descriptor set")
+    }
+
+    fn fixture_props(message: &str) -> Result<ArrowBatchProps> {
+        ArrowBatchProps::try_new(fixtures().descriptor_pool, format!("{FIXTURES}.{message}"))
+    }
+
+    #[cfg(feature = "protoc")]
     fn converter_for(proto_file: &str) -> SchemaConverter {
-        let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let mut d = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
         d.push("../protos/test");
 
         let proto = d.join(proto_file);
@@ -82,6 +240,215 @@ mod tests {
             .unwrap_or_else(|_| panic!("Failed to compile {proto_file}"))
     }
 
+    /// A converter with just the file, i.e. after changing it so it conflicts with the original
+    fn converter_with(
+        file: prost_reflect::prost_types::FileDescriptorProto,
+    ) -> Result<SchemaConverter> {
+        let mut pool = DescriptorPool::new();
+        pool.add_file_descriptor_proto(file)?;
+        Ok(SchemaConverter::new(pool))
+    }
+
+    #[cfg(feature = "protoc")]
+    #[test]
+    fn protoc_errors_are_returned() {
+        let mut d = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("../protos/test");
+
+        let missing = d.join("missing.proto");
+        match SchemaConverter::compile(&[missing], &[d]) {
+            Err(KatnissArrowError::ProtocFailed(stderr)) => {
+                assert!(stderr.contains("missing.proto"))
+            }
+            Err(err) => panic!("expected protoc to fail, got {err}"),
+            Ok(_) => panic!("compiled a file that doesn't exist"),
+        }
+    }
+
+    #[cfg(feature = "protoc")]
+    #[test]
+    fn well_known_types_can_be_imported() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let proto = dir.path().join("reading.proto");
+        std::fs::write(
+            &proto,
+            "syntax = \"proto3\";\n\
+             import \"google/protobuf/timestamp.proto\";\n\
+             message Reading { google.protobuf.Timestamp at = 1; }\n",
+        )?;
+
+        let converter = SchemaConverter::compile(&[proto], &[dir.path()])?;
+        let schema = converter
+            .get_arrow_schema("Reading", &[])?
+            .expect("compiled Reading");
+        assert!(matches!(
+            schema.field_with_name("at").unwrap().data_type(),
+            DataType::Struct(fields) if fields.len() == 2
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn it_builds_from_descriptor_sets() -> Result<()> {
+        let bytes = katniss_test::protos::FILE_DESCRIPTOR_BYTES;
+        let packet = "eto.pb2arrow.tests.spacecorp.Packet".to_owned();
+
+        let converter = SchemaConverter::from_descriptor_set_bytes(bytes)?;
+        assert!(converter.get_arrow_schema(&packet, &[])?.is_some());
+
+        let mut file = tempfile::NamedTempFile::new()?;
+        std::io::Write::write_all(&mut file, bytes)?;
+        let props = ArrowBatchProps::from_descriptor_set_file(file.path(), packet.clone())?;
+        assert_eq!(props.descriptor.full_name(), packet);
+
+        assert!(matches!(
+            ArrowBatchProps::from_descriptor_set_bytes(&[0xff], packet),
+            Err(KatnissArrowError::DescriptorError(_))
+        ));
+        Ok(())
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn props_can_be_configured_from_json() -> anyhow::Result<()> {
+        let pool = DescriptorPool::decode(katniss_test::protos::FILE_DESCRIPTOR_BYTES)?;
+        let json = r#"{"message": "spacecorp.Packet", "projection": ["sender_uid"],
+                       "conversion_mode": "lenient"}"#;
+        let config: ArrowBatchConfig = serde_json::from_str(json)?;
+        assert_eq!(config.records_per_arrow_batch, 1024);
+
+        let props = config.props(pool.clone())?;
+        assert_eq!(
+            props.descriptor.full_name(),
+            "eto.pb2arrow.tests.spacecorp.Packet"
+        );
+        assert_eq!(props.schema.fields().len(), 1);
+        assert_eq!(props.conversion_mode, ConversionMode::Lenient);
+
+        let pinned = ArrowBatchConfig {
+            expected_fingerprint: Some(SchemaFingerprint::of(&props.schema)),
+            ..config
+        };
+        let round_tripped: ArrowBatchConfig =
+            serde_json::from_str(&serde_json::to_string(&pinned)?)?;
+        assert_eq!(round_tripped, pinned);
+
+        let drifted = ArrowBatchConfig {
+            projection: Vec::new(),
+            ..round_tripped
+        };
+        assert!(matches!(
+            drifted.props(pool),
+            Err(KatnissArrowError::SchemaDrift(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn descriptor_sources_can_be_merged() -> Result<()> {
+        let version_3 = fixtures()
+            .descriptor_pool
+            .get_file_by_name("version_3.proto")
+            .expect("compiled by katniss-test")
+            .file_descriptor_proto()
+            .clone();
+        // only the comments and line numbers differ
+        let mut recompiled = version_3.clone();
+        recompiled.source_code_info = Some(Default::default());
+        let merged = SchemaConverter::merge([
+            fixtures(),
+            converter_with(version_3.clone())?,
+            converter_with(recompiled)?,
+        ])?;
+        assert!(merged
+            .get_message_by_name("eto.pb2arrow.tests.spacecorp.Packet")
+            .is_ok());
+        assert!(merged
+            .get_message_by_name("eto.pb2arrow.tests.v3.Foo")
+            .is_ok());
+
+        let mut changed = version_3.clone();
+        changed.message_type[0].field[0].r#type =
+            Some(prost_reflect::prost_types::field_descriptor_proto::Type::Int64 as i32);
+        assert!(matches!(
+            SchemaConverter::merge([fixtures(), converter_with(changed)?]),
+            Err(KatnissArrowError::ConflictingFile(file)) if file == "version_3.proto"
+        ));
+
+        let mut moved = version_3;
+        moved.name = Some("moved.proto".to_owned());
+        assert!(matches!(
+            SchemaConverter::merge([fixtures(), converter_with(moved)?]),
+            Err(KatnissArrowError::DescriptorError(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn descriptors_can_be_explored() -> Result<()> {
+        // protos/test/acme.proto
+        let converter = fixtures();
+
+        let names = |messages: Vec<MessageDescriptor>| -> Vec<String> {
+            messages.iter().map(|m| m.full_name().to_owned()).collect()
+        };
+        assert_eq!(
+            names(converter.messages_in_package("acme")),
+            ["acme.telemetry.Reading", "acme.telemetry.User"]
+        );
+        assert!(converter.messages_in_package("acme.tele").is_empty());
+        assert!(converter.messages().len() > 2);
+        assert_eq!(converter.enums_in_package("acme.telemetry").len(), 1);
+
+        let sensitive = converter.fields_with_option("acme.telemetry.sensitive")?;
+        assert_eq!(sensitive.len(), 1);
+        assert_eq!(sensitive[0].0.full_name(), "acme.telemetry.User.email");
+        assert_eq!(sensitive[0].1, prost_reflect::Value::Bool(true));
+
+        assert!(matches!(
+            converter.fields_with_option("acme.missing"),
+            Err(KatnissArrowError::DescriptorNotFound(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn short_names_must_be_unambiguous() -> Result<()> {
+        let converter = fixtures();
+
+        match converter.resolve_message("Bar") {
+            Err(KatnissArrowError::AmbiguousName(name, candidates)) => {
+                assert_eq!(name, "Bar");
+                assert_eq!(
+                    candidates,
+                    ["eto.pb2arrow.tests.v2.Bar", "eto.pb2arrow.tests.v3.Bar"]
+                );
+            }
+            Err(err) => panic!("expected an ambiguous name, got {err}"),
+            Ok(msg) => panic!("picked {} out of two Bars", msg.full_name()),
+        }
+
+        let resolved = |msg: Result<MessageDescriptor>| msg.map(|m| m.full_name().to_owned());
+        assert_eq!(
+            resolved(converter.resolve_message("Foo"))?,
+            "eto.pb2arrow.tests.v3.Foo"
+        );
+        assert_eq!(
+            resolved(converter.resolve_message("v2.Bar"))?,
+            "eto.pb2arrow.tests.v2.Bar"
+        );
+        assert_eq!(
+            resolved(converter.resolve_message_in_package("eto.pb2arrow.tests.v2", "Bar"))?,
+            "eto.pb2arrow.tests.v2.Bar"
+        );
+        assert!(matches!(
+            converter.resolve_message_in_package("eto.pb2arrow.tests.v2", "Foo"),
+            Err(KatnissArrowError::DescriptorNotFound(_))
+        ));
+        Ok(())
+    }
+
+    #[cfg(feature = "protoc")]
     #[test]
     fn test_load_protobuf() {
         let converter = converter_for("version_3.proto");
@@ -96,6 +463,7 @@ mod tests {
         assert_eq!(schema, expected_schema);
     }
 
+    #[cfg(feature = "protoc")]
     #[test]
     fn test_enum() -> Result<()> {
         let converter = converter_for("version_3.proto");
@@ -124,6 +492,248 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn empty_clones_share_the_schema() -> Result<()> {
+        let converter = fixtures();
+        let props = ArrowBatchProps::try_new(
+            converter.descriptor_pool,
+            "eto.pb2arrow.tests.v3.Foo".to_string(),
+        )?;
+        let mut records = RecordConverter::try_new(&props)?;
+        records.append_message(&DynamicMessage::new(props.descriptor.clone()))?;
+
+        let mut clone = records.try_clone_empty()?;
+        assert!(clone.is_empty());
+        assert!(Arc::ptr_eq(clone.schema(), records.schema()));
+        assert_eq!(records.records()?.num_rows(), 1);
+        assert_eq!(clone.records()?.schema(), props.schema);
+        Ok(())
+    }
+
+    #[test]
+    fn repeated_enums_are_converted() -> Result<()> {
+        let props = fixture_props("ModesHolder")?;
+
+        let modes_name = format!("{FIXTURES}.Modes");
+        let mut inner = DynamicMessage::new(fixtures().get_message_by_name(&modes_name)?);
+        let modes = vec![
+            Value::EnumNumber(1),
+            Value::EnumNumber(0),
+            Value::EnumNumber(7),
+        ];
+        inner.set_field_by_name("modes", Value::List(modes));
+        let mut outer = DynamicMessage::new(props.descriptor.clone());
+        outer.set_field_by_name("inner", Value::Message(inner));
+
+        assert!(matches!(
+            RecordConverter::try_new(&props)?.append_message(&outer),
+            Err(KatnissArrowError::NoEnumValue(path, 7)) if path == "inner.modes[2]"
+        ));
+
+        let props = props.with_conversion_mode(ConversionMode::Lenient);
+        let mut records = RecordConverter::try_new(&props)?;
+        records.append_message(&outer)?;
+        // a missing inner message has no list to look up enum names for
+        records.append_message(&DynamicMessage::new(props.descriptor.clone()))?;
+        assert_eq!(records.records()?.num_rows(), 2);
+        assert_eq!(records.take_warnings().len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn repeated_numbers_are_appended_in_bulk() -> anyhow::Result<()> {
+        use arrow_array::{cast::AsArray, types::Float32Type};
+
+        let props = fixture_props("Cloud")?;
+
+        let cloud = |xs: Vec<Value>| {
+            let mut cloud = DynamicMessage::new(props.descriptor.clone());
+            cloud.set_field_by_name("xs", Value::List(xs));
+            cloud.set_field_by_name("ids", Value::List(vec![Value::I64(-1), Value::I64(2)]));
+            cloud
+        };
+        let mut records = RecordConverter::try_new(&props)?;
+        records.append_message(&cloud(vec![Value::F32(0.5), Value::F32(1.5)]))?;
+        records.append_message(&cloud(vec![]))?;
+        let batch = records.records()?;
+        let xs = batch.column(0).as_list::<i32>();
+        assert_eq!(xs.value_offsets(), [0, 2, 2]);
+        let values = xs.values().as_primitive::<Float32Type>();
+        assert_eq!(&values.values()[..], [0.5, 1.5]);
+        assert_eq!(batch.column(1).as_list::<i32>().values().len(), 4);
+
+        // a value of the wrong type still fails with its index
+        let mixed = cloud(vec![Value::F32(0.5), Value::String("far".into())]);
+        assert!(matches!(
+            records.append_message(&mixed),
+            Err(KatnissArrowError::TypeCastError(path, _)) if path == "xs[1]"
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn repeated_values_are_appended_in_place() -> anyhow::Result<()> {
+        use arrow_array::{cast::AsArray, types::Int32Type};
+
+        let props = fixture_props("Tags")?.with_conversion_mode(ConversionMode::Lenient);
+
+        let mut records = RecordConverter::try_new(&props)?;
+        let mut tags = DynamicMessage::new(props.descriptor.clone());
+        let mixed = vec![
+            Value::String("a".into()),
+            Value::I32(1),
+            Value::String("c".into()),
+        ];
+        tags.set_field_by_name("tags", Value::List(mixed));
+        records.append_message(&tags)?;
+        let mut counted = DynamicMessage::new(props.descriptor.clone());
+        counted.set_field_by_name("count", Value::I32(0));
+        records.append_message(&counted)?;
+
+        let batch = records.records()?;
+        let lists = batch.column(0).as_list::<i32>();
+        assert_eq!(lists.value_offsets(), [0, 3, 3]);
+        let values = lists.values().as_string::<i32>();
+        assert_eq!(
+            values.iter().collect::<Vec<_>>(),
+            [Some("a"), None, Some("c")]
+        );
+        let counts = batch.column(1).as_primitive::<Int32Type>();
+        assert_eq!(counts.iter().collect::<Vec<_>>(), [None, Some(0)]);
+        assert!(matches!(
+            records.take_warnings().as_slice(),
+            [KatnissArrowError::TypeCastError(path, _)] if path == "tags[1]"
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn conversion_errors_have_field_paths() -> Result<()> {
+        let props = fixture_props("Climate")?;
+
+        // messages decoded with a descriptor that doesn't match the props
+        let actual = fixtures();
+        let message = |name: &str| -> Result<DynamicMessage> {
+            let name = format!("{FIXTURES}.Mislabeled{name}");
+            Ok(DynamicMessage::new(actual.get_message_by_name(&name)?))
+        };
+        let mut readings = vec![];
+        for _ in 0..4 {
+            let mut reading = message("Reading")?;
+            reading.set_field_by_name("temp_c", Value::String("warm".into()));
+            readings.push(Value::Message(reading));
+        }
+        let mut status = message("Status")?;
+        status.set_field_by_name("readings", Value::List(readings));
+        let mut packet = message("Climate")?;
+        packet.set_field_by_name("climate_status", Value::Message(status));
+
+        let mut records = RecordConverter::try_new(&props)?;
+        match records.append_message(&packet) {
+            Err(KatnissArrowError::TypeCastError(path, _)) => {
+                assert_eq!(path, "climate_status.readings[0].temp_c")
+            }
+            Err(err) => panic!("expected a cast error, got {err}"),
+            Ok(()) => panic!("appended a string to a double column"),
+        }
+
+        let props = props.with_conversion_mode(ConversionMode::Lenient);
+        let mut records = RecordConverter::try_new(&props)?;
+        records.append_message(&packet)?;
+        let batch = records.records()?;
+        assert_eq!(batch.num_rows(), 1);
+        assert_eq!(records.take_warnings().len(), 4);
+        assert!(records.take_warnings().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn round_trip_mode_keeps_what_columns_cant_hold() -> anyhow::Result<()> {
+        use katniss_test::protos::spacecorp::{
+            packet, JumpDriveStatus, Packet, QuantumSpaceTimeReading,
+        };
+        use prost_reflect::prost::Message;
+
+        let packet = Packet {
+            sender_uid: 7,
+            msg: Some(packet::Msg::JumpDriveStatus(JumpDriveStatus {
+                mode: 42,
+                history: vec![QuantumSpaceTimeReading {
+                    vxs: vec![1.5],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+        let mut bytes = packet.encode_to_vec();
+        // field 999 = 5, which Packet doesn't have
+        bytes.extend([0xb8, 0x3e, 0x05]);
+
+        let pool = DescriptorPool::decode(katniss_test::protos::FILE_DESCRIPTOR_BYTES)?;
+        let name = "eto.pb2arrow.tests.spacecorp.Packet".to_owned();
+        let descriptor = pool.get_message_by_name(&name).unwrap();
+        let messages = [
+            DynamicMessage::decode(descriptor.clone(), bytes.as_slice())?,
+            DynamicMessage::new(descriptor),
+        ];
+
+        let props = ArrowBatchProps::try_new(pool.clone(), name.clone())?;
+        assert!(matches!(
+            verify_round_trip(&props, &messages),
+            Err(KatnissArrowError::NoEnumValue(_, 42))
+        ));
+        let props = props.with_conversion_mode(ConversionMode::Lenient);
+        assert!(matches!(
+            verify_round_trip(&props, &messages),
+            Err(KatnissArrowError::RoundTripMismatch(0, fields)) if fields == ["jump_drive_status"]
+        ));
+
+        let props = props.with_conversion_mode(ConversionMode::RoundTrip);
+        verify_round_trip(&props, &messages)?;
+        let sender_uid = props.schema.field_with_name("sender_uid")?;
+        assert_eq!(sender_uid.metadata()[FIELD_NUMBER_KEY], "2");
+        assert_eq!(sender_uid.metadata()[PROTO_TYPE_KEY], "uint64");
+        assert!(props.schema.field_with_name(RESIDUAL_COLUMN).is_ok());
+
+        // fields outside the projection go in the residual too
+        let projected = ArrowBatchProps::try_new_projected(pool, name, &["sender_uid"])?
+            .with_conversion_mode(ConversionMode::RoundTrip);
+        verify_round_trip(&projected, &messages)?;
+
+        let strict = props.with_conversion_mode(ConversionMode::Strict);
+        assert!(strict.schema.field_with_name(RESIDUAL_COLUMN).is_err());
+        assert!(strict
+            .schema
+            .field_with_name("sender_uid")?
+            .metadata()
+            .is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn messages_can_be_validated_before_appending() -> Result<()> {
+        let props = fixture_props("Reading")?;
+        let records = RecordConverter::try_new(&props)?;
+
+        let mut reading = DynamicMessage::new(props.descriptor.clone());
+        reading.set_field_by_name("temp_c", Value::F64(21.5));
+        assert!(records.validate(&reading).is_valid());
+
+        let mislabeled = format!("{FIXTURES}.MislabeledReading");
+        let mut reading = DynamicMessage::new(fixtures().get_message_by_name(&mislabeled)?);
+        reading.set_field_by_name("temp_c", Value::String("warm".into()));
+        reading.set_field_by_name("mode", Value::EnumNumber(9));
+        let report = records.validate(&reading);
+        assert_eq!(report.errors().len(), 2);
+        assert!(matches!(
+            report.into_result(),
+            Err(KatnissArrowError::TypeCastError(path, _)) if path == "temp_c"
+        ));
+        assert!(records.is_empty());
+        Ok(())
+    }
+
     #[test]
     fn test_read_messages() {
         // _run_messages_test(2, "version_2.proto", "eto.pb2arrow.tests.v2.Bar");
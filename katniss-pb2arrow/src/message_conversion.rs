@@ -0,0 +1,463 @@
+use arrow_array::{
+    downcast_dictionary_array, Array, ArrayRef, BinaryArray, BooleanArray, Float32Array,
+    Float64Array, GenericListArray, Int32Array, Int64Array, LargeBinaryArray, LargeStringArray,
+    OffsetSizeTrait, RecordBatch, StringArray, StructArray, UInt32Array, UInt64Array,
+};
+use std::fmt;
+
+use arrow_schema::{DataType, FieldRef, Schema};
+use prost_reflect::{
+    prost::bytes::Bytes, DynamicMessage, FieldDescriptor, Kind, MessageDescriptor, Value,
+};
+
+use crate::round_trip::{restore, split_residual};
+use crate::{KatnissArrowError, Result};
+
+/// Converts record batches back into protobuf messages, the reverse of RecordConverter
+/// Columns are matched to fields by name, fields without a column are left unset
+/// so batches of projected columns convert too. Enum columns can be dictionaries with any key
+/// type (what other parquet writers tend to pick), plain strings or int32 enum numbers.
+/// A RESIDUAL_COLUMN is put back onto each row's message
+#[derive(Debug, Clone)]
+pub struct ArrowToProtoConverter {
+    descriptor: MessageDescriptor,
+}
+
+impl ArrowToProtoConverter {
+    pub fn new(descriptor: MessageDescriptor) -> Self {
+        Self { descriptor }
+    }
+
+    pub fn descriptor(&self) -> &MessageDescriptor {
+        &self.descriptor
+    }
+
+    /// Every column the converter couldn't read its field from, empty if the whole schema
+    /// converts. Checked against the descriptor rather than the schema it converts to, so
+    /// columns only need to be something messages() reads, i.e. LargeUtf8 for a string field
+    pub fn schema_mismatches(&self, schema: &Schema) -> Vec<FieldMismatch> {
+        let mut mismatches = Vec::new();
+        let (columns, _) = split_residual(schema.fields());
+        check_fields(&self.descriptor, columns, "", &mut mismatches);
+        mismatches
+    }
+
+    /// Errors with every mismatch, see schema_mismatches
+    pub fn check_schema(&self, schema: &Schema) -> Result<()> {
+        let mismatches = self.schema_mismatches(schema);
+        if !mismatches.is_empty() {
+            return Err(KatnissArrowError::SchemaMismatch(mismatches));
+        }
+        Ok(())
+    }
+
+    /// One message per row
+    pub fn messages(&self, batch: &RecordBatch) -> Result<Vec<DynamicMessage>> {
+        let schema = batch.schema();
+        let (fields, has_residual) = split_residual(schema.fields());
+        let columns: Vec<_> = fields
+            .iter()
+            .map(|f| f.name().as_str())
+            .zip(batch.columns())
+            .collect();
+        let residuals = has_residual.then(|| batch.column(fields.len()));
+
+        (0..batch.num_rows())
+            .map(|row| {
+                let mut msg = message_at(&self.descriptor, &columns, row)?;
+                if let Some(residuals) = residuals {
+                    restore(&mut msg, residuals, row)?;
+                }
+                Ok(msg)
+            })
+            .collect()
+    }
+}
+
+/// A column of a batch that doesn't line up with the message, paths are dot separated
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FieldMismatch {
+    /// The message has no field with the column's name
+    UnknownField(String),
+    /// The column's type can't hold the field's values, i.e. utf8 for an int32 field
+    WrongType {
+        path: String,
+        field_type: String,
+        column_type: DataType,
+    },
+}
+
+impl fmt::Display for FieldMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownField(path) => write!(f, "{path} isn't a field of the message"),
+            Self::WrongType {
+                path,
+                field_type,
+                column_type,
+            } => write!(f, "{path} is {field_type} but its column is {column_type}"),
+        }
+    }
+}
+
+fn check_fields(
+    descriptor: &MessageDescriptor,
+    fields: &[FieldRef],
+    parent: &str,
+    mismatches: &mut Vec<FieldMismatch>,
+) {
+    for column in fields {
+        let path = match parent {
+            "" => column.name().to_owned(),
+            parent => format!("{parent}.{}", column.name()),
+        };
+        let Some(field) = descriptor.get_field_by_name(column.name()) else {
+            mismatches.push(FieldMismatch::UnknownField(path));
+            continue;
+        };
+
+        let data_type = match (field.is_list(), column.data_type()) {
+            (true, DataType::List(item) | DataType::LargeList(item)) => item.data_type(),
+            (true, _) => &DataType::Null,
+            (false, data_type) => data_type,
+        };
+        if !kind_fits(&field.kind(), data_type, &path, mismatches) {
+            mismatches.push(FieldMismatch::WrongType {
+                path,
+                field_type: field_type(&field),
+                column_type: column.data_type().clone(),
+            });
+        }
+    }
+}
+
+/// Whether kind_value can read the kind from an array of the data type, structs are checked
+/// field by field
+fn kind_fits(
+    kind: &Kind,
+    data_type: &DataType,
+    path: &str,
+    mismatches: &mut Vec<FieldMismatch>,
+) -> bool {
+    match (kind, data_type) {
+        (Kind::Double, DataType::Float64)
+        | (Kind::Float, DataType::Float32)
+        | (Kind::Int32 | Kind::Sint32 | Kind::Sfixed32, DataType::Int32)
+        | (Kind::Int64 | Kind::Sint64 | Kind::Sfixed64, DataType::Int64)
+        | (Kind::Uint32 | Kind::Fixed32, DataType::UInt32)
+        | (Kind::Uint64 | Kind::Fixed64, DataType::UInt64)
+        | (Kind::Bool, DataType::Boolean)
+        | (Kind::String, DataType::Utf8 | DataType::LargeUtf8)
+        | (Kind::Bytes, DataType::Binary | DataType::LargeBinary)
+        | (Kind::Enum(_), DataType::Int32 | DataType::Utf8 | DataType::LargeUtf8) => true,
+        (Kind::Enum(_), DataType::Dictionary(_, values)) => {
+            matches!(values.as_ref(), DataType::Utf8 | DataType::LargeUtf8)
+        }
+        (Kind::Message(descriptor), DataType::Boolean) => descriptor.fields().len() == 0,
+        (Kind::Message(descriptor), DataType::Struct(fields)) => {
+            check_fields(descriptor, fields, path, mismatches);
+            true
+        }
+        _ => false,
+    }
+}
+
+/// i.e. "repeated int32" or "eto.pb2arrow.tests.spacecorp.JumpDriveMode"
+pub(crate) fn field_type(field: &FieldDescriptor) -> String {
+    let kind = match field.kind() {
+        Kind::Message(descriptor) => descriptor.full_name().to_owned(),
+        Kind::Enum(descriptor) => descriptor.full_name().to_owned(),
+        kind => format!("{kind:?}").to_lowercase(),
+    };
+    match field.is_list() {
+        true => format!("repeated {kind}"),
+        false => kind,
+    }
+}
+
+fn message_at(
+    descriptor: &MessageDescriptor,
+    columns: &[(&str, &ArrayRef)],
+    row: usize,
+) -> Result<DynamicMessage> {
+    let mut msg = DynamicMessage::new(descriptor.clone());
+    for (name, column) in columns {
+        let field = descriptor
+            .get_field_by_name(name)
+            .ok_or_else(|| KatnissArrowError::DescriptorNotFound((*name).to_owned()))?;
+
+        if let Some(value) = field_value(&field, column, row)? {
+            msg.set_field(&field, value);
+        }
+    }
+    Ok(msg)
+}
+
+/// None if the field is null in this row
+fn field_value(field: &FieldDescriptor, column: &ArrayRef, row: usize) -> Result<Option<Value>> {
+    if column.is_null(row) {
+        return Ok(None);
+    }
+    if !field.is_list() {
+        return kind_value(field, &field.kind(), column, row);
+    }
+
+    let items = match column.data_type() {
+        DataType::List(_) => list_items::<i32>(field, column, row)?,
+        DataType::LargeList(_) => list_items::<i64>(field, column, row)?,
+        _ => return Err(mismatch(field, column)),
+    };
+    Ok(Some(Value::List(items)))
+}
+
+fn list_items<O: OffsetSizeTrait>(
+    field: &FieldDescriptor,
+    column: &ArrayRef,
+    row: usize,
+) -> Result<Vec<Value>> {
+    let items = downcast::<GenericListArray<O>>(field, column)?.value(row);
+    let kind = field.kind();
+
+    let mut values = Vec::with_capacity(items.len());
+    for i in 0..items.len() {
+        // repeated fields can't hold nulls, the closest thing is leaving them out
+        if let Some(value) = kind_value(field, &kind, &items, i)? {
+            values.push(value);
+        }
+    }
+    Ok(values)
+}
+
+fn kind_value(
+    field: &FieldDescriptor,
+    kind: &Kind,
+    array: &ArrayRef,
+    i: usize,
+) -> Result<Option<Value>> {
+    if array.is_null(i) {
+        return Ok(None);
+    }
+
+    let value = match kind {
+        Kind::Double => Value::F64(downcast::<Float64Array>(field, array)?.value(i)),
+        Kind::Float => Value::F32(downcast::<Float32Array>(field, array)?.value(i)),
+        Kind::Int32 | Kind::Sint32 | Kind::Sfixed32 => {
+            Value::I32(downcast::<Int32Array>(field, array)?.value(i))
+        }
+        Kind::Int64 | Kind::Sint64 | Kind::Sfixed64 => {
+            Value::I64(downcast::<Int64Array>(field, array)?.value(i))
+        }
+        Kind::Uint32 | Kind::Fixed32 => Value::U32(downcast::<UInt32Array>(field, array)?.value(i)),
+        Kind::Uint64 | Kind::Fixed64 => Value::U64(downcast::<UInt64Array>(field, array)?.value(i)),
+        Kind::Bool => Value::Bool(downcast::<BooleanArray>(field, array)?.value(i)),
+        Kind::String => Value::String(string_at(field, array, i)?.to_owned()),
+        Kind::Bytes => {
+            let bytes = match array.data_type() {
+                DataType::LargeBinary => downcast::<LargeBinaryArray>(field, array)?.value(i),
+                _ => downcast::<BinaryArray>(field, array)?.value(i),
+            };
+            Value::Bytes(Bytes::copy_from_slice(bytes))
+        }
+        Kind::Enum(enum_descriptor) => {
+            let name = downcast_dictionary_array!(
+                array => match array.key(i) {
+                    Some(key) => string_at(field, array.values(), key)?,
+                    None => return Ok(None),
+                },
+                // numbers aren't checked against the descriptor, proto3 enums are open
+                DataType::Int32 => {
+                    let number = downcast::<Int32Array>(field, array)?.value(i);
+                    return Ok(Some(Value::EnumNumber(number)));
+                }
+                _ => string_at(field, array, i)?
+            );
+            let value = enum_descriptor
+                .get_value_by_name(name)
+                .ok_or_else(|| KatnissArrowError::NoEnumName(name.to_owned()))?;
+            Value::EnumNumber(value.number())
+        }
+        // unit messages are stored as a bool of whether they're set
+        Kind::Message(descriptor) if descriptor.fields().len() == 0 => {
+            if !downcast::<BooleanArray>(field, array)?.value(i) {
+                return Ok(None);
+            }
+            Value::Message(DynamicMessage::new(descriptor.clone()))
+        }
+        Kind::Message(descriptor) => {
+            let DataType::Struct(fields) = array.data_type() else {
+                return Err(mismatch(field, array));
+            };
+            let array = downcast::<StructArray>(field, array)?;
+            let columns: Vec<_> = fields
+                .iter()
+                .enumerate()
+                .map(|(c, f)| (f.name().as_str(), array.column(c)))
+                .collect();
+            Value::Message(message_at(descriptor, &columns, i)?)
+        }
+    };
+    Ok(Some(value))
+}
+
+fn string_at<'a>(field: &FieldDescriptor, array: &'a ArrayRef, i: usize) -> Result<&'a str> {
+    Ok(match array.data_type() {
+        DataType::LargeUtf8 => downcast::<LargeStringArray>(field, array)?.value(i),
+        _ => downcast::<StringArray>(field, array)?.value(i),
+    })
+}
+
+fn downcast<'a, A: Array + 'static>(field: &FieldDescriptor, array: &'a ArrayRef) -> Result<&'a A> {
+    array
+        .as_any()
+        .downcast_ref::<A>()
+        .ok_or_else(|| mismatch(field, array))
+}
+
+fn mismatch(field: &FieldDescriptor, array: &ArrayRef) -> KatnissArrowError {
+    KatnissArrowError::ColumnTypeMismatch(field.full_name().to_owned(), array.data_type().clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use anyhow::Result;
+    use arrow_array::{
+        types::{Int8Type, UInt16Type},
+        DictionaryArray,
+    };
+    use arrow_schema::Field;
+    use katniss_test::{
+        descriptor_pool,
+        protos::spacecorp::{
+            packet, GalacticCoordinate, JumpDriveMode, JumpDriveStatus, NewComponentControl,
+            Packet, QuantumSpaceTimeReading, Timestamp,
+        },
+        test_util::ProtoBatch,
+    };
+
+    use super::*;
+
+    #[test]
+    fn it_converts_batches_back_into_messages() -> Result<()> {
+        let packets = vec![
+            Packet {
+                timestamp: Some(Timestamp::system_now()),
+                sender_uid: 7,
+                msg: Some(packet::Msg::JumpDriveStatus(JumpDriveStatus {
+                    target: Some(GalacticCoordinate { x: 1, y: -1 }),
+                    mode: JumpDriveMode::PhaseShifted as i32,
+                    history: vec![QuantumSpaceTimeReading {
+                        coord: None,
+                        when: Some(Timestamp::system_now()),
+                        vxs: vec![1.0, 2.5],
+                    }],
+                })),
+            },
+            Packet {
+                msg: Some(packet::Msg::UnitMessage(NewComponentControl {})),
+                ..Default::default()
+            },
+            Packet::default(),
+        ];
+        let batch = ProtoBatch::SpaceCorp(&packets).arrow_batch()?;
+
+        let descriptor = descriptor_pool()?
+            .get_message_by_name("eto.pb2arrow.tests.spacecorp.Packet")
+            .unwrap();
+        let messages = ArrowToProtoConverter::new(descriptor).messages(&batch)?;
+
+        let round_tripped = messages
+            .iter()
+            .map(|m| m.transcode_to::<Packet>())
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        assert_eq!(round_tripped, packets);
+        Ok(())
+    }
+
+    #[test]
+    fn mismatched_columns_are_reported_by_path() -> Result<()> {
+        let packets = [Packet::default()];
+        let schema = ProtoBatch::SpaceCorp(&packets).arrow_batch()?.schema();
+        let descriptor = descriptor_pool()?
+            .get_message_by_name("eto.pb2arrow.tests.spacecorp.Packet")
+            .unwrap();
+        let converter = ArrowToProtoConverter::new(descriptor);
+        assert!(converter.schema_mismatches(&schema).is_empty());
+
+        let schema = Schema::new(vec![
+            Field::new("sender_uid", DataType::LargeUtf8, true),
+            Field::new("callsign", DataType::Utf8, true),
+            Field::new(
+                "jump_drive_status",
+                DataType::Struct(
+                    vec![
+                        Field::new("mode", DataType::Int32, true),
+                        Field::new("history", DataType::Float64, true),
+                    ]
+                    .into(),
+                ),
+                true,
+            ),
+        ]);
+        let mismatches = converter.schema_mismatches(&schema);
+        assert_eq!(
+            mismatches,
+            [
+                FieldMismatch::WrongType {
+                    path: "sender_uid".to_owned(),
+                    field_type: "uint64".to_owned(),
+                    column_type: DataType::LargeUtf8,
+                },
+                FieldMismatch::UnknownField("callsign".to_owned()),
+                FieldMismatch::WrongType {
+                    path: "jump_drive_status.history".to_owned(),
+                    field_type: "repeated eto.pb2arrow.tests.spacecorp.QuantumSpaceTimeReading"
+                        .to_owned(),
+                    column_type: DataType::Float64,
+                },
+            ]
+        );
+        let err = converter.check_schema(&schema).unwrap_err().to_string();
+        assert!(
+            err.contains("callsign isn't a field of the message"),
+            "{err}"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn enums_convert_from_any_dictionary_or_their_numbers() -> Result<()> {
+        let descriptor = descriptor_pool()?
+            .get_message_by_name("eto.pb2arrow.tests.spacecorp.JumpDriveStatus")
+            .unwrap();
+        let converter = ArrowToProtoConverter::new(descriptor);
+        let modes = |column: ArrayRef| -> Result<Vec<i32>> {
+            let batch = RecordBatch::try_from_iter([("mode", column)])?;
+            Ok(converter
+                .messages(&batch)?
+                .iter()
+                .map(|m| m.transcode_to::<JumpDriveStatus>().map(|s| s.mode))
+                .collect::<std::result::Result<_, _>>()?)
+        };
+
+        let names = ["HYPERDRIVE_ENGAGED", "OFF", "HYPERDRIVE_ENGAGED"];
+        let expected = [2, 0, 2];
+        let dictionary: DictionaryArray<Int8Type> = names.into_iter().collect();
+        assert_eq!(modes(Arc::new(dictionary))?, expected);
+        let dictionary: DictionaryArray<UInt16Type> = names.into_iter().collect();
+        assert_eq!(modes(Arc::new(dictionary))?, expected);
+        assert_eq!(
+            modes(Arc::new(StringArray::from(names.to_vec())))?,
+            expected
+        );
+        assert_eq!(modes(Arc::new(Int32Array::from(vec![2, 0, 2])))?, expected);
+
+        let unknown = StringArray::from(vec!["WARP"]);
+        assert!(matches!(
+            modes(Arc::new(unknown)),
+            Err(e) if e.to_string().contains("WARP")
+        ));
+        Ok(())
+    }
+}
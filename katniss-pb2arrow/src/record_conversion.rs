@@ -1,59 +1,122 @@
+use std::sync::Arc;
+
 use arrow_array::builder::*;
-use arrow_array::RecordBatch;
+use arrow_array::{RecordBatch, RecordBatchOptions};
 use arrow_schema::SchemaRef;
-use prost_reflect::DynamicMessage;
+use prost_reflect::{DynamicMessage, MessageDescriptor};
 
-use self::builder_appending::append_all_fields;
+use self::builder_appending::{append_all_fields, append_with_residual, FieldPath, Warnings};
 use self::builder_creation::BuilderFactory;
-use crate::ArrowBatchProps;
+use self::validation::validate_fields;
+pub use self::validation::ValidationReport;
+use crate::round_trip::{residual, split_residual};
 use crate::KatnissArrowError;
 use crate::Result;
+use crate::{ArrowBatchProps, ConversionMode};
 
 mod builder_appending;
 mod builder_creation;
+mod validation;
 
 /// Converterts records from protobuf to arrow
 /// Holds records in the builder until records() is called draining builder.
 pub struct RecordConverter {
-    pub(crate) schema: SchemaRef,
+    shared: Arc<Shared>,
     builder: StructBuilder, // fields align with schema
+    warnings: Vec<KatnissArrowError>,
+}
+
+/// The parts of the props converters only read, one copy for every converter made from
+/// the same one, see try_clone_empty
+struct Shared {
+    schema: SchemaRef,
     factory: BuilderFactory,
-    props: ArrowBatchProps,
+    descriptor: MessageDescriptor,
+    records_per_arrow_batch: usize,
+    conversion_mode: ConversionMode,
 }
 
 impl RecordConverter {
     pub fn try_new(props: &ArrowBatchProps) -> Result<Self> {
-        let batch_size = props.records_per_arrow_batch;
-        let factory: BuilderFactory =
-            BuilderFactory::new_with_dictionary(props.dictionaries.clone());
-        let builder = factory.try_from_fields(props.schema.fields().to_owned(), batch_size)?;
-        Ok(Self {
+        Self::try_from_shared(Arc::new(Shared {
             schema: props.schema.clone(),
-            builder,
-            factory,
-            props: props.clone(),
+            factory: BuilderFactory::new_with_dictionary(props.dictionaries.clone()),
+            descriptor: props.descriptor.clone(),
+            records_per_arrow_batch: props.records_per_arrow_batch,
+            conversion_mode: props.conversion_mode,
+        }))
+    }
+
+    /// An empty converter for the same props that only has to make its builders,
+    /// i.e. one per worker
+    pub fn try_clone_empty(&self) -> Result<Self> {
+        Self::try_from_shared(self.shared.clone())
+    }
+
+    fn try_from_shared(shared: Arc<Shared>) -> Result<Self> {
+        Ok(Self {
+            builder: shared.new_builder()?,
+            shared,
+            warnings: Vec::new(),
         })
     }
 
-    /// Append a new protobuf message to this batch
+    pub fn schema(&self) -> &SchemaRef {
+        &self.shared.schema
+    }
+
+    /// Append a new protobuf message to this batch, values that can't be converted fail it
+    /// unless the props are ConversionMode::Lenient
     pub fn append_message(&mut self, msg: &DynamicMessage) -> Result<()> {
-        append_all_fields(self.schema.fields(), &mut self.builder, Some(msg))
+        let mut warnings = Warnings::new(self.shared.conversion_mode, &mut self.warnings);
+        if let (columns, true) = split_residual(self.shared.schema.fields()) {
+            let residual = residual(columns, msg);
+            return append_with_residual(
+                columns,
+                &mut self.builder,
+                msg,
+                residual.as_deref(),
+                &mut warnings,
+            );
+        }
+        append_all_fields(
+            self.shared.schema.fields(),
+            &mut self.builder,
+            Some(msg),
+            &FieldPath::Root,
+            &mut warnings,
+        )
+    }
+
+    /// Checks msg against the schema without appending it, so sources can turn bad messages
+    /// away before they fail (or get nulled in) a batch
+    pub fn validate(&self, msg: &DynamicMessage) -> ValidationReport {
+        let mut report = ValidationReport::default();
+        let (columns, _) = split_residual(self.shared.schema.fields());
+        validate_fields(columns, msg, &FieldPath::Root, &mut report);
+        report
+    }
+
+    /// The errors lenient conversion appended nulls for since the last call
+    pub fn take_warnings(&mut self) -> Vec<KatnissArrowError> {
+        std::mem::take(&mut self.warnings)
     }
 
-    /// Returns record batch and resets the builder
+    /// Returns record batch and resets the builder, the rows stay buffered if the
+    /// new builder can't be made
     pub fn records(&mut self) -> Result<RecordBatch> {
-        let struct_array = self.builder.finish();
-        self.builder = self
-            .factory
-            .try_from_fields(
-                self.props.schema.fields().to_owned(),
-                self.props.records_per_arrow_batch,
-            )
-            .unwrap();
-
-        Ok(RecordBatch::from(&struct_array)
-            .with_schema(self.schema.clone())
-            .unwrap())
+        let builder = self.shared.new_builder()?;
+        let options = RecordBatchOptions::new().with_row_count(Some(self.len()));
+        let struct_array = std::mem::replace(&mut self.builder, builder).finish();
+
+        RecordBatch::try_new_with_options(
+            self.shared.schema.clone(),
+            struct_array.columns().to_vec(),
+            &options,
+        )
+        .map_err(|err| {
+            KatnissArrowError::RecordsMismatch(self.shared.descriptor.full_name().to_owned(), err)
+        })
     }
 
     /// Number of rows in this batch so far
@@ -67,6 +130,13 @@ impl RecordConverter {
     }
 }
 
+impl Shared {
+    fn new_builder(&self) -> Result<StructBuilder> {
+        self.factory
+            .try_from_fields(self.schema.fields(), self.records_per_arrow_batch)
+    }
+}
+
 impl TryFrom<&ArrowBatchProps> for RecordConverter {
     type Error = KatnissArrowError;
     fn try_from(props: &ArrowBatchProps) -> Result<Self> {
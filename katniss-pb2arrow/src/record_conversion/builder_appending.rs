@@ -1,22 +1,92 @@
+use std::borrow::Cow;
+use std::fmt::{self, Display};
+
 use arrow_array::builder::*;
 use arrow_array::types::Int32Type;
-use arrow_schema::{DataType, Field, Fields};
-use prost_reflect::{DynamicMessage, ReflectMessage, Value};
+use arrow_array::ArrowPrimitiveType;
+use arrow_schema::{DataType, Field, FieldRef};
+use prost_reflect::{DynamicMessage, EnumValueDescriptor, FieldDescriptor, ReflectMessage, Value};
+
+use crate::{ConversionMode, KatnissArrowError, Result};
+
+/// Where in the message an append is, i.e. `climate_status.readings[3].temp_c`. Lives on the
+/// stack and only becomes a string when it goes into an error
+#[derive(Debug, Clone, Copy)]
+pub enum FieldPath<'a> {
+    Root,
+    Field(&'a FieldPath<'a>, &'a str),
+    Index(&'a FieldPath<'a>, usize),
+}
+
+impl Display for FieldPath<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FieldPath::Root => Ok(()),
+            FieldPath::Field(FieldPath::Root, name) => write!(f, "{name}"),
+            FieldPath::Field(parent, name) => write!(f, "{parent}.{name}"),
+            FieldPath::Index(parent, i) => write!(f, "{parent}[{i}]"),
+        }
+    }
+}
+
+/// Where lenient conversion keeps the errors it appended nulls for, strict has nowhere to keep
+/// them so they're returned. Round trip nulls unknown enum numbers without a warning since the
+/// residual keeps them
+pub struct Warnings<'w> {
+    mode: ConversionMode,
+    warnings: &'w mut Vec<KatnissArrowError>,
+}
 
-use crate::{KatnissArrowError, Result};
+impl<'w> Warnings<'w> {
+    pub fn new(mode: ConversionMode, warnings: &'w mut Vec<KatnissArrowError>) -> Self {
+        Self { mode, warnings }
+    }
+
+    /// An error becomes None if it can be kept as a warning
+    fn or_null<T>(&mut self, result: Result<Option<T>>) -> Result<Option<T>> {
+        match (result, self.mode) {
+            (Err(err), ConversionMode::Lenient) => {
+                self.warnings.push(err);
+                Ok(None)
+            }
+            (Err(KatnissArrowError::NoEnumValue(_, _)), ConversionMode::RoundTrip) => Ok(None),
+            (result, _) => result,
+        }
+    }
+}
 
 pub fn append_all_fields(
-    fields: &Fields,
+    fields: &[FieldRef],
     builder: &mut StructBuilder,
     msg: Option<&DynamicMessage>,
+    path: &FieldPath,
+    warnings: &mut Warnings,
 ) -> Result<()> {
     for (i, field) in fields.iter().enumerate() {
-        append_field(i, field, msg, builder)?;
+        let path = FieldPath::Field(path, field.name());
+        append_field(i, field, msg, builder, &path, warnings)?;
     }
     builder.append(msg.is_some());
     Ok(())
 }
 
+/// append_all_fields for a round trip row, the residual column comes after the message's
+pub fn append_with_residual(
+    columns: &[FieldRef],
+    builder: &mut StructBuilder,
+    msg: &DynamicMessage,
+    residual: Option<&[u8]>,
+    warnings: &mut Warnings,
+) -> Result<()> {
+    for (i, field) in columns.iter().enumerate() {
+        let path = FieldPath::Field(&FieldPath::Root, field.name());
+        append_field(i, field, Some(msg), builder, &path, warnings)?;
+    }
+    field_builder::<BinaryBuilder>(builder, columns.len()).append_option(residual);
+    builder.append(true);
+    Ok(())
+}
+
 /// Append a protobuf value from the same field name to the
 /// i-th field builder. Assumes that the i-th field builder is the
 /// ArrayBuilder for the given field
@@ -25,82 +95,94 @@ fn append_field(
     f: &Field,
     msg: Option<&DynamicMessage>,
     builder: &mut StructBuilder,
+    path: &FieldPath,
+    warnings: &mut Warnings,
 ) -> Result<()> {
     match f.data_type() {
-        DataType::List(_) | DataType::LargeList(_) => append_list_value(f, builder, i, msg),
-        _ => append_non_list_value(f, builder, i, msg),
+        DataType::List(_) | DataType::LargeList(_) => {
+            append_list_value(f, builder, i, msg, path, warnings)
+        }
+        _ => append_non_list_value(f, builder, i, msg, path, warnings),
     }
 }
 
-fn append_non_list_value(
+/// The field's descriptor and its value in msg, no value if the field has presence and isn't
+/// set. Looks the field up by name once, the rest goes through the descriptor
+fn field_value<'m>(
     f: &Field,
-    struct_builder: &mut StructBuilder,
-    i: usize,
-    msg: Option<&DynamicMessage>,
-) -> Result<()> {
-    let fd_option = msg
-        .map(|msg| {
+    msg: Option<&'m DynamicMessage>,
+    path: &FieldPath,
+    warnings: &mut Warnings,
+) -> Result<(Option<FieldDescriptor>, Option<Cow<'m, Value>>)> {
+    let fd_option = warnings.or_null(
+        msg.map(|msg| {
             msg.descriptor()
                 .get_field_by_name(f.name())
-                .ok_or_else(|| KatnissArrowError::DescriptorNotFound(f.name().to_owned()))
+                .ok_or_else(|| KatnissArrowError::DescriptorNotFound(path.to_string()))
         })
-        .transpose()?;
-
-    let cow = msg.and_then(|msg| msg.get_field_by_name(f.name()));
+        .transpose(),
+    )?;
 
-    let has_field = msg
-        .map(|msg| msg.has_field_by_name(f.name()))
-        .unwrap_or(false);
-    let has_presence = fd_option
-        .clone()
-        .map(|fd| fd.supports_presence())
-        .unwrap_or(false);
-    let val = if has_presence && !has_field {
-        None
-    } else {
-        cow.as_deref()
+    let val = match (msg, &fd_option) {
+        (Some(msg), Some(fd)) if !fd.supports_presence() || msg.has_field(fd) => {
+            Some(msg.get_field(fd))
+        }
+        _ => None,
     };
+    Ok((fd_option, val))
+}
+
+fn append_non_list_value(
+    f: &Field,
+    struct_builder: &mut StructBuilder,
+    i: usize,
+    msg: Option<&DynamicMessage>,
+    path: &FieldPath,
+    warnings: &mut Warnings,
+) -> Result<()> {
+    let (fd_option, cow) = field_value(f, msg, path, warnings)?;
+    let val = cow.as_deref();
 
     match f.data_type() {
         DataType::Float64 => extend_builder(
             field_builder::<Float64Builder>(struct_builder, i),
-            parse_val(val, Value::as_f64)?,
+            parse_val(path, val, Value::as_f64, warnings)?,
         ),
         DataType::Float32 => extend_builder(
             field_builder::<Float32Builder>(struct_builder, i),
-            parse_val(val, Value::as_f32)?,
+            parse_val(path, val, Value::as_f32, warnings)?,
         ),
         DataType::Int64 => extend_builder(
             field_builder::<Int64Builder>(struct_builder, i),
-            parse_val(val, Value::as_i64)?,
+            parse_val(path, val, Value::as_i64, warnings)?,
         ),
         DataType::Int32 => extend_builder(
             field_builder::<Int32Builder>(struct_builder, i),
-            parse_val(val, Value::as_i32)?,
+            parse_val(path, val, Value::as_i32, warnings)?,
         ),
         DataType::UInt64 => extend_builder(
             field_builder::<UInt64Builder>(struct_builder, i),
-            parse_val(val, Value::as_u64)?,
+            parse_val(path, val, Value::as_u64, warnings)?,
         ),
         DataType::UInt32 => extend_builder(
             field_builder::<UInt32Builder>(struct_builder, i),
-            parse_val(val, Value::as_u32)?,
+            parse_val(path, val, Value::as_u32, warnings)?,
         ),
         DataType::Utf8 => extend_builder(
             field_builder::<StringBuilder>(struct_builder, i),
-            parse_val(val, Value::as_str)?,
+            parse_val(path, val, Value::as_str, warnings)?,
         ),
         DataType::LargeUtf8 => extend_builder(
             field_builder::<LargeStringBuilder>(struct_builder, i),
-            parse_val(val, Value::as_str)?,
+            parse_val(path, val, Value::as_str, warnings)?,
         ),
         DataType::Binary => extend_builder(
             field_builder::<BinaryBuilder>(struct_builder, i),
-            parse_val(val, Value::as_bytes)?,
+            parse_val(path, val, Value::as_bytes, warnings)?,
         ),
         DataType::LargeBinary => extend_builder(
             field_builder::<LargeBinaryBuilder>(struct_builder, i),
-            parse_val(val, Value::as_bytes)?,
+            parse_val(path, val, Value::as_bytes, warnings)?,
         ),
         DataType::Boolean => extend_builder(
             field_builder::<BooleanBuilder>(struct_builder, i),
@@ -108,44 +190,36 @@ fn append_non_list_value(
                 //unit variant structs
                 Some(true)
             } else {
-                parse_val(val, Value::as_bool)?
+                parse_val(path, val, Value::as_bool, warnings)?
             },
         ),
         DataType::Dictionary(_, _) => {
-            let f = field_builder::<StringDictionaryBuilder<Int32Type>>(struct_builder, i);
-
-            let intval = val.and_then(|v| v.as_enum_number());
-            match intval {
-                Some(intval) => {
-                    let kind = fd_option.as_ref().unwrap().kind();
-                    let enum_descriptor = kind
-                        .as_enum()
-                        .ok_or_else(|| KatnissArrowError::NonEnumField)?;
-
-                    let enum_value = enum_descriptor
-                        .get_value(intval)
-                        .ok_or_else(|| KatnissArrowError::NoEnumValue(intval))?;
+            let enum_value = match &fd_option {
+                Some(fd) => warnings.or_null(enum_value(path, fd, val))?,
+                None => None,
+            };
 
-                    f.append_value(enum_value.name());
-                }
+            let f = field_builder::<StringDictionaryBuilder<Int32Type>>(struct_builder, i);
+            match enum_value {
+                Some(enum_value) => f.append_value(enum_value.name()),
                 None => f.append_null(),
             };
             Ok(())
         }
         DataType::Struct(nested_fields) => {
             let b = field_builder::<StructBuilder>(struct_builder, i);
-            match val {
-                Some(v) => append_all_fields(nested_fields, b, v.as_message())?,
-                None => {
-                    append_all_fields(nested_fields, b, None)?;
-                }
-            };
-            Ok(())
+            append_all_fields(
+                nested_fields,
+                b,
+                val.and_then(Value::as_message),
+                path,
+                warnings,
+            )
         }
-        _ => unimplemented!(
-            "{}",
-            format!("Unsupported field {} with type {}", f.name(), f.data_type())
-        ),
+        t => Err(KatnissArrowError::UnsupportedDataType(
+            path.to_string(),
+            t.clone(),
+        )),
     }
 }
 
@@ -154,108 +228,120 @@ fn append_list_value(
     struct_builder: &mut StructBuilder,
     i: usize,
     msg: Option<&DynamicMessage>,
+    path: &FieldPath,
+    warnings: &mut Warnings,
 ) -> Result<()> {
-    let fd_option = msg
-        .map(|msg| {
-            msg.descriptor()
-                .get_field_by_name(f.name())
-                .ok_or_else(|| KatnissArrowError::DescriptorNotFound(f.name().to_owned()))
-        })
-        .transpose()?;
-
-    let cow = msg.and_then(|msg| msg.get_field_by_name(f.name()));
-
-    let has_field = msg
-        .map(|msg| msg.has_field_by_name(f.name()))
-        .unwrap_or(false);
-    let has_presence = fd_option
-        .clone()
-        .map(|fd| fd.supports_presence())
-        .unwrap_or(false);
-
-    let v: Option<&Value> = if has_presence && !has_field {
-        None
-    } else {
-        cow.as_deref()
-    };
-
-    let values = if let Some(v) = v { v.as_list() } else { None };
+    let (fd_option, cow) = field_value(f, msg, path, warnings)?;
+    let values = parse_val(path, cow.as_deref(), Value::as_list, warnings)?;
 
     let (DataType::List(inner) | DataType::LargeList(inner)) = f.data_type() else {
-        return Err(KatnissArrowError::NonListField)
+        return Err(KatnissArrowError::NonListField);
     };
 
     match inner.data_type() {
-        DataType::Float64 => extend_builder(
+        DataType::Float64 => append_primitive_list(
             field_builder::<ListBuilder<Float64Builder>>(struct_builder, i),
-            parse_list(values, Value::as_f64)?,
+            path,
+            values,
+            Value::as_f64,
+            warnings,
         ),
-        DataType::Float32 => extend_builder(
+        DataType::Float32 => append_primitive_list(
             field_builder::<ListBuilder<Float32Builder>>(struct_builder, i),
-            parse_list(values, Value::as_f32)?,
+            path,
+            values,
+            Value::as_f32,
+            warnings,
         ),
-        DataType::Int64 => extend_builder(
+        DataType::Int64 => append_primitive_list(
             field_builder::<ListBuilder<Int64Builder>>(struct_builder, i),
-            parse_list(values, Value::as_i64)?,
+            path,
+            values,
+            Value::as_i64,
+            warnings,
         ),
-        DataType::Int32 => extend_builder(
+        DataType::Int32 => append_primitive_list(
             field_builder::<ListBuilder<Int32Builder>>(struct_builder, i),
-            parse_list(values, Value::as_i32)?,
+            path,
+            values,
+            Value::as_i32,
+            warnings,
         ),
-        DataType::UInt64 => extend_builder(
+        DataType::UInt64 => append_primitive_list(
             field_builder::<ListBuilder<UInt64Builder>>(struct_builder, i),
-            parse_list(values, Value::as_u64)?,
+            path,
+            values,
+            Value::as_u64,
+            warnings,
         ),
-        DataType::UInt32 => extend_builder(
+        DataType::UInt32 => append_primitive_list(
             field_builder::<ListBuilder<UInt32Builder>>(struct_builder, i),
-            parse_list(values, Value::as_u32)?,
+            path,
+            values,
+            Value::as_u32,
+            warnings,
         ),
-        DataType::Utf8 => extend_builder(
+        DataType::Utf8 => append_list(
             field_builder::<ListBuilder<StringBuilder>>(struct_builder, i),
-            parse_list(values, Value::as_str)?,
+            path,
+            values,
+            Value::as_str,
+            warnings,
         ),
-        DataType::LargeUtf8 => extend_builder(
+        DataType::LargeUtf8 => append_list(
             field_builder::<ListBuilder<LargeStringBuilder>>(struct_builder, i),
-            parse_list(values, Value::as_str)?,
+            path,
+            values,
+            Value::as_str,
+            warnings,
         ),
-        DataType::Binary => extend_builder(
+        DataType::Binary => append_list(
             field_builder::<ListBuilder<BinaryBuilder>>(struct_builder, i),
-            parse_list(values, Value::as_bytes)?,
+            path,
+            values,
+            Value::as_bytes,
+            warnings,
         ),
-        DataType::LargeBinary => extend_builder(
+        DataType::LargeBinary => append_list(
             field_builder::<ListBuilder<LargeBinaryBuilder>>(struct_builder, i),
-            parse_list(values, Value::as_bytes)?,
+            path,
+            values,
+            Value::as_bytes,
+            warnings,
         ),
-        DataType::Boolean => extend_builder(
+        DataType::Boolean => append_list(
             field_builder::<ListBuilder<BooleanBuilder>>(struct_builder, i),
-            parse_list(values, Value::as_bool)?,
+            path,
+            values,
+            Value::as_bool,
+            warnings,
         ),
         DataType::Dictionary(_, _) => {
-            let kind = fd_option.unwrap().kind();
-            let enum_descriptor = kind
-                .as_enum()
-                .ok_or_else(|| KatnissArrowError::NonEnumField)?;
             let f: &mut ListBuilder<StringDictionaryBuilder<Int32Type>> =
                 field_builder(struct_builder, i);
-            let val_lst: Option<Vec<Option<String>>> = values.map(|vs| {
-                vs.iter()
-                    .map(Value::as_i32)
-                    .map(|v| match v {
-                        Some(v) => enum_descriptor.get_value(v).map(|v| v.name().to_string()),
-                        None => panic!("parse_errorlolol"),
-                    })
-                    .collect::<_>()
-            });
-
-            f.extend(std::iter::once(val_lst));
+            match (values, &fd_option) {
+                (Some(values), Some(fd)) => {
+                    for (index, v) in values.iter().enumerate() {
+                        let path = FieldPath::Index(path, index);
+                        match warnings.or_null(enum_value(&path, fd, Some(v)))? {
+                            Some(enum_value) => f.values().append_value(enum_value.name()),
+                            None => f.values().append_null(),
+                        }
+                    }
+                    f.append(true);
+                }
+                _ => f.append(false),
+            }
             Ok(())
         }
         DataType::Struct(nested_fields) => {
             let b = field_builder::<ListBuilder<StructBuilder>>(struct_builder, i);
             match values {
                 Some(lst) => {
-                    for v in lst {
-                        append_all_fields(nested_fields, b.values(), v.as_message())?;
+                    for (index, v) in lst.iter().enumerate() {
+                        let path = FieldPath::Index(path, index);
+                        let msg = v.as_message();
+                        append_all_fields(nested_fields, b.values(), msg, &path, warnings)?;
                     }
                     b.append(true);
                 }
@@ -263,47 +349,114 @@ fn append_list_value(
                     // I'm really curious about append_all_fields None,
                     // Must we append all child fields or can we lift the null higher?
                     // In that case append_all_fields could just take a DynamicMessage rather than an Option
-                    append_all_fields(nested_fields, b.values(), None)?;
+                    append_all_fields(nested_fields, b.values(), None, path, warnings)?;
                     b.append(false);
                 }
             }
             Ok(())
         }
-        inner_type => unimplemented!("Unsupported inner_type {}", inner_type),
+        t => Err(KatnissArrowError::UnsupportedDataType(
+            path.to_string(),
+            t.clone(),
+        )),
     }
 }
 
+/// Fast path for repeated numbers, i.e. point clouds: the list is decoded once and appended to
+/// the values builder as one slice instead of a Value at a time. Takes append_list's path if any
+/// item isn't the column's type so errors (or lenient nulls) come out the same
+fn append_primitive_list<T, F>(
+    builder: &mut ListBuilder<PrimitiveBuilder<T>>,
+    path: &FieldPath,
+    values: Option<&[Value]>,
+    getter: F,
+    warnings: &mut Warnings,
+) -> Result<()>
+where
+    T: ArrowPrimitiveType,
+    F: Fn(&Value) -> Option<T::Native>,
+{
+    let Some(values) = values else {
+        builder.append(false);
+        return Ok(());
+    };
+    // decoded before appending since the child builder can't be rolled back partway through a list
+    let Some(natives) = values.iter().map(&getter).collect::<Option<Vec<_>>>() else {
+        return append_list(builder, path, Some(values), getter, warnings);
+    };
+    builder.values().append_slice(&natives);
+    builder.append(true);
+    Ok(())
+}
+
 fn field_builder<T: ArrayBuilder>(builder: &mut StructBuilder, i: usize) -> &mut T {
     builder.field_builder(i).expect("schema conversion error?")
 }
 
-fn parse_val<'val, 'ret: 'val, R, F>(value: Option<&'val Value>, getter: F) -> Result<Option<R>>
+fn parse_val<'val, 'ret: 'val, R, F>(
+    path: &FieldPath,
+    value: Option<&'val Value>,
+    getter: F,
+    warnings: &mut Warnings,
+) -> Result<Option<R>>
 where
     F: Fn(&'val Value) -> Option<R> + 'ret,
 {
-    value
-        .map(|v| getter(v).ok_or_else(|| KatnissArrowError::TypeCastError(v.clone())))
-        .transpose()
+    warnings.or_null(value.map(|v| cast(path, v, getter)).transpose())
 }
 
-fn parse_list<'val, 'ret: 'val, F, R>(
+/// Appends each value straight into the list's child builder, a value that isn't the column's
+/// type fails the list (or is null in it for lenient conversion)
+fn append_list<'val, B, R, F>(
+    builder: &mut ListBuilder<B>,
+    path: &FieldPath,
     values: Option<&'val [Value]>,
     getter: F,
-) -> Result<Option<Vec<Option<R>>>>
+    warnings: &mut Warnings,
+) -> Result<()>
 where
-    R: std::fmt::Debug,
-    F: Fn(&'val Value) -> Option<R> + 'ret,
+    B: ArrayBuilder + Extend<Option<R>>,
+    F: Fn(&'val Value) -> Option<R>,
 {
-    values
-        .map(|vs| {
-            vs.iter()
-                .map(|v| match getter(v) {
-                    Some(v) => Ok(Some(v)),
-                    None => Err(KatnissArrowError::TypeCastError(v.clone())),
-                })
-                .collect::<Result<Vec<_>>>()
-        })
-        .transpose()
+    let Some(values) = values else {
+        builder.append(false);
+        return Ok(());
+    };
+    for (index, v) in values.iter().enumerate() {
+        let path = FieldPath::Index(path, index);
+        let item = warnings.or_null(cast(&path, v, &getter).map(Some))?;
+        builder.values().extend(std::iter::once(item));
+    }
+    builder.append(true);
+    Ok(())
+}
+
+pub(super) fn cast<'val, R>(
+    path: &FieldPath,
+    value: &'val Value,
+    getter: impl Fn(&'val Value) -> Option<R>,
+) -> Result<R> {
+    getter(value).ok_or_else(|| KatnissArrowError::TypeCastError(path.to_string(), value.clone()))
+}
+
+/// The value of the enum field fd that val is the number of, None without a val
+pub(super) fn enum_value(
+    path: &FieldPath,
+    fd: &FieldDescriptor,
+    val: Option<&Value>,
+) -> Result<Option<EnumValueDescriptor>> {
+    let Some(val) = val else {
+        return Ok(None);
+    };
+    let number = cast(path, val, Value::as_enum_number)?;
+    let kind = fd.kind();
+    let enum_descriptor = kind
+        .as_enum()
+        .ok_or_else(|| KatnissArrowError::NonEnumField(path.to_string()))?;
+    enum_descriptor
+        .get_value(number)
+        .map(Some)
+        .ok_or_else(|| KatnissArrowError::NoEnumValue(path.to_string(), number))
 }
 
 fn extend_builder<B, V>(builder: &mut B, val: V) -> Result<()>
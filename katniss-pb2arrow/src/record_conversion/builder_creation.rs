@@ -6,7 +6,7 @@ use arrow_schema::{DataType, Field, Fields};
 
 use crate::errors::Result;
 use crate::schema_conversion::DictValuesContainer;
-use crate::KatnissArrowError::{BatchConversionError, DictNotFound};
+use crate::KatnissArrowError::{BatchConversionError, DictNotFound, UnsupportedDataType};
 
 pub struct BuilderFactory {
     dictionaries: Arc<DictValuesContainer>,
@@ -17,12 +17,12 @@ impl BuilderFactory {
         BuilderFactory { dictionaries }
     }
 
-    pub fn try_from_fields(&self, fields: Fields, capacity: usize) -> Result<StructBuilder> {
+    pub fn try_from_fields(&self, fields: &Fields, capacity: usize) -> Result<StructBuilder> {
         let field_builders: Vec<Box<dyn ArrayBuilder>> = fields
             .iter()
             .map(|f| self.make_builder(f, capacity))
             .collect::<Result<Vec<_>>>()?;
-        Ok(StructBuilder::new(fields, field_builders))
+        Ok(StructBuilder::new(fields.clone(), field_builders))
     }
 
     /// Create the appropriate ArrayBuilder for the given field and capacity
@@ -66,10 +66,8 @@ impl BuilderFactory {
 
                 wrap_builder(builder, kind)
             }
-            DataType::Struct(fields) => {
-                wrap_builder(self.try_from_fields(fields.clone(), capacity)?, kind)
-            }
-            t => panic!("Data type {:?} is not currently supported", t),
+            DataType::Struct(fields) => wrap_builder(self.try_from_fields(fields, capacity)?, kind),
+            t => Err(UnsupportedDataType(field.name().to_owned(), t.clone())),
         }
     }
 }
@@ -89,3 +87,30 @@ fn wrap_builder<T: ArrayBuilder>(builder: T, kind: ListKind) -> Result<Box<dyn A
         ListKind::NotList => Box::new(builder),
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use arrow_schema::TimeUnit;
+
+    use super::*;
+    use crate::KatnissArrowError;
+
+    #[test]
+    fn unsupported_types_are_errors() {
+        let factory = BuilderFactory::new_with_dictionary(Arc::new(DictValuesContainer::default()));
+        let at = DataType::Timestamp(TimeUnit::Nanosecond, None);
+        let fields = Fields::from(vec![
+            Field::new("id", DataType::Int32, true),
+            Field::new("at", DataType::new_list(at.clone(), true), true),
+        ]);
+
+        match factory.try_from_fields(&fields, 8) {
+            Err(KatnissArrowError::UnsupportedDataType(name, data_type)) => {
+                assert_eq!(name, "at");
+                assert_eq!(data_type, at);
+            }
+            Err(err) => panic!("expected an unsupported type, got {err}"),
+            Ok(_) => panic!("built a timestamp column"),
+        }
+    }
+}
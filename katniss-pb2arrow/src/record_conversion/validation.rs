@@ -0,0 +1,112 @@
+use arrow_schema::{DataType, FieldRef};
+use prost_reflect::{DynamicMessage, FieldDescriptor, ReflectMessage, Value};
+
+use super::builder_appending::{cast, enum_value, FieldPath};
+use crate::{KatnissArrowError, Result};
+
+/// Everything RecordConverter::validate found wrong with a message, not just the first thing
+#[derive(Debug, Default)]
+pub struct ValidationReport {
+    errors: Vec<KatnissArrowError>,
+}
+
+impl ValidationReport {
+    pub fn is_valid(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    pub fn errors(&self) -> &[KatnissArrowError] {
+        &self.errors
+    }
+
+    /// Err with the first problem if there are any
+    pub fn into_result(self) -> Result<()> {
+        match self.errors.into_iter().next() {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    fn check(&mut self, result: Result<()>) {
+        if let Err(err) = result {
+            self.errors.push(err);
+        }
+    }
+}
+
+/// Walks msg like append_all_fields but only looks, unset fields are fine since they're null
+pub(super) fn validate_fields(
+    fields: &[FieldRef],
+    msg: &DynamicMessage,
+    path: &FieldPath,
+    report: &mut ValidationReport,
+) {
+    for oneof in msg.descriptor().oneofs() {
+        let set: Vec<_> = oneof
+            .fields()
+            .filter(|fd| msg.has_field(fd))
+            .map(|fd| fd.name().to_owned())
+            .collect();
+        if set.len() > 1 {
+            let path = FieldPath::Field(path, oneof.name());
+            report.check(Err(KatnissArrowError::OneofConflict(path.to_string(), set)));
+        }
+    }
+
+    for field in fields {
+        let path = FieldPath::Field(path, field.name());
+        let Some(fd) = msg.descriptor().get_field_by_name(field.name()) else {
+            report.check(Err(KatnissArrowError::DescriptorNotFound(path.to_string())));
+            continue;
+        };
+        if fd.supports_presence() && !msg.has_field(&fd) {
+            continue;
+        }
+
+        let value = msg.get_field(&fd);
+        match field.data_type() {
+            DataType::List(inner) | DataType::LargeList(inner) => {
+                match cast(&path, &value, Value::as_list) {
+                    Ok(values) => {
+                        for (index, value) in values.iter().enumerate() {
+                            let path = FieldPath::Index(&path, index);
+                            validate_value(inner.data_type(), &fd, value, &path, report);
+                        }
+                    }
+                    Err(err) => report.check(Err(err)),
+                }
+            }
+            data_type => validate_value(data_type, &fd, &value, &path, report),
+        }
+    }
+}
+
+fn validate_value(
+    data_type: &DataType,
+    fd: &FieldDescriptor,
+    value: &Value,
+    path: &FieldPath,
+    report: &mut ValidationReport,
+) {
+    let checked = match data_type {
+        DataType::Float64 => cast(path, value, Value::as_f64).map(|_| ()),
+        DataType::Float32 => cast(path, value, Value::as_f32).map(|_| ()),
+        DataType::Int64 => cast(path, value, Value::as_i64).map(|_| ()),
+        DataType::Int32 => cast(path, value, Value::as_i32).map(|_| ()),
+        DataType::UInt64 => cast(path, value, Value::as_u64).map(|_| ()),
+        DataType::UInt32 => cast(path, value, Value::as_u32).map(|_| ()),
+        DataType::Utf8 | DataType::LargeUtf8 => cast(path, value, Value::as_str).map(|_| ()),
+        DataType::Binary | DataType::LargeBinary => cast(path, value, Value::as_bytes).map(|_| ()),
+        // unit variant structs
+        DataType::Boolean if value.as_message().is_some() => Ok(()),
+        DataType::Boolean => cast(path, value, Value::as_bool).map(|_| ()),
+        DataType::Dictionary(_, _) => enum_value(path, fd, Some(value)).map(|_| ()),
+        DataType::Struct(fields) => cast(path, value, Value::as_message)
+            .map(|msg| validate_fields(fields, msg, path, report)),
+        t => Err(KatnissArrowError::UnsupportedDataType(
+            path.to_string(),
+            t.clone(),
+        )),
+    };
+    report.check(checked);
+}
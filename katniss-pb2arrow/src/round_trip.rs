@@ -0,0 +1,243 @@
+use std::sync::Arc;
+
+use arrow_array::{Array, ArrayRef, BinaryArray};
+use arrow_schema::{DataType, Field, FieldRef, Fields, Schema};
+use prost_reflect::{
+    prost::Message, DynamicMessage, FieldDescriptor, Kind, MessageDescriptor, ReflectMessage, Value,
+};
+
+use crate::message_conversion::field_type;
+use crate::{ArrowBatchProps, ArrowToProtoConverter, KatnissArrowError, RecordConverter, Result};
+
+/// Binary column ConversionMode::RoundTrip adds after the message's columns. Each row is an
+/// encoded message of the row's type with what the other columns can't hold: unknown fields,
+/// enum numbers the descriptor doesn't have and fields without a column. Null if there's nothing
+pub const RESIDUAL_COLUMN: &str = "_katniss_residual";
+
+/// Field metadata with the proto field number, i.e. "2"
+pub const FIELD_NUMBER_KEY: &str = "katniss.field_number";
+
+/// Field metadata with the proto type, i.e. "sint64" or "repeated acme.telemetry.Reading"
+pub const PROTO_TYPE_KEY: &str = "katniss.proto_type";
+
+/// Converts the messages to a batch and back, errors with the first one that doesn't come back
+/// byte for byte. Only ConversionMode::RoundTrip props are meant to pass for every message,
+/// i.e. to check a sample before switching a pipeline to another mode
+pub fn verify_round_trip(props: &ArrowBatchProps, messages: &[DynamicMessage]) -> Result<()> {
+    let mut records = RecordConverter::try_new(props)?;
+    for msg in messages {
+        records.append_message(msg)?;
+    }
+    let converter = ArrowToProtoConverter::new(props.descriptor.clone());
+    let converted = converter.messages(&records.records()?)?;
+
+    for (index, (msg, converted)) in messages.iter().zip(&converted).enumerate() {
+        if msg.encode_to_vec() == converted.encode_to_vec() {
+            continue;
+        }
+        let mut differing: Vec<_> = msg
+            .descriptor()
+            .fields()
+            .filter(|fd| msg.get_field(fd) != converted.get_field(fd))
+            .map(|fd| fd.name().to_owned())
+            .collect();
+        if differing.is_empty() {
+            differing.push("unknown fields".to_owned());
+        }
+        return Err(KatnissArrowError::RoundTripMismatch(index, differing));
+    }
+    Ok(())
+}
+
+/// With a descriptor the schema gets the residual column and each field's number and proto
+/// type in its metadata, without one they're taken back out
+pub(crate) fn round_trip_schema(schema: &Schema, descriptor: Option<&MessageDescriptor>) -> Schema {
+    let (columns, _) = split_residual(schema.fields());
+    let mut fields: Vec<FieldRef> = columns
+        .iter()
+        .map(|f| {
+            let fd = descriptor.and_then(|d| d.get_field_by_name(f.name()));
+            annotated(f, fd.as_ref())
+        })
+        .collect();
+    if descriptor.is_some() {
+        fields.push(Arc::new(Field::new(
+            RESIDUAL_COLUMN,
+            DataType::Binary,
+            true,
+        )));
+    }
+    Schema::new_with_metadata(fields, schema.metadata().clone())
+}
+
+/// The message's columns and whether the residual column comes after them
+pub(crate) fn split_residual(fields: &[FieldRef]) -> (&[FieldRef], bool) {
+    match fields.split_last() {
+        Some((last, columns)) if last.name() == RESIDUAL_COLUMN => (columns, true),
+        _ => (fields, false),
+    }
+}
+
+fn annotated(field: &Field, fd: Option<&FieldDescriptor>) -> FieldRef {
+    let mut metadata = field.metadata().clone();
+    metadata.remove(FIELD_NUMBER_KEY);
+    metadata.remove(PROTO_TYPE_KEY);
+    if let Some(fd) = fd {
+        metadata.insert(FIELD_NUMBER_KEY.to_owned(), fd.number().to_string());
+        metadata.insert(PROTO_TYPE_KEY.to_owned(), field_type(fd));
+    }
+
+    let kind = fd.map(FieldDescriptor::kind);
+    let data_type = annotated_type(field.data_type(), kind.as_ref().and_then(Kind::as_message));
+    Arc::new(
+        field
+            .clone()
+            .with_data_type(data_type)
+            .with_metadata(metadata),
+    )
+}
+
+/// Structs (and lists of them) with their children annotated, other types as they are
+fn annotated_type(data_type: &DataType, message: Option<&MessageDescriptor>) -> DataType {
+    let item = |item: &FieldRef| {
+        let data_type = annotated_type(item.data_type(), message);
+        Arc::new(item.as_ref().clone().with_data_type(data_type))
+    };
+    match data_type {
+        DataType::Struct(children) => {
+            let children: Vec<_> = children
+                .iter()
+                .map(|child| {
+                    let fd = message.and_then(|m| m.get_field_by_name(child.name()));
+                    annotated(child, fd.as_ref())
+                })
+                .collect();
+            DataType::Struct(Fields::from(children))
+        }
+        DataType::List(inner) => DataType::List(item(inner)),
+        DataType::LargeList(inner) => DataType::LargeList(item(inner)),
+        data_type => data_type.clone(),
+    }
+}
+
+/// The encoded residual of msg, None if its columns hold all of it
+pub(crate) fn residual(columns: &[FieldRef], msg: &DynamicMessage) -> Option<Vec<u8>> {
+    let residual = residual_message(columns, msg);
+    (residual.encoded_len() > 0).then(|| residual.encode_to_vec())
+}
+
+/// msg with every field its columns hold cleared, unknown fields stay
+fn residual_message(columns: &[FieldRef], msg: &DynamicMessage) -> DynamicMessage {
+    let mut residual = msg.clone();
+    for fd in msg.descriptor().fields() {
+        // without a column the whole field is kept
+        let Some(column) = columns.iter().find(|c| c.name() == fd.name()) else {
+            continue;
+        };
+        match kept(column.data_type(), &fd, &msg.get_field(&fd)) {
+            Some(value) => residual.set_field(&fd, value),
+            None => residual.clear_field(&fd),
+        }
+    }
+    residual
+}
+
+/// What of a field's value its column can't hold
+fn kept(data_type: &DataType, fd: &FieldDescriptor, value: &Value) -> Option<Value> {
+    let item_type = match data_type {
+        DataType::List(item) | DataType::LargeList(item) => item.data_type(),
+        data_type => data_type,
+    };
+
+    match (fd.kind(), value) {
+        (Kind::Enum(descriptor), Value::EnumNumber(number)) => descriptor
+            .get_value(*number)
+            .is_none()
+            .then(|| value.clone()),
+        // the whole list, the column only has the values the descriptor knows
+        (Kind::Enum(descriptor), Value::List(values)) => values
+            .iter()
+            .filter_map(Value::as_enum_number)
+            .any(|number| descriptor.get_value(number).is_none())
+            .then(|| value.clone()),
+        (Kind::Message(_), Value::Message(nested)) => {
+            nested_residual(item_type, nested).map(Value::Message)
+        }
+        (Kind::Message(descriptor), Value::List(items)) => {
+            let residuals: Vec<_> = items
+                .iter()
+                .map(|item| {
+                    item.as_message()
+                        .and_then(|m| nested_residual(item_type, m))
+                })
+                .collect();
+            if residuals.iter().all(Option::is_none) {
+                return None;
+            }
+            // placeholders keep the residuals lined up with the items
+            let residuals = residuals
+                .into_iter()
+                .map(|r| {
+                    Value::Message(r.unwrap_or_else(|| DynamicMessage::new(descriptor.clone())))
+                })
+                .collect();
+            Some(Value::List(residuals))
+        }
+        _ => None,
+    }
+}
+
+/// Unit messages are a bool column so all of them is residual
+fn nested_residual(data_type: &DataType, msg: &DynamicMessage) -> Option<DynamicMessage> {
+    let columns: &[FieldRef] = match data_type {
+        DataType::Struct(children) => children,
+        _ => &[],
+    };
+    let residual = residual_message(columns, msg);
+    (residual.encoded_len() > 0).then_some(residual)
+}
+
+/// Puts the row's residual back onto the message converted from the other columns
+pub(crate) fn restore(msg: &mut DynamicMessage, residuals: &ArrayRef, row: usize) -> Result<()> {
+    if residuals.is_null(row) {
+        return Ok(());
+    }
+    let residuals = residuals
+        .as_any()
+        .downcast_ref::<BinaryArray>()
+        .ok_or_else(|| {
+            KatnissArrowError::ColumnTypeMismatch(
+                RESIDUAL_COLUMN.to_owned(),
+                residuals.data_type().clone(),
+            )
+        })?;
+    let residual = DynamicMessage::decode(msg.descriptor(), residuals.value(row))?;
+    overlay(msg, residual)
+}
+
+fn overlay(msg: &mut DynamicMessage, mut residual: DynamicMessage) -> Result<()> {
+    for fd in residual.descriptor().fields() {
+        if !residual.has_field(&fd) {
+            continue;
+        }
+        let value = residual.get_field(&fd).into_owned();
+        residual.clear_field(&fd);
+
+        match (value, msg.get_field_mut(&fd)) {
+            (Value::Message(nested), Value::Message(restored)) => overlay(restored, nested)?,
+            (Value::List(items), Value::List(restored))
+                if fd.kind().as_message().is_some() && items.len() == restored.len() =>
+            {
+                for (item, restored) in items.into_iter().zip(restored) {
+                    if let (Value::Message(item), Value::Message(restored)) = (item, restored) {
+                        overlay(restored, item)?;
+                    }
+                }
+            }
+            (value, restored) => *restored = value,
+        }
+    }
+    // only unknown fields are left
+    msg.merge(residual.encode_to_vec().as_slice())?;
+    Ok(())
+}
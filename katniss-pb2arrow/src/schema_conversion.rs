@@ -4,15 +4,23 @@
 use arrow_array::StringArray;
 use std::cell::RefCell;
 use std::collections::{HashMap, HashSet};
+#[cfg(feature = "protoc")]
 use std::io::{BufReader, Read};
 use std::path::Path;
+#[cfg(feature = "protoc")]
 use std::process::Command;
 use std::sync::Arc;
 
 use arrow_schema::{DataType, Field, Fields, Schema};
-use prost_reflect::{DescriptorPool, FieldDescriptor, MessageDescriptor};
+use prost_reflect::{
+    prost_types::FileDescriptorProto, DescriptorPool, EnumDescriptor, FieldDescriptor,
+    MessageDescriptor, Value,
+};
+#[cfg(feature = "protoc")]
 use tempfile::NamedTempFile;
 
+#[cfg(feature = "protoc")]
+use crate::well_known_types;
 use crate::{KatnissArrowError, Result};
 
 /// Holds dictionary values for fields. Not threadsafe
@@ -20,12 +28,18 @@ use crate::{KatnissArrowError, Result};
 pub struct DictValuesContainer {
     /// Arrow Field.dict_id -> dictionary values
     dictionaries: HashMap<i64, StringArray>,
+    /// EnumDescriptor full name -> dict_id and whether its values are sorted
+    enums: HashMap<String, (i64, bool)>,
 }
 
 impl DictValuesContainer {
     pub fn new() -> Self {
         let dictionaries = HashMap::new();
-        DictValuesContainer { dictionaries }
+        let enums = HashMap::new();
+        DictValuesContainer {
+            dictionaries,
+            enums,
+        }
     }
 
     /// Add a new set of dictionary values and return dict_id
@@ -37,6 +51,20 @@ impl DictValuesContainer {
         new_id
     }
 
+    /// The dict_id for the enum's value names and whether they're sorted. Fields of the same
+    /// enum share it, the names are only collected the first time
+    pub fn add_enum(&mut self, e: &EnumDescriptor) -> (i64, bool) {
+        if let Some(cached) = self.enums.get(e.full_name()) {
+            return *cached;
+        }
+        let enum_values = e.values().map(|v| v.name().to_string()).collect::<Vec<_>>();
+        let is_ordered = enum_values.windows(2).all(|w| w[0] <= w[1]);
+        let dict_id = self.add_dictionary(enum_values);
+        self.enums
+            .insert(e.full_name().to_owned(), (dict_id, is_ordered));
+        (dict_id, is_ordered)
+    }
+
     /// Get the dictionary values for the specified dict_id
     pub fn get_dict_values(&self, dict_id: i64) -> Option<&StringArray> {
         self.dictionaries.get(&dict_id)
@@ -61,6 +89,11 @@ impl FieldConverter {
         FieldConverter { dictionaries }
     }
 
+    /// Adds to dictionaries from an earlier conversion, enums already in them are reused
+    pub fn with_dictionaries(dictionaries: DictValuesContainer) -> Self {
+        FieldConverter { dictionaries }
+    }
+
     /// Convert prost FieldDescriptor to arrow Field
     pub fn to_arrow_mut(&mut self, f: &FieldDescriptor) -> Field {
         let name = f.name();
@@ -79,15 +112,7 @@ impl FieldConverter {
             let item = Arc::new(Field::new("item", data_type, true));
             Field::new(name, DataType::List(item), true)
         } else if matches!(data_type, DataType::Dictionary(_, _)) {
-            let enum_values = f
-                .kind()
-                .as_enum()
-                .unwrap()
-                .values()
-                .map(|v| v.name().to_string())
-                .collect::<Vec<_>>();
-            let is_ordered = enum_values.windows(2).all(|w| w[0] <= w[1]);
-            let dict_id = self.dictionaries.add_dictionary(enum_values);
+            let (dict_id, is_ordered) = self.dictionaries.add_enum(f.kind().as_enum().unwrap());
             Field::new_dict(name, data_type, true, dict_id, is_ordered)
         } else {
             Field::new(name, data_type, true)
@@ -135,25 +160,44 @@ pub struct SchemaConverter {
     pub(crate) descriptor_pool: DescriptorPool,
     /// message name -> dictionary values for the schema
     dictionary_map: RefCell<HashMap<String, DictValuesContainer>>,
+    /// Every enum's dictionary so far, deriving schemas again doesn't rebuild them
+    enum_dictionaries: RefCell<DictValuesContainer>,
 }
 
 impl SchemaConverter {
     pub fn new(descriptor_pool: DescriptorPool) -> Self {
         let dictionary_map = RefCell::new(HashMap::new());
+        let enum_dictionaries = RefCell::new(DictValuesContainer::new());
         Self {
             descriptor_pool,
             dictionary_map,
+            enum_dictionaries,
         }
     }
+
+    /// From a serialized FileDescriptorSet, i.e. the output of
+    /// `protoc --include_imports -o file_descriptor_set.bin` or `buf build -o`
+    pub fn from_descriptor_set_bytes(bytes: &[u8]) -> Result<Self> {
+        Ok(Self::new(DescriptorPool::decode(bytes)?))
+    }
+
+    /// Reads and decodes a FileDescriptorSet file, see from_descriptor_set_bytes
+    pub fn from_descriptor_set_file(path: impl AsRef<Path>) -> Result<Self> {
+        Self::from_descriptor_set_bytes(&std::fs::read(path)?)
+    }
+
     /// Compile protobuf files and build the converter.
+    /// Imports of google/protobuf's well known types (i.e. timestamp.proto) work without
+    /// including a protobuf install, copies are bundled in case protoc didn't come with them
     ///
-    /// ```rust
+    /// ```no_run
     ///   use katniss_pb2arrow::SchemaConverter;
     ///
     ///   let convert = SchemaConverter::compile(
     ///       &["protos/foo.proto"], &["protos"]
     ///   ).unwrap();
     /// ```
+    #[cfg(feature = "protoc")]
     pub fn compile(protos: &[impl AsRef<Path>], includes: &[impl AsRef<Path>]) -> Result<Self> {
         let protoc = match which::which("protoc") {
             Ok(path) => path,
@@ -162,6 +206,8 @@ impl SchemaConverter {
 
         let mut file_descriptor_file = NamedTempFile::new()?;
 
+        let well_known_types = well_known_types::include_dir(&protoc)?;
+
         let mut cmd = Command::new(protoc);
         cmd.arg("--include_imports")
             .arg("-o")
@@ -170,31 +216,155 @@ impl SchemaConverter {
         for include_path in includes {
             cmd.arg("-I").arg(include_path.as_ref().as_os_str());
         }
-        cmd.output()?;
+        if let Some(dir) = &well_known_types {
+            // protoc only defaults to the current directory when there are no includes at all
+            if includes.is_empty() {
+                cmd.arg("-I").arg(".");
+            }
+            cmd.arg("-I").arg(dir.path());
+        }
+        let output = cmd.output()?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(KatnissArrowError::ProtocFailed(stderr.trim().to_owned()));
+        }
         let mut reader = BufReader::new(file_descriptor_file.as_file_mut());
         let mut buffer = Vec::new();
         reader.read_to_end(&mut buffer)?;
 
-        let pool =
-            DescriptorPool::decode(buffer.as_slice()).expect("Failed to decode descriptor pool");
+        Self::from_descriptor_set_bytes(&buffer)
+    }
+
+    /// Fetch a module from the Buf Schema Registry and build the converter, i.e.
+    /// "buf.build/acme/telemetry:8c1ea1a6c8e94b6b9a5f2a4d3e1f0b7c" pinned to a commit, or without
+    /// the ":commit" for the latest. Runs `buf build` so it needs buf on the PATH, and a
+    /// `buf registry login` for private modules
+    ///
+    /// ```no_run
+    ///   use katniss_pb2arrow::SchemaConverter;
+    ///
+    ///   let convert = SchemaConverter::from_buf_module("buf.build/acme/telemetry:main").unwrap();
+    /// ```
+    #[cfg(feature = "protoc")]
+    pub fn from_buf_module(reference: &str) -> Result<Self> {
+        let buf = which::which("buf")
+            .map_err(|e| KatnissArrowError::BufFailed(format!("couldn't find buf on path: {e}")))?;
+
+        // buf picks the output format from the extension
+        let image = tempfile::Builder::new().suffix(".binpb").tempfile()?;
+        let output = Command::new(buf)
+            .arg("build")
+            .arg(reference)
+            .arg("--as-file-descriptor-set")
+            .arg("-o")
+            .arg(image.path())
+            .output()?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(KatnissArrowError::BufFailed(stderr.trim().to_owned()));
+        }
+        Self::from_descriptor_set_file(image.path())
+    }
+
+    /// One converter with the files of all of them, i.e. a registry module plus local .proto
+    /// files that import it. A file that's in more than one has to be identical in each (comments
+    /// and line numbers aside), otherwise it fails with ConflictingFile, and so does the same
+    /// type defined in two different files
+    pub fn merge(converters: impl IntoIterator<Item = SchemaConverter>) -> Result<Self> {
+        let mut pool = DescriptorPool::new();
+        for converter in converters {
+            // files come dependencies first, so each one's imports are already in the pool
+            for file in converter.descriptor_pool.files() {
+                let proto = file.file_descriptor_proto();
+                let existing = pool.get_file_by_name(file.name());
+                match existing.as_ref().map(|f| f.file_descriptor_proto()) {
+                    Some(existing) if same_definitions(existing, proto) => {}
+                    Some(_) => {
+                        return Err(KatnissArrowError::ConflictingFile(file.name().to_owned()))
+                    }
+                    None => pool.add_file_descriptor_proto(proto.clone())?,
+                }
+            }
+        }
         Ok(Self::new(pool))
     }
 
+    pub fn descriptor_pool(&self) -> &DescriptorPool {
+        &self.descriptor_pool
+    }
+
+    /// Every message in the pool by full name, without the generated map entries
+    pub fn messages(&self) -> Vec<MessageDescriptor> {
+        let mut messages: Vec<_> = self
+            .descriptor_pool
+            .all_messages()
+            .filter(|msg| !msg.is_map_entry())
+            .collect();
+        messages.sort_by(|a, b| a.full_name().cmp(b.full_name()));
+        messages
+    }
+
+    /// Every enum in the pool by full name
+    pub fn enums(&self) -> Vec<EnumDescriptor> {
+        let mut enums: Vec<_> = self.descriptor_pool.all_enums().collect();
+        enums.sort_by(|a, b| a.full_name().cmp(b.full_name()));
+        enums
+    }
+
+    /// Messages in a package or the packages under it, i.e. "eto.pb2arrow" has
+    /// eto.pb2arrow.tests.v3.Foo but not eto.pb2arrowx.Bar
+    pub fn messages_in_package(&self, package: &str) -> Vec<MessageDescriptor> {
+        let mut messages = self.messages();
+        messages.retain(|msg| in_package(msg.package_name(), package));
+        messages
+    }
+
+    /// Enums in a package or the packages under it, see messages_in_package
+    pub fn enums_in_package(&self, package: &str) -> Vec<EnumDescriptor> {
+        let mut enums = self.enums();
+        enums.retain(|e| in_package(e.package_name(), package));
+        enums
+    }
+
+    /// Fields of any message that set a custom field option, with its value, i.e.
+    /// "acme.sensitive" for `string email = 1 [(acme.sensitive) = true];`.
+    /// Fails with DescriptorNotFound if no file in the pool defines the option
+    pub fn fields_with_option(&self, option: &str) -> Result<Vec<(FieldDescriptor, Value)>> {
+        let extension = self
+            .descriptor_pool
+            .get_extension_by_name(option)
+            .ok_or_else(|| KatnissArrowError::DescriptorNotFound(option.to_owned()))?;
+
+        let mut fields = Vec::new();
+        for msg in self.messages() {
+            for field in msg.fields() {
+                let options = field.options();
+                if options.has_extension(&extension) {
+                    let value = options.get_extension(&extension).into_owned();
+                    fields.push((field, value));
+                }
+            }
+        }
+        Ok(fields)
+    }
+
     /// Get the arrow schema of the protobuf message, specified by the qualified message name.
     pub fn get_arrow_schema(&self, name: &str, projection: &[&str]) -> Result<Option<Schema>> {
         let msg = match self.descriptor_pool.get_message_by_name(name) {
             Some(m) => m,
             None => return Ok(None),
         };
-        let mut field_converter = FieldConverter::new();
+        let mut field_converter = FieldConverter::with_dictionaries(self.enum_dictionaries.take());
         let schema = Schema::new(
             msg.fields()
                 .map(|f| field_converter.to_arrow_mut(&f))
                 .collect::<Vec<_>>(),
         );
+        let dictionaries = field_converter.dictionaries;
         self.dictionary_map
             .borrow_mut()
-            .insert(name.to_string(), field_converter.dictionaries);
+            .insert(name.to_string(), dictionaries.clone());
+        self.enum_dictionaries.replace(dictionaries);
 
         if projection.is_empty() {
             Ok(Some(schema))
@@ -221,6 +391,60 @@ impl SchemaConverter {
             .get_message_by_name(name)
             .ok_or_else(|| KatnissArrowError::DescriptorNotFound(name.to_owned()))
     }
+
+    /// A message by full name, or by its short name (or the end of its full name, i.e.
+    /// "v3.Foo") if only one message has it. Fails with AmbiguousName and the candidates
+    /// otherwise, see resolve_message_in_package
+    pub fn resolve_message(&self, name: &str) -> Result<MessageDescriptor> {
+        self.resolve_message_in_package("", name)
+    }
+
+    /// Like resolve_message but only messages in the package or the packages under it are
+    /// candidates, i.e. ("eto.pb2arrow.tests.v2", "Bar")
+    pub fn resolve_message_in_package(
+        &self,
+        package: &str,
+        name: &str,
+    ) -> Result<MessageDescriptor> {
+        if let Some(msg) = self.descriptor_pool.get_message_by_name(name) {
+            if in_package(msg.package_name(), package) {
+                return Ok(msg);
+            }
+        }
+
+        let suffix = format!(".{name}");
+        let mut candidates = self.messages_in_package(package);
+        candidates.retain(|msg| msg.full_name().ends_with(&suffix));
+        match candidates.len() {
+            0 => Err(KatnissArrowError::DescriptorNotFound(name.to_owned())),
+            1 => Ok(candidates.remove(0)),
+            _ => Err(KatnissArrowError::AmbiguousName(
+                name.to_owned(),
+                candidates
+                    .iter()
+                    .map(|m| m.full_name().to_owned())
+                    .collect(),
+            )),
+        }
+    }
+}
+
+/// Equal apart from source_code_info, which only has the comments and line numbers
+fn same_definitions(a: &FileDescriptorProto, b: &FileDescriptorProto) -> bool {
+    let definitions = |file: &FileDescriptorProto| FileDescriptorProto {
+        source_code_info: None,
+        ..file.clone()
+    };
+    definitions(a) == definitions(b)
+}
+
+/// The package or one nested in it, by whole name segments
+fn in_package(name: &str, package: &str) -> bool {
+    name == package
+        || package.is_empty()
+        || name
+            .strip_prefix(package)
+            .map_or(false, |rest| rest.starts_with('.'))
 }
 
 fn project_fields(prefix: &str, fields: &Fields, projection: &HashSet<&str>) -> Vec<Arc<Field>> {
@@ -289,4 +513,38 @@ mod tests {
         );
         Ok(())
     }
+
+    #[test]
+    fn enum_dictionaries_are_shared() -> Result<()> {
+        let converter = schema_converter()?;
+        let dict_id = |schema: &Schema, path: &[&str]| {
+            let mut field = schema.field_with_name(path[0]).unwrap();
+            for name in &path[1..] {
+                let DataType::Struct(children) = field.data_type() else {
+                    panic!("{} isn't a struct", field.name());
+                };
+                field = children.find(name).unwrap().1.as_ref();
+            }
+            field.dict_id().unwrap()
+        };
+
+        let packet = "eto.pb2arrow.tests.spacecorp.Packet";
+        let (schema, dictionaries) = converter.get_arrow_schema_with_dictionaries(packet, &[])?;
+        let schema = schema.unwrap();
+        let requested = dict_id(&schema, &["jump_drive_control", "requested_mode"]);
+        assert_eq!(requested, dict_id(&schema, &["jump_drive_status", "mode"]));
+
+        let (again, again_dictionaries) =
+            converter.get_arrow_schema_with_dictionaries(packet, &[])?;
+        assert_eq!(again.unwrap(), schema);
+        let values =
+            |d: Option<DictValuesContainer>| d.unwrap().get_dict_values(requested).cloned();
+        let (first, second) = (
+            values(dictionaries).unwrap(),
+            values(again_dictionaries).unwrap(),
+        );
+        assert_eq!(first.value(0), "OFF");
+        assert_eq!(first.values().as_ptr(), second.values().as_ptr());
+        Ok(())
+    }
 }
@@ -0,0 +1,78 @@
+//! Sources of google/protobuf's well known types, so protos that import them
+//! compile without pointing protoc at a protobuf include directory
+
+use std::path::Path;
+
+use tempfile::TempDir;
+
+use crate::Result;
+
+/// (import path, source) of every bundled file
+const WELL_KNOWN_TYPES: &[(&str, &str)] = &[
+    (
+        "google/protobuf/any.proto",
+        include_str!("../well_known_types/google/protobuf/any.proto"),
+    ),
+    (
+        "google/protobuf/api.proto",
+        include_str!("../well_known_types/google/protobuf/api.proto"),
+    ),
+    (
+        "google/protobuf/descriptor.proto",
+        include_str!("../well_known_types/google/protobuf/descriptor.proto"),
+    ),
+    (
+        "google/protobuf/duration.proto",
+        include_str!("../well_known_types/google/protobuf/duration.proto"),
+    ),
+    (
+        "google/protobuf/empty.proto",
+        include_str!("../well_known_types/google/protobuf/empty.proto"),
+    ),
+    (
+        "google/protobuf/field_mask.proto",
+        include_str!("../well_known_types/google/protobuf/field_mask.proto"),
+    ),
+    (
+        "google/protobuf/source_context.proto",
+        include_str!("../well_known_types/google/protobuf/source_context.proto"),
+    ),
+    (
+        "google/protobuf/struct.proto",
+        include_str!("../well_known_types/google/protobuf/struct.proto"),
+    ),
+    (
+        "google/protobuf/timestamp.proto",
+        include_str!("../well_known_types/google/protobuf/timestamp.proto"),
+    ),
+    (
+        "google/protobuf/type.proto",
+        include_str!("../well_known_types/google/protobuf/type.proto"),
+    ),
+    (
+        "google/protobuf/wrappers.proto",
+        include_str!("../well_known_types/google/protobuf/wrappers.proto"),
+    ),
+];
+
+/// The bundled files written out to a temporary include directory, None if protoc was
+/// installed with its own copies (in ../include next to the binary), which it already finds.
+/// Passed after the user's includes so copies in those win too
+pub(crate) fn include_dir(protoc: &Path) -> Result<Option<TempDir>> {
+    let protoc = std::fs::canonicalize(protoc)?;
+    let installed = protoc
+        .parent()
+        .and_then(Path::parent)
+        .map(|prefix| prefix.join("include/google/protobuf/descriptor.proto"));
+    if installed.is_some_and(|descriptor| descriptor.exists()) {
+        return Ok(None);
+    }
+
+    let dir = tempfile::tempdir()?;
+    for (name, source) in WELL_KNOWN_TYPES {
+        let path = dir.path().join(name);
+        std::fs::create_dir_all(path.parent().expect("bundled files are in a directory"))?;
+        std::fs::write(path, source)?;
+    }
+    Ok(Some(dir))
+}
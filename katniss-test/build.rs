@@ -10,8 +10,20 @@ fn main() -> Result<()> {
                 .join("file_descriptor_set.bin"),
         )
         .compile_protos(
-            &["spacecorp.proto", "version_2.proto", "version_3.proto"],
-            &["../protos/test"],
+            &[
+                "spacecorp.proto",
+                "version_2.proto",
+                "version_3.proto",
+                "encodings.proto",
+                "fixtures.proto",
+                "acme.proto",
+            ],
+            // the bundled well-known types, so acme.proto's import doesn't depend on protoc's
+            &[
+                "../protos/test",
+                "../protos",
+                "../katniss-pb2arrow/well_known_types",
+            ],
         )?;
     Ok(())
 }
@@ -67,6 +67,28 @@ pub fn to_dynamic<P: Message>(proto: &P, message_name: &str) -> Result<DynamicMe
     Ok(message)
 }
 
+/// Full name of the message made by reading_props
+pub const READING: &str = "eto.katniss.tests.Reading";
+
+/// Props for a Reading message with the given fields, i.e. "int32 id = 1; double value = 2;",
+/// for tests that need several versions of the same message
+#[cfg(feature = "protoc")]
+pub fn reading_props(fields: &str) -> Result<ArrowBatchProps> {
+    let dir = tempfile::tempdir()?;
+    let proto = dir.path().join("reading.proto");
+    std::fs::write(
+        &proto,
+        format!(
+            "syntax = \"proto3\";\npackage eto.katniss.tests;\nmessage Reading {{ {fields} }}\n"
+        ),
+    )?;
+    let converter = katniss_pb2arrow::SchemaConverter::compile(&[proto], &[dir.path()])?;
+    Ok(ArrowBatchProps::try_new(
+        converter.descriptor_pool().clone(),
+        READING.to_owned(),
+    )?)
+}
+
 fn type_name_of_val<T: ?Sized>(_val: &T) -> &'static str {
     type_name::<T>()
 }
@@ -0,0 +1,339 @@
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use arrow_schema::TimeUnit;
+use clap::{Args, ValueEnum};
+use futures::TryStreamExt;
+use object_store::ObjectMeta;
+
+use katniss::ingestor::{
+    follow_file, sinks::store_from_uri, Backfill, BackfillReport, EventTime, Framing,
+    PipelineBuilder, Predicate,
+};
+
+use crate::{schema::print_schema, serve::until_ctrl_c, OutputArgs, SchemaArgs};
+
+#[derive(Args)]
+pub struct BackfillArgs {
+    /// Files, directories whose files are read in name order, or glob patterns whose matches
+    /// are, i.e. 'logs/2024-06-*/packets.bin'. All of them go into the one output in the order given.
+    /// Gzip and zstd compressed files are decompressed as they're read
+    #[arg(required = true)]
+    inputs: Vec<PathBuf>,
+
+    #[command(flatten)]
+    schema: SchemaArgs,
+
+    #[command(flatten)]
+    output: OutputArgs,
+
+    /// Dot separated path to the event timestamp field, i.e. header.stamp
+    #[arg(long)]
+    event_time: String,
+
+    /// Unit of the event time field if it's an integer
+    #[arg(long, value_enum, default_value_t = Unit::Ms)]
+    event_time_unit: Unit,
+
+    /// Length of each window
+    #[arg(long, default_value_t = 60)]
+    period_secs: u64,
+
+    /// How long to wait for out of order messages before closing a window
+    #[arg(long, default_value_t = 0)]
+    allowed_lateness_secs: u64,
+
+    /// How messages are laid out in the files
+    #[arg(long, value_enum, default_value_t = InputFraming::LengthDelimited)]
+    framing: InputFraming,
+
+    /// Files are a single message of this type holding the messages in --wrapper-field,
+    /// instead of --framing
+    #[arg(long, requires = "wrapper_field", conflicts_with = "framing")]
+    wrapper_message: Option<String>,
+
+    #[arg(long, requires = "wrapper_message")]
+    wrapper_field: Option<String>,
+
+    /// Only ingest messages matching, i.e. "severity >= WARN", can be repeated
+    #[arg(long)]
+    filter: Vec<Predicate>,
+
+    /// Don't print progress to stderr
+    #[arg(long)]
+    no_progress: bool,
+
+    /// Only decode and convert this many messages (1000 if no number is given), then print
+    /// the schema, an estimate of the total and any errors instead of writing anything
+    #[arg(long, num_args = 0..=1, default_missing_value = "1000")]
+    dry_run: Option<usize>,
+
+    /// Skip this many messages from the start of the inputs, i.e. the offset printed by a run
+    /// that failed, so a rerun with the same inputs carries on without duplicating rows
+    #[arg(long, default_value_t = 0)]
+    resume_from_offset: u64,
+
+    /// Keep reading the (single, length delimited) input as it's appended to, rotating every
+    /// --period-secs until ctrl-c, i.e. to ingest a recorder's log while it's being written
+    #[arg(long, conflicts_with_all = ["dry_run", "resume_from_offset", "wrapper_message"])]
+    follow: bool,
+
+    /// Write a json summary of the run here (- for stdout): rows, bytes read and written,
+    /// drops, null rate per field and elapsed time
+    #[arg(long, conflicts_with_all = ["dry_run", "follow"])]
+    stats_json: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum InputFraming {
+    /// Varint length prefixes, i.e. protobuf's writeDelimitedTo
+    LengthDelimited,
+    /// 4 byte big endian length prefixes
+    FixedLength,
+    /// One message per line in protobuf's JSON mapping
+    JsonLines,
+    /// One base64 encoded message per line
+    Base64Lines,
+}
+
+impl From<InputFraming> for Framing {
+    fn from(framing: InputFraming) -> Self {
+        match framing {
+            InputFraming::LengthDelimited => Framing::LengthDelimited,
+            InputFraming::FixedLength => Framing::FixedLength,
+            InputFraming::JsonLines => Framing::JsonLines,
+            InputFraming::Base64Lines => Framing::Base64Lines,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Unit {
+    S,
+    Ms,
+    Us,
+    Ns,
+}
+
+impl From<Unit> for TimeUnit {
+    fn from(unit: Unit) -> Self {
+        match unit {
+            Unit::S => TimeUnit::Second,
+            Unit::Ms => TimeUnit::Millisecond,
+            Unit::Us => TimeUnit::Microsecond,
+            Unit::Ns => TimeUnit::Nanosecond,
+        }
+    }
+}
+
+pub async fn run(args: BackfillArgs) -> anyhow::Result<()> {
+    let props = args.schema.props()?;
+
+    let event_time = EventTime::new(&args.event_time)
+        .with_unit(args.event_time_unit.into())
+        .with_allowed_lateness(Duration::from_secs(args.allowed_lateness_secs));
+
+    let framing = match (args.wrapper_message, args.wrapper_field) {
+        (Some(message), Some(field)) => Framing::Wrapped { message, field },
+        _ => args.framing.into(),
+    };
+
+    let files = expand(&args.inputs)?;
+    if args.follow {
+        let [file] = files.as_slice() else {
+            anyhow::bail!("--follow takes a single file, got {}", files.len());
+        };
+        if !matches!(framing, Framing::LengthDelimited) {
+            anyhow::bail!("--follow only reads length delimited files");
+        }
+        let descriptor = props.descriptor.clone();
+        let sink = args.output.sink(&props)?;
+        let builder = PipelineBuilder::new(props, Duration::from_secs(args.period_secs), sink)
+            .with_event_time(event_time);
+        let builder = args
+            .filter
+            .into_iter()
+            .fold(builder, PipelineBuilder::with_filter);
+        let (head, mut tasks) = builder.build()?;
+        let file = file.clone();
+        let follower = tasks.spawn(async move { follow_file(&file, descriptor, head).await });
+        return until_ctrl_c(tasks, vec![follower]).await;
+    }
+
+    let job = args.filter.into_iter().fold(
+        Backfill::new(
+            props.clone(),
+            event_time,
+            Duration::from_secs(args.period_secs),
+        )
+        .with_framing(framing)
+        .with_resume_from(args.resume_from_offset),
+        Backfill::with_filter,
+    );
+
+    if let Some(limit) = args.dry_run {
+        print_schema(&props);
+        let report = job.dry_run(&files, limit).await?;
+        println!(
+            "converted {} messages from {} of {} files, about {} messages in all",
+            report.messages_converted,
+            report.files_read,
+            files.len(),
+            report.estimated_messages
+        );
+        for error in &report.errors {
+            println!("error: {error}");
+        }
+        if !report.errors.is_empty() {
+            anyhow::bail!("{} errors", report.errors.len());
+        }
+        return Ok(());
+    }
+
+    let mut sink = args.output.sink(&props)?;
+    let bytes_before = match args.stats_json {
+        Some(_) => output_bytes(&args.output.output).await?,
+        None => 0,
+    };
+    let total_bytes = files
+        .iter()
+        .map(|f| Ok(std::fs::metadata(f)?.len()))
+        .sum::<std::io::Result<u64>>()?;
+    let started = Instant::now();
+    let mut job = if args.no_progress {
+        job
+    } else {
+        let mut printed_at = started;
+        job.with_progress(move |report| {
+            if printed_at.elapsed() >= Duration::from_millis(500) {
+                printed_at = Instant::now();
+                eprint!(
+                    "\r{}",
+                    progress_line(report, total_bytes, started.elapsed())
+                );
+            }
+        })
+    };
+
+    let report = match job.run(&files, &mut sink).await {
+        Ok(report) => report,
+        Err(err) => {
+            eprintln!();
+            eprintln!("rerun with --resume-from-offset {}", job.offset());
+            return Err(err.into());
+        }
+    };
+    let elapsed = started.elapsed();
+    if !args.no_progress {
+        eprintln!("\r{}", progress_line(&report, total_bytes, elapsed));
+    }
+    println!("{report:#?}");
+    println!(
+        "took {:.1}s, final offset {}",
+        elapsed.as_secs_f64(),
+        report.offset
+    );
+
+    if let Some(path) = &args.stats_json {
+        let bytes_written = output_bytes(&args.output.output)
+            .await?
+            .saturating_sub(bytes_before);
+        let stats = run_stats(
+            &report,
+            props.descriptor.full_name(),
+            bytes_written,
+            elapsed,
+        );
+        let stats = serde_json::to_string_pretty(&stats)?;
+        if path.as_os_str() == "-" {
+            println!("{stats}");
+        } else {
+            std::fs::write(path, stats)?;
+        }
+    }
+    Ok(())
+}
+
+/// The --stats-json summary, i.e.
+/// `{"messages": 120, "rows": {"spacecorp.Packet": 118}, "null_rates": {"header.stamp": 0.0}, ...}`
+fn run_stats(
+    report: &BackfillReport,
+    message: &str,
+    bytes_written: u64,
+    elapsed: Duration,
+) -> serde_json::Value {
+    let rows = report.rows.max(1) as f64;
+    let null_rates: serde_json::Map<_, _> = report
+        .null_counts
+        .iter()
+        .map(|(column, nulls)| (column.clone(), (*nulls as f64 / rows).into()))
+        .collect();
+    serde_json::json!({
+        "elapsed_secs": elapsed.as_secs_f64(),
+        "files": report.files,
+        "bytes_read": report.bytes,
+        "bytes_written": bytes_written,
+        "messages": report.messages,
+        "buffers": report.buffers,
+        "rows": { message: report.rows },
+        "dropped": {
+            "filtered": report.dropped_filtered,
+            "late": report.dropped_late,
+            "missing_time": report.dropped_missing_time,
+        },
+        "null_rates": null_rates,
+        "offset": report.offset,
+    })
+}
+
+/// Total size of everything under the output, lance datasets included
+async fn output_bytes(uri: &str) -> anyhow::Result<u64> {
+    let (store, prefix) = store_from_uri(uri)?;
+    let files: Vec<ObjectMeta> = store.list(Some(&prefix)).await?.try_collect().await?;
+    Ok(files.iter().map(|f| f.size as u64).sum())
+}
+
+/// i.e. `[#########-----------]  45%  1.2/2.7 GB  81234 msgs/s  12.3 MB/s`
+fn progress_line(report: &BackfillReport, total_bytes: u64, elapsed: Duration) -> String {
+    const WIDTH: usize = 20;
+    let done = report.bytes as f64 / total_bytes.max(1) as f64;
+    let filled = ((done * WIDTH as f64) as usize).min(WIDTH);
+    let secs = elapsed.as_secs_f64().max(0.001);
+    let gb = |bytes: u64| bytes as f64 / 1e9;
+    format!(
+        "[{}{}] {:>3.0}%  {:.1}/{:.1} GB  {:.0} msgs/s  {:.1} MB/s",
+        "#".repeat(filled),
+        "-".repeat(WIDTH - filled),
+        done * 100.0,
+        gb(report.bytes),
+        gb(total_bytes),
+        report.messages as f64 / secs,
+        report.bytes as f64 / 1e6 / secs,
+    )
+}
+
+fn expand(inputs: &[PathBuf]) -> anyhow::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for input in inputs {
+        let pattern = input.to_string_lossy();
+        if !input.exists() && pattern.contains(['*', '?', '[']) {
+            let mut matched = glob::glob(&pattern)?.collect::<Result<Vec<_>, _>>()?;
+            matched.retain(|f| f.is_file());
+            if matched.is_empty() {
+                anyhow::bail!("no files match {pattern}");
+            }
+            matched.sort();
+            files.extend(matched);
+        } else if input.is_dir() {
+            let mut dir = std::fs::read_dir(input)?
+                .map(|entry| Ok(entry?.path()))
+                .collect::<std::io::Result<Vec<_>>>()?;
+            dir.retain(|f| f.is_file());
+            dir.sort();
+            files.extend(dir);
+        } else {
+            files.push(input.clone());
+        }
+    }
+    Ok(files)
+}
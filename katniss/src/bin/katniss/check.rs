@@ -0,0 +1,81 @@
+use std::path::PathBuf;
+
+use clap::Args;
+use tokio::net::TcpListener;
+
+use katniss::ingestor::sinks::store_from_uri;
+use katniss::pb2arrow::RecordConverter;
+
+use crate::config::{load, PipelineEntry};
+
+#[derive(Args)]
+pub struct CheckArgs {
+    /// Same toml file as `katniss run --config`
+    config: PathBuf,
+}
+
+/// Goes through everything a pipeline needs before it's started, printing a line per check.
+/// Fails if any of them did
+pub async fn run(args: CheckArgs) -> anyhow::Result<()> {
+    let mut failed = 0;
+    for (i, pipeline) in load(&args.config)?.iter().enumerate() {
+        for (check, result) in check_pipeline(pipeline).await {
+            match result {
+                Ok(()) => println!("pipeline {i}: {check} ok"),
+                Err(err) => {
+                    failed += 1;
+                    println!("pipeline {i}: {check} FAILED: {err:#}");
+                }
+            }
+        }
+    }
+    if failed > 0 {
+        anyhow::bail!("{failed} checks failed");
+    }
+    Ok(())
+}
+
+async fn check_pipeline(pipeline: &PipelineEntry) -> Vec<(String, anyhow::Result<()>)> {
+    let mut checks = Vec::new();
+    if let Err(err) = pipeline.sources.pool() {
+        checks.push(("descriptors".to_owned(), Err(err)));
+        return checks;
+    }
+    checks.push(("descriptors".to_owned(), Ok(())));
+
+    // the message has to exist and every field's type convert for props to be made
+    let props = match pipeline.props() {
+        Ok(props) => props,
+        Err(err) => {
+            checks.push(("message schema".to_owned(), Err(err)));
+            return checks;
+        }
+    };
+    let converter = RecordConverter::try_new(&props).map(|_| ());
+    checks.push((
+        format!("{} schema", props.descriptor.full_name()),
+        converter.map_err(Into::into),
+    ));
+
+    let listen = TcpListener::bind(&pipeline.listen).await.map(|_| ());
+    checks.push((
+        format!("listen on {}", pipeline.listen),
+        listen.map_err(Into::into),
+    ));
+
+    for sink in &pipeline.pipeline.sinks {
+        let result = match sink.sink(&props) {
+            Ok(_) => reachable(&sink.output).await,
+            Err(err) => Err(err.into()),
+        };
+        checks.push((format!("sink {}", sink.output), result));
+    }
+    checks
+}
+
+/// Lists the output's prefix, which needs the store to be up and the credentials to work
+async fn reachable(uri: &str) -> anyhow::Result<()> {
+    let (store, prefix) = store_from_uri(uri)?;
+    store.list_with_delimiter(Some(&prefix)).await?;
+    Ok(())
+}
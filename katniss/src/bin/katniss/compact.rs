@@ -0,0 +1,39 @@
+use clap::Args;
+
+use katniss::ingestor::{sinks::ParquetEncoder, Compactor};
+
+use crate::{compression, Codec};
+
+#[derive(Args)]
+pub struct CompactArgs {
+    /// Directories of parquet files, i.e. s3://bucket/packets/2023-03-08/20
+    #[arg(required = true)]
+    partitions: Vec<String>,
+
+    /// Files are merged up to about this size, bigger ones are left alone
+    #[arg(long, default_value_t = 128)]
+    target_file_size_mb: usize,
+
+    /// Compression codec of the merged files
+    #[arg(long, value_enum, default_value_t = Codec::Snappy)]
+    compression: Codec,
+
+    /// Level for zstd (1-22) or gzip (0-10), the codec's default if not given
+    #[arg(long)]
+    compression_level: Option<u32>,
+}
+
+pub async fn run(args: CompactArgs) -> anyhow::Result<()> {
+    let encoder = ParquetEncoder::new()
+        .with_compression(compression(args.compression, args.compression_level)?);
+
+    for partition in &args.partitions {
+        let report = Compactor::from_uri(partition)?
+            .with_target_file_size(args.target_file_size_mb * 1024 * 1024)
+            .with_encoder(encoder.clone())
+            .compact()
+            .await?;
+        println!("{partition}: {report:?}");
+    }
+    Ok(())
+}
@@ -0,0 +1,173 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use clap::Args;
+use serde::Deserialize;
+use tokio::net::TcpListener;
+use toml::{Table, Value};
+
+use katniss::ingestor::{
+    serve_tcp,
+    sinks::{SinkConfig, SinkFormat},
+    LoopJoinSet, PipelineConfig,
+};
+use katniss::pb2arrow::ArrowBatchProps;
+
+use crate::{kept_columns, serve::until_ctrl_c, SchemaSources};
+
+#[derive(Args)]
+pub struct RunArgs {
+    /// Toml file with a [[pipeline]] per message, each with one or more [[pipeline.sink]]s.
+    /// Top level keys apply to every pipeline, sink keys outside a sink to each of its sinks
+    #[arg(long)]
+    config: PathBuf,
+}
+
+/// One `[[pipeline]]` of the config, where its descriptors come from and what it listens on.
+/// The rest of its keys are a PipelineConfig, with a SinkConfig per `[[pipeline.sink]]`.
+/// Top level keys apply to every pipeline. Sink keys at the top level or on a pipeline are
+/// defaults for each of its sinks, or its only sink if it has no `[[pipeline.sink]]`s, i.e.
+///
+/// ```toml
+/// descriptor_set = ["protos.desc"]
+/// compression = "zstd"
+///
+/// [[pipeline]]
+/// message = "spacecorp.Packet"
+/// listen = "0.0.0.0:7878"
+/// period = 300
+/// exclude = ["camera.image"]
+///
+/// [[pipeline.sink]]
+/// format = "parquet"
+/// output = "s3://archive/packets"
+/// file_naming = "{date}/{hour}/{start}.{ext}"
+/// manifests = true
+///
+/// [[pipeline.sink]]
+/// output = "file:///data/packets.lance"
+/// ```
+#[derive(Deserialize)]
+pub struct PipelineEntry {
+    #[serde(flatten)]
+    pub sources: SchemaSources,
+
+    #[serde(default = "PipelineEntry::default_listen")]
+    pub listen: String,
+
+    /// Columns to drop after the projection, like `katniss serve --exclude`
+    #[serde(default)]
+    pub exclude: Vec<String>,
+
+    #[serde(flatten)]
+    pub pipeline: PipelineConfig,
+}
+
+impl PipelineEntry {
+    fn default_listen() -> String {
+        "0.0.0.0:7878".to_owned()
+    }
+
+    pub fn props(&self) -> anyhow::Result<ArrowBatchProps> {
+        let pool = self.sources.pool()?;
+        let props = self.pipeline.batch.props(pool.clone())?;
+        if self.exclude.is_empty() {
+            return Ok(props);
+        }
+
+        let kept = kept_columns("", props.schema.fields(), &self.exclude);
+        if kept.is_empty() {
+            anyhow::bail!("exclude drops every column");
+        }
+        let mut batch = self.pipeline.batch.clone();
+        batch.projection = kept;
+        Ok(batch.props(pool)?)
+    }
+}
+
+/// Every pipeline in the config with its sinks, top level keys merged into each pipeline
+pub fn load(path: &Path) -> anyhow::Result<Vec<PipelineEntry>> {
+    let mut config: Table = std::fs::read_to_string(path)?.parse()?;
+    let pipelines = match config.remove("pipeline") {
+        Some(Value::Array(pipelines)) => pipelines,
+        _ => anyhow::bail!("{path:?} has no [[pipeline]]s"),
+    };
+
+    let mut entries = Vec::new();
+    for (i, pipeline) in pipelines.into_iter().enumerate() {
+        let Value::Table(pipeline) = pipeline else {
+            anyhow::bail!("pipeline {i} isn't a table");
+        };
+        let mut merged = config.clone();
+        merged.extend(pipeline);
+        apply_sink_defaults(&mut merged).with_context(|| format!("pipeline {i}"))?;
+        let entry: PipelineEntry = Value::Table(merged)
+            .try_into()
+            .with_context(|| format!("pipeline {i}"))?;
+        if entry.pipeline.sinks.is_empty() {
+            anyhow::bail!("pipeline {i} has no [[pipeline.sink]]s");
+        }
+        entry
+            .pipeline
+            .validate()
+            .with_context(|| format!("pipeline {i}"))?;
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+/// Moves sink keys given directly on the pipeline into each of its sinks that doesn't set
+/// them, or into a sink of their own if there are none
+fn apply_sink_defaults(pipeline: &mut Table) -> anyhow::Result<()> {
+    let keys = match serde_json::to_value(SinkConfig::new("", SinkFormat::default()))? {
+        serde_json::Value::Object(sink) => sink.into_iter().map(|(key, _)| key),
+        _ => unreachable!("SinkConfig serializes as a map"),
+    };
+    let defaults: Table = keys
+        .filter_map(|key| pipeline.remove(&key).map(|value| (key, value)))
+        .collect();
+    if defaults.is_empty() {
+        return Ok(());
+    }
+
+    let sinks = pipeline
+        .entry("sink")
+        .or_insert_with(|| Value::Array(vec![Value::Table(Table::new())]));
+    let Value::Array(sinks) = sinks else {
+        anyhow::bail!("sink should be [[pipeline.sink]]s");
+    };
+    for sink in sinks {
+        let Value::Table(sink) = sink else {
+            anyhow::bail!("sink should be [[pipeline.sink]]s");
+        };
+        for (key, value) in &defaults {
+            sink.entry(key).or_insert_with(|| value.clone());
+        }
+    }
+    Ok(())
+}
+
+/// Starts every pipeline in the config, each with its own listener, and runs until ctrl-c
+pub async fn run(args: RunArgs) -> anyhow::Result<()> {
+    let mut tasks = LoopJoinSet::new();
+    let mut servers = Vec::new();
+    for entry in load(&args.config)? {
+        let props = entry.props()?;
+        let sink = entry.pipeline.sink(&props)?;
+        let descriptor = props.descriptor.clone();
+        let head = entry
+            .pipeline
+            .builder_with_props(props, sink)?
+            .spawn_into(&mut tasks)?;
+
+        let listener = TcpListener::bind(&entry.listen).await?;
+        println!(
+            "{} listening on {}",
+            descriptor.full_name(),
+            listener.local_addr()?
+        );
+        servers.push(tasks.spawn(serve_tcp(listener, descriptor, head)));
+    }
+
+    until_ctrl_c(tasks, servers).await
+}
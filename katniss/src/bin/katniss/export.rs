@@ -0,0 +1,96 @@
+use std::fs::File;
+use std::io::{stdout, BufWriter};
+use std::path::PathBuf;
+
+use clap::Args;
+use futures::{stream, TryStreamExt};
+use object_store::ObjectMeta;
+
+use katniss::ingestor::{
+    export, sinks::store_from_uri, DelimitedWriter, ExportReport, LanceMessageReader,
+    MessageWriter, ParquetMessageReader,
+};
+use katniss::pb2arrow::exports::prost_reflect::MessageDescriptor;
+
+use crate::tail::is_lance;
+
+#[derive(Args)]
+pub struct ExportArgs {
+    /// A lance dataset, a parquet file or a directory of them written with their descriptor,
+    /// i.e. file:///data/packets
+    input: String,
+
+    /// File to write, stdout if not given
+    #[arg(long, short)]
+    output: Option<PathBuf>,
+
+    /// Write an MCAP recording with the messages on this topic instead of length delimited
+    /// messages
+    #[cfg(feature = "mcap")]
+    #[arg(long, requires = "output")]
+    mcap_topic: Option<String>,
+
+    /// Field to take MCAP log times from, i.e. header.stamp, the export time if not given
+    #[cfg(feature = "mcap")]
+    #[arg(long, requires = "mcap_topic")]
+    event_time: Option<String>,
+}
+
+impl ExportArgs {
+    #[cfg_attr(not(feature = "mcap"), allow(unused_variables))]
+    fn writer(&self, descriptor: &MessageDescriptor) -> anyhow::Result<Box<dyn MessageWriter>> {
+        let Some(output) = &self.output else {
+            return Ok(Box::new(DelimitedWriter::new(BufWriter::new(stdout()))));
+        };
+        let file = BufWriter::new(File::create(output)?);
+        #[cfg(feature = "mcap")]
+        if let Some(topic) = &self.mcap_topic {
+            let writer = katniss::ingestor::McapWriter::try_new(file, topic, descriptor)?;
+            return Ok(Box::new(match &self.event_time {
+                Some(field) => writer.with_event_time(katniss::ingestor::EventTime::new(field)),
+                None => writer,
+            }));
+        }
+        Ok(Box::new(DelimitedWriter::new(file)))
+    }
+}
+
+/// Messages go out in storage order, for a directory of parquet files that's by file name
+pub async fn run(args: ExportArgs) -> anyhow::Result<()> {
+    let (store, prefix) = store_from_uri(&args.input)?;
+    let report = if is_lance(store.as_ref(), &prefix).await? {
+        let reader = LanceMessageReader::open(&args.input).await?;
+        let mut writer = args.writer(reader.descriptor())?;
+        let report = export(reader.messages().await?, writer.as_mut()).await?;
+        writer.finish()?;
+        report
+    } else {
+        let mut files: Vec<ObjectMeta> = store.list(Some(&prefix)).await?.try_collect().await?;
+        files.retain(|f| f.location.extension() == Some("parquet"));
+        files.sort_by(|a, b| a.location.cmp(&b.location));
+
+        let mut report = ExportReport::default();
+        let mut writer = None;
+        for file in &files {
+            let bytes = store.get(&file.location).await?.bytes().await?;
+            let reader = ParquetMessageReader::try_new(bytes)?;
+            let writer = match &mut writer {
+                Some(writer) => writer,
+                None => writer.insert(args.writer(reader.descriptor())?),
+            };
+            let exported = export(stream::iter(reader), writer.as_mut()).await?;
+            report.messages += exported.messages;
+            report.bytes += exported.bytes;
+        }
+        match writer {
+            Some(mut writer) => writer.finish()?,
+            None => anyhow::bail!("no parquet files or lance dataset at {}", args.input),
+        }
+        report
+    };
+    eprintln!(
+        "exported {} messages ({} bytes)",
+        report.messages, report.bytes
+    );
+    Ok(())
+}
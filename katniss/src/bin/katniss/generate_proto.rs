@@ -0,0 +1,64 @@
+use std::path::PathBuf;
+
+use arrow_schema::Schema;
+use clap::Args;
+use futures::TryStreamExt;
+use lance::dataset::Dataset;
+use object_store::ObjectMeta;
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+use katniss::ingestor::{
+    proto_from_schema,
+    sinks::{descriptor_from_schema, store_from_uri},
+};
+
+use crate::tail::is_lance;
+
+#[derive(Args)]
+pub struct GenerateProtoArgs {
+    /// A lance dataset, a parquet file or a directory of them (the first one is used),
+    /// i.e. file:///data/readings
+    input: String,
+
+    /// Fully qualified name for the message, i.e. acme.telemetry.Reading. Defaults to the
+    /// embedded descriptor's message for outputs katniss wrote
+    #[arg(long)]
+    message: Option<String>,
+
+    /// File to write, stdout if not given
+    #[arg(long, short)]
+    output: Option<PathBuf>,
+}
+
+pub async fn run(args: GenerateProtoArgs) -> anyhow::Result<()> {
+    let schema = schema(&args.input).await?;
+    let message = match (args.message, descriptor_from_schema(&schema)?) {
+        (Some(message), _) => message,
+        (None, Some(descriptor)) => descriptor.full_name().to_owned(),
+        (None, None) => anyhow::bail!("{} has no embedded descriptor, pass --message", args.input),
+    };
+
+    let proto = proto_from_schema(&schema, &message)?;
+    match args.output {
+        Some(path) => std::fs::write(path, proto)?,
+        None => print!("{proto}"),
+    }
+    Ok(())
+}
+
+async fn schema(input: &str) -> anyhow::Result<Schema> {
+    let (store, prefix) = store_from_uri(input)?;
+    if is_lance(store.as_ref(), &prefix).await? {
+        let dataset = Dataset::open(input).await?;
+        return Ok(Schema::from(dataset.schema()));
+    }
+
+    let mut files: Vec<ObjectMeta> = store.list(Some(&prefix)).await?.try_collect().await?;
+    files.retain(|f| f.location.extension() == Some("parquet"));
+    let Some(file) = files.iter().min_by(|a, b| a.location.cmp(&b.location)) else {
+        anyhow::bail!("no parquet files or lance dataset at {input}");
+    };
+    let bytes = store.get(&file.location).await?.bytes().await?;
+    let builder = ParquetRecordBatchReaderBuilder::try_new(bytes)?;
+    Ok(builder.schema().as_ref().clone())
+}
@@ -0,0 +1,46 @@
+use std::time::Duration;
+
+use clap::Args;
+
+use katniss::ingestor::{consume_kafka, kafka_consumer, PipelineBuilder};
+
+use crate::{serve::until_ctrl_c, OutputArgs, SchemaArgs};
+
+#[derive(Args)]
+pub struct KafkaArgs {
+    #[command(flatten)]
+    schema: SchemaArgs,
+
+    #[command(flatten)]
+    output: OutputArgs,
+
+    /// Comma separated host:port list, i.e. kafka-1:9092,kafka-2:9092
+    #[arg(long, env = "KATNISS_KAFKA_BROKERS")]
+    kafka_brokers: String,
+
+    /// Topic whose records are each one --message
+    #[arg(long)]
+    topic: String,
+
+    /// Consumer group, offsets are committed once a record's buffer is written
+    #[arg(long, default_value = "katniss")]
+    group_id: String,
+
+    /// How often buffers are rotated into the sink
+    #[arg(long, default_value_t = 60)]
+    period_secs: u64,
+}
+
+/// Runs until ctrl-c, then flushes what's buffered to the sink before exiting
+pub async fn run(args: KafkaArgs) -> anyhow::Result<()> {
+    let props = args.schema.props()?;
+    let sink = args.output.sink(&props)?;
+    let descriptor = props.descriptor.clone();
+
+    let consumer = kafka_consumer(&args.kafka_brokers, &args.group_id, &args.topic)?;
+    let builder = PipelineBuilder::new(props, Duration::from_secs(args.period_secs), sink);
+    let handle = builder.handle();
+    let (head, mut tasks) = builder.build_acked()?;
+    let source = tasks.spawn(consume_kafka(consumer, descriptor, head, handle));
+    until_ctrl_c(tasks, vec![source]).await
+}
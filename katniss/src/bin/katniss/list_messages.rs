@@ -0,0 +1,29 @@
+use std::path::PathBuf;
+
+use clap::Args;
+
+use katniss::pb2arrow::SchemaConverter;
+
+#[derive(Args)]
+pub struct ListMessagesArgs {
+    /// Serialized FileDescriptorSet, i.e. from `protoc --include_imports -o`
+    #[arg(long)]
+    descriptor_set: PathBuf,
+
+    /// Only messages in this package or the ones under it, i.e. eto.pb2arrow.tests
+    #[arg(long)]
+    package: Option<String>,
+}
+
+pub fn run(args: ListMessagesArgs) -> anyhow::Result<()> {
+    let converter = SchemaConverter::from_descriptor_set_file(&args.descriptor_set)?;
+    let messages = match &args.package {
+        Some(package) => converter.messages_in_package(package),
+        None => converter.messages(),
+    };
+
+    for msg in messages {
+        println!("{}\t{} fields", msg.full_name(), msg.fields().len());
+    }
+    Ok(())
+}
@@ -0,0 +1,443 @@
+//! Command line tools for getting protobufs into (and out of) columnar storage
+
+mod backfill;
+mod check;
+mod compact;
+mod config;
+mod export;
+mod generate_proto;
+#[cfg(feature = "kafka")]
+mod kafka;
+mod list_messages;
+mod migrate;
+mod schema;
+mod serve;
+mod tail;
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use arrow_schema::{DataType, Fields};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use lance::dataset::Dataset;
+use parquet::basic::Compression;
+use prost_reflect::{DescriptorPool, MessageDescriptor};
+use serde::Deserialize;
+
+use katniss::ingestor::{
+    lance_descriptor,
+    sinks::{self, BufferSink, ColumnStatistics, PartitionBy, SinkConfig, SinkFormat},
+};
+use katniss::pb2arrow::{ArrowBatchProps, SchemaConverter};
+
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Reprocess recorded messages through event time windows into a sink
+    Backfill(backfill::BackfillArgs),
+    /// Check a `katniss run` config's schemas, listeners and outputs without starting it
+    Check(check::CheckArgs),
+    /// Merge small parquet files in output directories into bigger ones
+    Compact(compact::CompactArgs),
+    /// Write the messages in a lance or parquet output back out as a protobuf stream
+    Export(export::ExportArgs),
+    /// Print a .proto definition for the schema of a lance or parquet output
+    GenerateProto(generate_proto::GenerateProtoArgs),
+    /// Ingest a topic of protobuf records into a sink
+    #[cfg(feature = "kafka")]
+    Kafka(kafka::KafkaArgs),
+    /// Print the messages in a descriptor set and their field counts
+    ListMessages(list_messages::ListMessagesArgs),
+    /// Convert a directory of parquet files into lance datasets
+    Migrate(migrate::MigrateArgs),
+    /// Print the arrow schema a message converts to
+    Schema(schema::PrintSchemaArgs),
+    /// Run the pipelines described in a toml config
+    Run(config::RunArgs),
+    /// Ingest length delimited messages sent over tcp into a sink
+    Serve(serve::ServeArgs),
+    /// Print the last (or a sample of) rows of a lance or parquet output
+    #[command(alias = "cat")]
+    Tail(tail::TailArgs),
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    match Cli::parse().command {
+        Command::Backfill(args) => backfill::run(args).await,
+        Command::Check(args) => check::run(args).await,
+        Command::Compact(args) => compact::run(args).await,
+        Command::Export(args) => export::run(args).await,
+        Command::GenerateProto(args) => generate_proto::run(args).await,
+        #[cfg(feature = "kafka")]
+        Command::Kafka(args) => kafka::run(args).await,
+        Command::ListMessages(args) => list_messages::run(args),
+        Command::Migrate(args) => migrate::run(args).await,
+        Command::Schema(args) => schema::run(args),
+        Command::Run(args) => config::run(args).await,
+        Command::Serve(args) => serve::run(args).await,
+        Command::Tail(args) => tail::run(args).await,
+    }
+}
+
+/// Where to find the message being ingested
+#[derive(Args)]
+pub struct SchemaArgs {
+    #[command(flatten)]
+    sources: SchemaSources,
+
+    /// Fully qualified message name, i.e. eto.pb2arrow.tests.spacecorp.Packet, or just Packet
+    /// if no other package has one, see `katniss list-messages`
+    #[arg(long)]
+    message: String,
+
+    /// Only these columns, dot separated for nested fields, i.e. --include header,pose.position
+    #[arg(long, value_delimiter = ',')]
+    include: Vec<String>,
+
+    /// Drop these columns (after --include), i.e. --exclude camera.image
+    #[arg(long, value_delimiter = ',')]
+    exclude: Vec<String>,
+}
+
+impl SchemaArgs {
+    pub fn props(&self) -> anyhow::Result<ArrowBatchProps> {
+        let pool = self.pool()?;
+        let message = SchemaConverter::new(pool.clone())
+            .resolve_message(&self.message)?
+            .full_name()
+            .to_owned();
+        let unknown = {
+            let everything =
+                ArrowBatchProps::try_new_projected(pool.clone(), message.clone(), &[])?;
+            self.include
+                .iter()
+                .chain(&self.exclude)
+                .filter(|path| !has_column(everything.schema.fields(), path))
+                .map(String::as_str)
+                .collect::<Vec<_>>()
+        };
+        if !unknown.is_empty() {
+            anyhow::bail!("{message} has no field {}", unknown.join(", "));
+        }
+
+        let include: Vec<_> = self.include.iter().map(String::as_str).collect();
+        let props = ArrowBatchProps::try_new_projected(pool.clone(), message.clone(), &include)?;
+        if self.exclude.is_empty() {
+            return Ok(props);
+        }
+
+        let kept = kept_columns("", props.schema.fields(), &self.exclude);
+        if kept.is_empty() {
+            anyhow::bail!("--exclude drops every column");
+        }
+        let kept: Vec<_> = kept.iter().map(String::as_str).collect();
+        Ok(ArrowBatchProps::try_new_projected(pool, message, &kept)?)
+    }
+
+    pub fn pool(&self) -> anyhow::Result<DescriptorPool> {
+        self.sources.pool()
+    }
+}
+
+/// Descriptors to load, the same flags (or keys of a `katniss run` config) for every command
+#[derive(Args, Default, Deserialize)]
+#[serde(default)]
+pub struct SchemaSources {
+    /// Serialized FileDescriptorSet, i.e. from `protoc --include_imports -o`, can be repeated.
+    /// Schema sources can be combined, their files are merged into one pool
+    #[arg(long, required_unless_present_any = ["proto", "buf_module"])]
+    descriptor_set: Vec<PathBuf>,
+
+    /// Buf Schema Registry module, i.e. buf.build/acme/telemetry:<commit>, can be repeated.
+    /// Needs buf on the PATH
+    #[arg(long)]
+    buf_module: Vec<String>,
+
+    /// .proto files to compile, can be repeated. Needs protoc on the PATH
+    #[arg(long)]
+    proto: Vec<PathBuf>,
+
+    /// Import paths for --proto, can be repeated
+    #[arg(long, short = 'I', requires = "proto")]
+    proto_path: Vec<PathBuf>,
+}
+
+impl SchemaSources {
+    pub fn pool(&self) -> anyhow::Result<DescriptorPool> {
+        let mut converters = Vec::new();
+        for reference in &self.buf_module {
+            converters.push(SchemaConverter::from_buf_module(reference)?);
+        }
+        for descriptor_set in &self.descriptor_set {
+            converters.push(SchemaConverter::from_descriptor_set_file(descriptor_set)?);
+        }
+        if !self.proto.is_empty() {
+            converters.push(SchemaConverter::compile(
+                self.proto.as_slice(),
+                self.proto_path.as_slice(),
+            )?);
+        }
+        Ok(SchemaConverter::merge(converters)?
+            .descriptor_pool()
+            .clone())
+    }
+}
+
+/// Whether the dot separated path names a column, nested ones through struct columns
+fn has_column(fields: &Fields, path: &str) -> bool {
+    let (name, rest) = match path.split_once('.') {
+        Some((name, rest)) => (name, Some(rest)),
+        None => (path, None),
+    };
+    let Some(field) = fields.iter().find(|f| f.name() == name) else {
+        return false;
+    };
+    match (rest, field.data_type()) {
+        (None, _) => true,
+        (Some(rest), DataType::Struct(children)) => has_column(children, rest),
+        (Some(_), _) => false,
+    }
+}
+
+/// The shallowest column paths that cover every field but the excluded ones
+fn kept_columns(prefix: &str, fields: &Fields, exclude: &[String]) -> Vec<String> {
+    let mut kept = Vec::new();
+    for field in fields {
+        let path = if prefix.is_empty() {
+            field.name().to_owned()
+        } else {
+            format!("{prefix}.{}", field.name())
+        };
+        let nested = format!("{path}.");
+        if exclude.contains(&path) {
+            continue;
+        }
+        match field.data_type() {
+            DataType::Struct(children) if exclude.iter().any(|e| e.starts_with(&nested)) => {
+                kept.extend(kept_columns(&path, children, exclude));
+            }
+            _ => kept.push(path),
+        }
+    }
+    kept
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum OutputFormat {
+    Lance,
+    Parquet,
+    Arrow,
+    Jsonl,
+    Csv,
+    #[cfg(feature = "avro")]
+    Avro,
+}
+
+/// Where rotated buffers get written
+#[derive(Args, Clone)]
+pub struct OutputArgs {
+    /// Storage uri, i.e. file:///data/packets or s3://bucket/packets
+    #[arg(long)]
+    output: String,
+
+    #[arg(long, value_enum, default_value_t = OutputFormat::Lance)]
+    format: OutputFormat,
+
+    /// How the first buffer is written to a lance dataset, later ones always append
+    #[arg(long, value_enum, default_value_t = LanceMode::Append)]
+    lance_mode: LanceMode,
+
+    /// Delete all but this many of the newest lance dataset versions after each write
+    #[arg(long)]
+    lance_max_versions: Option<usize>,
+
+    /// Delete lance rows from buffers that ended more than this many seconds ago
+    #[arg(long)]
+    lance_row_ttl_secs: Option<u64>,
+
+    /// Parquet compression codec
+    #[arg(long, value_enum, default_value_t = Codec::Snappy)]
+    compression: Codec,
+
+    /// Level for zstd (1-22) or gzip (0-10), the codec's default if not given
+    #[arg(long)]
+    compression_level: Option<u32>,
+
+    /// Parquet column to write bloom filters for, i.e. header.device_id, can be repeated
+    #[arg(long)]
+    bloom_filter: Vec<String>,
+
+    /// Parquet column statistics level, i.e. payload=none or ts=page, can be repeated
+    #[arg(long, value_parser = parse_statistics)]
+    statistics: Vec<(String, Statistics)>,
+
+    /// Parquet column encoding, i.e. counter=DELTA_BINARY_PACKED, can be repeated.
+    /// Added to encodings from katniss.parquet_encoding field options
+    #[arg(long, value_parser = parse_encoding)]
+    encoding: Vec<(String, String)>,
+
+    /// Sort each parquet file's rows by this column, i.e. timestamp or header.stamp
+    #[arg(long)]
+    sort_column: Option<String>,
+
+    /// Finish each output directory with a _manifest.jsonl and a _SUCCESS marker
+    #[arg(long)]
+    manifests: bool,
+
+    /// File names (and so partitions) relative to the output, i.e. "{date}/{hour}/{start}.{ext}",
+    /// see FileNaming::template for the placeholders
+    #[arg(long)]
+    file_naming: Option<String>,
+
+    /// Hive style partitions, time:day or time:hour for date=2024-06-01/hour=13/ directories
+    /// (not with --file-naming) and field:<path> for header.device_id=7/ directories.
+    /// Can be repeated, field partitions nest in the order given with time innermost
+    #[arg(long, value_parser = parse_partition_by)]
+    partition_by: Vec<PartitionBy>,
+}
+
+fn parse_partition_by(arg: &str) -> Result<PartitionBy, String> {
+    arg.parse()
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum LanceMode {
+    Create,
+    Append,
+    Overwrite,
+}
+
+impl From<LanceMode> for sinks::LanceMode {
+    fn from(mode: LanceMode) -> Self {
+        match mode {
+            LanceMode::Create => Self::Create,
+            LanceMode::Append => Self::Append,
+            LanceMode::Overwrite => Self::Overwrite,
+        }
+    }
+}
+
+impl From<OutputFormat> for SinkFormat {
+    fn from(format: OutputFormat) -> Self {
+        match format {
+            OutputFormat::Lance => Self::Lance,
+            OutputFormat::Parquet => Self::Parquet,
+            OutputFormat::Arrow => Self::Arrow,
+            OutputFormat::Jsonl => Self::Jsonl,
+            OutputFormat::Csv => Self::Csv,
+            #[cfg(feature = "avro")]
+            OutputFormat::Avro => Self::Avro,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum Codec {
+    None,
+    Snappy,
+    Lz4,
+    Zstd,
+    Gzip,
+}
+
+impl From<Codec> for sinks::Codec {
+    fn from(codec: Codec) -> Self {
+        match codec {
+            Codec::None => Self::None,
+            Codec::Snappy => Self::Snappy,
+            Codec::Lz4 => Self::Lz4,
+            Codec::Zstd => Self::Zstd,
+            Codec::Gzip => Self::Gzip,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Statistics {
+    None,
+    Chunk,
+    Page,
+}
+
+impl From<Statistics> for ColumnStatistics {
+    fn from(statistics: Statistics) -> Self {
+        match statistics {
+            Statistics::None => Self::None,
+            Statistics::Chunk => Self::Chunk,
+            Statistics::Page => Self::Page,
+        }
+    }
+}
+
+/// Level is for zstd or gzip, the codec's default if not given
+fn compression(codec: Codec, level: Option<u32>) -> anyhow::Result<Compression> {
+    Ok(sinks::Codec::from(codec).compression(level)?)
+}
+
+fn parse_statistics(arg: &str) -> Result<(String, Statistics), String> {
+    let (column, level) = arg
+        .split_once('=')
+        .ok_or_else(|| format!("expected column=level, got {arg}"))?;
+    Ok((column.to_owned(), Statistics::from_str(level, true)?))
+}
+
+/// Checked up front, SinkConfig keeps the name
+fn parse_encoding(arg: &str) -> Result<(String, String), String> {
+    let (column, encoding) = arg
+        .split_once('=')
+        .ok_or_else(|| format!("expected column=encoding, got {arg}"))?;
+    sinks::parse_encoding(encoding).map_err(|err| err.to_string())?;
+    Ok((column.to_owned(), encoding.to_owned()))
+}
+
+impl OutputArgs {
+    /// Descriptor an existing lance dataset being appended to was written with,
+    /// None for new datasets, datasets without one and other formats
+    pub async fn previous_descriptor(&self) -> anyhow::Result<Option<MessageDescriptor>> {
+        if !matches!(
+            (self.format, self.lance_mode),
+            (OutputFormat::Lance, LanceMode::Append)
+        ) {
+            return Ok(None);
+        }
+        match Dataset::open(&self.output).await {
+            Ok(dataset) => Ok(lance_descriptor(&dataset)?),
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// The flags as the `[[pipeline.sink]]` of a `katniss run` config
+    pub fn config(&self) -> SinkConfig {
+        SinkConfig {
+            output: self.output.clone(),
+            format: self.format.into(),
+            lance_mode: self.lance_mode.into(),
+            lance_max_versions: self.lance_max_versions,
+            lance_row_ttl: self.lance_row_ttl_secs.map(Duration::from_secs),
+            compression: self.compression.into(),
+            compression_level: self.compression_level,
+            bloom_filter: self.bloom_filter.clone(),
+            statistics: self
+                .statistics
+                .iter()
+                .map(|(column, level)| (column.clone(), (*level).into()))
+                .collect(),
+            encoding: self.encoding.iter().cloned().collect(),
+            sort_column: self.sort_column.clone(),
+            manifests: self.manifests,
+            file_naming: self.file_naming.clone(),
+            partition_by: self.partition_by.clone(),
+        }
+    }
+
+    pub fn sink(&self, props: &ArrowBatchProps) -> anyhow::Result<Box<dyn BufferSink>> {
+        Ok(self.config().sink(props)?)
+    }
+}
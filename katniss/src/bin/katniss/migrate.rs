@@ -0,0 +1,18 @@
+use clap::Args;
+
+use katniss::ingestor::migrate_parquet_to_lance;
+
+#[derive(Args)]
+pub struct MigrateArgs {
+    /// Directory of parquet files written by katniss, i.e. s3://bucket/packets
+    parquet_uri: String,
+
+    /// Where the lance datasets go, one per partition directory
+    lance_uri: String,
+}
+
+pub async fn run(args: MigrateArgs) -> anyhow::Result<()> {
+    let report = migrate_parquet_to_lance(&args.parquet_uri, &args.lance_uri).await?;
+    println!("{report:?}");
+    Ok(())
+}
@@ -0,0 +1,110 @@
+use std::collections::BTreeMap;
+
+use arrow_schema::{DataType, Field};
+use clap::Args;
+
+use katniss::pb2arrow::ArrowBatchProps;
+
+use crate::SchemaArgs;
+
+#[derive(Args)]
+pub struct PrintSchemaArgs {
+    #[command(flatten)]
+    schema: SchemaArgs,
+
+    /// Print the schema and dictionary values as JSON
+    #[arg(long)]
+    json: bool,
+}
+
+pub fn run(args: PrintSchemaArgs) -> anyhow::Result<()> {
+    let props = args.schema.props()?;
+    let dictionaries = dictionaries(&props);
+
+    if args.json {
+        let json = serde_json::json!({
+            "schema": props.schema.as_ref(),
+            "dictionaries": dictionaries,
+        });
+        println!("{}", serde_json::to_string_pretty(&json)?);
+        return Ok(());
+    }
+
+    print_schema(&props);
+    Ok(())
+}
+
+/// One line per field with enum values and metadata, see print_field
+pub fn print_schema(props: &ArrowBatchProps) {
+    let dictionaries = dictionaries(props);
+    for (key, value) in props.schema.metadata() {
+        println!("# {key}: {value}");
+    }
+    for field in props.schema.fields() {
+        print_field(field, 0, &dictionaries);
+    }
+}
+
+/// Enum values by dict_id
+fn dictionaries(props: &ArrowBatchProps) -> BTreeMap<i64, Vec<String>> {
+    let mut ids = Vec::new();
+    props
+        .schema
+        .fields()
+        .iter()
+        .for_each(|f| dict_ids(f, &mut ids));
+    ids.into_iter()
+        .filter_map(|id| {
+            let values = props.dictionaries.get_dict_values(id)?;
+            let values = values.iter().flatten().map(str::to_owned).collect();
+            Some((id, values))
+        })
+        .collect()
+}
+
+fn dict_ids(field: &Field, ids: &mut Vec<i64>) {
+    ids.extend(field.dict_id());
+    for child in children(field.data_type()) {
+        dict_ids(child, ids);
+    }
+}
+
+fn children(data_type: &DataType) -> Vec<&Field> {
+    match data_type {
+        DataType::Struct(fields) => fields.iter().map(AsRef::as_ref).collect(),
+        DataType::List(item) | DataType::LargeList(item) | DataType::FixedSizeList(item, _) => {
+            vec![item.as_ref()]
+        }
+        DataType::Map(entries, _) => vec![entries.as_ref()],
+        _ => Vec::new(),
+    }
+}
+
+/// One line per field, children indented under their parent, i.e.
+/// `timestamp: Struct (nullable)` then `  seconds: Int64`
+fn print_field(field: &Field, depth: usize, dictionaries: &BTreeMap<i64, Vec<String>>) {
+    let indent = "  ".repeat(depth);
+    let data_type = match field.data_type() {
+        DataType::Struct(_) => "Struct".to_owned(),
+        DataType::List(_) | DataType::LargeList(_) => "List".to_owned(),
+        DataType::Map(_, _) => "Map".to_owned(),
+        data_type => format!("{data_type:?}"),
+    };
+    let nullable = if field.is_nullable() {
+        " (nullable)"
+    } else {
+        ""
+    };
+    println!("{indent}{}: {data_type}{nullable}", field.name());
+
+    let values = field.dict_id().and_then(|id| dictionaries.get(&id));
+    if let Some(values) = values {
+        println!("{indent}  values: {}", values.join(", "));
+    }
+    for (key, value) in field.metadata() {
+        println!("{indent}  # {key}: {value}");
+    }
+    for child in children(field.data_type()) {
+        print_field(child, depth + 1, dictionaries);
+    }
+}
@@ -0,0 +1,171 @@
+use std::convert::Infallible;
+use std::time::Duration;
+
+use clap::Args;
+use tokio::{
+    net::TcpListener,
+    task::{AbortHandle, JoinError},
+};
+
+use katniss::ingestor::{errors::KatinssIngestorError, serve_tcp, LoopJoinSet, PipelineBuilder};
+
+use crate::{OutputArgs, SchemaArgs};
+
+#[derive(Args)]
+pub struct ServeArgs {
+    #[command(flatten)]
+    schema: SchemaArgs,
+
+    #[command(flatten)]
+    output: OutputArgs,
+
+    /// Address to accept length delimited messages on
+    #[arg(long, env = "KATNISS_LISTEN", default_value = "0.0.0.0:7878")]
+    listen: String,
+
+    /// How often buffers are rotated into the sink
+    #[arg(long, default_value_t = 60)]
+    period_secs: u64,
+
+    /// Accept websocket connections sending a message per binary frame instead
+    #[cfg(feature = "websocket")]
+    #[arg(long)]
+    websocket: bool,
+
+    /// With --websocket, each binary frame holds any number of length delimited messages
+    #[cfg(feature = "websocket")]
+    #[arg(long, requires = "websocket")]
+    delimited_frames: bool,
+
+    /// Also answer Flight SQL queries on the buffers that haven't been written yet, as the
+    /// table `live`. Only local clients can connect unless an address is given
+    #[cfg(feature = "flight-sql")]
+    #[arg(
+        long,
+        num_args = 0..=1,
+        default_missing_value = katniss::ingestor::DEFAULT_FLIGHT_SQL_ADDR
+    )]
+    flight_sql: Option<std::net::SocketAddr>,
+
+    /// Token Flight SQL clients have to send, as a bearer token or basic auth password
+    #[cfg(feature = "flight-sql")]
+    #[arg(long, env = "KATNISS_FLIGHT_SQL_TOKEN", requires = "flight_sql")]
+    flight_sql_token: Option<String>,
+
+    /// Memory the already written buffers kept queryable for --flight-sql may take
+    #[cfg(feature = "flight-sql")]
+    #[arg(long, requires = "flight_sql")]
+    live_max_mb: Option<usize>,
+}
+
+/// Runs until ctrl-c, then flushes what's buffered to the sink before exiting.
+/// On SIGHUP the schema is read again and swapped in without stopping.
+/// Both then and at startup, changes to the message are checked with the default SchemaGuard
+pub async fn run(args: ServeArgs) -> anyhow::Result<()> {
+    let props = args.schema.props()?;
+    let sink = args.output.sink(&props)?;
+    let descriptor = props.descriptor.clone();
+    #[cfg(feature = "flight-sql")]
+    let schema = props.schema.clone();
+
+    let mut builder = PipelineBuilder::new(props, Duration::from_secs(args.period_secs), sink);
+    if let Some(previous) = args.output.previous_descriptor().await? {
+        builder = builder.with_previous_descriptor(previous);
+    }
+    #[cfg(feature = "flight-sql")]
+    let flight_sql = match args.flight_sql {
+        Some(addr) => {
+            let mut live = katniss::ingestor::LiveBuffers::new(schema, 4);
+            if let Some(max_mb) = args.live_max_mb {
+                live = live.with_max_bytes(max_mb * 1024 * 1024);
+            }
+            builder = builder.with_live_buffers(live.clone());
+            let mut endpoint =
+                katniss::ingestor::FlightSqlEndpoint::for_live_buffers(&live, "live")?;
+            if let Some(token) = args.flight_sql_token {
+                endpoint = endpoint.with_token(token);
+            }
+            Some((endpoint, addr))
+        }
+        None => None,
+    };
+    #[cfg(unix)]
+    reload_on_hangup(args.schema, builder.schema_reloader())?;
+    let handle = builder.handle();
+    let (head, mut tasks) = builder.build()?;
+    for warning in handle.status().schema_warnings {
+        eprintln!("warning: {warning}");
+    }
+    #[allow(unused_mut)]
+    let mut servers = Vec::new();
+    #[cfg(feature = "flight-sql")]
+    if let Some((endpoint, addr)) = flight_sql {
+        println!("answering flight sql on {addr}");
+        servers.push(tasks.spawn(async move {
+            endpoint.serve(addr).await?;
+            Err(KatinssIngestorError::PipelineClosed)
+        }));
+    }
+    let listener = TcpListener::bind(&args.listen).await?;
+    println!("listening on {}", listener.local_addr()?);
+    #[cfg(feature = "websocket")]
+    if args.websocket {
+        use katniss::ingestor::{serve_websocket, FrameContents};
+
+        let contents = match args.delimited_frames {
+            true => FrameContents::Delimited,
+            false => FrameContents::Message,
+        };
+        servers.push(tasks.spawn(serve_websocket(listener, descriptor, head, contents)));
+        return until_ctrl_c(tasks, servers).await;
+    }
+    servers.push(tasks.spawn(serve_tcp(listener, descriptor, head)));
+    until_ctrl_c(tasks, servers).await
+}
+
+/// Reloads the schema every time the process gets a SIGHUP, i.e. after the descriptor set's
+/// been rebuilt. A schema that fails to load or isn't compatible is reported and ignored
+#[cfg(unix)]
+fn reload_on_hangup(
+    schema: SchemaArgs,
+    reloader: katniss::ingestor::SchemaReloader,
+) -> anyhow::Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangups = signal(SignalKind::hangup())?;
+    tokio::spawn(async move {
+        while hangups.recv().await.is_some() {
+            match schema.props().and_then(|props| Ok(reloader.reload(props)?)) {
+                Ok(warnings) => {
+                    println!("reloaded {}", schema.message);
+                    warnings.iter().for_each(|w| eprintln!("warning: {w}"));
+                }
+                Err(err) => eprintln!("kept the old schema, reload failed: {err}"),
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Waits for ctrl-c then stops the servers, which closes their pipelines once they've flushed
+pub async fn until_ctrl_c(mut tasks: LoopJoinSet, servers: Vec<AbortHandle>) -> anyhow::Result<()> {
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => servers.iter().for_each(AbortHandle::abort),
+        Some(stopped) = tasks.join_next() => check(stopped)?,
+    }
+    while let Some(stopped) = tasks.join_next().await {
+        check(stopped)?;
+    }
+    Ok(())
+}
+
+/// Stages exit with PipelineClosed once the server's gone and everything's written
+fn check(stopped: Result<katniss::ingestor::Result<Infallible>, JoinError>) -> anyhow::Result<()> {
+    match stopped {
+        Ok(Ok(never)) => match never {},
+        Ok(Err(KatinssIngestorError::PipelineClosed)) => Ok(()),
+        Ok(Err(err)) => Err(err.into()),
+        Err(err) if err.is_cancelled() => Ok(()),
+        Err(err) => Err(err.into()),
+    }
+}
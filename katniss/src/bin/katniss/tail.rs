@@ -0,0 +1,137 @@
+use arrow_array::{BooleanArray, RecordBatch};
+use arrow_cast::pretty::pretty_format_batches;
+use arrow_select::filter::filter_record_batch;
+use clap::Args;
+use futures::TryStreamExt;
+use lance::dataset::Dataset;
+use object_store::{path::Path, ObjectMeta, ObjectStore};
+use parquet::arrow::{arrow_reader::ParquetRecordBatchReaderBuilder, ProjectionMask};
+
+use katniss::ingestor::sinks::store_from_uri;
+
+#[derive(Args)]
+pub struct TailArgs {
+    /// A lance dataset, a parquet file or a directory of them, i.e. file:///data/packets
+    input: String,
+
+    /// Rows to print
+    #[arg(short = 'n', long, default_value_t = 20)]
+    rows: usize,
+
+    /// Top level columns to print, all of them if not given, i.e. --columns timestamp,sender_uid
+    #[arg(long, value_delimiter = ',')]
+    columns: Vec<String>,
+
+    /// Print rows spread evenly through the whole output instead of the last ones
+    #[arg(long)]
+    sample: bool,
+}
+
+pub async fn run(args: TailArgs) -> anyhow::Result<()> {
+    let (store, prefix) = store_from_uri(&args.input)?;
+    let batches = if is_lance(store.as_ref(), &prefix).await? {
+        lance_batches(&args).await?
+    } else {
+        let mut files: Vec<ObjectMeta> = store.list(Some(&prefix)).await?.try_collect().await?;
+        files.retain(|f| f.location.extension() == Some("parquet"));
+        files.sort_by(|a, b| a.location.cmp(&b.location));
+        parquet_batches(&args, store.as_ref(), &files).await?
+    };
+
+    let batches = if args.sample {
+        sample(&batches, args.rows)?
+    } else {
+        last_rows(&batches, args.rows)
+    };
+    println!("{}", pretty_format_batches(&batches)?);
+    Ok(())
+}
+
+/// Whether the prefix is a lance dataset rather than parquet files
+pub async fn is_lance(store: &dyn ObjectStore, prefix: &Path) -> anyhow::Result<bool> {
+    Ok(store
+        .list_with_delimiter(Some(prefix))
+        .await?
+        .common_prefixes
+        .iter()
+        .any(|dir| dir.filename() == Some("_versions")))
+}
+
+async fn lance_batches(args: &TailArgs) -> anyhow::Result<Vec<RecordBatch>> {
+    let dataset = Dataset::open(&args.input).await?;
+    let mut scanner = dataset.scan();
+    if !args.columns.is_empty() {
+        let columns: Vec<_> = args.columns.iter().map(String::as_str).collect();
+        scanner.project(&columns)?;
+    }
+    if !args.sample {
+        let total = dataset.count_rows().await?;
+        let offset = total.saturating_sub(args.rows);
+        scanner.limit(args.rows as i64, Some(offset as i64))?;
+    }
+    Ok(scanner.try_into_stream().await?.try_collect().await?)
+}
+
+/// Files are read newest (last by name) first until there are enough rows, every file is read
+/// when sampling
+async fn parquet_batches(
+    args: &TailArgs,
+    store: &dyn ObjectStore,
+    files: &[ObjectMeta],
+) -> anyhow::Result<Vec<RecordBatch>> {
+    let mut per_file: Vec<Vec<RecordBatch>> = Vec::new();
+    let mut rows = 0;
+    for file in files.iter().rev() {
+        if !args.sample && rows >= args.rows {
+            break;
+        }
+        let bytes = store.get(&file.location).await?.bytes().await?;
+        let builder = ParquetRecordBatchReaderBuilder::try_new(bytes)?;
+        let builder = if args.columns.is_empty() {
+            builder
+        } else {
+            let roots = args
+                .columns
+                .iter()
+                .map(|column| builder.schema().index_of(column))
+                .collect::<Result<Vec<_>, _>>()?;
+            let mask = ProjectionMask::roots(builder.parquet_schema(), roots);
+            builder.with_projection(mask)
+        };
+        let batches = builder.build()?.collect::<Result<Vec<_>, _>>()?;
+        rows += batches.iter().map(RecordBatch::num_rows).sum::<usize>();
+        per_file.push(batches);
+    }
+    Ok(per_file.into_iter().rev().flatten().collect())
+}
+
+fn last_rows(batches: &[RecordBatch], n: usize) -> Vec<RecordBatch> {
+    let mut keep = Vec::new();
+    let mut rows = 0;
+    for batch in batches.iter().rev() {
+        if rows >= n {
+            break;
+        }
+        let take = batch.num_rows().min(n - rows);
+        keep.push(batch.slice(batch.num_rows() - take, take));
+        rows += take;
+    }
+    keep.reverse();
+    keep
+}
+
+/// Every k-th row so that about n are left
+fn sample(batches: &[RecordBatch], n: usize) -> anyhow::Result<Vec<RecordBatch>> {
+    let total = batches.iter().map(RecordBatch::num_rows).sum::<usize>();
+    let step = (total / n.max(1)).max(1);
+    let mut offset = 0;
+    let mut sampled = Vec::new();
+    for batch in batches {
+        let keep: BooleanArray = (offset..offset + batch.num_rows())
+            .map(|i| Some(i % step == 0 && i / step < n))
+            .collect();
+        sampled.push(filter_record_batch(batch, &keep)?);
+        offset += batch.num_rows();
+    }
+    Ok(sampled)
+}
@@ -5,5 +5,6 @@ pub mod exports {
     pub use arrow_array;
     pub use arrow_schema;
     pub use object_store;
+    pub use parquet;
     pub use prost_reflect;
 }